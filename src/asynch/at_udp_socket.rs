@@ -1,34 +1,122 @@
-use embassy_net::{udp::UdpSocket, Ipv4Address};
+use core::cell::RefCell;
+use core::future::poll_fn;
+
+use embassy_net::udp::{BindError, PacketMetadata, RecvError, SendError, UdpSocket};
+use embassy_net::{IpEndpoint, Stack};
 use embedded_io_async::{Read, Write};
 
+use super::state::{self, AtTransportStatus};
 use crate::config::Transport;
 
-pub struct AtUdpSocket<'a>(pub(crate) UdpSocket<'a>);
+/// Error returned when reading from or writing to an [`AtUdpSocket`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    Recv(RecvError),
+    Send(SendError),
+    /// The socket was closed, and reopening it failed.
+    Bind(BindError),
+}
+
+impl embedded_io_async::Error for Error {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        match self {
+            Error::Recv(RecvError::Truncated) => embedded_io_async::ErrorKind::OutOfMemory,
+            Error::Send(SendError::NoRoute) => embedded_io_async::ErrorKind::NotConnected,
+            Error::Bind(_) => embedded_io_async::ErrorKind::NotConnected,
+            _ => embedded_io_async::ErrorKind::Other,
+        }
+    }
+}
+
+/// UDP socket tunneling AT commands to the module while the serial link is
+/// in PPP mode.
+///
+/// If the stack closes the socket, it is reopened on the next read or write,
+/// and the outcome is reported as the [`AtTransportStatus`].
+pub struct AtUdpSocket<'a> {
+    socket: RefCell<UdpSocket<'a>>,
+    /// Endpoint of the module, whose port is also the local port.
+    remote: IpEndpoint,
+    ch: state::Runner<'a>,
+}
 
 impl<'a> AtUdpSocket<'a> {
-    pub(crate) const PPP_AT_PORT: u16 = 23;
-    pub(crate) const PPP_AT_IP: Ipv4Address = Ipv4Address::new(172, 30, 0, 251);
+    /// Create a socket on `stack` sending to the module at `remote`, bound to
+    /// the same port.
+    pub(crate) fn bind(
+        stack: Stack<'a>,
+        ch: state::Runner<'a>,
+        remote: IpEndpoint,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self, BindError> {
+        let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer);
+        socket.bind(remote.port)?;
+        ch.set_at_transport(AtTransportStatus::Udp);
+        Ok(Self {
+            socket: RefCell::new(socket),
+            remote,
+            ch,
+        })
+    }
+
+    /// Bind `socket` again if the stack closed it.
+    fn reopen(&self, socket: &mut UdpSocket<'a>) -> Result<(), Error> {
+        if socket.is_open() {
+            return Ok(());
+        }
+
+        warn!("AT socket closed, reopening");
+        if let Err(e) = socket.bind(self.remote.port) {
+            self.ch.set_at_transport(AtTransportStatus::Reconnecting);
+            return Err(Error::Bind(e));
+        }
+        self.ch.mark_at_reconnect();
+        self.ch.set_at_transport(AtTransportStatus::Udp);
+        Ok(())
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        // Closing the socket wakes a pending receive, which then reopens it
+        poll_fn(|cx| {
+            let mut socket = self.socket.borrow_mut();
+            self.reopen(&mut socket)?;
+            socket
+                .poll_recv_from(buf, cx)
+                .map(|res| res.map(|(len, _)| len).map_err(Error::Recv))
+        })
+        .await
+    }
+
+    async fn send(&self, buf: &[u8]) -> Result<usize, Error> {
+        poll_fn(|cx| {
+            let mut socket = self.socket.borrow_mut();
+            self.reopen(&mut socket)?;
+            socket
+                .poll_send_to(buf, self.remote, cx)
+                .map_err(Error::Send)
+        })
+        .await?;
+        Ok(buf.len())
+    }
 }
 
 impl<'a> embedded_io_async::ErrorType for &AtUdpSocket<'a> {
-    type Error = core::convert::Infallible;
+    type Error = Error;
 }
 
 impl<'a> Read for &AtUdpSocket<'a> {
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let (len, _) = self.0.recv_from(buf).await.unwrap();
-        Ok(len)
+        self.recv(buf).await
     }
 }
 
 impl<'a> Write for &AtUdpSocket<'a> {
     async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        self.0
-            .send_to(buf, (AtUdpSocket::PPP_AT_IP, AtUdpSocket::PPP_AT_PORT))
-            .await
-            .unwrap();
-
-        Ok(buf.len())
+        self.send(buf).await
     }
 
     async fn flush(&mut self) -> Result<(), Self::Error> {
@@ -46,28 +134,222 @@ impl<'a> Transport for AtUdpSocket<'a> {
     }
 }
 
+impl<'a> Transport for &AtUdpSocket<'a> {
+    fn set_baudrate(&mut self, _baudrate: u32) {
+        // Nothing to do here
+    }
+
+    fn split_ref(&mut self) -> (impl Write, impl Read) {
+        (&**self, &**self)
+    }
+}
+
 impl<'a> embedded_io_async::ErrorType for AtUdpSocket<'a> {
-    type Error = core::convert::Infallible;
+    type Error = Error;
 }
 
 impl<'a> Read for AtUdpSocket<'a> {
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let (len, _) = self.0.recv_from(buf).await.unwrap();
-        Ok(len)
+        self.recv(buf).await
     }
 }
 
 impl<'a> Write for AtUdpSocket<'a> {
     async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        self.0
-            .send_to(buf, (AtUdpSocket::PPP_AT_IP, AtUdpSocket::PPP_AT_PORT))
-            .await
-            .unwrap();
-
-        Ok(buf.len())
+        self.send(buf).await
     }
 
     async fn flush(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+    use core::task::{Context, Waker};
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use atat::asynch::AtatClient as _;
+    use embassy_net::driver::{Capabilities, Driver, HardwareAddress, LinkState, Medium};
+    use embassy_net::{Config, Ipv4Address, Ipv4Cidr, StackResources, StaticConfigV4};
+    use embassy_time::Instant;
+
+    use super::*;
+    use crate::asynch::control::ProxyClient;
+    use crate::asynch::runner::at_bridge;
+    use crate::asynch::test_support::leak_resources;
+    use crate::command::AT;
+
+    const MODULE_IP: [u8; 4] = [172, 30, 0, 251];
+    const PORT: u16 = 23;
+
+    /// IP link to a module answering every datagram sent to its AT port with
+    /// `OK`.
+    #[derive(Default)]
+    struct MockLink {
+        rx: VecDeque<Vec<u8>>,
+        waker: Option<Waker>,
+    }
+
+    /// Reply of the module to the IPv4 packet `packet`, if it is a datagram
+    /// for its AT port.
+    fn reply(packet: &[u8]) -> Option<Vec<u8>> {
+        let ihl = usize::from(packet[0] & 0x0f) * 4;
+        if packet[9] != 17 || packet[16..20] != MODULE_IP {
+            return None;
+        }
+        let udp = &packet[ihl..];
+        if u16::from_be_bytes([udp[2], udp[3]]) != PORT {
+            return None;
+        }
+
+        let payload = b"\r\nOK\r\n";
+        let total_len = (20 + 8 + payload.len()) as u16;
+        let mut reply = Vec::new();
+        reply.extend_from_slice(&[0x45, 0]);
+        reply.extend_from_slice(&total_len.to_be_bytes());
+        reply.extend_from_slice(&[0, 0, 0x40, 0, 64, 17, 0, 0]);
+        reply.extend_from_slice(&MODULE_IP);
+        reply.extend_from_slice(&packet[12..16]);
+        let checksum = !reply.chunks(2).fold(0u32, |sum, word| {
+            let sum = sum + u32::from(u16::from_be_bytes([word[0], word[1]]));
+            (sum & 0xffff) + (sum >> 16)
+        }) as u16;
+        reply[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        // UDP header without a checksum
+        reply.extend_from_slice(&PORT.to_be_bytes());
+        reply.extend_from_slice(&udp[0..2]);
+        reply.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+        reply.extend_from_slice(&[0, 0]);
+        reply.extend_from_slice(payload);
+        Some(reply)
+    }
+
+    struct MockRxToken(Vec<u8>);
+
+    impl embassy_net::driver::RxToken for MockRxToken {
+        fn consume<R, F: FnOnce(&mut [u8]) -> R>(mut self, f: F) -> R {
+            f(&mut self.0)
+        }
+    }
+
+    struct MockTxToken<'a>(&'a mut MockLink);
+
+    impl embassy_net::driver::TxToken for MockTxToken<'_> {
+        fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+            let mut packet = std::vec![0; len];
+            let res = f(&mut packet);
+            if let Some(reply) = reply(&packet) {
+                self.0.rx.push_back(reply);
+                if let Some(waker) = self.0.waker.take() {
+                    waker.wake();
+                }
+            }
+            res
+        }
+    }
+
+    impl Driver for MockLink {
+        type RxToken<'a> = MockRxToken;
+        type TxToken<'a> = MockTxToken<'a>;
+
+        fn receive(&mut self, cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+            match self.rx.pop_front() {
+                Some(packet) => Some((MockRxToken(packet), MockTxToken(self))),
+                None => {
+                    self.waker = Some(cx.waker().clone());
+                    None
+                }
+            }
+        }
+
+        fn transmit(&mut self, _cx: &mut Context) -> Option<Self::TxToken<'_>> {
+            Some(MockTxToken(self))
+        }
+
+        fn link_state(&mut self, _cx: &mut Context) -> LinkState {
+            LinkState::Up
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            let mut caps = Capabilities::default();
+            caps.max_transmission_unit = 1500;
+            caps.medium = Medium::Ip;
+            caps
+        }
+
+        fn hardware_address(&self) -> HardwareAddress {
+            HardwareAddress::Ip
+        }
+    }
+
+    #[test]
+    fn reopens_closed_socket() {
+        let resources = leak_resources::<256, 4>();
+        let ch = state::Runner::new(&mut resources.ch);
+        let client = ProxyClient::new(
+            resources.req_slot.sender(),
+            &resources.res_slot,
+            &resources.cmd_lock,
+        );
+        let mut ingress = atat::Ingress::new(
+            atat::AtDigester::new(),
+            &mut resources.ingress_buf,
+            &resources.res_slot,
+            &resources.urc_channel,
+        );
+
+        let config = Config::ipv4_static(StaticConfigV4 {
+            address: Ipv4Cidr::new(Ipv4Address::new(172, 30, 0, 1), 24),
+            gateway: None,
+            dns_servers: Default::default(),
+        });
+        let (stack, mut net) = embassy_net::new(
+            MockLink::default(),
+            config,
+            Box::leak(Box::new(StackResources::<2>::new())),
+            0,
+        );
+
+        let mut rx_meta = [PacketMetadata::EMPTY; 1];
+        let mut tx_meta = [PacketMetadata::EMPTY; 1];
+        let mut rx_buf = [0u8; 64];
+        let mut tx_buf = [0u8; 64];
+        let socket = AtUdpSocket::bind(
+            stack,
+            ch.clone(),
+            IpEndpoint::new(Ipv4Address::from(MODULE_IP).into(), PORT),
+            &mut rx_meta,
+            &mut rx_buf,
+            &mut tx_meta,
+            &mut tx_buf,
+        )
+        .unwrap();
+        assert_eq!(ch.at_transport(None), AtTransportStatus::Udp);
+
+        let last_rx = Cell::new(Instant::now());
+        let test = async {
+            (&client).send(&AT).await.unwrap();
+
+            // Kill the socket while a command is in flight
+            let _ = embassy_futures::join::join((&client).send(&AT), async {
+                socket.socket.borrow_mut().close();
+            })
+            .await;
+
+            (&client).send(&AT).await.unwrap();
+        };
+
+        crate::asynch::test_support::block_on(embassy_futures::select::select3(
+            net.run(),
+            at_bridge(&mut &socket, &resources.req_slot, &mut ingress, &last_rx),
+            test,
+        ));
+
+        assert_eq!(ch.at_reconnects(), 1);
+        assert_eq!(ch.at_transport(None), AtTransportStatus::Udp);
+    }
+}
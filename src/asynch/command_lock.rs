@@ -0,0 +1,204 @@
+//! Fair lock serializing the AT commands sent by all handles sharing the same
+//! [`Resources`](super::Resources).
+//!
+//! The module processes one command at a time, and responses are delivered
+//! through a single response slot. A command therefore holds the lock from
+//! sending the request until its response has been consumed. Tasks are
+//! granted the lock in the order they requested it.
+//!
+//! A command cancelled while waiting for its response leaves the response to
+//! be dropped by the next command, rather than taken for its own.
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embassy_sync::blocking_mutex::{raw::NoopRawMutex, Mutex};
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use embassy_time::{Duration, Instant, Timer};
+
+/// Number of waiting tasks that are queued in order. Further tasks wait
+/// until there is room in the queue.
+const MAX_WAITERS: usize = 8;
+
+pub(crate) struct CommandLock {
+    state: Mutex<NoopRawMutex, RefCell<LockState>>,
+}
+
+struct LockState {
+    locked: bool,
+    next_ticket: u32,
+    /// Tickets of the tasks waiting for the lock, oldest first.
+    queue: heapless::Vec<u32, MAX_WAITERS>,
+    wakers: MultiWakerRegistration<MAX_WAITERS>,
+    /// Earliest time the next command may be sent.
+    ready_at: Option<Instant>,
+    /// Time until which the response to a cancelled command may still
+    /// arrive.
+    abandoned_until: Option<Instant>,
+}
+
+impl CommandLock {
+    pub(crate) const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(LockState {
+                locked: false,
+                next_ticket: 0,
+                queue: heapless::Vec::new(),
+                wakers: MultiWakerRegistration::new(),
+                ready_at: None,
+                abandoned_until: None,
+            })),
+        }
+    }
+
+    /// Wait for all commands requested before this call to complete, and
+    /// take the lock.
+    pub(crate) async fn lock(&self) -> CommandGuard<'_> {
+        let mut waiter = Waiter {
+            lock: self,
+            ticket: None,
+        };
+
+        poll_fn(|cx| {
+            self.state.lock(|s| {
+                let s = &mut *s.borrow_mut();
+
+                if waiter.ticket.is_none() && s.queue.push(s.next_ticket).is_ok() {
+                    waiter.ticket = Some(s.next_ticket);
+                    s.next_ticket = s.next_ticket.wrapping_add(1);
+                }
+
+                if !s.locked && waiter.ticket.is_some() && s.queue.first() == waiter.ticket.as_ref()
+                {
+                    s.queue.remove(0);
+                    s.locked = true;
+                    waiter.ticket = None;
+                    return Poll::Ready(());
+                }
+
+                s.wakers.register(cx.waker());
+                Poll::Pending
+            })
+        })
+        .await;
+
+        CommandGuard { lock: self }
+    }
+}
+
+/// Position in the queue of a task waiting for the lock, released if the
+/// task stops waiting.
+struct Waiter<'a> {
+    lock: &'a CommandLock,
+    ticket: Option<u32>,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket {
+            self.lock.state.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                s.queue.retain(|t| *t != ticket);
+                s.wakers.wake();
+            })
+        }
+    }
+}
+
+/// Exclusive access to the module for sending a command.
+pub(crate) struct CommandGuard<'a> {
+    lock: &'a CommandLock,
+}
+
+impl CommandGuard<'_> {
+    /// Wait for the cooldown after the previous command to expire.
+    pub(crate) async fn cooldown(&self) {
+        let ready_at = self.lock.state.lock(|s| s.borrow_mut().ready_at.take());
+        if let Some(ready_at) = ready_at {
+            Timer::at(ready_at).await
+        }
+    }
+
+    /// Record that the response to the command sent under this guard will not
+    /// be waited for, while it may still arrive until `until`.
+    pub(crate) fn abandon_response(&self, until: Instant) {
+        self.lock
+            .state
+            .lock(|s| s.borrow_mut().abandoned_until = Some(until))
+    }
+
+    /// Take the time until which the response to a cancelled command may
+    /// still arrive, if any.
+    pub(crate) fn take_abandoned_response(&self) -> Option<Instant> {
+        self.lock
+            .state
+            .lock(|s| s.borrow_mut().abandoned_until.take())
+            .filter(|until| *until > Instant::now())
+    }
+
+    /// Delay the next command, from any handle, by `duration`.
+    pub(crate) fn start_cooldown(&self, duration: Duration) {
+        self.lock
+            .state
+            .lock(|s| s.borrow_mut().ready_at = Some(Instant::now() + duration))
+    }
+}
+
+impl Drop for CommandGuard<'_> {
+    fn drop(&mut self) {
+        self.lock.state.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.locked = false;
+            s.wakers.wake();
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asynch::test_support::block_on;
+    use embassy_futures::join::join;
+    use embassy_futures::select::{select, Either};
+    use embassy_futures::yield_now;
+
+    #[test]
+    fn granted_in_request_order() {
+        let lock = CommandLock::new();
+        let order = RefCell::new(std::vec::Vec::new());
+
+        let task = |id: u8| {
+            let lock = &lock;
+            let order = &order;
+            async move {
+                for _ in 0..2 {
+                    let _guard = lock.lock().await;
+                    order.borrow_mut().push(id);
+                    yield_now().await;
+                }
+            }
+        };
+
+        block_on(join(task(0), task(1)));
+
+        assert_eq!(*order.borrow(), [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn cancelled_waiter_leaves_queue() {
+        let lock = CommandLock::new();
+
+        block_on(async {
+            let guard = lock.lock().await;
+            // Gives up waiting while the lock is held
+            assert!(matches!(
+                select(lock.lock(), yield_now()).await,
+                Either::Second(())
+            ));
+            drop(guard);
+
+            let _guard = lock.lock().await;
+        });
+    }
+}
@@ -1,30 +1,73 @@
 use core::cell::Cell;
-use core::net::Ipv4Addr;
+use core::fmt::Write as _;
+use core::net::{Ipv4Addr, Ipv6Addr};
+use core::ops::ControlFlow;
 use core::str::FromStr as _;
 
 use atat::AtatCmd;
 use atat::{asynch::AtatClient, response_slot::ResponseSlotGuard, UrcChannel};
 use embassy_futures::select::{select, Either};
-use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Sender};
-use embassy_time::{with_timeout, Duration, Timer};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Sender, pipe::Pipe};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
 use heapless::Vec;
 
-use crate::command::general::responses::SoftwareVersionResponse;
-use crate::command::general::types::FirmwareVersion;
-use crate::command::general::SoftwareVersion;
-use crate::command::gpio::responses::ReadGPIOResponse;
+use crate::command::data_mode::types::{RemoteConfiguration, WatchdogSetting};
+#[cfg(feature = "internal-network-stack")]
+use crate::command::data_mode::{types::ConnectScheme, SetDefaultRemotePeer};
+use crate::command::data_mode::{
+    ConfigureServer, ServerConfigBuilder, SetServerFlags, SetWatchdogSettings,
+};
+#[cfg(feature = "internal-network-stack")]
+use crate::command::edm::join_confirmations;
+use crate::command::ethernet::responses::EthernetConfigurationResponse;
+use crate::command::ethernet::types::{
+    EthernetConfig, EthernetConfigAction, EthernetConfigParameter, EthernetConfigR,
+    EthernetDuplexMode, EthernetSpeed,
+};
+use crate::command::ethernet::{
+    EthernetConfigurationAction, GetEthernetConfiguration, SetEthernetConfiguration,
+};
+use crate::command::firmware_update::types::{SoftwareUpdateBaudRate, SoftwareUpdateMode};
+use crate::command::firmware_update::SoftwareUpdate;
+use crate::command::general::responses::{ModelIdentificationResponse, SoftwareVersionResponse};
+use crate::command::general::types::{FirmwareVersion, GreetingTextMode, ModuleFamily};
+use crate::command::general::{ModelIdentification, SetGreetingText, SoftwareVersion};
 use crate::command::gpio::types::GPIOMode;
 use crate::command::gpio::ConfigureGPIO;
 use crate::command::network::responses::NetworkStatusResponse;
-use crate::command::network::types::{NetworkStatus, NetworkStatusParameter};
-use crate::command::network::GetNetworkStatus;
+use crate::command::network::types::{
+    BridgeAction, BridgeConfig, BridgeConfigId, IPv4Mode, RoutingTag,
+};
+use crate::command::network::types::{NetworkErrorReason, NetworkStatus, NetworkStatusParameter};
+use crate::command::network::{
+    BridgeConfigurationAction, GetNetworkStatus, Layer2Routing, SetBridgeConfiguration,
+};
 #[cfg(feature = "ppp")]
 use crate::command::ping::Ping;
+use crate::command::security::responses::{SecurityDataImport, SecurityDataMD5};
+use crate::command::security::types::SecurityDataType;
+use crate::command::security::{
+    GetSecurityDataMD5, PrepareSecurityDataImport, SecurityDataChunk, SendSecurityDataImport,
+};
 use crate::command::system::responses::LocalAddressResponse;
-use crate::command::system::types::InterfaceID;
-use crate::command::system::GetLocalAddress;
-use crate::command::wifi::types::{IPv4Mode, PasskeyR};
-use crate::command::wifi::{ExecWifiStationAction, GetWifiStatus, SetWifiStationConfig};
+use crate::command::system::responses::RS232SettingsResponse;
+use crate::command::system::types::{BaudRate, ChangeAfterConfirm, InterfaceID};
+use crate::command::system::{
+    GetLocalAddress, GetRS232Settings, RebootDCE, SetRS232Settings, StoreCurrentConfig,
+};
+use crate::command::wifi::responses::{
+    ChannelListResponse, WiFiAPStationCountResponse, WiFiAPStationListResponse, WifiConfigResponse,
+    WifiScanWithResponse,
+};
+use crate::command::wifi::types::{
+    AccessPointStatus, AccessPointStatusId, ApStation, IPv4Mode, PasskeyR, PowerSaveMode,
+    ScannedWifiNetwork, TLSVersion, WifiConfig, WifiConfigParameter, WifiMode, WifiRegion,
+};
+use crate::command::wifi::{
+    ExecWifiStationAction, GetChannelList, GetWifiConfig, GetWifiStatus, ResetChannelList,
+    SetChannelList, SetWifiConfig, SetWifiStationConfig, WiFiAPStationCount, WiFiAPStationList,
+    WifiAPStatus, WifiScanWith,
+};
 use crate::command::OnOff;
 use crate::command::{
     gpio::ReadGPIO,
@@ -44,36 +87,149 @@ use crate::command::{
     wifi::SetWifiAPConfig,
 };
 use crate::command::{network::SetNetworkHostName, wifi::types::AccessPointConfig};
-use crate::command::{
-    system::{RebootDCE, ResetToFactoryDefaults},
-    wifi::types::AccessPointId,
+use crate::command::{system::ResetToFactoryDefaults, wifi::types::AccessPointId};
+use crate::connection::{DnsServers, StaticConfigV4, StaticConfigV6, Uplink, WiFiState};
+use crate::error::{BridgeError, ChannelListError, Error, ModuleError, PowerLevelError};
+use crate::options::{
+    BridgeInterface, BridgeProfile, ConnectionOptions, EnterpriseAuth, EnterpriseCertificate,
+    HotspotOptions, WifiAuthentication,
+};
+#[cfg(feature = "provisional-at")]
+use crate::{
+    command::wifi::{
+        responses::GetWifiStationConfigResponse,
+        types::{WifiStationConfigParameter, WifiStationConfigR, PMF},
+        GetWifiStationConfig,
+    },
+    options::WifiCredentials,
 };
-use crate::connection::{DnsServers, StaticConfigV4, WiFiState};
-use crate::error::Error;
-use crate::options::{ConnectionOptions, HotspotOptions, WifiAuthentication};
 
-use super::runner::{MAX_CMD_LEN, URC_SUBSCRIBERS};
-use super::state::LinkState;
-use super::{state, UbloxUrc};
+use super::command_lock::{CommandGuard, CommandLock};
+use super::firmware_update::FirmwareUpdater;
+use super::runner::{DEFAULT_CMD_BUF_SIZE, FW_RX_SIZE, URC_SUBSCRIBERS};
+use super::state::{AtTransportStatus, InitStatus, IpFamily, LinkState, ModuleInfo};
+use super::{state, OnDrop, UbloxUrc};
 
 const CONFIG_ID: u8 = 0;
-
-pub(crate) struct ProxyClient<'a, const INGRESS_BUF_SIZE: usize> {
-    pub(crate) req_sender: Sender<'a, NoopRawMutex, Vec<u8, MAX_CMD_LEN>, 1>,
+/// Number of times a command rejected with [`Error::ModuleBusy`] is retried.
+const BUSY_RETRIES: usize = 5;
+const BUSY_RETRY_DELAY: Duration = Duration::from_millis(200);
+const MAX_GREETING_TEXT_LEN: usize = 48;
+#[cfg(feature = "internal-network-stack")]
+const MAX_DEFAULT_REMOTE_PEER_ID: u8 = 6;
+const MAX_SERVER_ID: u8 = 6;
+
+/// Client sending AT commands to the module through the runner.
+///
+/// All clients created from the same [`Resources`](super::Resources) share a
+/// [`CommandLock`], so commands from different clients are sent one at a
+/// time, in the order they were issued.
+pub(crate) struct ProxyClient<'a, const INGRESS_BUF_SIZE: usize, const CMD_BUF_SIZE: usize> {
+    pub(crate) req_sender: Sender<'a, NoopRawMutex, Vec<u8, CMD_BUF_SIZE>, 1>,
     pub(crate) res_slot: &'a atat::ResponseSlot<INGRESS_BUF_SIZE>,
-    cooldown_timer: Cell<Option<Timer>>,
+    cmd_lock: &'a CommandLock,
+    /// Error reported by the module for the last failed command.
+    module_error: Cell<Option<ModuleError>>,
+}
+
+impl<const INGRESS_BUF_SIZE: usize, const CMD_BUF_SIZE: usize> Clone
+    for ProxyClient<'_, INGRESS_BUF_SIZE, CMD_BUF_SIZE>
+{
+    fn clone(&self) -> Self {
+        Self::new(self.req_sender, self.res_slot, self.cmd_lock)
+    }
 }
 
-impl<'a, const INGRESS_BUF_SIZE: usize> ProxyClient<'a, INGRESS_BUF_SIZE> {
+impl<'a, const INGRESS_BUF_SIZE: usize, const CMD_BUF_SIZE: usize>
+    ProxyClient<'a, INGRESS_BUF_SIZE, CMD_BUF_SIZE>
+{
     pub fn new(
-        req_sender: Sender<'a, NoopRawMutex, Vec<u8, MAX_CMD_LEN>, 1>,
+        req_sender: Sender<'a, NoopRawMutex, Vec<u8, CMD_BUF_SIZE>, 1>,
         res_slot: &'a atat::ResponseSlot<INGRESS_BUF_SIZE>,
+        cmd_lock: &'a CommandLock,
     ) -> Self {
         Self {
             req_sender,
             res_slot,
-            cooldown_timer: Cell::new(None),
+            cmd_lock,
+            module_error: Cell::new(None),
+        }
+    }
+
+    /// Take the error reported by the module for the last failed command, if
+    /// it could be recovered from the response.
+    pub(crate) fn take_module_error(&self) -> Option<ModuleError> {
+        self.module_error.take()
+    }
+
+    /// Send a command, retrying up to `retries` times, `delay` apart, while it
+    /// times out, e.g. because the module is busy associating.
+    ///
+    /// Only use this for commands that are safe to repeat.
+    pub(crate) async fn send_with_retry<Cmd: AtatCmd>(
+        &self,
+        cmd: &Cmd,
+        retries: usize,
+        delay: Duration,
+    ) -> Result<Cmd::Response, atat::Error> {
+        let mut client = self;
+        let mut attempt = 0;
+        loop {
+            match client.send_retry(cmd).await {
+                Err(atat::Error::Timeout) if attempt < retries => {
+                    attempt += 1;
+                    warn!("Command timed out, retrying ({}/{})", attempt, retries);
+                    Timer::after(delay).await;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Import a certificate or private key, writing `data` in chunks that fit
+    /// the command buffer.
+    pub(crate) async fn import_security_data(
+        &self,
+        data_type: SecurityDataType,
+        name: &str,
+        data: &[u8],
+        password: Option<&str>,
+    ) -> Result<SecurityDataImport, atat::Error> {
+        // The module expects the data right after the prepare command
+        let guard = self.cmd_lock.lock().await;
+        self.send_locked(
+            &guard,
+            &PrepareSecurityDataImport {
+                data_type,
+                internal_name: name,
+                data_size: data.len(),
+                password,
+            },
+        )
+        .await?;
+
+        self.write_security_data(&guard, data).await
+    }
+
+    async fn write_security_data(
+        &self,
+        guard: &CommandGuard<'_>,
+        data: &[u8],
+    ) -> Result<SecurityDataImport, atat::Error> {
+        let mut chunks = data.chunks(CMD_BUF_SIZE);
+        let last = chunks.next_back().unwrap_or_default();
+        for chunk in chunks {
+            self.send_locked(guard, &SecurityDataChunk { data: chunk })
+                .await?;
         }
+
+        self.send_locked(
+            guard,
+            &SendSecurityDataImport {
+                data: atat::serde_bytes::Bytes::new(last),
+            },
+        )
+        .await
     }
 
     async fn wait_response(
@@ -84,13 +240,14 @@ impl<'a, const INGRESS_BUF_SIZE: usize> ProxyClient<'a, INGRESS_BUF_SIZE> {
             .await
             .map_err(|_| atat::Error::Timeout)
     }
-}
 
-impl<'a, const INGRESS_BUF_SIZE: usize> atat::asynch::AtatClient
-    for &ProxyClient<'a, INGRESS_BUF_SIZE>
-{
-    async fn send<Cmd: atat::AtatCmd>(&mut self, cmd: &Cmd) -> Result<Cmd::Response, atat::Error> {
-        let mut buf = [0u8; MAX_CMD_LEN];
+    /// Send `cmd` while holding the command lock.
+    async fn send_locked<Cmd: atat::AtatCmd>(
+        &self,
+        guard: &CommandGuard<'_>,
+        cmd: &Cmd,
+    ) -> Result<Cmd::Response, atat::Error> {
+        let mut buf = [0u8; CMD_BUF_SIZE];
         let len = cmd.write(&mut buf);
 
         if len < 50 {
@@ -101,12 +258,19 @@ impl<'a, const INGRESS_BUF_SIZE: usize> atat::asynch::AtatClient
         } else {
             trace!("Sending command with long payload ({} bytes)", len);
         }
+        #[cfg(feature = "trace-at")]
+        crate::trace::emit(crate::trace::TraceKind::Command, &buf[..len]);
+
+        guard.cooldown().await;
 
-        if let Some(cooldown) = self.cooldown_timer.take() {
-            cooldown.await
+        // Drop the late response to a cancelled command, rather than taking
+        // it for that of `cmd`
+        if let Some(until) = guard.take_abandoned_response() {
+            let _ = select(self.res_slot.get(), Timer::at(until)).await;
         }
 
-        // TODO: Guard against race condition!
+        self.module_error.set(None);
+
         with_timeout(
             Duration::from_secs(1),
             self.req_sender.send(Vec::try_from(&buf[..len]).unwrap()),
@@ -114,45 +278,327 @@ impl<'a, const INGRESS_BUF_SIZE: usize> atat::asynch::AtatClient
         .await
         .map_err(|_| atat::Error::Timeout)?;
 
-        self.cooldown_timer.set(Some(Timer::after_millis(20)));
+        guard.start_cooldown(Duration::from_millis(20));
 
         if !Cmd::EXPECTS_RESPONSE_CODE {
             cmd.parse(Ok(&[]))
         } else {
-            let response = self
-                .wait_response(Duration::from_millis(Cmd::MAX_TIMEOUT_MS.into()))
-                .await?;
+            let timeout = Duration::from_millis(Cmd::MAX_TIMEOUT_MS.into());
+            let until = Instant::now() + timeout;
+            let on_cancel = OnDrop::new(|| guard.abandon_response(until));
+            let response = self.wait_response(timeout).await;
+            on_cancel.defuse();
+            let response = response?;
             let response: &atat::Response<INGRESS_BUF_SIZE> = &response.borrow();
-            cmd.parse(response.into())
+            let raw: Result<&[u8], atat::InternalError> = response.into();
+
+            // Responses split across several EDM packets are parsed as one
+            #[cfg(feature = "internal-network-stack")]
+            let res = match raw
+                .as_ref()
+                .ok()
+                .and_then(|raw| join_confirmations::<INGRESS_BUF_SIZE>(raw))
+            {
+                Some(joined) => cmd.parse(Ok(&joined)),
+                None => cmd.parse(response.into()),
+            };
+            #[cfg(not(feature = "internal-network-stack"))]
+            let res = cmd.parse(response.into());
+
+            if res.is_err() {
+                self.module_error
+                    .set(raw.ok().and_then(ModuleError::from_response));
+            }
+            res
+        }
+    }
+}
+
+impl<'a, const INGRESS_BUF_SIZE: usize, const CMD_BUF_SIZE: usize> atat::asynch::AtatClient
+    for &ProxyClient<'a, INGRESS_BUF_SIZE, CMD_BUF_SIZE>
+{
+    async fn send<Cmd: atat::AtatCmd>(&mut self, cmd: &Cmd) -> Result<Cmd::Response, atat::Error> {
+        let guard = self.cmd_lock.lock().await;
+        self.send_locked(&guard, cmd).await
+    }
+}
+
+/// Snapshot of the most important Wi-Fi configuration parameters.
+///
+/// Parameters not supported by the module firmware are left as `None`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WifiSettings {
+    pub wifi_enabled: Option<WifiMode>,
+    pub power_save_mode: Option<PowerSaveMode>,
+    /// Transmit power level in dBm, 255 meaning adaptive.
+    pub power_level: Option<u8>,
+    pub good_rssi: Option<i32>,
+    pub bad_rssi: Option<i32>,
+    pub slow_scan_sleep_timeout: Option<u32>,
+    pub fast_scan_sleep_timeout: Option<u32>,
+    pub minimum_tls_version: Option<TLSVersion>,
+    pub maximum_tls_version: Option<TLSVersion>,
+}
+
+/// Data watchdog settings of the module, see
+/// [`Control::configure_watchdog`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WatchdogConfig {
+    /// Reset the module when the Wi-Fi station connection is dropped, other
+    /// than by actively closing it.
+    pub disconnect_reset: bool,
+    /// Time before a peer is disconnected if a write is not acknowledged,
+    /// 0 disabling it.
+    pub write_timeout_ms: u16,
+    /// Time without data activity before all links are disconnected, 0
+    /// disabling it.
+    pub inactivity_timeout_ms: u16,
+}
+
+/// Status of the Ethernet interface.
+///
+/// Speed and duplex mode are the configured values, which may differ from
+/// the negotiated ones when auto-negotiation is enabled.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EthernetLinkStatus {
+    pub link_up: bool,
+    pub speed_mbps: u32,
+    pub full_duplex: bool,
+    pub mac: [u8; 6],
+}
+
+impl EthernetLinkStatus {
+    /// Query the Ethernet configuration of the module.
+    pub(crate) async fn query<A: AtatClient>(
+        at_client: &mut A,
+        link_up: bool,
+    ) -> Result<Self, Error> {
+        let EthernetConfigurationResponse {
+            param_tag: EthernetConfigR::Speed(speed),
+        } = at_client
+            .send_retry(&GetEthernetConfiguration {
+                param_tag: EthernetConfigParameter::Speed,
+            })
+            .await?
+        else {
+            return Err(Error::AT(atat::Error::InvalidResponse));
+        };
+
+        let EthernetConfigurationResponse {
+            param_tag: EthernetConfigR::DuplexMode(duplex),
+        } = at_client
+            .send_retry(&GetEthernetConfiguration {
+                param_tag: EthernetConfigParameter::DuplexMode,
+            })
+            .await?
+        else {
+            return Err(Error::AT(atat::Error::InvalidResponse));
+        };
+
+        let LocalAddressResponse { mac } = at_client
+            .send_retry(&GetLocalAddress {
+                interface_id: InterfaceID::Ethernet,
+            })
+            .await?;
+
+        Ok(Self {
+            link_up,
+            speed_mbps: match speed {
+                EthernetSpeed::Mbps10 => 10,
+                EthernetSpeed::Mbps100 => 100,
+            },
+            full_duplex: duplex == EthernetDuplexMode::FullDuplex,
+            mac: mac.to_be_bytes()[2..].try_into().unwrap(),
+        })
+    }
+}
+
+/// Addresses of a module interface, see [`Control::get_local_address`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LocalAddressInfo {
+    pub mac: [u8; 6],
+    /// IPv4 address, `None` until one has been acquired.
+    pub local_ip: Option<Ipv4Addr>,
+    /// IPv6 link-local address, `None` until one has been assigned.
+    pub local_ip6: Option<Ipv6Addr>,
+}
+
+/// Status of the Wi-Fi station interface.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WifiStationStatus {
+    pub ssid: heapless::String<64>,
+    pub bssid: [u8; 6],
+    pub channel: u8,
+    pub status: WifiStatusVal,
+    /// RSSI of the current connection, `None` if not connected.
+    pub rssi: Option<i16>,
+    pub mobility_domain: Option<heapless::String<64>>,
+    pub region: Option<WifiRegion>,
+}
+
+/// Status of the access point, see [`Control::ap_status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ApStatus {
+    /// Whether the access point is enabled. The other fields are empty while
+    /// it is not.
+    pub enabled: bool,
+    pub ssid: heapless::String<64>,
+    pub bssid: [u8; 6],
+    pub channel: u8,
+}
+
+/// IPv4 configuration leased by DHCP, see [`Control::dhcp_lease`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DhcpLease {
+    pub ip: Ipv4Addr,
+    pub mask: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub dns1: Ipv4Addr,
+    pub dns2: Option<Ipv4Addr>,
+}
+
+/// Round trip statistics of a series of pings, see [`Control::ping`].
+///
+/// Round trip times are in milliseconds, and `None` if no replies were
+/// received.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PingStats {
+    pub sent: u8,
+    pub received: u8,
+    pub min_rtt: Option<u32>,
+    pub max_rtt: Option<u32>,
+    pub avg_rtt: Option<u32>,
+    pub packet_loss_percent: u8,
+    rtt_sum: u32,
+}
+
+impl PingStats {
+    fn new(sent: u8) -> Self {
+        Self {
+            sent,
+            packet_loss_percent: if sent > 0 { 100 } else { 0 },
+            ..Default::default()
+        }
+    }
+
+    /// Account for a reported round trip time, where a negative value means
+    /// the echo request timed out.
+    fn record(&mut self, rtt: i32) {
+        let Ok(rtt) = u32::try_from(rtt) else {
+            return;
+        };
+
+        self.received = self.received.saturating_add(1);
+        self.rtt_sum = self.rtt_sum.saturating_add(rtt);
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        self.max_rtt = Some(self.max_rtt.map_or(rtt, |max| max.max(rtt)));
+        self.avg_rtt = Some(self.rtt_sum / self.received as u32);
+
+        let lost = self.sent.saturating_sub(self.received) as u32;
+        self.packet_loss_percent = (lost * 100 / self.sent.max(1) as u32) as u8;
+    }
+}
+
+/// Validate a channel list against the channels supported by the module and
+/// the regulatory `region`, if known.
+fn check_channel_list(channels: &[u8], region: Option<&WifiRegion>) -> Result<Vec<u8, 10>, Error> {
+    let list = Vec::from_slice(channels).map_err(|_| Error::TooManyEntries)?;
+
+    let mut error = ChannelListError {
+        unsupported: Vec::new(),
+        restricted: Vec::new(),
+    };
+    for &channel in &list {
+        if !crate::module_caps::is_supported_channel(channel) {
+            let _ = error.unsupported.push(channel);
+        } else if region.is_some_and(|r| !crate::module_caps::is_channel_allowed(channel, r)) {
+            let _ = error.restricted.push(channel);
         }
     }
+
+    if error.unsupported.is_empty() && error.restricted.is_empty() {
+        Ok(list)
+    } else {
+        Err(Error::ChannelList(error))
+    }
 }
 
-pub struct Control<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize> {
+/// Parse a BSSID reported as 12 hex characters into bytes.
+fn parse_bssid(bssid: &[u8]) -> Option<[u8; 6]> {
+    let mut hex: [u8; 12] = bssid.try_into().ok()?;
+    crate::hex::from_hex(&mut hex).ok()?.try_into().ok()
+}
+
+/// Parse an MD5 sum reported as 32 hex characters into bytes.
+fn parse_md5(md5: &str) -> Option<[u8; 16]> {
+    let mut hex: [u8; 32] = md5.as_bytes().try_into().ok()?;
+    crate::hex::from_hex(&mut hex).ok()?.try_into().ok()
+}
+
+/// Handle for configuring and querying the module.
+///
+/// `Control` can be cloned to issue commands from several tasks. Commands
+/// are sent to the module one at a time, including those of the runner and
+/// the network stack, in the order they were issued. A command only holds
+/// the module for as long as it takes to respond, so a long-running
+/// operation like [`Control::ping`] delays commands from other handles by at
+/// most the timeout of a single command. Sequences of commands issued by one
+/// handle may be interleaved with commands from other handles.
+pub struct Control<
+    'a,
+    const INGRESS_BUF_SIZE: usize,
+    const URC_CAPACITY: usize,
+    const CMD_BUF_SIZE: usize = DEFAULT_CMD_BUF_SIZE,
+> {
     state_ch: state::Runner<'a>,
-    at_client: ProxyClient<'a, INGRESS_BUF_SIZE>,
+    at_client: ProxyClient<'a, INGRESS_BUF_SIZE, CMD_BUF_SIZE>,
     urc_channel: &'a UrcChannel<UbloxUrc, URC_CAPACITY, { URC_SUBSCRIBERS }>,
+    fw_rx: &'a Pipe<NoopRawMutex, FW_RX_SIZE>,
+}
+
+impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize, const CMD_BUF_SIZE: usize> Clone
+    for Control<'_, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>
+{
+    fn clone(&self) -> Self {
+        Self {
+            state_ch: self.state_ch.clone(),
+            at_client: self.at_client.clone(),
+            urc_channel: self.urc_channel,
+            fw_rx: self.fw_rx,
+        }
+    }
 }
 
-impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
-    Control<'a, INGRESS_BUF_SIZE, URC_CAPACITY>
+impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize, const CMD_BUF_SIZE: usize>
+    Control<'a, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>
 {
     pub(crate) fn new(
         state_ch: state::Runner<'a>,
         urc_channel: &'a UrcChannel<UbloxUrc, URC_CAPACITY, { URC_SUBSCRIBERS }>,
-        req_sender: Sender<'a, NoopRawMutex, Vec<u8, MAX_CMD_LEN>, 1>,
+        req_sender: Sender<'a, NoopRawMutex, Vec<u8, CMD_BUF_SIZE>, 1>,
         res_slot: &'a atat::ResponseSlot<INGRESS_BUF_SIZE>,
+        cmd_lock: &'a CommandLock,
+        fw_rx: &'a Pipe<NoopRawMutex, FW_RX_SIZE>,
     ) -> Self {
         Self {
             state_ch,
-            at_client: ProxyClient::new(req_sender, res_slot),
+            at_client: ProxyClient::new(req_sender, res_slot, cmd_lock),
             urc_channel,
+            fw_rx,
         }
     }
 
     /// Set the hostname of the device
     pub async fn set_hostname(&self, hostname: &str) -> Result<(), Error> {
-        self.state_ch.wait_for_initialized().await;
+        self.state_ch.wait_for_initialized().await?;
 
         (&self.at_client)
             .send_retry(&SetNetworkHostName {
@@ -164,155 +610,764 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
 
     /// Gets the firmware version of the device
     pub async fn get_version(&self) -> Result<FirmwareVersion, Error> {
-        self.state_ch.wait_for_initialized().await;
+        self.state_ch.wait_for_initialized().await?;
 
         let SoftwareVersionResponse { version } =
             (&self.at_client).send_retry(&SoftwareVersion).await?;
         Ok(version)
     }
 
-    /// Gets the MAC address of the device
-    pub async fn hardware_address(&mut self) -> Result<[u8; 6], Error> {
-        self.state_ch.wait_for_initialized().await;
+    /// Gets the firmware version read when the module was initialized,
+    /// querying the module only if it is not known.
+    pub async fn get_firmware_version(&self) -> Result<FirmwareVersion, Error> {
+        self.state_ch.wait_for_initialized().await?;
 
-        let LocalAddressResponse { mac } = (&self.at_client)
-            .send_retry(&GetLocalAddress {
-                interface_id: InterfaceID::WiFi,
-            })
+        match self.cached_version() {
+            Some(version) => Ok(version),
+            None => self.get_version().await,
+        }
+    }
+
+    /// Firmware version read when the module was initialized, `None` until
+    /// it is initialized.
+    pub fn cached_version(&self) -> Option<FirmwareVersion> {
+        match self.state_ch.init_status(None) {
+            InitStatus::Initialized(info) => Some(info.sw_version),
+            _ => None,
+        }
+    }
+
+    /// Fail with [`Error::UnsupportedFirmware`] if the module runs firmware
+    /// older than `min`. Passes if the version is not known.
+    #[cfg(feature = "provisional-at")]
+    fn require_firmware(&self, min: FirmwareVersion) -> Result<(), Error> {
+        match self.cached_version() {
+            Some(version) if version < min => Err(Error::UnsupportedFirmware),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail with [`Error::UnsupportedFirmware`] if joining or hosting a
+    /// network with `auth` requires newer firmware, i.e. for WPA3.
+    ///
+    /// The 8.0.0 minimum for WPA3 is provisional, it is not confirmed by the
+    /// AT commands manual or release notes yet.
+    #[cfg(feature = "provisional-at")]
+    fn require_auth_firmware(&self, auth: &WifiAuthentication<'_>) -> Result<(), Error> {
+        match auth {
+            WifiAuthentication::Wpa2Wpa3Personal(_) | WifiAuthentication::Wpa3Personal(_) => {
+                self.require_firmware(FirmwareVersion::new(8, 0, 0))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Restart the module into its boot loader to update the u-connect
+    /// software over the serial port.
+    ///
+    /// The module leaves EDM/PPP mode when restarting into the boot loader,
+    /// and the runner hands the transport over to the returned
+    /// [`FirmwareUpdater`] until it is finalized or dropped. Dropping it
+    /// before [`FirmwareUpdater::finalize`] aborts the update, after which the
+    /// runner resets the module back to the previous image.
+    ///
+    /// Commands from other handles wait until the updater is finalized or
+    /// dropped.
+    pub async fn start_firmware_update(
+        &self,
+        baud: SoftwareUpdateBaudRate,
+    ) -> Result<FirmwareUpdater<'_, 'a, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        // Held by the updater, so no other command interleaves with the transfer
+        let guard = self.at_client.cmd_lock.lock().await;
+        self.at_client
+            .send_locked(
+                &guard,
+                &SoftwareUpdate {
+                    mode: SoftwareUpdateMode::SoftwareUpdate,
+                    baud,
+                },
+            )
             .await?;
 
-        Ok(mac.to_be_bytes()[2..].try_into().unwrap())
+        self.state_ch.set_firmware_update(Some(baud as u32));
+
+        let mut updater = FirmwareUpdater::new(
+            self,
+            self.state_ch.clone(),
+            self.at_client.req_sender.clone(),
+            self.fw_rx,
+            guard,
+        );
+        updater.wait_for_receiver().await?;
+
+        Ok(updater)
     }
 
-    pub async fn get_wifi_status(&self) -> Result<WifiStatusVal, Error> {
-        match (&self.at_client)
-            .send_retry(&GetWifiStatus {
-                status_id: StatusId::Status,
-            })
-            .await?
-            .status_id
-        {
-            WifiStatus::Status(s) => Ok(s),
-            _ => Err(Error::AT(atat::Error::InvalidResponse)),
+    /// Gets the module family of the device
+    pub async fn get_module_family(&self) -> Result<ModuleFamily, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let ModelIdentificationResponse { model } =
+            (&self.at_client).send_retry(&ModelIdentification).await?;
+        Ok(ModuleFamily::from_model(&model))
+    }
+
+    /// Identification of the module, read by the runner while initializing
+    /// it.
+    pub async fn module_info(&self) -> Result<ModuleInfo, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        self.state_ch.module_info().ok_or(Error::Uninitialized)
+    }
+
+    /// Set the greeting text shown by the module when booting into command
+    /// mode, or disable it with `None`.
+    ///
+    /// The text is at most 48 characters, and takes effect from the next
+    /// boot.
+    pub async fn set_greeting_text(&self, text: Option<&str>) -> Result<(), Error> {
+        if text.is_some_and(|text| text.len() > MAX_GREETING_TEXT_LEN) {
+            return Err(Error::BadLength);
         }
+
+        self.state_ch.wait_for_initialized().await?;
+
+        let mode = match text {
+            Some(text) => GreetingTextMode::On(Some(text)),
+            None => GreetingTextMode::Off,
+        };
+        self.send(&SetGreetingText { mode }).await?;
+        Ok(())
     }
 
-    pub async fn get_wifi_channel(&self) -> Result<u8, Error> {
-        match (&self.at_client)
-            .send_retry(&GetWifiStatus {
-                status_id: StatusId::Channel,
-            })
-            .await?
-            .status_id
-        {
-            WifiStatus::Channel(c) => Ok(c),
-            _ => Err(Error::AT(atat::Error::InvalidResponse)),
+    /// Set the Wi-Fi transmit power level in dBm.
+    ///
+    /// Valid values are 0-20, or 255 to enable adaptive transmit power level
+    /// control. With the `provisional-at` feature, the values are further
+    /// limited by `ModuleFamily::max_power_level` of the detected module.
+    pub async fn set_power_level(&self, dbm: u8) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        if dbm != 255 {
+            if dbm > 20 {
+                return Err(PowerLevelError::OutOfRange.into());
+            }
+
+            #[cfg(feature = "provisional-at")]
+            {
+                let max = self.get_module_family().await?.max_power_level();
+                if dbm > max {
+                    return Err(PowerLevelError::ExceedsMaximum { max }.into());
+                }
+            }
         }
+
+        (&self.at_client)
+            .send_retry(&SetWifiConfig {
+                config_param: WifiConfig::PowerLevel(dbm),
+            })
+            .await?;
+        Ok(())
     }
 
-    pub async fn get_signal_strength(&self) -> Result<i8, Error> {
-        match (&self.at_client)
-            .send_retry(&GetWifiStatus {
-                status_id: StatusId::Rssi,
+    /// Set the minimum Protected Management Frames (PMF) policy of all
+    /// station connections.
+    ///
+    /// With [`PMF::Required`], the module no longer connects to access points
+    /// that do not advertise PMF capability, whatever the configuration of the
+    /// network.
+    ///
+    /// Provisional, see the `provisional-at` feature.
+    #[cfg(feature = "provisional-at")]
+    pub async fn set_minimum_pmf(&self, pmf: PMF) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        (&self.at_client)
+            .send_retry(&SetWifiConfig {
+                config_param: WifiConfig::PMFMode(pmf),
             })
-            .await?
-            .status_id
-        {
-            WifiStatus::Rssi(-32768) => Err(Error::NotConnected),
-            WifiStatus::Rssi(s) => s
-                .try_into()
-                .map_err(|_| Error::AT(atat::Error::InvalidResponse)),
-            _ => Err(Error::AT(atat::Error::InvalidResponse)),
+            .await?;
+        Ok(())
+    }
+
+    /// Set the channels used in station mode, or restore the factory default
+    /// channel list if `channels` is empty.
+    ///
+    /// Channels are validated against the channels supported by the module
+    /// and, if reported by the firmware, the restrictions of the current
+    /// regulatory region.
+    pub async fn set_channel_list(&self, channels: &[u8]) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        if channels.is_empty() {
+            (&self.at_client).send_retry(&ResetChannelList).await?;
+            return Ok(());
         }
+
+        let region = match self.wifi_status_param(StatusId::Region).await {
+            Ok(r) => Some(WifiRegion::try_from(r)?),
+            Err(Error::AT(atat::Error::Error)) => None,
+            Err(e) => return Err(e),
+        };
+
+        let channels = check_channel_list(channels, region.as_ref())?;
+        (&self.at_client)
+            .send_retry(&SetChannelList { channels })
+            .await?;
+        Ok(())
     }
 
-    pub async fn wait_for_link_state(&self, link_state: LinkState) {
-        self.state_ch.wait_for_link_state(link_state).await
+    /// Get the channel list of station mode.
+    pub async fn channel_list(&self) -> Result<Vec<u8, 64>, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let ChannelListResponse { channels } =
+            (&self.at_client).send_retry(&GetChannelList).await?;
+        Ok(channels)
     }
-    pub fn is_connected(&self) -> bool {
-        self.state_ch.link_state(None) == LinkState::Up
+
+    /// Read a single Wi-Fi configuration parameter
+    pub async fn wifi_config(&self, param: WifiConfigParameter) -> Result<WifiConfig, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let WifiConfigResponse { config_param } = (&self.at_client)
+            .send_retry(&GetWifiConfig {
+                config_param: param,
+            })
+            .await?;
+        Ok(config_param)
     }
 
-    pub async fn config_v4(&self) -> Result<Option<StaticConfigV4>, Error> {
-        let NetworkStatusResponse {
-            status: NetworkStatus::IPv4Address(ipv4),
-            ..
-        } = (&self.at_client)
-            .send_retry(&GetNetworkStatus {
-                interface_id: 0,
-                status: NetworkStatusParameter::IPv4Address,
+    /// Read the most important Wi-Fi configuration parameters, skipping any
+    /// that are not supported by the module firmware.
+    pub async fn dump_settings(&self) -> Result<WifiSettings, Error> {
+        let mut settings = WifiSettings::default();
+
+        for param in [
+            WifiConfigParameter::WifiEnabled,
+            WifiConfigParameter::PowerSaveMode,
+            WifiConfigParameter::PowerLevel,
+            WifiConfigParameter::GoodRSSIValue,
+            WifiConfigParameter::BadRSSIValue,
+            WifiConfigParameter::SlowScanSleepTimeout,
+            WifiConfigParameter::FastScanSleepTimeout,
+            WifiConfigParameter::MinimumTlsVersion,
+            WifiConfigParameter::MaximumTlsVersion,
+        ] {
+            let config = match self.wifi_config(param).await {
+                Ok(config) => config,
+                // Older firmware responds with `ERROR` on unknown parameters
+                Err(Error::AT(atat::Error::Error)) => continue,
+                Err(e) => return Err(e),
+            };
+
+            match config {
+                WifiConfig::WifiEnabled(v) => settings.wifi_enabled = Some(v),
+                WifiConfig::PowerSaveMode(v) => settings.power_save_mode = Some(v),
+                WifiConfig::PowerLevel(v) => settings.power_level = Some(v),
+                WifiConfig::GoodRSSIValue(v) => settings.good_rssi = Some(v),
+                WifiConfig::BadRSSIValue(v) => settings.bad_rssi = Some(v),
+                WifiConfig::SlowScanSleepTimeout(v) => settings.slow_scan_sleep_timeout = Some(v),
+                WifiConfig::FastScanSleepTimeout(v) => settings.fast_scan_sleep_timeout = Some(v),
+                WifiConfig::MinimumTlsVersion(v) => settings.minimum_tls_version = Some(v),
+                WifiConfig::MaximumTlsVersion(v) => settings.maximum_tls_version = Some(v),
+                _ => {}
+            }
+        }
+
+        Ok(settings)
+    }
+
+    /// Gets the MAC address of the device
+    pub async fn hardware_address(&mut self) -> Result<[u8; 6], Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let LocalAddressResponse { mac } = (&self.at_client)
+            .send_retry(&GetLocalAddress {
+                interface_id: InterfaceID::WiFi,
             })
-            .await?
-        else {
-            return Err(Error::Network);
-        };
+            .await?;
 
-        let ipv4_addr = core::str::from_utf8(ipv4.as_slice())
-            .ok()
-            .and_then(|s| Ipv4Addr::from_str(s).ok())
-            .and_then(|ip| (!ip.is_unspecified()).then_some(ip));
+        Ok(mac.to_be_bytes()[2..].try_into().unwrap())
+    }
 
-        let NetworkStatusResponse {
-            status: NetworkStatus::SubnetMask(subnet),
-            ..
-        } = (&self.at_client)
-            .send_retry(&GetNetworkStatus {
-                interface_id: 0,
-                status: NetworkStatusParameter::SubnetMask,
+    /// Get the MAC address of `interface`, along with its IP addresses.
+    ///
+    /// `+UMLA` only reports the MAC address, so the IP addresses are read
+    /// from the network status of the interface. They are only reported for
+    /// the Wi-Fi station and Ethernet interfaces, once the module has brought
+    /// up their network interface.
+    pub async fn get_local_address(
+        &self,
+        interface: InterfaceID,
+    ) -> Result<LocalAddressInfo, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let LocalAddressResponse { mac } = (&self.at_client)
+            .send_retry(&GetLocalAddress {
+                interface_id: interface.clone(),
             })
-            .await?
-        else {
-            return Err(Error::Network);
+            .await?;
+        let mut info = LocalAddressInfo {
+            mac: mac.to_be_bytes()[2..].try_into().unwrap(),
+            local_ip: None,
+            local_ip6: None,
         };
 
-        let subnet_mask = core::str::from_utf8(subnet.as_slice())
-            .ok()
-            .and_then(|s| Ipv4Addr::from_str(s).ok())
-            .and_then(|ip| (!ip.is_unspecified()).then_some(ip));
+        let Some(interface_id) = self.state_ch.network_interface_id(&interface) else {
+            return Ok(info);
+        };
 
         let NetworkStatusResponse {
-            status: NetworkStatus::Gateway(gateway),
+            status: NetworkStatus::IPv4Address(ipv4),
             ..
         } = (&self.at_client)
             .send_retry(&GetNetworkStatus {
-                interface_id: 0,
-                status: NetworkStatusParameter::Gateway,
+                interface_id,
+                status: NetworkStatusParameter::IPv4Address,
             })
             .await?
         else {
             return Err(Error::Network);
         };
 
-        let gateway_addr = core::str::from_utf8(gateway.as_slice())
-            .ok()
-            .and_then(|s| Ipv4Addr::from_str(s).ok())
-            .and_then(|ip| (!ip.is_unspecified()).then_some(ip));
-
         let NetworkStatusResponse {
-            status: NetworkStatus::PrimaryDNS(primary),
+            status: NetworkStatus::IPv6LinkLocalAddress(link_local),
             ..
         } = (&self.at_client)
             .send_retry(&GetNetworkStatus {
-                interface_id: 0,
-                status: NetworkStatusParameter::PrimaryDNS,
+                interface_id,
+                status: NetworkStatusParameter::IPv6LinkLocalAddress,
             })
             .await?
         else {
             return Err(Error::Network);
         };
 
-        let primary = core::str::from_utf8(primary.as_slice())
+        info.local_ip = core::str::from_utf8(ipv4.as_slice())
             .ok()
             .and_then(|s| Ipv4Addr::from_str(s).ok())
-            .and_then(|ip| (!ip.is_unspecified()).then_some(ip));
+            .filter(|ip| !ip.is_unspecified());
+        // The address may carry a zone index, e.g. `fe80::1%0`
+        info.local_ip6 = core::str::from_utf8(link_local.as_slice())
+            .ok()
+            .and_then(|s| s.split('%').next())
+            .and_then(|s| Ipv6Addr::from_str(s).ok())
+            .filter(|ip| !ip.is_unspecified());
 
-        let NetworkStatusResponse {
-            status: NetworkStatus::SecondaryDNS(secondary),
-            ..
-        } = (&self.at_client)
-            .send_retry(&GetNetworkStatus {
-                interface_id: 0,
-                status: NetworkStatusParameter::SecondaryDNS,
+        Ok(info)
+    }
+
+    /// Get the status of the Ethernet interface.
+    ///
+    /// Once the module has reported an Ethernet link, the status is returned
+    /// without querying the module.
+    pub async fn ethernet_link_status(&self) -> Result<EthernetLinkStatus, Error> {
+        if let Some(status) = self.state_ch.ethernet_link_status() {
+            return Ok(status);
+        }
+
+        self.state_ch.wait_for_initialized().await?;
+
+        EthernetLinkStatus::query(&mut &self.at_client, false).await
+    }
+
+    /// Configure the Ethernet interface with the given parameters.
+    ///
+    /// The interface must be down while it is being configured, see
+    /// [`Self::ethernet_down`].
+    pub async fn configure_ethernet(&self, config: &[EthernetConfig]) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        for param_tag in config {
+            (&self.at_client)
+                .send_retry(&SetEthernetConfiguration {
+                    param_tag: param_tag.clone(),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Activate the Ethernet interface and select it as the uplink.
+    ///
+    /// From then on the link state follows the Ethernet link and its IP
+    /// configuration instead of the WiFi connection.
+    pub async fn ethernet_up(&self) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        (&self.at_client)
+            .send_retry(&EthernetConfigurationAction {
+                action: EthernetConfigAction::Activate,
+            })
+            .await?;
+
+        self.state_ch.set_uplink(Uplink::Ethernet);
+        Ok(())
+    }
+
+    /// Deactivate the Ethernet interface, and select WiFi as the uplink
+    /// again.
+    pub async fn ethernet_down(&self) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        (&self.at_client)
+            .send_retry(&EthernetConfigurationAction {
+                action: EthernetConfigAction::Deactivate,
+            })
+            .await?;
+
+        self.state_ch.set_uplink(Uplink::WiFi);
+        self.state_ch.update_ethernet_with(|eth| eth.reset_config());
+        Ok(())
+    }
+
+    async fn wifi_status_param(&self, status_id: StatusId) -> Result<WifiStatus, Error> {
+        Ok((&self.at_client)
+            .send_retry(&GetWifiStatus { status_id })
+            .await?
+            .status_id)
+    }
+
+    pub async fn get_wifi_status(&self) -> Result<WifiStatusVal, Error> {
+        Ok(self.wifi_status_param(StatusId::Status).await?.try_into()?)
+    }
+
+    pub async fn get_wifi_channel(&self) -> Result<u8, Error> {
+        Ok(self
+            .wifi_status_param(StatusId::Channel)
+            .await?
+            .try_into()?)
+    }
+
+    pub async fn get_signal_strength(&self) -> Result<i8, Error> {
+        self.rssi()
+            .await?
+            .ok_or(Error::NotConnected)?
+            .try_into()
+            .map_err(|_| Error::AT(atat::Error::InvalidResponse))
+    }
+
+    /// Read the RSSI of the current station connection in dBm, or `None` if
+    /// not connected.
+    pub async fn rssi(&self) -> Result<Option<i16>, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        Ok(self.wifi_status_param(StatusId::Rssi).await?.into_rssi()?)
+    }
+
+    /// RSSI in dBm measured when the station link last came up, without
+    /// querying the module. `None` while the link is down.
+    pub fn link_rssi(&self) -> Option<i16> {
+        self.state_ch.link_rssi()
+    }
+
+    /// Read the full status of the Wi-Fi station.
+    ///
+    /// Mobility domain and region are `None` on firmware not supporting them
+    /// (ODIN-W2 before 6.0.0).
+    pub async fn wifi_status(&self) -> Result<WifiStationStatus, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let ssid = self
+            .wifi_status_param(StatusId::SSID)
+            .await?
+            .into_ssid()
+            .ok_or(Error::AT(atat::Error::InvalidResponse))?;
+
+        let bssid = self
+            .wifi_status_param(StatusId::BSSID)
+            .await?
+            .into_bssid()
+            .and_then(|b| parse_bssid(&b))
+            .ok_or(Error::AT(atat::Error::InvalidResponse))?;
+
+        let channel = self
+            .wifi_status_param(StatusId::Channel)
+            .await?
+            .try_into()?;
+        let status = self.wifi_status_param(StatusId::Status).await?.try_into()?;
+        let rssi = self.wifi_status_param(StatusId::Rssi).await?.into_rssi()?;
+
+        let mobility_domain = match self.wifi_status_param(StatusId::MobilityDomain).await {
+            Ok(m) => m.into_mobility_domain(),
+            Err(Error::AT(atat::Error::Error)) => None,
+            Err(e) => return Err(e),
+        };
+
+        let region = match self.wifi_status_param(StatusId::Region).await {
+            Ok(r) => Some(WifiRegion::try_from(r)?),
+            Err(Error::AT(atat::Error::Error)) => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok(WifiStationStatus {
+            ssid,
+            bssid,
+            channel,
+            status,
+            rssi,
+            mobility_domain,
+            region,
+        })
+    }
+
+    /// Outcome of initializing the module, including the firmware version and
+    /// model once initialized.
+    pub fn init_status(&self) -> InitStatus {
+        self.state_ch.init_status(None)
+    }
+
+    /// Wait for the outcome of initializing the module to change, e.g. for
+    /// [`InitStatus::Pending`] to become [`InitStatus::Initialized`], or for
+    /// another failed attempt.
+    pub async fn wait_for_init_status_change(&self) -> InitStatus {
+        self.state_ch.wait_for_init_status_change().await
+    }
+
+    /// Make the runner retry initializing the module after it has given up,
+    /// i.e. once the status is [`InitStatus::Failed`].
+    pub fn retry_init(&self) {
+        self.state_ch.request_reset();
+    }
+
+    /// Number of times the runner reset the module after it stopped
+    /// responding, see [`WifiConfig::SUPERVISION_TIMEOUT`](crate::WifiConfig::SUPERVISION_TIMEOUT).
+    pub fn watchdog_recoveries(&self) -> u32 {
+        self.state_ch.watchdog_recoveries()
+    }
+
+    /// Health of the transport carrying AT commands to the module.
+    pub fn at_transport_status(&self) -> AtTransportStatus {
+        self.state_ch.at_transport(None)
+    }
+
+    /// Wait for the health of the transport carrying AT commands to change,
+    /// e.g. for the UDP side channel of a PPP link to be reopened.
+    pub async fn wait_for_at_transport_change(&self) -> AtTransportStatus {
+        self.state_ch.wait_for_at_transport_change().await
+    }
+
+    /// Number of times the UDP socket tunneling AT commands over a PPP link
+    /// was reopened after it was closed.
+    pub fn at_transport_reconnects(&self) -> u32 {
+        self.state_ch.at_reconnects()
+    }
+
+    /// Configure the data watchdog of the module.
+    ///
+    /// The watchdog is only active in (extended) data mode, and the settings
+    /// are persisted with the module configuration.
+    pub async fn configure_watchdog(&self, config: WatchdogConfig) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        for setting_type in [
+            WatchdogSetting::SPP(config.write_timeout_ms),
+            WatchdogSetting::InactivityTimeout(config.inactivity_timeout_ms),
+            WatchdogSetting::WiFiDisconnectReset(config.disconnect_reset.into()),
+        ] {
+            self.send(&SetWatchdogSettings { setting_type }).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the link to reach `link_state`, requiring an IPv4 address for
+    /// [`LinkState::Up`].
+    #[deprecated(note = "use `wait_for_link_state_or_error`, which fails on network errors")]
+    pub async fn wait_for_link_state(&self, link_state: LinkState) {
+        self.state_ch.wait_for_link_state(link_state).await
+    }
+
+    /// Wait for the link to reach `link_state`, as seen from the given IP
+    /// address `family`.
+    #[deprecated(note = "use `wait_for_link_state_or_error_for`, which fails on network errors")]
+    pub async fn wait_for_link_state_for(&self, link_state: LinkState, family: IpFamily) {
+        self.state_ch
+            .wait_for_link_state_for(link_state, family)
+            .await
+    }
+
+    /// Wait for the link to reach `link_state`, requiring an IPv4 address for
+    /// [`LinkState::Up`].
+    ///
+    /// Waiting for [`LinkState::Up`] fails with [`Error::NetworkError`] if the
+    /// module reports a network error in the meantime.
+    pub async fn wait_for_link_state_or_error(&self, link_state: LinkState) -> Result<(), Error> {
+        self.wait_for_link_state_or_error_for(link_state, IpFamily::default())
+            .await
+    }
+
+    /// Wait for the link to reach `link_state`, as seen from the given IP
+    /// address `family`, failing like
+    /// [`wait_for_link_state_or_error`](Self::wait_for_link_state_or_error).
+    pub async fn wait_for_link_state_or_error_for(
+        &self,
+        link_state: LinkState,
+        family: IpFamily,
+    ) -> Result<(), Error> {
+        let network_errors = self.state_ch.network_errors(None);
+        let wait = self.state_ch.wait_for_link_state_for(link_state, family);
+        if link_state != LinkState::Up {
+            wait.await;
+            return Ok(());
+        }
+
+        match select(wait, self.state_ch.wait_for_network_error(network_errors)).await {
+            Either::First(()) => Ok(()),
+            Either::Second(reason) => Err(Error::NetworkError(reason)),
+        }
+    }
+
+    /// Last network error reported by the module, e.g. an IP address
+    /// conflict.
+    pub fn last_network_error(&self) -> Option<NetworkErrorReason> {
+        self.state_ch.network_error()
+    }
+    pub fn is_connected(&self) -> bool {
+        self.state_ch.link_state(None) == LinkState::Up
+    }
+
+    /// Wait for a global IPv6 address to be assigned, e.g. through SLAAC, and
+    /// return it.
+    #[cfg(feature = "ipv6")]
+    pub async fn wait_for_ipv6_address(&self) -> Result<core::net::Ipv6Addr, Error> {
+        self.wait_for_link_state_or_error_for(LinkState::Up, IpFamily::V6)
+            .await?;
+
+        let NetworkStatusResponse {
+            status: NetworkStatus::IPv6Address1(ipv6),
+            ..
+        } = (&self.at_client)
+            .send_retry(&GetNetworkStatus {
+                interface_id: self.state_ch.uplink_interface_id(),
+                status: NetworkStatusParameter::IPv6Address1,
+            })
+            .await?
+        else {
+            return Err(Error::Network);
+        };
+
+        core::str::from_utf8(ipv6.as_slice())
+            .ok()
+            .and_then(|s| core::net::Ipv6Addr::from_str(s).ok())
+            .filter(|ip| !ip.is_unspecified())
+            .ok_or(Error::Network)
+    }
+
+    /// IPv6 configuration of the module, `None` until a link-local address
+    /// has been assigned.
+    pub async fn config_v6(&self) -> Result<Option<StaticConfigV6>, Error> {
+        let NetworkStatusResponse {
+            status: NetworkStatus::IPv6LinkLocalAddress(link_local),
+            ..
+        } = (&self.at_client)
+            .send_retry(&GetNetworkStatus {
+                interface_id: self.state_ch.uplink_interface_id(),
+                status: NetworkStatusParameter::IPv6LinkLocalAddress,
+            })
+            .await?
+        else {
+            return Err(Error::Network);
+        };
+
+        // The address may carry a zone index, e.g. `fe80::1%0`
+        Ok(core::str::from_utf8(link_local.as_slice())
+            .ok()
+            .and_then(|s| s.split('%').next())
+            .and_then(|s| core::net::Ipv6Addr::from_str(s).ok())
+            .filter(|ip| !ip.is_unspecified())
+            .map(|link_local| StaticConfigV6 { link_local }))
+    }
+
+    pub async fn config_v4(&self) -> Result<Option<StaticConfigV4>, Error> {
+        let interface_id = self.state_ch.uplink_interface_id();
+
+        let NetworkStatusResponse {
+            status: NetworkStatus::IPv4Address(ipv4),
+            ..
+        } = (&self.at_client)
+            .send_retry(&GetNetworkStatus {
+                interface_id,
+                status: NetworkStatusParameter::IPv4Address,
+            })
+            .await?
+        else {
+            return Err(Error::Network);
+        };
+
+        // DHCPv4 may still be pending even though the link is up on IPv6
+        let Some(address) = core::str::from_utf8(ipv4.as_slice())
+            .ok()
+            .and_then(|s| Ipv4Addr::from_str(s).ok())
+            .and_then(|ip| (!ip.is_unspecified()).then_some(ip))
+        else {
+            return Ok(None);
+        };
+
+        let NetworkStatusResponse {
+            status: NetworkStatus::SubnetMask(subnet),
+            ..
+        } = (&self.at_client)
+            .send_retry(&GetNetworkStatus {
+                interface_id,
+                status: NetworkStatusParameter::SubnetMask,
+            })
+            .await?
+        else {
+            return Err(Error::Network);
+        };
+
+        let subnet_mask = core::str::from_utf8(subnet.as_slice())
+            .ok()
+            .and_then(|s| Ipv4Addr::from_str(s).ok())
+            .and_then(|ip| (!ip.is_unspecified()).then_some(ip));
+
+        let NetworkStatusResponse {
+            status: NetworkStatus::Gateway(gateway),
+            ..
+        } = (&self.at_client)
+            .send_retry(&GetNetworkStatus {
+                interface_id,
+                status: NetworkStatusParameter::Gateway,
+            })
+            .await?
+        else {
+            return Err(Error::Network);
+        };
+
+        let gateway_addr = core::str::from_utf8(gateway.as_slice())
+            .ok()
+            .and_then(|s| Ipv4Addr::from_str(s).ok())
+            .and_then(|ip| (!ip.is_unspecified()).then_some(ip));
+
+        let NetworkStatusResponse {
+            status: NetworkStatus::PrimaryDNS(primary),
+            ..
+        } = (&self.at_client)
+            .send_retry(&GetNetworkStatus {
+                interface_id,
+                status: NetworkStatusParameter::PrimaryDNS,
+            })
+            .await?
+        else {
+            return Err(Error::Network);
+        };
+
+        let primary = core::str::from_utf8(primary.as_slice())
+            .ok()
+            .and_then(|s| Ipv4Addr::from_str(s).ok())
+            .and_then(|ip| (!ip.is_unspecified()).then_some(ip));
+
+        let NetworkStatusResponse {
+            status: NetworkStatus::SecondaryDNS(secondary),
+            ..
+        } = (&self.at_client)
+            .send_retry(&GetNetworkStatus {
+                interface_id,
+                status: NetworkStatusParameter::SecondaryDNS,
             })
             .await?
         else {
@@ -324,7 +1379,7 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
             .and_then(|s| Ipv4Addr::from_str(s).ok())
             .and_then(|ip| (!ip.is_unspecified()).then_some(ip));
 
-        Ok(ipv4_addr.map(|address| StaticConfigV4 {
+        Ok(Some(StaticConfigV4 {
             address,
             subnet_mask,
             gateway: gateway_addr,
@@ -332,45 +1387,207 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
         }))
     }
 
+    /// Read the IPv4 configuration leased by DHCP on the uplink.
+    ///
+    /// Fails with [`Error::NotConnected`] unless the link is
+    /// [`LinkState::Up`], and with [`Error::Network`] if the lease lacks a
+    /// subnet mask, gateway or DNS server.
+    pub async fn dhcp_lease(&self) -> Result<DhcpLease, Error> {
+        if self.state_ch.link_state(None) != LinkState::Up {
+            return Err(Error::NotConnected);
+        }
+
+        let config = self.config_v4().await?.ok_or(Error::NotConnected)?;
+
+        Ok(DhcpLease {
+            ip: config.address,
+            mask: config.subnet_mask.ok_or(Error::Network)?,
+            gateway: config.gateway.ok_or(Error::Network)?,
+            dns1: config.dns_servers.primary.ok_or(Error::Network)?,
+            dns2: config.dns_servers.secondary,
+        })
+    }
+
     pub async fn get_connected_ssid(&self) -> Result<heapless::String<64>, Error> {
-        match (&self.at_client)
-            .send_retry(&GetWifiStatus {
-                status_id: StatusId::SSID,
-            })
+        self.wifi_status_param(StatusId::SSID)
             .await?
-            .status_id
-        {
-            WifiStatus::SSID(s) => Ok(s),
-            _ => Err(Error::AT(atat::Error::InvalidResponse)),
+            .into_ssid()
+            .ok_or(Error::AT(atat::Error::InvalidResponse))
+    }
+
+    /// Fail with [`Error::SocketsOpen`] if any sockets hold a peer on the
+    /// module, unless `force` is set.
+    fn ensure_sockets_closed(&self, force: bool) -> Result<(), Error> {
+        match self.state_ch.open_sockets() {
+            n if n > 0 && !force => Err(Error::SocketsOpen(n)),
+            _ => Ok(()),
         }
     }
 
-    pub async fn factory_reset(&self) -> Result<(), Error> {
-        self.state_ch.wait_for_initialized().await;
+    /// Restore the module to its factory defaults, clearing all stored
+    /// profiles, and re-initialize it.
+    ///
+    /// The link goes down and any sockets are invalidated, so the module can
+    /// be re-provisioned as soon as this returns. Fails with
+    /// [`Error::SocketsOpen`] if sockets are still open on the module, unless
+    /// `force` is set. Sockets are only tracked by the internal network stack;
+    /// with `ppp` they are owned by `embassy-net` and simply lose their link.
+    pub async fn factory_reset(&self, force: bool) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+        self.ensure_sockets_closed(force)?;
 
         (&self.at_client)
             .send_retry(&ResetToFactoryDefaults)
             .await?;
-        (&self.at_client).send_retry(&RebootDCE).await?;
+
+        // The runner reboots the module, which applies the factory defaults.
+        self.state_ch.request_reset();
+        self.state_ch.wait_for_initialized().await?;
+
+        Ok(())
+    }
+
+    /// Clear the stored station profile `config_id`, resetting all of its
+    /// parameters to their factory defaults.
+    ///
+    /// Fails with [`Error::SocketsOpen`] if sockets are still open on the
+    /// module, unless `force` is set.
+    pub async fn clear_profile(&self, config_id: u8, force: bool) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+        self.ensure_sockets_closed(force)?;
+
+        (&self.at_client)
+            .send_retry(&ExecWifiStationAction {
+                config_id,
+                action: WifiStationAction::Reset,
+            })
+            .await?;
+        (&self.at_client)
+            .send_retry(&ExecWifiStationAction {
+                config_id,
+                action: WifiStationAction::Store,
+            })
+            .await?;
+        self.state_ch.record_nvm_store();
 
         Ok(())
     }
+
+    /// Reboot the module, and wait for it to be initialized again.
+    ///
+    /// The runner reboots the module the same way it does at start up, i.e.
+    /// with `RebootDCE` unless a reset pin is configured, waits for the
+    /// `+STARTUP` URC and re-enters extended data mode.
+    ///
+    /// This first waits for the module to be initialized. Once the reboot is
+    /// requested, dropping this future does not cancel it, and the module is
+    /// uninitialized until the runner is done. Fails with
+    /// [`Error::Timeout`] if the module is not initialized again within 5
+    /// seconds of the request.
     pub async fn reboot(&self) -> Result<(), Error> {
-        self.state_ch.wait_for_initialized().await;
+        self.state_ch.wait_for_initialized().await?;
 
-        // Setting wifi state to inactive will trigger network runner to reboot device.
-        self.state_ch
-            .update_connection_with(|con| con.wifi_state = WiFiState::Inactive);
+        self.state_ch.request_reset();
+        with_timeout(Duration::from_secs(5), self.state_ch.wait_for_initialized())
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
 
+    /// Store the current configuration of the module to its non-volatile
+    /// memory, so it is restored on the next boot.
+    ///
+    /// Settings are otherwise only stored when RS232 settings change, as they
+    /// have to be stored before the reboot applying them: by the runner while
+    /// initializing, unless
+    /// [`WifiConfig::SKIP_NVM_STORE`](crate::WifiConfig::SKIP_NVM_STORE) is
+    /// set, and by [`Self::change_baud_rate`]. NVM has limited write
+    /// endurance, so call this once settings have actually changed, rather
+    /// than on every boot. See [`Self::nvm_stores`].
+    pub async fn persist(&self) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        (&self.at_client).send_retry(&StoreCurrentConfig).await?;
+        self.state_ch.record_nvm_store();
         Ok(())
     }
 
+    /// Number of times the module configuration was stored to NVM since the
+    /// runner was created, whether by [`Self::persist`], by
+    /// [`Self::clear_profile`] or implicitly when RS232 settings changed.
+    pub fn nvm_stores(&self) -> u32 {
+        self.state_ch.nvm_stores()
+    }
+
+    /// Change the baud rate of the serial link to the module at runtime.
+    ///
+    /// The new rate is stored to the module profile, keeping the other RS232
+    /// settings, and the module is rebooted into it, as settings changed in
+    /// extended data mode only apply after a reboot. The runner then switches
+    /// the host UART through [`Transport::set_baudrate`], and verifies the
+    /// link with an AT command before re-initializing the module.
+    ///
+    /// RS232 settings have to be stored before the reboot, which also stores
+    /// any other changed settings, as [`Self::persist`] does. The settings,
+    /// the store and the reboot are sent under one hold of the command lock,
+    /// so no command of another handle lands in between. Nothing is stored
+    /// or rebooted if the module already uses `baud_rate`.
+    ///
+    /// If the module does not answer at the new rate, the runner
+    /// re-synchronizes at the previous one, restoring the previous settings,
+    /// and the error is returned once the module is initialized again.
+    ///
+    /// [`Transport::set_baudrate`]: crate::Transport::set_baudrate
+    pub async fn change_baud_rate(&self, baud_rate: BaudRate) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let RS232SettingsResponse {
+            baud_rate: current,
+            flow_control,
+            data_bits,
+            stop_bits,
+            parity,
+            ..
+        } = (&self.at_client).send_retry(&GetRS232Settings).await?;
+        if current == baud_rate as u32 {
+            return Ok(());
+        }
+
+        let guard = self.at_client.cmd_lock.lock().await;
+        self.at_client
+            .send_locked(
+                &guard,
+                &SetRS232Settings {
+                    baud_rate,
+                    flow_control,
+                    data_bits,
+                    stop_bits,
+                    parity,
+                    change_after_confirm: ChangeAfterConfirm::StoreAndReset,
+                },
+            )
+            .await?;
+        self.at_client
+            .send_locked(&guard, &StoreCurrentConfig)
+            .await?;
+        self.state_ch.record_nvm_store();
+        self.at_client.send_locked(&guard, &RebootDCE).await?;
+
+        self.state_ch.request_baud_rate_change(baud_rate);
+        // The runner verifies the new rate through the command lock
+        drop(guard);
+        let res = self.state_ch.wait_baud_rate_changed().await;
+        self.state_ch.wait_for_initialized().await?;
+        res
+    }
+
     pub async fn start_ap(
         &self,
         options: ConnectionOptions<'_>,
         configuration: HotspotOptions,
     ) -> Result<(), Error> {
-        self.state_ch.wait_for_initialized().await;
+        self.state_ch.wait_for_initialized().await?;
+        #[cfg(feature = "provisional-at")]
+        self.require_auth_firmware(&options.auth)?;
 
         // Deactivate network id 0
         (&self.at_client)
@@ -464,55 +1681,58 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
             })
             .await?;
 
-        match options.auth {
-            WifiAuthentication::None => {
-                (&self.at_client)
-                    .send_retry(&SetWifiAPConfig {
-                        ap_config_id: AccessPointId::Id0,
-                        ap_config_param: AccessPointConfig::SecurityMode(
-                            SecurityMode::Open,
-                            SecurityModePSK::Open,
-                        ),
-                    })
-                    .await?;
-            }
-            WifiAuthentication::WpaPsk(passphrase) => {
-                (&self.at_client)
-                    .send_retry(&SetWifiAPConfig {
-                        ap_config_id: AccessPointId::Id0,
-                        ap_config_param: AccessPointConfig::SecurityMode(
-                            SecurityMode::Wpa2AesCcmp,
-                            SecurityModePSK::PSK,
-                        ),
-                    })
-                    .await?;
+        let (security_mode, security_mode_psk, pmf, passphrase) = match options.auth {
+            WifiAuthentication::None => (SecurityMode::Open, SecurityModePSK::Open, None, None),
+            WifiAuthentication::WpaPsk(passphrase) => (
+                SecurityMode::Wpa2AesCcmp,
+                SecurityModePSK::PSK,
+                None,
+                Some(passphrase),
+            ),
+            // Stations joining with WPA3 must use PMF
+            #[cfg(feature = "provisional-at")]
+            WifiAuthentication::Wpa2Wpa3Personal(passphrase) => (
+                SecurityMode::Wpa2Wpa3Mixed,
+                SecurityModePSK::PSK,
+                Some(PMF::Optional),
+                Some(passphrase),
+            ),
+            #[cfg(feature = "provisional-at")]
+            WifiAuthentication::Wpa3Personal(passphrase) => (
+                SecurityMode::Wpa3Sae,
+                SecurityModePSK::PSK,
+                Some(PMF::Required),
+                Some(passphrase),
+            ),
+        };
 
-                // Input passphrase
-                (&self.at_client)
-                    .send_retry(&SetWifiAPConfig {
-                        ap_config_id: AccessPointId::Id0,
-                        ap_config_param: AccessPointConfig::PSKPassphrase(PasskeyR::Passphrase(
-                            // FIXME:
-                            heapless::String::try_from(passphrase).unwrap(),
-                        )),
-                    })
-                    .await?;
-            } // WifiAuthentication::Wpa2Psk(_psk) => {
-              //     unimplemented!()
-              //     // (&self.at_client)
-              //     //     .send_retry(&SetWifiStationConfig {
-              //     //         config_id: CONFIG_ID,
-              //     //         config_param: WifiStationConfig::Authentication(Authentication::WpaWpa2Psk),
-              //     //     })
-              //     //     .await?;
-
-              //     // (&self.at_client)
-              //     //     .send_retry(&SetWifiStationConfig {
-              //     //         config_id: CONFIG_ID,
-              //     //         config_param: WifiStationConfig::WpaPskOrPassphrase(todo!("hex values?!")),
-              //     //     })
-              //     //     .await?;
-              // }
+        (&self.at_client)
+            .send_retry(&SetWifiAPConfig {
+                ap_config_id: AccessPointId::Id0,
+                ap_config_param: AccessPointConfig::SecurityMode(security_mode, security_mode_psk),
+            })
+            .await?;
+
+        if let Some(pmf) = pmf {
+            (&self.at_client)
+                .send_retry(&SetWifiAPConfig {
+                    ap_config_id: AccessPointId::Id0,
+                    ap_config_param: AccessPointConfig::ProtectedManagementFrames(pmf),
+                })
+                .await?;
+        }
+
+        if let Some(passphrase) = passphrase {
+            // Input passphrase
+            (&self.at_client)
+                .send_retry(&SetWifiAPConfig {
+                    ap_config_id: AccessPointId::Id0,
+                    ap_config_param: AccessPointConfig::PSKPassphrase(PasskeyR::Passphrase(
+                        // FIXME:
+                        heapless::String::try_from(passphrase).unwrap(),
+                    )),
+                })
+                .await?;
         }
 
         if let Some(channel) = configuration.channel {
@@ -524,6 +1744,15 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
                 .await?;
         }
 
+        if let Some(dtim) = configuration.dtim {
+            (&self.at_client)
+                .send_retry(&SetWifiAPConfig {
+                    ap_config_id: AccessPointId::Id0,
+                    ap_config_param: AccessPointConfig::DTIM(dtim),
+                })
+                .await?;
+        }
+
         (&self.at_client)
             .send_retry(&WifiAPAction {
                 ap_config_id: AccessPointId::Id0,
@@ -538,7 +1767,7 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
 
     /// Closes access point.
     pub async fn close_ap(&self) -> Result<(), Error> {
-        self.state_ch.wait_for_initialized().await;
+        self.state_ch.wait_for_initialized().await?;
         self.state_ch.set_should_connect(false);
 
         (&self.at_client)
@@ -550,153 +1779,736 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
         Ok(())
     }
 
-    pub async fn peek_join_sta(&self, options: ConnectionOptions<'_>) -> Result<(), Error> {
-        self.state_ch.wait_for_initialized().await;
+    /// Limit the number of stations allowed to connect to the access point.
+    ///
+    /// Valid values are 1-10. The access point must not be active, so this
+    /// should be set before calling [`Self::start_ap`].
+    pub async fn set_max_ap_stations(&self, max: u8) -> Result<(), Error> {
+        if !(1..=10).contains(&max) {
+            return Err(Error::OutOfRange);
+        }
+
+        self.state_ch.wait_for_initialized().await?;
 
         (&self.at_client)
-            .send_retry(&ExecWifiStationAction {
+            .send_retry(&SetWifiAPConfig {
+                ap_config_id: AccessPointId::Id0,
+                ap_config_param: AccessPointConfig::MaxStations(max),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Hide the SSID of the access point from beacons.
+    pub async fn set_ap_hidden(&self, hidden: bool) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        self.send(&SetWifiAPConfig {
+            ap_config_id: AccessPointId::Id0,
+            ap_config_param: AccessPointConfig::HiddenSSID(hidden.into()),
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Only allow the stations in `macs` to connect to the access point, or
+    /// any station if `macs` is empty.
+    pub async fn set_ap_mac_whitelist(&self, macs: &[[u8; 6]]) -> Result<(), Error> {
+        self.set_ap_mac_list(macs, false).await
+    }
+
+    /// Reject the stations in `macs` from connecting to the access point.
+    pub async fn set_ap_mac_blacklist(&self, macs: &[[u8; 6]]) -> Result<(), Error> {
+        self.set_ap_mac_list(macs, true).await
+    }
+
+    async fn set_ap_mac_list(&self, macs: &[[u8; 6]], blacklist: bool) -> Result<(), Error> {
+        let mut entries = Vec::<heapless::String<12>, 10>::new();
+        for mac in macs {
+            let mut entry = heapless::String::new();
+            for byte in mac {
+                write!(entry, "{:02X}", byte).map_err(|_| Error::Overflow)?;
+            }
+            entries.push(entry).map_err(|_| Error::TooManyEntries)?;
+        }
+
+        // The module takes all entries at once, "0" clears the list.
+        let entry = |i: usize| entries.get(i).map(|e| e.as_str());
+        let [e0, e1, e2, e3, e4, e5, e6, e7, e8, e9] = core::array::from_fn(entry);
+        let e0 = e0.unwrap_or("0");
+        let ap_config_param = if blacklist {
+            AccessPointConfig::BlackList(e0, e1, e2, e3, e4, e5, e6, e7, e8, e9)
+        } else {
+            AccessPointConfig::WhiteList(e0, e1, e2, e3, e4, e5, e6, e7, e8, e9)
+        };
+
+        self.state_ch.wait_for_initialized().await?;
+
+        self.send(&SetWifiAPConfig {
+            ap_config_id: AccessPointId::Id0,
+            ap_config_param,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Channel of the access point, as read when it came up, without querying
+    /// the module. `None` while the access point is down.
+    pub fn ap_channel(&self) -> Option<u8> {
+        self.state_ch.ap_channel()
+    }
+
+    async fn ap_status_param(&self, id: AccessPointStatusId) -> Result<AccessPointStatus, Error> {
+        Ok((&self.at_client)
+            .send_retry(&WifiAPStatus { ap_status_id: id })
+            .await?
+            .ap_status_id)
+    }
+
+    /// Read the status of the access point, e.g. to confirm the channel
+    /// chosen by the module, or the SSID in use.
+    ///
+    /// A disabled access point is reported with `enabled` cleared, instead
+    /// of an error.
+    pub async fn ap_status(&self) -> Result<ApStatus, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let invalid = || Error::AT(atat::Error::InvalidResponse);
+
+        let enabled = self
+            .ap_status_param(AccessPointStatusId::Status)
+            .await?
+            .into_enabled()
+            .ok_or_else(invalid)?;
+        if !enabled {
+            return Ok(ApStatus {
+                enabled,
+                ssid: heapless::String::new(),
+                bssid: [0; 6],
+                channel: 0,
+            });
+        }
+
+        let ssid = self
+            .ap_status_param(AccessPointStatusId::SSID)
+            .await?
+            .into_ssid()
+            .ok_or_else(invalid)?;
+        let bssid = self
+            .ap_status_param(AccessPointStatusId::BSSID)
+            .await?
+            .into_bssid()
+            .and_then(|b| parse_bssid(&b))
+            .ok_or_else(invalid)?;
+        let channel = self
+            .ap_status_param(AccessPointStatusId::Channel)
+            .await?
+            .into_channel()
+            .ok_or_else(invalid)?;
+
+        Ok(ApStatus {
+            enabled,
+            ssid,
+            bssid,
+            channel,
+        })
+    }
+
+    /// Number of stations currently connected to the access point.
+    pub async fn get_connected_station_count(&self) -> Result<usize, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let WiFiAPStationCountResponse { count } =
+            (&self.at_client).send_retry(&WiFiAPStationCount).await?;
+        Ok(count)
+    }
+
+    /// Stations currently connected to the access point.
+    pub async fn get_ap_stations(&self) -> Result<Vec<ApStation, 10>, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let WiFiAPStationListResponse { stations } =
+            (&self.at_client).send_retry(&WiFiAPStationList).await?;
+        Ok(stations)
+    }
+
+    /// Configure the network bridge `config_id` as `profile`.
+    ///
+    /// The bridged interfaces must be configured and active first, i.e. the
+    /// access point started with [`Self::start_ap`] and Ethernet brought up
+    /// with [`Self::ethernet_up`]. An active bridge is rejected with
+    /// [`BridgeError::Active`], see [`Self::reconfigure_bridge`]. The bridge
+    /// takes effect once activated with [`Self::bridge_action`].
+    pub async fn configure_bridge(
+        &self,
+        config_id: BridgeConfigId,
+        profile: &BridgeProfile,
+    ) -> Result<(), Error> {
+        let interfaces = &profile.interfaces;
+        if interfaces.len() < 2
+            || interfaces
+                .iter()
+                .enumerate()
+                .any(|(i, interface)| interfaces[..i].contains(interface))
+        {
+            return Err(BridgeError::TooFewInterfaces.into());
+        }
+        if profile.dhcp && profile.static_v4.is_none() {
+            return Err(BridgeError::DhcpWithoutAddress.into());
+        }
+
+        self.state_ch.wait_for_initialized().await?;
+
+        if self.state_ch.bridge_active(config_id.clone()) {
+            return Err(BridgeError::Active.into());
+        }
+        for &interface in interfaces {
+            if !self.interface_active(interface).await? {
+                return Err(BridgeError::InterfaceInactive(interface).into());
+            }
+        }
+
+        let set = |config_tag| SetBridgeConfiguration {
+            config_id: config_id.clone(),
+            config_tag,
+        };
+        let [l0, l1] = core::array::from_fn(|i| interfaces.get(i).map(|&iface| iface as u8));
+        self.send(&set(BridgeConfig::LinkLayerList(l0, l1, None, None)))
+            .await?;
+
+        match &profile.static_v4 {
+            Some(config) => {
+                self.send(&set(BridgeConfig::IPInterfaceList(l0, l1, None, None)))
+                    .await?;
+                self.send(&set(BridgeConfig::IPv4Mode(IPv4Mode::Static)))
+                    .await?;
+                self.send(&set(BridgeConfig::IPv4Address(config.address)))
+                    .await?;
+                if let Some(mask) = config.subnet_mask {
+                    self.send(&set(BridgeConfig::SubnetMask(mask))).await?;
+                }
+                if let Some(gateway) = config.gateway {
+                    self.send(&set(BridgeConfig::DefaultGateway(gateway)))
+                        .await?;
+                }
+                if let Some(dns) = config.dns_servers.primary {
+                    self.send(&set(BridgeConfig::PrimaryDNS(dns))).await?;
+                }
+                if let Some(dns) = config.dns_servers.secondary {
+                    self.send(&set(BridgeConfig::SecondaryDNS(dns))).await?;
+                }
+            }
+            None => {
+                self.send(&set(BridgeConfig::IPInterfaceList(None, None, None, None)))
+                    .await?;
+            }
+        }
+
+        self.send(&set(BridgeConfig::DHCPServer(profile.dhcp.into())))
+            .await?;
+        Ok(())
+    }
+
+    /// Apply `profile` to the network bridge `config_id` like
+    /// [`Self::configure_bridge`], deactivating the bridge first if it is
+    /// active, and activate it.
+    pub async fn reconfigure_bridge(
+        &self,
+        config_id: BridgeConfigId,
+        profile: &BridgeProfile,
+    ) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        if self.state_ch.bridge_active(config_id.clone()) {
+            self.bridge_action(config_id.clone(), BridgeAction::Deactivate)
+                .await?;
+        }
+        self.configure_bridge(config_id.clone(), profile).await?;
+        self.bridge_action(config_id, BridgeAction::Activate).await
+    }
+
+    /// Execute `action` on the network bridge `config_id`, e.g. activate it
+    /// once configured.
+    ///
+    /// After a bridge is deactivated, the bridged interfaces must be
+    /// deactivated and activated again to restore their own IP settings.
+    pub async fn bridge_action(
+        &self,
+        config_id: BridgeConfigId,
+        action: BridgeAction,
+    ) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let active = match action {
+            BridgeAction::Activate => Some(true),
+            BridgeAction::Deactivate => Some(false),
+            _ => None,
+        };
+
+        self.send(&BridgeConfigurationAction {
+            config_id: config_id.clone(),
+            action,
+        })
+        .await?;
+
+        if let Some(active) = active {
+            self.state_ch.set_bridge_active(config_id, active);
+        }
+        Ok(())
+    }
+
+    /// Whether `interface` is configured and active, so that it may be
+    /// bridged.
+    async fn interface_active(&self, interface: BridgeInterface) -> Result<bool, Error> {
+        match interface {
+            BridgeInterface::WifiAccessPoint => self
+                .ap_status_param(AccessPointStatusId::Status)
+                .await?
+                .into_enabled()
+                .ok_or(Error::AT(atat::Error::InvalidResponse)),
+            BridgeInterface::Ethernet => Ok(self.state_ch.ethernet_active()),
+        }
+    }
+
+    /// Enable or disable layer-2 routing between the network interfaces,
+    /// which bridged setups typically need.
+    pub async fn set_layer2_routing(&self, enabled: bool) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        self.send(&Layer2Routing {
+            routing_tag: RoutingTag::Enabled,
+            routing_value: enabled.into(),
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Configure `url` as default remote peer `peer_id` (0-6), connected by the
+    /// module on its own according to `scheme`.
+    ///
+    /// The configuration is persisted with the module settings, and takes
+    /// effect when the module enters (extended) data mode. Data of the
+    /// connected peer is dropped unless a socket is attached to it with
+    /// [`TcpSocket::attach_default_peer`](crate::asynch::ublox_stack::tcp::TcpSocket::attach_default_peer).
+    #[cfg(feature = "internal-network-stack")]
+    pub async fn set_default_remote_peer(
+        &self,
+        peer_id: u8,
+        url: &crate::asynch::ublox_stack::PeerUrl,
+        scheme: ConnectScheme,
+    ) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        if peer_id > MAX_DEFAULT_REMOTE_PEER_ID {
+            return Err(Error::OutOfRange);
+        }
+
+        self.send(&SetDefaultRemotePeer {
+            peer_id,
+            url: url.as_str(),
+            connect_scheme: scheme,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Remove default remote peer `peer_id` (0-6).
+    ///
+    /// The module clears a peer configured with an empty URL. Like
+    /// [`set_default_remote_peer`](Self::set_default_remote_peer), this takes
+    /// effect the next time the module enters (extended) data mode, so a peer
+    /// already connected stays connected until then.
+    #[cfg(feature = "internal-network-stack")]
+    pub async fn clear_default_remote_peer(&self, peer_id: u8) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        if peer_id > MAX_DEFAULT_REMOTE_PEER_ID {
+            return Err(Error::OutOfRange);
+        }
+
+        self.send(&SetDefaultRemotePeer {
+            peer_id,
+            url: "",
+            connect_scheme: ConnectScheme::ExternalConnect,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Start the server configured by `server`, built with
+    /// [`ServerConfigBuilder`], listening for peers to connect.
+    ///
+    /// A server already configured under the same ID (0-6) is disabled first.
+    /// Inbound TCP peers are picked up with
+    /// [`TcpSocket::accept`](crate::asynch::ublox_stack::tcp::TcpSocket::accept).
+    pub async fn open_server(&self, server: ConfigureServer) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let id = server.id();
+        if id > MAX_SERVER_ID {
+            return Err(Error::OutOfRange);
+        }
+
+        self.send(&ServerConfigBuilder::new(id).disabled()).await?;
+
+        if !server.is_disabled() {
+            self.send(&server).await?;
+        }
+        Ok(())
+    }
+
+    /// Stop server `id` (0-6). Peers already connected stay connected.
+    pub async fn close_server(&self, id: u8) -> Result<(), Error> {
+        self.open_server(ServerConfigBuilder::new(id).disabled())
+            .await
+    }
+
+    /// Allow peers of server `id` (0-6) to enter command mode with the escape
+    /// sequence, and configure the module over the air.
+    pub async fn set_server_remote_configuration(&self, id: u8, enable: bool) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        if id > MAX_SERVER_ID {
+            return Err(Error::OutOfRange);
+        }
+
+        self.send(&SetServerFlags {
+            id,
+            flag: if enable {
+                RemoteConfiguration::Enable
+            } else {
+                RemoteConfiguration::Disable
+            },
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn peek_join_sta(&self, options: ConnectionOptions<'_>) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        if options.ssid.is_empty() || options.ssid.len() > 32 {
+            return Err(ModuleError::InvalidSsid.into());
+        }
+        #[cfg(feature = "provisional-at")]
+        self.require_auth_firmware(&options.auth)?;
+
+        self.send(&ExecWifiStationAction {
+            config_id: CONFIG_ID,
+            action: WifiStationAction::Reset,
+        })
+        .await?;
+
+        self.retry_on_busy(&SetWifiStationConfig {
+            config_id: CONFIG_ID,
+            config_param: WifiStationConfig::ActiveOnStartup(OnOff::Off),
+        })
+        .await?;
+
+        self.retry_on_busy(&SetWifiStationConfig {
+            config_id: CONFIG_ID,
+            config_param: WifiStationConfig::SSID(options.ssid),
+        })
+        .await?;
+
+        let (authentication, passphrase) = match options.auth {
+            WifiAuthentication::None => (Authentication::Open, None),
+            WifiAuthentication::WpaPsk(passphrase) => {
+                (Authentication::WpaWpa2Psk, Some(passphrase))
+            }
+            #[cfg(feature = "provisional-at")]
+            WifiAuthentication::Wpa2Wpa3Personal(passphrase) => {
+                (Authentication::Wpa2Wpa3Psk, Some(passphrase))
+            }
+            #[cfg(feature = "provisional-at")]
+            WifiAuthentication::Wpa3Personal(passphrase) => {
+                (Authentication::Wpa3Psk, Some(passphrase))
+            }
+        };
+
+        self.retry_on_busy(&SetWifiStationConfig {
+            config_id: CONFIG_ID,
+            config_param: WifiStationConfig::Authentication(authentication),
+        })
+        .await?;
+
+        if let Some(passphrase) = passphrase {
+            self.retry_on_busy(&SetWifiStationConfig {
                 config_id: CONFIG_ID,
-                action: WifiStationAction::Reset,
+                config_param: WifiStationConfig::WpaPskOrPassphrase(passphrase),
             })
             .await?;
+        }
 
-        (&self.at_client)
-            .send_retry(&SetWifiStationConfig {
+        if options.ip.is_some() || options.subnet.is_some() || options.gateway.is_some() {
+            self.retry_on_busy(&SetWifiStationConfig {
                 config_id: CONFIG_ID,
-                config_param: WifiStationConfig::ActiveOnStartup(OnOff::Off),
+                config_param: WifiStationConfig::IPv4Mode(IPv4Mode::Static),
             })
             .await?;
+        }
 
-        (&self.at_client)
-            .send_retry(&SetWifiStationConfig {
+        // Network IP address
+        if let Some(ip) = options.ip {
+            self.retry_on_busy(&SetWifiStationConfig {
+                config_id: CONFIG_ID,
+                config_param: WifiStationConfig::IPv4Address(ip),
+            })
+            .await?;
+        }
+        // Network Subnet mask
+        if let Some(subnet) = options.subnet {
+            self.retry_on_busy(&SetWifiStationConfig {
                 config_id: CONFIG_ID,
-                config_param: WifiStationConfig::SSID(options.ssid),
+                config_param: WifiStationConfig::SubnetMask(subnet),
             })
             .await?;
+        }
+        // Network Default gateway
+        if let Some(gateway) = options.gateway {
+            self.retry_on_busy(&SetWifiStationConfig {
+                config_id: CONFIG_ID,
+                config_param: WifiStationConfig::DefaultGateway(gateway),
+            })
+            .await?;
+        }
 
-        match options.auth {
-            WifiAuthentication::None => {
-                (&self.at_client)
-                    .send_retry(&SetWifiStationConfig {
-                        config_id: CONFIG_ID,
-                        config_param: WifiStationConfig::Authentication(Authentication::Open),
-                    })
-                    .await?;
-            }
-            WifiAuthentication::WpaPsk(passphrase) => {
-                (&self.at_client)
-                    .send_retry(&SetWifiStationConfig {
-                        config_id: CONFIG_ID,
-                        config_param: WifiStationConfig::Authentication(Authentication::WpaWpa2Psk),
-                    })
-                    .await?;
+        self.send(&ExecWifiStationAction {
+            config_id: CONFIG_ID,
+            action: WifiStationAction::Activate,
+        })
+        .await?;
 
+        self.wait_for_join(options.ssid, Duration::from_secs(20))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn join_sta(&self, options: ConnectionOptions<'_>) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let status = self.get_wifi_status().await?;
+
+        match status {
+            WifiStatusVal::Disabled => {}
+            WifiStatusVal::Disconnected => {
+                // Wifi is disabled. Enable it
                 (&self.at_client)
-                    .send_retry(&SetWifiStationConfig {
+                    .send_retry(&ExecWifiStationAction {
                         config_id: CONFIG_ID,
-                        config_param: WifiStationConfig::WpaPskOrPassphrase(passphrase),
+                        action: WifiStationAction::Deactivate,
                     })
                     .await?;
-            } // WifiAuthentication::Wpa2Psk(_psk) => {
-              //     unimplemented!()
-              //     // (&self.at_client)
-              //     //     .send_retry(&SetWifiStationConfig {
-              //     //         config_id: CONFIG_ID,
-              //     //         config_param: WifiStationConfig::Authentication(Authentication::WpaWpa2Psk),
-              //     //     })
-              //     //     .await?;
-
-              //     // (&self.at_client)
-              //     //     .send_retry(&SetWifiStationConfig {
-              //     //         config_id: CONFIG_ID,
-              //     //         config_param: WifiStationConfig::WpaPskOrPassphrase(todo!("hex values?!")),
-              //     //     })
-              //     //     .await?;
-              // }
+            }
+            WifiStatusVal::Connected => {
+                // Wifi already connected. Check if the SSID is the same
+                let current_ssid = self.get_connected_ssid().await?;
+                if current_ssid.as_str() == options.ssid {
+                    self.state_ch.set_should_connect(true);
+                    return Ok(());
+                } else {
+                    self.wait_leave().await?;
+                };
+            }
         }
 
-        if options.ip.is_some() || options.subnet.is_some() || options.gateway.is_some() {
-            (&self.at_client)
-                .send_retry(&SetWifiStationConfig {
-                    config_id: CONFIG_ID,
-                    config_param: WifiStationConfig::IPv4Mode(IPv4Mode::Static),
-                })
-                .await?;
+        self.peek_join_sta(options).await?;
+
+        self.state_ch.set_should_connect(true);
+        Ok(())
+    }
+
+    /// Join the WPA-Enterprise network `ssid`.
+    ///
+    /// Certificates with data are imported before the station is configured.
+    pub async fn join_enterprise(&self, ssid: &str, auth: EnterpriseAuth<'_>) -> Result<(), Error> {
+        if ssid.is_empty() || ssid.len() > 32 {
+            return Err(ModuleError::InvalidSsid.into());
         }
 
-        // Network IP address
-        if let Some(ip) = options.ip {
-            (&self.at_client)
-                .send_retry(&SetWifiStationConfig {
-                    config_id: CONFIG_ID,
-                    config_param: WifiStationConfig::IPv4Address(ip),
-                })
-                .await?;
+        let (username, certs) = match auth {
+            EnterpriseAuth::PEAP {
+                username,
+                password,
+                domain,
+                ca_cert,
+            } => {
+                if password.len() > 31 || domain.is_some_and(|d| d.len() > 63) {
+                    return Err(Error::BadLength);
+                }
+                (
+                    username,
+                    [
+                        (SecurityDataType::TrustedRootCA, ca_cert.certificate()),
+                        (SecurityDataType::ClientCertificate, None),
+                        (SecurityDataType::ClientPrivateKey, None),
+                    ],
+                )
+            }
+            EnterpriseAuth::EAPTLS {
+                username,
+                ca_cert,
+                client_cert,
+                client_key,
+            } => (
+                username,
+                [
+                    (SecurityDataType::TrustedRootCA, ca_cert.certificate()),
+                    (SecurityDataType::ClientCertificate, Some(client_cert)),
+                    (SecurityDataType::ClientPrivateKey, Some(client_key)),
+                ],
+            ),
+        };
+        if username.len() > 31 || certs.iter().flat_map(|(_, c)| c).any(|c| c.name.len() > 32) {
+            return Err(Error::BadLength);
         }
-        // Network Subnet mask
-        if let Some(subnet) = options.subnet {
-            (&self.at_client)
-                .send_retry(&SetWifiStationConfig {
-                    config_id: CONFIG_ID,
-                    config_param: WifiStationConfig::SubnetMask(subnet),
-                })
-                .await?;
+
+        let ca_cert = certs[0].1;
+
+        self.state_ch.wait_for_initialized().await?;
+
+        for (data_type, cert) in certs {
+            if let Some(EnterpriseCertificate {
+                name,
+                data: Some(data),
+            }) = cert
+            {
+                self.import_credentials(data_type, name, data, None).await?;
+            }
         }
-        // Network Default gateway
-        if let Some(gateway) = options.gateway {
-            (&self.at_client)
-                .send_retry(&SetWifiStationConfig {
-                    config_id: CONFIG_ID,
-                    config_param: WifiStationConfig::DefaultGateway(gateway),
-                })
-                .await?;
+
+        self.send(&ExecWifiStationAction {
+            config_id: CONFIG_ID,
+            action: WifiStationAction::Reset,
+        })
+        .await?;
+
+        let mut params: Vec<WifiStationConfig, 8> = Vec::new();
+        params.extend([
+            WifiStationConfig::ActiveOnStartup(OnOff::Off),
+            WifiStationConfig::SSID(ssid),
+            WifiStationConfig::UserName(username),
+        ]);
+        match auth {
+            EnterpriseAuth::PEAP {
+                password, domain, ..
+            } => {
+                params.extend([
+                    WifiStationConfig::Authentication(Authentication::PEAP),
+                    WifiStationConfig::EAPPassword(password),
+                ]);
+                if let Some(domain) = domain {
+                    params.push(WifiStationConfig::DomainName(domain)).ok();
+                }
+            }
+            EnterpriseAuth::EAPTLS {
+                client_cert,
+                client_key,
+                ..
+            } => params.extend([
+                WifiStationConfig::Authentication(Authentication::EAPTLS),
+                WifiStationConfig::ClientCertificateName(client_cert.name),
+                WifiStationConfig::ClientPrivateKey(client_key.name),
+            ]),
         }
+        params
+            .push(match ca_cert {
+                Some(ca_cert) => WifiStationConfig::CACertificateName(ca_cert.name),
+                None => WifiStationConfig::ValidateCACertificate(OnOff::Off),
+            })
+            .ok();
 
-        (&self.at_client)
-            .send_retry(&ExecWifiStationAction {
+        for config_param in params {
+            self.retry_on_busy(&SetWifiStationConfig {
                 config_id: CONFIG_ID,
-                action: WifiStationAction::Activate,
+                config_param,
             })
             .await?;
+        }
 
-        self.wait_for_join(options.ssid, Duration::from_secs(20))
-            .await?;
+        self.send(&ExecWifiStationAction {
+            config_id: CONFIG_ID,
+            action: WifiStationAction::Activate,
+        })
+        .await?;
 
+        self.wait_for_join(ssid, Duration::from_secs(20)).await?;
+
+        self.state_ch.set_should_connect(true);
         Ok(())
     }
 
-    pub async fn join_sta(&self, options: ConnectionOptions<'_>) -> Result<(), Error> {
-        self.state_ch.wait_for_initialized().await;
+    /// Join a network using WPS push-button configuration.
+    ///
+    /// Returns the negotiated credentials, so they can be stored and used for
+    /// future reconnections.
+    ///
+    /// Provisional, see the `provisional-at` feature.
+    #[cfg(feature = "provisional-at")]
+    pub async fn start_wps(&self, timeout: Duration) -> Result<WifiCredentials, Error> {
+        self.state_ch.wait_for_initialized().await?;
 
-        let status = self.get_wifi_status().await?;
+        (&self.at_client)
+            .send_retry(&ExecWifiStationAction {
+                config_id: CONFIG_ID,
+                action: WifiStationAction::ActivateWPS,
+            })
+            .await?;
 
-        match status {
-            WifiStatusVal::Disabled => {}
-            WifiStatusVal::Disconnected => {
-                // Wifi is disabled. Enable it
-                (&self.at_client)
+        let wait_connected = async {
+            loop {
+                if self.get_wifi_status().await? == WifiStatusVal::Connected {
+                    return Ok::<(), Error>(());
+                }
+                Timer::after_millis(500).await;
+            }
+        };
+
+        match with_timeout(timeout, wait_connected).await {
+            Ok(r) => r?,
+            Err(_) => {
+                let _ = (&self.at_client)
                     .send_retry(&ExecWifiStationAction {
                         config_id: CONFIG_ID,
-                        action: WifiStationAction::Deactivate,
+                        action: WifiStationAction::AbortWPS,
                     })
-                    .await?;
-            }
-            WifiStatusVal::Connected => {
-                // Wifi already connected. Check if the SSID is the same
-                let current_ssid = self.get_connected_ssid().await?;
-                if current_ssid.as_str() == options.ssid {
-                    self.state_ch.set_should_connect(true);
-                    return Ok(());
-                } else {
-                    self.wait_leave().await?;
-                };
+                    .await;
+                return Err(Error::Timeout);
             }
         }
 
-        self.peek_join_sta(options).await?;
+        let GetWifiStationConfigResponse {
+            parameter: WifiStationConfigR::SSID(ssid),
+            ..
+        } = (&self.at_client)
+            .send_retry(&GetWifiStationConfig {
+                config_id: CONFIG_ID,
+                parameter: Some(WifiStationConfigParameter::SSID),
+            })
+            .await?
+        else {
+            return Err(Error::AT(atat::Error::InvalidResponse));
+        };
+
+        // Failing to read the passphrase must not be taken for an open network
+        let GetWifiStationConfigResponse {
+            parameter: WifiStationConfigR::WpaPskOrPassphrase(passphrase),
+            ..
+        } = (&self.at_client)
+            .send_retry(&GetWifiStationConfig {
+                config_id: CONFIG_ID,
+                parameter: Some(WifiStationConfigParameter::WpaPskPassphrase),
+            })
+            .await?
+        else {
+            return Err(Error::AT(atat::Error::InvalidResponse));
+        };
+        let passphrase = (!passphrase.is_empty()).then_some(passphrase);
 
         self.state_ch.set_should_connect(true);
-        Ok(())
+
+        Ok(WifiCredentials { ssid, passphrase })
     }
 
     /// Leave the wifi and wait, with which we are currently associated.
@@ -716,9 +2528,40 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
         Ok(())
     }
     /// Leave the wifi, with which we are currently associated.
-    pub fn leave(&self) {
-        self.state_ch.set_should_connect(false);
+    ///
+    /// Deactivates the station configuration and waits for the link and the
+    /// network to go down. All open sockets fail with `ConnectionReset`, and
+    /// no reconnection is attempted until the next join. Calling this while
+    /// already disconnected is a no-op.
+    pub async fn leave(&self) -> Result<(), Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        // Mark the leave before the link goes down, so the disconnect is not
+        // taken as a reason to reconnect.
+        self.state_ch.mark_left();
+
+        if self.get_wifi_status().await? != WifiStatusVal::Disabled {
+            self.send(&ExecWifiStationAction {
+                config_id: CONFIG_ID,
+                action: WifiStationAction::Deactivate,
+            })
+            .await?;
+
+            let wait_down = async {
+                while self.state_ch.wifi_state(None) == WiFiState::Connected {
+                    self.state_ch.wait_for_wifi_state_change().await;
+                }
+                self.state_ch.wait_connection_down().await;
+            };
+
+            with_timeout(Duration::from_secs(10), wait_down)
+                .await
+                .map_err(|_| Error::Timeout)?;
+        }
+
         self.state_ch.update_connection_with(|con| con.reset());
+
+        Ok(())
     }
 
     pub async fn wait_for_join(&self, ssid: &str, timeout: Duration) -> Result<(), Error> {
@@ -740,13 +2583,14 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
         match with_timeout(
             timeout,
             select(
-                self.state_ch.wait_for_link_state(LinkState::Up),
+                self.wait_for_link_state_or_error(LinkState::Up),
                 wait_for_security_error,
             ),
         )
         .await
         {
-            Ok(Either::First(_)) => {
+            Ok(Either::First(Err(e))) => Err(e),
+            Ok(Either::First(Ok(()))) => {
                 // Link is up - check that SSID matches
                 let current_ssid = self.get_connected_ssid().await?;
                 if ssid != current_ssid.as_str() {
@@ -762,7 +2606,7 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
                         action: WifiStationAction::Deactivate,
                     })
                     .await;
-                Err(Error::SecurityProblems)
+                Err(ModuleError::SecurityFailure.into())
             }
             Err(_) if self.state_ch.wifi_state(None) == WiFiState::SecurityProblems => {
                 let _ = (&self.at_client)
@@ -771,34 +2615,131 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
                         action: WifiStationAction::Deactivate,
                     })
                     .await;
-                Err(Error::SecurityProblems)
+                Err(ModuleError::SecurityFailure.into())
+            }
+            // Associated with the network, but no IP address was acquired
+            Err(_) if self.state_ch.wifi_state(None) == WiFiState::Connected => {
+                Err(ModuleError::DhcpTimeout.into())
             }
             Err(_) => Err(Error::Timeout),
         }
     }
 
-    // /// Start a wifi scan
-    // ///
-    // /// Returns a `Stream` of networks found by the device
-    // ///
-    // /// # Note
-    // /// Device events are currently implemented using a bounded queue.
-    // /// To not miss any events, you should make sure to always await the stream.
-    // pub async fn scan(&mut self, scan_opts: ScanOptions) -> Scanner<'_> {
-    //     todo!()
-    // }
+    /// Scan for networks, directed at `ssid` if given, handing each network
+    /// found to `visit` one at a time.
+    ///
+    /// Returning [`ControlFlow::Break`] from `visit` skips the remaining
+    /// networks, e.g. once the wanted SSID is seen, and its value is returned.
+    ///
+    /// The module only responds once the scan is complete, which may take
+    /// several seconds. The returned future may be cancelled, e.g. with
+    /// `with_timeout`, in which case the next command first waits for the
+    /// late response and drops it.
+    pub async fn scan_with<B>(
+        &self,
+        ssid: Option<&str>,
+        visit: impl FnMut(ScannedWifiNetwork) -> ControlFlow<B>,
+    ) -> Result<Option<B>, Error> {
+        self.state_ch.wait_for_initialized().await?;
+
+        let WifiScanWithResponse { stopped, .. } =
+            self.send(&WifiScanWith::new(ssid, visit)).await?;
+        Ok(stopped)
+    }
+
+    /// Scan directed at `ssid`, returning the first network found with that
+    /// SSID, or `None` if the scan does not complete within `timeout`.
+    ///
+    /// A directed scan is much faster than a full one, e.g. to learn the
+    /// channel of an access point before roaming to it.
+    pub async fn scan_on_ssid(
+        &self,
+        ssid: &str,
+        timeout: Duration,
+    ) -> Result<Option<ScannedWifiNetwork>, Error> {
+        let scan = self.scan_with(Some(ssid), |network| {
+            if network.ssid == ssid {
+                ControlFlow::Break(network)
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        match with_timeout(timeout, scan).await {
+            Ok(found) => found,
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Scan for networks like [`Self::scan_with`], filling `networks`.
+    ///
+    /// Networks found once `networks` is full are left out, rather than
+    /// failing the scan.
+    pub async fn scan<const N: usize>(
+        &self,
+        ssid: Option<&str>,
+        networks: &mut Vec<ScannedWifiNetwork, N>,
+    ) -> Result<(), Error> {
+        networks.clear();
+        self.scan_with(ssid, |network| match networks.push(network) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        })
+        .await?;
+        Ok(())
+    }
 
     pub async fn send_at<Cmd: AtatCmd>(&self, cmd: &Cmd) -> Result<Cmd::Response, Error> {
-        self.state_ch.wait_for_initialized().await;
-        Ok((&self.at_client).send_retry(cmd).await?)
+        self.state_ch.wait_for_initialized().await?;
+        self.send(cmd).await
+    }
+
+    /// Send `cmd`, returning the error reported by the module as
+    /// [`Error::Module`] when it can be recovered from the response.
+    async fn send<Cmd: AtatCmd>(&self, cmd: &Cmd) -> Result<Cmd::Response, Error> {
+        (&self.at_client).send_retry(cmd).await.map_err(|e| {
+            match self.at_client.take_module_error() {
+                Some(module_error) => module_error.into(),
+                None => e.into(),
+            }
+        })
+    }
+
+    /// Send `cmd` like [`Self::send`], retrying while the module reports
+    /// [`Error::ModuleBusy`], e.g. during Wi-Fi association.
+    ///
+    /// Only use this for commands that are safe to repeat.
+    async fn retry_on_busy<Cmd: AtatCmd>(&self, cmd: &Cmd) -> Result<Cmd::Response, Error> {
+        let mut attempt = 0;
+        loop {
+            match self.send(cmd).await {
+                Err(Error::ModuleBusy) if attempt < BUSY_RETRIES => {
+                    attempt += 1;
+                    warn!("Module busy, retrying ({}/{})", attempt, BUSY_RETRIES);
+                    Timer::after(BUSY_RETRY_DELAY).await;
+                }
+                res => return res,
+            }
+        }
     }
 
+    /// Configure a GPIO pin of the module.
+    ///
+    /// A pin must be disabled before changing it between input and output.
     pub async fn gpio_configure(&self, id: GPIOId, mode: GPIOMode) -> Result<(), Error> {
+        if !crate::module_caps::SUPPORTS_GPIO {
+            return Err(Error::Pin);
+        }
+
         self.send_at(&ConfigureGPIO { id, mode }).await?;
         Ok(())
     }
 
-    pub async fn gpio_set(&self, id: GPIOId, value: bool) -> Result<(), Error> {
+    /// Set the level of a GPIO pin configured as output.
+    pub async fn gpio_write(&self, id: GPIOId, value: bool) -> Result<(), Error> {
+        if !crate::module_caps::SUPPORTS_GPIO {
+            return Err(Error::Pin);
+        }
+
         let value = if value {
             GPIOValue::High
         } else {
@@ -809,70 +2750,810 @@ impl<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
         Ok(())
     }
 
+    /// Read the level of an enabled GPIO pin, configured as either input or
+    /// output.
+    pub async fn gpio_read(&self, id: GPIOId) -> Result<bool, Error> {
+        if !crate::module_caps::SUPPORTS_GPIO {
+            return Err(Error::Pin);
+        }
+
+        let value = self
+            .send_at(&ReadGPIO { id })
+            .await?
+            .value()
+            .ok_or(Error::AT(atat::Error::InvalidResponse))?;
+        Ok(value == GPIOValue::High)
+    }
+
+    /// Set the level of a GPIO pin configured as output.
+    #[deprecated(note = "renamed to `gpio_write`")]
+    pub async fn gpio_set(&self, id: GPIOId, value: bool) -> Result<(), Error> {
+        self.gpio_write(id, value).await
+    }
+
+    /// Read the level of an enabled GPIO pin.
+    #[deprecated(note = "renamed to `gpio_read`")]
     pub async fn gpio_get(&self, id: GPIOId) -> Result<bool, Error> {
-        let ReadGPIOResponse { value, .. } = self.send_at(&ReadGPIO { id }).await?;
-        Ok(value as u8 != 0)
+        self.gpio_read(id).await
     }
 
-    #[cfg(feature = "ppp")]
-    pub async fn ping(
+    /// Wait for a GPIO pin to reach `level`.
+    ///
+    /// The module reports no GPIO events, so the pin is polled every
+    /// `poll_interval`. Wrap in a timeout to bound the wait.
+    pub async fn gpio_wait_for(
         &self,
-        hostname: &str,
-    ) -> Result<crate::command::ping::urc::PingResponse, Error> {
+        id: GPIOId,
+        level: bool,
+        poll_interval: Duration,
+    ) -> Result<(), Error> {
+        while self.gpio_read(id.clone()).await? != level {
+            Timer::after(poll_interval).await;
+        }
+        Ok(())
+    }
+
+    /// Ping `hostname` `count` times with `size` bytes of payload, and
+    /// collect the round trip statistics.
+    #[cfg(feature = "ppp")]
+    pub async fn ping(&self, hostname: &str, count: u8, size: u16) -> Result<PingStats, Error> {
+        if count == 0 || !(4..=1472).contains(&size) {
+            return Err(Error::OutOfRange);
+        }
+
         let mut urc_sub = self.urc_channel.subscribe().map_err(|_| Error::Overflow)?;
 
-        self.send_at(&Ping {
+        let ping = Ping {
             hostname,
-            retry_num: 1,
-        })
-        .await?;
+            retry_num: count as i32,
+            data_size: size,
+            ..Default::default()
+        };
+        self.send_at(&ping).await?;
 
+        let mut stats = PingStats::new(count);
         let result_fut = async {
-            loop {
-                match urc_sub.next_message_pure().await {
-                    crate::command::Urc::PingResponse(r) => return Ok(r),
-                    crate::command::Urc::PingErrorResponse(e) => return Err(Error::Dns(e.error)),
-                    _ => {}
+            for _ in 0..count {
+                loop {
+                    match urc_sub.next_message_pure().await {
+                        crate::command::Urc::PingResponse(r) => {
+                            stats.record(r.rtt);
+                            break;
+                        }
+                        crate::command::Urc::PingErrorResponse(e) => {
+                            return Err(Error::Dns(e.error))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Ok(())
+        };
+
+        // Echo requests are sent every second, each waiting up to `timeout`
+        let per_ping = Duration::from_millis(ping.timeout as u64) + Duration::from_secs(1);
+        with_timeout(
+            per_ping * count as u32 + Duration::from_secs(10),
+            result_fut,
+        )
+        .await??;
+
+        Ok(stats)
+    }
+
+    /// Import a certificate or private key into the module under `name`.
+    ///
+    /// Data larger than the command buffer is written in several chunks. If
+    /// `md5_sum` is given, it is compared to the MD5 sum reported by the
+    /// module after the import.
+    pub async fn import_credentials(
+        &self,
+        data_type: SecurityDataType,
+        name: &str,
+        data: &[u8],
+        md5_sum: Option<&str>,
+    ) -> Result<(), Error> {
+        if name.len() > 32 || data.is_empty() || data.len() > 8192 {
+            return Err(Error::BadLength);
+        }
+
+        self.state_ch.wait_for_initialized().await?;
+
+        info!("Importing {:?} bytes as {:?}", data.len(), name);
+
+        let import = self
+            .at_client
+            .import_security_data(data_type, name, data, None)
+            .await
+            .map_err(|e| match self.at_client.take_module_error() {
+                Some(module_error) => module_error.into(),
+                None => e.into(),
+            })?;
+
+        if let Some(hash) = md5_sum {
+            if !import.md5_string.eq_ignore_ascii_case(hash) {
+                return Err(Error::SecurityProblems);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// MD5 sum of the certificate or private key imported under `name`.
+    ///
+    /// The module only reports sums of imported data, and keeps nothing of
+    /// the certificate a TLS peer presented. For a connected TLS socket, the
+    /// sum of the CA certificate in its credentials pins the trust anchor the
+    /// peer's certificate was validated against.
+    pub async fn security_data_md5(
+        &self,
+        data_type: SecurityDataType,
+        name: &str,
+    ) -> Result<[u8; 16], Error> {
+        let name = heapless::String::try_from(name).map_err(|_| Error::BadLength)?;
+
+        self.state_ch.wait_for_initialized().await?;
+
+        let SecurityDataMD5 { md5_string, .. } = self
+            .send(&GetSecurityDataMD5 {
+                types: data_type,
+                name,
+            })
+            .await?;
+        parse_md5(&md5_string).ok_or(Error::AT(atat::Error::InvalidResponse))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asynch::test_support::{block_on, ControlFixture};
+    use crate::command::wifi::types::{WifiStationConfigParameter, WifiStationConfigR};
+    use crate::command::wifi::GetWifiStationConfig;
+    use crate::options::CaValidation;
+    use embassy_sync::channel::Channel;
+
+    #[test]
+    fn import_4k_through_1k_command_buffer() {
+        let req_slot = Channel::<NoopRawMutex, Vec<u8, 1024>, 1>::new();
+        let res_slot = atat::ResponseSlot::<256>::new();
+        let cmd_lock = CommandLock::new();
+        let client = ProxyClient::new(req_slot.sender(), &res_slot, &cmd_lock);
+
+        let data: std::vec::Vec<u8> = (0..4096).map(|i| i as u8).collect();
+
+        let module = async {
+            let mut received = std::vec::Vec::new();
+            let mut writes = 0;
+            while received.len() < data.len() {
+                received.extend_from_slice(&req_slot.receive().await);
+                writes += 1;
+            }
+            res_slot
+                .signal_response(Ok(b"+USECMNG:0,0,\"ca\",\"0123456789abcdef\""))
+                .unwrap();
+            (received, writes)
+        };
+
+        let import = async {
+            let guard = cmd_lock.lock().await;
+            client.write_security_data(&guard, &data).await
+        };
+
+        let (import, (received, writes)) = block_on(embassy_futures::join::join(import, module));
+
+        assert_eq!(writes, 4);
+        assert_eq!(received, data);
+        let import = import.unwrap();
+        assert_eq!(import.internal_name.as_str(), "ca");
+        assert_eq!(import.md5_string.as_str(), "0123456789abcdef");
+    }
+
+    #[test]
+    fn security_data_md5() {
+        let ControlFixture {
+            control, module, ..
+        } = ControlFixture::initialized();
+
+        let (req_slot, res_slot) = (module.req_slot, module.res_slot);
+        let module = async {
+            let req = req_slot.receive().await;
+            res_slot
+                .signal_response(Ok(
+                    b"+USECMNG:0,\"ca\",\"00112233445566778899AABBCCDDEEFF\"",
+                ))
+                .unwrap();
+            req
+        };
+        let (md5, req) = block_on(embassy_futures::join::join(
+            control.security_data_md5(SecurityDataType::TrustedRootCA, "ca"),
+            module,
+        ));
+        assert_eq!(&req[..], b"AT+USECMNG=4,0,\"ca\"\r\n");
+        assert_eq!(
+            md5.unwrap(),
+            [
+                0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+                0xee, 0xff
+            ]
+        );
+
+        assert_eq!(parse_md5("0011"), None);
+        assert_eq!(parse_md5("0123456789abcdef0123456789abcdeg"), None);
+    }
+
+    #[test]
+    fn reboot_times_out() {
+        let ControlFixture { ch, control, .. } = ControlFixture::initialized();
+
+        // No runner takes the request, so the module never comes back
+        let start = Instant::now();
+        assert!(matches!(block_on(control.reboot()), Err(Error::Timeout)));
+        assert!(start.elapsed() >= Duration::from_secs(5));
+        assert_eq!(ch.link_state(None), LinkState::Uninitialized);
+        assert!(block_on(with_timeout(
+            Duration::from_millis(10),
+            ch.wait_reset_requested()
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn dhcp_lease_requires_link() {
+        let ControlFixture {
+            control, module, ..
+        } = ControlFixture::new();
+
+        // Nothing is sent to the module while the link is down
+        assert!(matches!(
+            block_on(control.dhcp_lease()),
+            Err(Error::NotConnected)
+        ));
+        assert!(module.req_slot.try_receive().is_err());
+    }
+
+    #[test]
+    fn network_error_fails_join() {
+        let ControlFixture { control, .. } = ControlFixture::new();
+
+        // Errors reported before waiting do not fail the wait
+        control
+            .state_ch
+            .set_network_error(NetworkErrorReason::Unknown(1));
+        assert!(block_on(with_timeout(
+            Duration::from_millis(50),
+            control.wait_for_link_state_or_error(LinkState::Up)
+        ))
+        .is_err());
+
+        let start = Instant::now();
+        let inject = async {
+            embassy_futures::yield_now().await;
+            control
+                .state_ch
+                .set_network_error(NetworkErrorReason::AddressConflict);
+        };
+        let (join, ()) = block_on(embassy_futures::join::join(
+            control.wait_for_join("UBXWifi", Duration::from_secs(30)),
+            inject,
+        ));
+
+        assert!(matches!(
+            join,
+            Err(Error::NetworkError(NetworkErrorReason::AddressConflict))
+        ));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(
+            control.last_network_error(),
+            Some(NetworkErrorReason::AddressConflict)
+        );
+    }
+
+    #[test]
+    fn retry_while_module_busy() {
+        let ControlFixture {
+            control, module, ..
+        } = ControlFixture::new();
+
+        let (req_slot, res_slot) = (module.req_slot, module.res_slot);
+        let module = async {
+            for _ in 0..3 {
+                req_slot.receive().await;
+                res_slot
+                    .signal_response(Ok(b"\r\n+CME ERROR: 10\r\n"))
+                    .unwrap();
+            }
+            req_slot.receive().await;
+            res_slot
+                .signal_response(Ok(b"+UWSC:0,2,\"UBXWifi\""))
+                .unwrap();
+        };
+
+        let cmd = GetWifiStationConfig {
+            config_id: CONFIG_ID,
+            parameter: Some(WifiStationConfigParameter::SSID),
+        };
+        let (resp, _) = block_on(embassy_futures::join::join(
+            control.retry_on_busy(&cmd),
+            module,
+        ));
+
+        assert!(matches!(
+            resp.unwrap().parameter,
+            WifiStationConfigR::SSID(ssid) if ssid == "UBXWifi"
+        ));
+    }
+
+    #[test]
+    fn interleave_commands_from_two_handles() {
+        let ControlFixture {
+            control, module, ..
+        } = ControlFixture::new();
+        let diagnostics = control.clone();
+
+        // Answer each command with the SSID of the configuration it reads
+        let (req_slot, res_slot) = (module.req_slot, module.res_slot);
+        let module = async {
+            let mut config_ids = std::vec::Vec::new();
+            for _ in 0..4 {
+                let req = req_slot.receive().await;
+                let config_id = req[b"AT+UWSC=".len()] - b'0';
+                config_ids.push(config_id);
+
+                let resp = std::format!("+UWSC:{},2,\"net{}\"", config_id, config_id);
+                res_slot.signal_response(Ok(resp.as_bytes())).unwrap();
+            }
+            config_ids
+        };
+
+        let read_ssid = |control: &Control<'_, 256, 2>, config_id: u8| {
+            let control = control.clone();
+            async move {
+                for _ in 0..2 {
+                    let resp = control
+                        .send(&GetWifiStationConfig {
+                            config_id,
+                            parameter: Some(WifiStationConfigParameter::SSID),
+                        })
+                        .await
+                        .unwrap();
+                    assert_eq!(resp.config_id, config_id);
+                    assert!(matches!(
+                        resp.parameter,
+                        WifiStationConfigR::SSID(ssid) if ssid == std::format!("net{}", config_id).as_str()
+                    ));
                 }
             }
         };
 
-        with_timeout(Duration::from_secs(15), result_fut).await?
+        let (_, _, config_ids) = block_on(embassy_futures::join::join3(
+            read_ssid(&control, 0),
+            read_ssid(&diagnostics, 1),
+            module,
+        ));
+
+        // Commands are sent in the order they were issued
+        assert_eq!(config_ids, [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn greeting_text_too_long() {
+        let ControlFixture { control, .. } = ControlFixture::new();
+
+        let text = "x".repeat(MAX_GREETING_TEXT_LEN + 1);
+        assert!(matches!(
+            block_on(control.set_greeting_text(Some(&text))),
+            Err(Error::BadLength)
+        ));
+    }
+
+    #[test]
+    fn join_enterprise_bad_length() {
+        let ControlFixture { control, .. } = ControlFixture::new();
+
+        let name = "x".repeat(33);
+        let auth = EnterpriseAuth::EAPTLS {
+            username: "device",
+            ca_cert: CaValidation::InsecureSkipCaValidation,
+            client_cert: EnterpriseCertificate::stored("cert"),
+            client_key: EnterpriseCertificate::stored(&name),
+        };
+        assert!(matches!(
+            block_on(control.join_enterprise("corp", auth)),
+            Err(Error::BadLength)
+        ));
+    }
+
+    #[cfg(feature = "provisional-at")]
+    #[test]
+    fn firmware_guard() {
+        let ControlFixture { ch, control, .. } = ControlFixture::new();
+
+        // Unknown until initialized, which passes the guard
+        assert_eq!(control.cached_version(), None);
+        assert!(control
+            .require_firmware(FirmwareVersion::new(8, 0, 0))
+            .is_ok());
+
+        ch.mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(5, 0, 0)));
+        assert_eq!(
+            control.cached_version(),
+            Some(FirmwareVersion::new(5, 0, 0))
+        );
+        assert!(matches!(
+            control.require_firmware(FirmwareVersion::new(8, 0, 0)),
+            Err(Error::UnsupportedFirmware)
+        ));
+        assert!(control
+            .require_firmware(FirmwareVersion::new(5, 0, 0))
+            .is_ok());
+    }
+
+    #[cfg(feature = "provisional-at")]
+    #[test]
+    fn wpa3_access_point() {
+        let ControlFixture {
+            ch,
+            control,
+            module,
+        } = ControlFixture::new();
+        let options = || ConnectionOptions::new("net").wpa3_personal("passphrase");
+
+        // Rejected without sending anything to the module
+        ch.mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(7, 0, 0)));
+        assert!(matches!(
+            block_on(control.start_ap(options(), HotspotOptions::new())),
+            Err(Error::UnsupportedFirmware)
+        ));
+        assert!(matches!(
+            block_on(control.peek_join_sta(options())),
+            Err(Error::UnsupportedFirmware)
+        ));
+        assert!(module.req_slot.try_receive().is_err());
+
+        ch.mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(8, 0, 0)));
+        let received = core::cell::RefCell::new(std::vec::Vec::new());
+        let resp = block_on(select(
+            control.start_ap(options(), HotspotOptions::new()),
+            module.serve(|req| {
+                received.borrow_mut().push(req.to_string());
+                b""
+            }),
+        ));
+        assert!(matches!(resp, Either::First(Ok(()))));
+
+        // PMF is required along with WPA3
+        let received = received.into_inner();
+        assert!(received.iter().any(|r| r == "AT+UWAPC=0,5,6,2\r\n"));
+        assert!(received.iter().any(|r| r == "AT+UWAPC=0,14,2\r\n"));
+    }
+
+    #[test]
+    fn local_address() {
+        let ControlFixture {
+            ch,
+            control,
+            module,
+        } = ControlFixture::initialized();
+        ch.set_wifi_interface_id(0);
+        ch.update_ethernet_with(|eth| eth.interface_id = Some(1));
+
+        let module = module.serve(|req| match req {
+            "AT+UMLA=2\r\n" => b"+UMLA:D4CA6EFD6CC0",
+            "AT+UMLA=3\r\n" => b"+UMLA:D4CA6EFD6CC1",
+            "AT+UMLA=4\r\n" => b"+UMLA:D4CA6EFD6CC2",
+            "AT+UNSTAT=0,101\r\n" => b"+UNSTAT:0,101,192.168.0.10",
+            "AT+UNSTAT=0,201\r\n" => b"+UNSTAT:0,201,FE80:0000:0000:0000:D6CA:6EFF:FEFD:6CC0",
+            // Ethernet has no address yet
+            "AT+UNSTAT=1,101\r\n" => b"+UNSTAT:1,101,0.0.0.0",
+            "AT+UNSTAT=1,201\r\n" => b"+UNSTAT:1,201,0:0:0:0:0:0:0:0",
+            req => panic!("unexpected request {:?}", req),
+        });
+
+        let test = async {
+            let wifi = control.get_local_address(InterfaceID::WiFi).await.unwrap();
+            assert_eq!(wifi.mac, [0xd4, 0xca, 0x6e, 0xfd, 0x6c, 0xc0]);
+            assert_eq!(wifi.local_ip, Some(Ipv4Addr::new(192, 168, 0, 10)));
+            assert_eq!(
+                wifi.local_ip6,
+                Some(Ipv6Addr::new(
+                    0xfe80, 0, 0, 0, 0xd6ca, 0x6eff, 0xfefd, 0x6cc0
+                ))
+            );
+
+            let ethernet = control
+                .get_local_address(InterfaceID::Ethernet)
+                .await
+                .unwrap();
+            assert_eq!(ethernet.mac, [0xd4, 0xca, 0x6e, 0xfd, 0x6c, 0xc1]);
+            assert_eq!(ethernet.local_ip, None);
+            assert_eq!(ethernet.local_ip6, None);
+
+            // Only the MAC address is known for the access point
+            let ap = control
+                .get_local_address(InterfaceID::WiFiAP)
+                .await
+                .unwrap();
+            assert_eq!(ap.mac, [0xd4, 0xca, 0x6e, 0xfd, 0x6c, 0xc2]);
+            assert_eq!(ap.local_ip, None);
+        };
+
+        assert!(matches!(block_on(select(test, module)), Either::First(())));
+    }
+
+    #[test]
+    fn persist() {
+        let ControlFixture {
+            control, module, ..
+        } = ControlFixture::initialized();
+
+        let module = module.serve(|req| match req {
+            "AT+UMRS?\r\n" => b"+UMRS:115200,2,8,1,1,1",
+            "AT&W0\r\n" | "AT+UWSCA=1,0\r\n" | "AT+UWSCA=1,1\r\n" => b"",
+            req => panic!("unexpected request {:?}", req),
+        });
+
+        let test = async {
+            assert_eq!(control.nvm_stores(), 0);
+            control.persist().await.unwrap();
+            assert_eq!(control.nvm_stores(), 1);
+
+            // The module already runs at the requested rate
+            control.change_baud_rate(BaudRate::B115200).await.unwrap();
+            assert_eq!(control.nvm_stores(), 1);
+
+            // Clearing a profile stores it
+            control.clear_profile(1, false).await.unwrap();
+            assert_eq!(control.nvm_stores(), 2);
+        };
+
+        assert!(matches!(block_on(select(test, module)), Either::First(())));
+    }
+
+    #[test]
+    fn scan_on_ssid() {
+        let ControlFixture {
+            control, module, ..
+        } = ControlFixture::initialized();
+
+        let (req_slot, res_slot) = (module.req_slot, module.res_slot);
+        let module = async {
+            loop {
+                let req = req_slot.receive().await;
+                let resp: &[u8] = match &req[..] {
+                    b"AT+UWSCAN=\"home\"\r\n" => {
+                        b"+UWSCAN:D4CA6E123456,1,\"homeoffice\",1,-40,18,8,8\r\n\
+                          +UWSCAN:D4CA6E654321,1,\"home\",6,-52,18,8,8\r\n\
+                          +UWSCAN:D4CA6EABCDEF,1,\"home\",11,-60,18,8,8"
+                    }
+                    // The scan takes longer than the caller is willing to wait
+                    b"AT+UWSCAN=\"away\"\r\n" => {
+                        Timer::after(Duration::from_millis(200)).await;
+                        b"+UWSCAN:D4CA6E0A0A0A,1,\"away\",1,-70,18,8,8"
+                    }
+                    req => panic!("unexpected request {:?}", req),
+                };
+                res_slot.signal_response(Ok(resp)).unwrap();
+            }
+        };
+
+        let test = async {
+            let network = control
+                .scan_on_ssid("home", Duration::from_secs(1))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(network.ssid, "home");
+            assert_eq!(network.channel, 6);
+
+            assert!(control
+                .scan_on_ssid("away", Duration::from_millis(100))
+                .await
+                .unwrap()
+                .is_none());
+
+            // The late response is dropped, rather than taken for that of the
+            // next scan
+            let network = control
+                .scan_on_ssid("home", Duration::from_secs(1))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(network.channel, 6);
+        };
+
+        assert!(matches!(block_on(select(test, module)), Either::First(())));
+    }
+
+    #[test]
+    fn firmware_updater_holds_command_lock() {
+        let ControlFixture {
+            control, module, ..
+        } = ControlFixture::initialized();
+
+        let (req_slot, res_slot, fw_rx) = (module.req_slot, module.res_slot, module.fw_rx);
+        let module = async {
+            let req = req_slot.receive().await;
+            assert!(req.starts_with(b"AT+UFWUPD="), "{:?}", req);
+            res_slot.signal_response(Ok(b"+UFWUPD:CCC")).unwrap();
+            // The boot loader requests the transfer
+            fw_rx
+                .write_all(&[crate::command::firmware_update::xmodem::CRC_REQUEST])
+                .await;
+
+            let req = req_slot.receive().await;
+            panic!("unexpected request {:?}", req);
+        };
+
+        let test = async {
+            let updater = control
+                .start_firmware_update(SoftwareUpdateBaudRate::B115200)
+                .await
+                .unwrap();
+
+            // Other commands wait until the update is over
+            assert!(matches!(
+                select(
+                    control.get_version(),
+                    Timer::after(Duration::from_millis(50))
+                )
+                .await,
+                Either::Second(())
+            ));
+            drop(updater);
+        };
+
+        assert!(matches!(block_on(select(test, module)), Either::First(())));
+    }
+
+    #[test]
+    fn bridge_configuration() {
+        let ControlFixture {
+            ch,
+            control,
+            module,
+        } = ControlFixture::initialized();
+
+        let received = core::cell::RefCell::new(std::vec::Vec::new());
+        let take = || core::mem::take(&mut *received.borrow_mut());
+        let module = module.serve(|req| {
+            received.borrow_mut().push(req.to_string());
+            // The access point is up
+            if req.starts_with("AT+UWAPSTAT") {
+                b"+UWAPSTAT:3,1"
+            } else {
+                b""
+            }
+        });
+
+        let interfaces = [BridgeInterface::WifiAccessPoint, BridgeInterface::Ethernet];
+        let profile = BridgeProfile::new(Vec::from_slice(&interfaces).unwrap());
+        let single = BridgeProfile::new(Vec::from_slice(&[BridgeInterface::Ethernet; 2]).unwrap());
+
+        let test = async {
+            // Rejected without sending anything to the module
+            assert!(matches!(
+                control.configure_bridge(BridgeConfigId::Id1, &single).await,
+                Err(Error::Bridge(BridgeError::TooFewInterfaces))
+            ));
+            assert!(matches!(
+                control
+                    .configure_bridge(BridgeConfigId::Id1, &profile.clone().dhcp(true))
+                    .await,
+                Err(Error::Bridge(BridgeError::DhcpWithoutAddress))
+            ));
+            assert!(take().is_empty());
+
+            assert!(matches!(
+                control
+                    .configure_bridge(BridgeConfigId::Id1, &profile)
+                    .await,
+                Err(Error::Bridge(BridgeError::InterfaceInactive(
+                    BridgeInterface::Ethernet
+                )))
+            ));
+            take();
+
+            ch.update_ethernet_with(|eth| {
+                eth.interface_id = Some(1);
+                eth.link_up = true;
+            });
+            control
+                .configure_bridge(BridgeConfigId::Id1, &profile)
+                .await
+                .unwrap();
+            assert_eq!(
+                take()[1..],
+                [
+                    "AT+UBRGC=0,1,2,3\r\n",
+                    "AT+UBRGC=0,2\r\n",
+                    "AT+UBRGC=0,106,0\r\n"
+                ]
+            );
+
+            control
+                .bridge_action(BridgeConfigId::Id1, BridgeAction::Activate)
+                .await
+                .unwrap();
+            take();
+            assert!(matches!(
+                control
+                    .configure_bridge(BridgeConfigId::Id1, &profile)
+                    .await,
+                Err(Error::Bridge(BridgeError::Active))
+            ));
+            assert!(take().is_empty());
+
+            // The other bridge is not active
+            control
+                .configure_bridge(BridgeConfigId::Id2, &profile)
+                .await
+                .unwrap();
+            take();
+
+            control
+                .reconfigure_bridge(BridgeConfigId::Id1, &profile)
+                .await
+                .unwrap();
+            let received = take();
+            assert_eq!(received.first().unwrap(), "AT+UBRGCA=0,4\r\n");
+            assert_eq!(received.last().unwrap(), "AT+UBRGCA=0,3\r\n");
+        };
+
+        assert!(matches!(block_on(select(test, module)), Either::First(())));
     }
 
-    // FIXME: This could probably be improved
-    // #[cfg(feature = "internal-network-stack")]
-    // pub async fn import_credentials(
-    //     &mut self,
-    //     data_type: SecurityDataType,
-    //     name: &str,
-    //     data: &[u8],
-    //     md5_sum: Option<&str>,
-    // ) -> Result<(), atat::Error> {
-    //     assert!(name.len() < 16);
+    #[test]
+    fn channel_list_validation() {
+        assert!(matches!(
+            check_channel_list(&[1; 11], None),
+            Err(Error::TooManyEntries)
+        ));
+
+        let Err(Error::ChannelList(error)) = check_channel_list(&[12, 200], Some(&WifiRegion::FCC))
+        else {
+            panic!("channel 200 must be rejected");
+        };
+        assert_eq!(error.unsupported.last(), Some(&200));
+        if crate::module_caps::is_supported_channel(12) {
+            assert_eq!(error.restricted, [12]);
+            assert_eq!(error.unsupported, [200]);
+        } else {
+            assert!(error.restricted.is_empty());
+            assert_eq!(error.unsupported, [12, 200]);
+        }
 
-    //     info!("Importing {:?} bytes as {:?}", data.len(), name);
+        if crate::module_caps::is_supported_channel(12) {
+            assert_eq!(
+                check_channel_list(&[1, 12], Some(&WifiRegion::ETSI)).unwrap(),
+                [1, 12]
+            );
+            assert!(check_channel_list(&[12], None).is_ok());
+        }
+    }
 
-    //     (&self.at_client)
-    //         .send_retry(&PrepareSecurityDataImport {
-    //             data_type,
-    //             data_size: data.len(),
-    //             internal_name: name,
-    //             password: None,
-    //         })
-    //         .await?;
+    #[test]
+    fn ping_stats() {
+        let mut stats = PingStats::new(4);
+        for rtt in [20, -1, 10, 30] {
+            stats.record(rtt);
+        }
 
-    //     let import_data = self
-    //         .at_client
-    //         .send_retry(&SendSecurityDataImport {
-    //             data: atat::serde_bytes::Bytes::new(data),
-    //         })
-    //         .await?;
+        assert_eq!(stats.received, 3);
+        assert_eq!(stats.min_rtt, Some(10));
+        assert_eq!(stats.max_rtt, Some(30));
+        assert_eq!(stats.avg_rtt, Some(20));
+        assert_eq!(stats.packet_loss_percent, 25);
+    }
 
-    //     if let Some(hash) = md5_sum {
-    //         assert_eq!(import_data.md5_string.as_str(), hash);
-    //     }
+    #[test]
+    fn ping_stats_all_lost() {
+        let mut stats = PingStats::new(2);
+        stats.record(-1);
+        stats.record(-1);
 
-    //     Ok(())
-    // }
+        assert_eq!(stats.received, 0);
+        assert_eq!(stats.avg_rtt, None);
+        assert_eq!(stats.packet_loss_percent, 100);
+    }
 }
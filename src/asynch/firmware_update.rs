@@ -0,0 +1,180 @@
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Sender, pipe::Pipe};
+use embassy_time::{with_timeout, Duration, Instant};
+use heapless::Vec;
+
+use crate::command::firmware_update::xmodem::{self, BLOCK_SIZE};
+use crate::command::general::types::FirmwareVersion;
+use crate::error::{Error, FirmwareUpdateError};
+
+use super::command_lock::CommandGuard;
+use super::control::Control;
+use super::runner::{DEFAULT_CMD_BUF_SIZE, FW_RX_SIZE};
+use super::state;
+
+/// Number of attempts at transferring a single block.
+const MAX_RETRIES: usize = 10;
+/// Time allowed for the boot loader to request the transfer.
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time allowed for the boot loader to reply to a block.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time allowed for the module to boot the new image.
+const BOOT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Serial firmware update in progress, started by
+/// [`Control::start_firmware_update`].
+///
+/// Image data is fed through [`write_chunk`](Self::write_chunk), in chunks of
+/// any size, and the update is completed by [`finalize`](Self::finalize).
+/// Dropping the updater before that aborts the update.
+///
+/// The updater holds the command lock while it exists, so no other command
+/// is sent in the middle of the transfer.
+pub struct FirmwareUpdater<
+    'c,
+    'a,
+    const INGRESS_BUF_SIZE: usize,
+    const URC_CAPACITY: usize,
+    const CMD_BUF_SIZE: usize = DEFAULT_CMD_BUF_SIZE,
+> {
+    control: &'c Control<'a, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>,
+    state_ch: state::Runner<'a>,
+    req_sender: Sender<'a, NoopRawMutex, Vec<u8, CMD_BUF_SIZE>, 1>,
+    fw_rx: &'a Pipe<NoopRawMutex, FW_RX_SIZE>,
+    /// Released once the transport is handed back to the runner.
+    cmd_guard: Option<CommandGuard<'a>>,
+    block: u8,
+    buf: Vec<u8, BLOCK_SIZE>,
+}
+
+impl<
+        'c,
+        'a,
+        const INGRESS_BUF_SIZE: usize,
+        const URC_CAPACITY: usize,
+        const CMD_BUF_SIZE: usize,
+    > FirmwareUpdater<'c, 'a, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>
+{
+    pub(crate) fn new(
+        control: &'c Control<'a, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>,
+        state_ch: state::Runner<'a>,
+        req_sender: Sender<'a, NoopRawMutex, Vec<u8, CMD_BUF_SIZE>, 1>,
+        fw_rx: &'a Pipe<NoopRawMutex, FW_RX_SIZE>,
+        cmd_guard: CommandGuard<'a>,
+    ) -> Self {
+        Self {
+            control,
+            state_ch,
+            req_sender,
+            fw_rx,
+            cmd_guard: Some(cmd_guard),
+            block: 1,
+            buf: Vec::new(),
+        }
+    }
+
+    async fn send_raw(&self, data: &[u8]) -> Result<(), Error> {
+        let msg = Vec::try_from(data).map_err(|_| Error::Overflow)?;
+        with_timeout(Duration::from_secs(1), self.req_sender.send(msg)).await?;
+        Ok(())
+    }
+
+    async fn read_byte(&self, timeout: Duration) -> Result<u8, Error> {
+        let mut byte = [0u8; 1];
+        with_timeout(timeout, self.fw_rx.read(&mut byte)).await?;
+        Ok(byte[0])
+    }
+
+    /// Wait for the boot loader to request a CRC transfer.
+    pub(crate) async fn wait_for_receiver(&mut self) -> Result<(), Error> {
+        let deadline = Instant::now() + START_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.read_byte(remaining).await? {
+                xmodem::CRC_REQUEST => return Ok(()),
+                xmodem::CAN => return Err(FirmwareUpdateError::Cancelled.into()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Send `packet` until it is acknowledged by the boot loader.
+    async fn send_packet(&mut self, packet: &[u8]) -> Result<(), Error> {
+        for _ in 0..MAX_RETRIES {
+            self.send_raw(packet).await?;
+
+            loop {
+                match self.read_byte(REPLY_TIMEOUT).await {
+                    Ok(xmodem::ACK) => return Ok(()),
+                    Ok(xmodem::CAN) => return Err(FirmwareUpdateError::Cancelled.into()),
+                    Ok(xmodem::NAK) | Err(Error::Timeout) => break,
+                    // Left-over transfer requests
+                    Ok(_) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Err(FirmwareUpdateError::TooManyRetries.into())
+    }
+
+    async fn send_block(&mut self) -> Result<(), Error> {
+        let packet = xmodem::packet(self.block, &self.buf);
+        self.send_packet(&packet).await?;
+        self.block = self.block.wrapping_add(1);
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Write the next part of the firmware image.
+    pub async fn write_chunk(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        while !data.is_empty() {
+            let n = (BLOCK_SIZE - self.buf.len()).min(data.len());
+            // Cannot fail, as `n` is bounded by the remaining capacity
+            let _ = self.buf.extend_from_slice(&data[..n]);
+            data = &data[n..];
+
+            if self.buf.is_full() {
+                self.send_block().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Complete the transfer, reboot into the new image and verify that the
+    /// module reports `expected` as its software version.
+    pub async fn finalize(mut self, expected: &FirmwareVersion) -> Result<(), Error> {
+        if !self.buf.is_empty() {
+            self.send_block().await?;
+        }
+        self.send_packet(&[xmodem::EOT]).await?;
+
+        // Let the runner reset the module and boot the new image
+        self.state_ch.set_firmware_update(None);
+        self.cmd_guard = None;
+
+        let version = with_timeout(BOOT_TIMEOUT, self.control.get_version()).await??;
+        if &version != expected {
+            warn!(
+                "Firmware update resulted in unexpected version: {:?}",
+                version
+            );
+            return Err(FirmwareUpdateError::VersionMismatch.into());
+        }
+
+        Ok(())
+    }
+
+    /// Cancel the transfer, leaving the previous image in place.
+    pub async fn abort(self) {
+        let _ = self.send_raw(&[xmodem::CAN; 3]).await;
+    }
+}
+
+impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize, const CMD_BUF_SIZE: usize> Drop
+    for FirmwareUpdater<'_, '_, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>
+{
+    fn drop(&mut self) {
+        // Hand the transport back to the runner, which reinitializes the module
+        self.state_ch.set_firmware_update(None);
+    }
+}
@@ -1,17 +1,28 @@
 #[cfg(feature = "ppp")]
 mod at_udp_socket;
+mod command_lock;
 pub mod control;
+mod firmware_update;
 pub mod network;
+#[cfg(feature = "ppp")]
+mod ppp;
 mod resources;
 pub mod runner;
+pub mod sntp;
+#[cfg(test)]
+pub(crate) mod test_support;
+#[cfg(feature = "test-utils")]
+pub mod throughput;
 #[cfg(feature = "internal-network-stack")]
 pub mod ublox_stack;
 
 pub(crate) mod state;
 
+pub use control::WatchdogConfig;
+pub use firmware_update::FirmwareUpdater;
 pub use resources::Resources;
 pub use runner::Runner;
-pub use state::LinkState;
+pub use state::{AtTransportStatus, InitStatus, IpFamily, LinkState, ModuleInfo};
 
 #[cfg(feature = "internal-network-stack")]
 pub type UbloxUrc = crate::command::edm::urc::EdmEvent;
@@ -19,12 +30,10 @@ pub type UbloxUrc = crate::command::edm::urc::EdmEvent;
 #[cfg(not(feature = "internal-network-stack"))]
 pub type UbloxUrc = crate::command::Urc;
 
-#[cfg(feature = "ppp")]
 pub struct OnDrop<F: FnOnce()> {
     f: core::mem::MaybeUninit<F>,
 }
 
-#[cfg(feature = "ppp")]
 impl<F: FnOnce()> OnDrop<F> {
     fn new(f: F) -> Self {
         Self {
@@ -32,13 +41,11 @@ impl<F: FnOnce()> OnDrop<F> {
         }
     }
 
-    #[allow(dead_code)]
     fn defuse(self) {
         core::mem::forget(self)
     }
 }
 
-#[cfg(feature = "ppp")]
 impl<F: FnOnce()> Drop for OnDrop<F> {
     fn drop(&mut self) {
         unsafe { self.f.as_ptr().read()() }
@@ -14,8 +14,9 @@ use crate::{
         },
         system::{RebootDCE, StoreCurrentConfig},
         wifi::{
-            types::{AccessPointStatus, DisconnectReason},
+            types::{AccessPointStatus, AccessPointStatusId, DisconnectReason, StatusId},
             urc::{WifiLinkConnected, WifiLinkDisconnected},
+            GetWifiStatus, WifiAPStatus,
         },
         Urc,
     },
@@ -25,7 +26,11 @@ use crate::{
     WifiConfig,
 };
 
-use super::{runner::URC_SUBSCRIBERS, state, UbloxUrc};
+use super::{control::EthernetLinkStatus, runner::URC_SUBSCRIBERS, state, UbloxUrc};
+
+/// Number of times switching to extended data mode is attempted.
+#[cfg(feature = "internal-network-stack")]
+const EDM_SWITCH_ATTEMPTS: usize = 3;
 
 pub(crate) struct NetDevice<'a, 'b, C, A, const URC_CAPACITY: usize> {
     ch: &'b state::Runner<'a>,
@@ -89,15 +94,33 @@ where
                 channel,
             }) => {
                 info!("wifi link connected");
+                // Measured before publishing the link, so it is known to
+                // anyone woken by the link state change
+                let rssi = match self
+                    .at_client
+                    .send_retry(&GetWifiStatus {
+                        status_id: StatusId::Rssi,
+                    })
+                    .await
+                    .map(|status| status.status_id.into_rssi())
+                {
+                    Ok(Ok(rssi)) => rssi,
+                    Ok(Err(e)) | Err(e) => {
+                        warn!("Failed to read RSSI: {:?}", e);
+                        None
+                    }
+                };
                 self.ch.update_connection_with(|con| {
                     con.wifi_state = WiFiState::Connected;
                     con.network
                         .replace(WifiNetwork::new_station(bssid, channel));
+                    con.rssi = rssi;
                 })
             }
             Urc::WifiLinkDisconnected(WifiLinkDisconnected { reason, .. }) => {
                 info!("Wifi link disconnected");
                 self.ch.update_connection_with(|con| {
+                    con.rssi = None;
                     con.wifi_state = match reason {
                         DisconnectReason::NetworkDisabled => {
                             con.network.take();
@@ -114,10 +137,34 @@ where
                     }
                 })
             }
-            Urc::WifiAPUp(_) => self.ch.update_connection_with(|con| {
-                con.wifi_state = WiFiState::Connected;
-                con.network.replace(WifiNetwork::new_ap());
-            }),
+            Urc::WifiAPUp(_) => {
+                // The module may have moved off the configured channel
+                let channel = match self
+                    .at_client
+                    .send_retry(&WifiAPStatus {
+                        ap_status_id: AccessPointStatusId::Channel,
+                    })
+                    .await
+                    .map(|status| status.ap_status_id.into_channel())
+                {
+                    Ok(Some(channel)) => channel,
+                    Ok(None) => {
+                        warn!("Invalid access point channel");
+                        0
+                    }
+                    Err(e) => {
+                        warn!("Failed to read access point channel: {:?}", e);
+                        0
+                    }
+                };
+                info!("Access point up on channel {}", channel);
+                self.ch.update_connection_with(|con| {
+                    con.wifi_state = WiFiState::Connected;
+                    let mut network = WifiNetwork::new_ap();
+                    network.channel = channel;
+                    con.network.replace(network);
+                })
+            }
             Urc::WifiAPDown(_) => self.ch.update_connection_with(|con| {
                 con.network.take();
                 con.wifi_state = WiFiState::Inactive;
@@ -126,8 +173,15 @@ where
             Urc::WifiAPStationDisconnected(_) => {
                 warn!("Not yet implemented [WifiAPStationDisconnected]")
             }
-            Urc::EthernetLinkUp(_) => warn!("Not yet implemented [EthernetLinkUp]"),
-            Urc::EthernetLinkDown(_) => warn!("Not yet implemented [EthernetLinkDown]"),
+            Urc::EthernetLinkUp(_) => {
+                info!("Ethernet link up");
+                let status = EthernetLinkStatus::query(&mut self.at_client, true).await?;
+                self.ch.set_ethernet_link_status(status);
+            }
+            Urc::EthernetLinkDown(_) => {
+                info!("Ethernet link down");
+                self.ch.set_ethernet_link_down();
+            }
             Urc::NetworkUp(NetworkUp { interface_id }) => {
                 if interface_id > 10 {
                     self.ap_status_callback().await?;
@@ -142,7 +196,14 @@ where
                     self.network_status_callback(interface_id).await?;
                 }
             }
-            Urc::NetworkError(_) => warn!("Not yet implemented [NetworkError]"),
+            Urc::NetworkError(err) => {
+                let reason = err.reason();
+                error!(
+                    "Network error on interface {}: {:?}",
+                    err.interface_id, reason
+                );
+                self.ch.set_network_error(reason);
+            }
             _ => {}
         }
 
@@ -159,7 +220,11 @@ where
         debug!("Entered network_status_callback");
         let NetworkStatusResponse {
             status:
-                NetworkStatus::InterfaceType(InterfaceType::WifiStation | InterfaceType::Unknown),
+                NetworkStatus::InterfaceType(
+                    interface_type @ (InterfaceType::WifiStation
+                    | InterfaceType::Unknown
+                    | InterfaceType::Ethernet),
+                ),
             ..
         } = self
             .at_client
@@ -247,6 +312,21 @@ where
         debug!("Network status callback ipv6: {:?}", ipv6_link_local_up);
 
         // Use `ipv4_addr` & `ipv6_addr` to determine link state
+        if interface_type == InterfaceType::Ethernet {
+            self.ch.update_ethernet_with(|eth| {
+                eth.interface_id = Some(interface_id);
+                eth.ipv6_link_local_up = ipv6_link_local_up;
+                eth.ipv4_up = ipv4_up;
+
+                #[cfg(feature = "ipv6")]
+                {
+                    eth.ipv6_up = ipv6_up
+                }
+            });
+            return Ok(());
+        }
+
+        self.ch.set_wifi_interface_id(interface_id);
         self.ch.update_connection_with(|con| {
             con.ipv6_link_local_up = ipv6_link_local_up;
             con.ipv4_up = ipv4_up;
@@ -335,12 +415,19 @@ where
         warn!("Soft resetting Ublox Short Range");
         if store {
             self.at_client.send_retry(&StoreCurrentConfig).await?;
+            self.ch.record_nvm_store();
         }
 
         self.at_client.send_retry(&RebootDCE).await?;
 
         self.ch.mark_uninitialized();
 
+        self.wait_rebooted().await
+    }
+
+    /// Wait for the module to start up again after a reboot, and bring it
+    /// back into extended data mode.
+    pub async fn wait_rebooted(&mut self) -> Result<(), Error> {
         self.wait_startup(Duration::from_secs(5)).await?;
 
         info!("Module started again");
@@ -350,33 +437,35 @@ where
         Ok(())
     }
 
+    /// Switch the module to extended data mode, waiting up to `timeout` for
+    /// each attempt to be confirmed by the EDM startup event.
     #[cfg(feature = "internal-network-stack")]
     pub async fn enter_edm(&mut self, timeout: Duration) -> Result<(), Error> {
+        use crate::command::edm::urc::EdmEvent;
+
         info!("Entering EDM mode");
 
-        // Switch to EDM on Init. If in EDM, fail and check with autosense
-        let fut = async {
-            loop {
-                // Ignore AT results until we are successful in EDM mode
-                if let Ok(_) = self
-                    .at_client
-                    .send_retry(&crate::command::edm::SwitchToEdmCommand)
-                    .await
-                {
-                    // After executing the data mode command or the extended data
-                    // mode command, a delay of 50 ms is required before start of
-                    // data transmission.
-                    Timer::after(Duration::from_millis(50)).await;
-                    break;
-                }
-                Timer::after(Duration::from_millis(10)).await;
-            }
-        };
+        for attempt in 1..=EDM_SWITCH_ATTEMPTS {
+            // Ignore AT results, the module may already be in EDM mode
+            self.at_client
+                .send(&crate::command::edm::SwitchToEdmCommand)
+                .await
+                .ok();
 
-        with_timeout(timeout, fut)
-            .await
-            .map_err(|_| Error::Timeout)?;
+            let started = async {
+                while self.urc_subscription.next_message_pure().await != EdmEvent::StartUp {}
+            };
 
-        Ok(())
+            if with_timeout(timeout, started).await.is_ok() {
+                // After executing the data mode command or the extended data
+                // mode command, a delay of 50 ms is required before start of
+                // data transmission.
+                Timer::after(Duration::from_millis(50)).await;
+                return Ok(());
+            }
+            warn!("No EDM startup event, attempt {}", attempt);
+        }
+
+        Err(Error::EdmSwitchFailed)
     }
 }
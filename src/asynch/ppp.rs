@@ -0,0 +1,26 @@
+//! Glue between the module PPP link and `embassy-net`.
+
+use embassy_net::{ConfigV4, Ipv4Cidr, StaticConfigV4};
+use embassy_net_ppp::Ipv4Status;
+
+/// Convert the IPv4 status negotiated by PPP into an `embassy-net` stack
+/// configuration.
+///
+/// Returns `None` if PPP did not provide an IP address.
+pub(crate) fn config_v4(ipv4: Ipv4Status) -> Option<ConfigV4> {
+    let Some(address) = ipv4.address else {
+        warn!("PPP did not provide an IP address.");
+        return None;
+    };
+
+    let mut dns_servers = heapless::Vec::new();
+    for s in ipv4.dns_servers.iter().flatten() {
+        let _ = dns_servers.push(*s);
+    }
+
+    Some(ConfigV4::Static(StaticConfigV4 {
+        address: Ipv4Cidr::new(address, 0),
+        gateway: None,
+        dns_servers,
+    }))
+}
@@ -1,39 +1,64 @@
 use atat::{ResponseSlot, UrcChannel};
-use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel, pipe::Pipe};
 
 use super::{
-    runner::{MAX_CMD_LEN, URC_SUBSCRIBERS},
+    command_lock::CommandLock,
+    runner::{DEFAULT_CMD_BUF_SIZE, FW_RX_SIZE, MIN_CMD_BUF_SIZE, URC_SUBSCRIBERS},
     state, UbloxUrc,
 };
 
-pub struct Resources<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize> {
+/// Buffers shared between the [`Runner`](super::Runner) and its handles.
+///
+/// `CMD_BUF_SIZE` is the size of the buffer holding a single command sent to
+/// the module, and must be at least [`MIN_CMD_BUF_SIZE`]. It defaults to
+/// [`DEFAULT_CMD_BUF_SIZE`].
+pub struct Resources<
+    const INGRESS_BUF_SIZE: usize,
+    const URC_CAPACITY: usize,
+    const CMD_BUF_SIZE: usize = DEFAULT_CMD_BUF_SIZE,
+> {
     pub(crate) ch: state::State,
 
     pub(crate) res_slot: ResponseSlot<INGRESS_BUF_SIZE>,
-    pub(crate) req_slot: Channel<NoopRawMutex, heapless::Vec<u8, MAX_CMD_LEN>, 1>,
+    pub(crate) req_slot: Channel<NoopRawMutex, heapless::Vec<u8, CMD_BUF_SIZE>, 1>,
+    pub(crate) cmd_lock: CommandLock,
     pub(crate) urc_channel: UrcChannel<UbloxUrc, URC_CAPACITY, { URC_SUBSCRIBERS }>,
     pub(crate) ingress_buf: [u8; INGRESS_BUF_SIZE],
+    pub(crate) fw_rx: Pipe<NoopRawMutex, FW_RX_SIZE>,
+    #[cfg(feature = "internal-network-stack")]
+    pub(crate) direct_rx: super::ublox_stack::DirectRx,
 }
 
-impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize> Default
-    for Resources<INGRESS_BUF_SIZE, URC_CAPACITY>
+impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize, const CMD_BUF_SIZE: usize> Default
+    for Resources<INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>
 {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
-    Resources<INGRESS_BUF_SIZE, URC_CAPACITY>
+impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize, const CMD_BUF_SIZE: usize>
+    Resources<INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>
 {
     pub fn new() -> Self {
+        const {
+            assert!(
+                CMD_BUF_SIZE >= MIN_CMD_BUF_SIZE,
+                "CMD_BUF_SIZE must be at least MIN_CMD_BUF_SIZE"
+            )
+        };
+
         Self {
             ch: state::State::new(),
 
             res_slot: ResponseSlot::new(),
             req_slot: Channel::new(),
+            cmd_lock: CommandLock::new(),
             urc_channel: UrcChannel::new(),
             ingress_buf: [0; INGRESS_BUF_SIZE],
+            fw_rx: Pipe::new(),
+            #[cfg(feature = "internal-network-stack")]
+            direct_rx: super::ublox_stack::DirectRx::new(),
         }
     }
 }
@@ -1,11 +1,24 @@
-use super::{control::Control, network::NetDevice, state, Resources, UbloxUrc};
+use core::cell::Cell;
+
+use super::{control::Control, network::NetDevice, state, state::ModuleInfo, Resources, UbloxUrc};
 use crate::{
-    asynch::control::ProxyClient,
+    asynch::{command_lock::CommandLock, control::ProxyClient},
     command::{
-        general::SoftwareVersion,
+        general::{
+            responses::{
+                IdentificationInformationMCUIDResponse, ModelIdentificationResponse,
+                SerialNumberResponse, SoftwareVersionResponse,
+            },
+            IdentificationInformationMCUID, ModelIdentification, SerialNumber, SoftwareVersion,
+        },
+        network::{
+            responses::BridgeConfigurationResponse,
+            types::{BridgeConfig, BridgeConfigId, BridgeConfigParameter},
+            GetBridgeConfiguration,
+        },
         system::{
             types::{BaudRate, ChangeAfterConfirm, EchoOn, FlowControl, Parity, StopBits},
-            SetEcho, SetRS232Settings,
+            GetRS232Settings, SetEcho, SetRS232Settings,
         },
         wifi::{
             types::{PowerSaveMode, WifiConfig as WifiConfigParam},
@@ -25,13 +38,13 @@ use crate::command::data_mode::{self, ChangeMode};
 
 #[cfg(feature = "ppp")]
 use atat::asynch::SimpleClient;
-use atat::{asynch::AtatClient as _, AtatIngress as _, UrcChannel};
-use embassy_futures::select::Either;
-use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
+use atat::{asynch::AtatClient, AtatIngress as _, UrcChannel};
+use embassy_futures::select::{Either, Either4};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel, pipe::Pipe};
 #[cfg(feature = "ppp")]
 use embassy_time::Duration;
-use embassy_time::Timer;
-use embedded_io_async::{BufRead, Write};
+use embassy_time::{Instant, Timer};
+use embedded_io_async::{BufRead, ErrorType, Read, Write};
 
 #[cfg(feature = "ppp")]
 pub(crate) const URC_SUBSCRIBERS: usize = 2;
@@ -43,27 +56,59 @@ pub(crate) const URC_SUBSCRIBERS: usize = 3;
 #[cfg(feature = "internal-network-stack")]
 type Digester = crate::command::custom_digest::EdmDigester;
 
-pub(crate) const MAX_CMD_LEN: usize = 256;
+#[cfg(not(feature = "trace-at"))]
+type TracedDigester = Digester;
+#[cfg(feature = "trace-at")]
+type TracedDigester = crate::trace::TraceDigester<Digester>;
 
-async fn at_bridge<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>(
+#[cfg(feature = "ppp")]
+type IngressDigester<'a> = TracedDigester;
+#[cfg(feature = "internal-network-stack")]
+type IngressDigester<'a> = super::ublox_stack::DirectRxDigester<'a, TracedDigester>;
+
+/// Smallest supported size of the buffer holding a single command sent to the
+/// module. Every AT command issued by the driver fits in it.
+pub const MIN_CMD_BUF_SIZE: usize = 256;
+/// Default size of the buffer holding a single command sent to the module.
+///
+/// With the internal network stack this fits a full default egress buffer of
+/// socket data, wrapped in an EDM data packet.
+#[cfg(feature = "ppp")]
+pub const DEFAULT_CMD_BUF_SIZE: usize = MIN_CMD_BUF_SIZE;
+#[cfg(feature = "internal-network-stack")]
+pub const DEFAULT_CMD_BUF_SIZE: usize =
+    super::ublox_stack::DEFAULT_EGRESS_BUF_SIZE + crate::command::edm::DATA_COMMAND_OVERHEAD;
+/// Buffered boot loader replies during a firmware update.
+pub(crate) const FW_RX_SIZE: usize = 16;
+
+pub(super) async fn at_bridge<
+    'a,
+    const INGRESS_BUF_SIZE: usize,
+    const URC_CAPACITY: usize,
+    const CMD_BUF_SIZE: usize,
+>(
     transport: &mut impl Transport,
-    req_slot: &Channel<NoopRawMutex, heapless::Vec<u8, MAX_CMD_LEN>, 1>,
+    req_slot: &Channel<NoopRawMutex, heapless::Vec<u8, CMD_BUF_SIZE>, 1>,
     ingress: &mut atat::Ingress<
         'a,
-        Digester,
+        IngressDigester<'a>,
         UbloxUrc,
         INGRESS_BUF_SIZE,
         URC_CAPACITY,
         { URC_SUBSCRIBERS },
     >,
+    last_rx: &Cell<Instant>,
 ) -> ! {
     ingress.clear();
 
     let (mut tx, rx) = transport.split_ref();
+    let rx = ActivityReader { inner: rx, last_rx };
 
     let tx_fut = async {
         loop {
             let msg = req_slot.receive().await;
+            #[cfg(feature = "trace-at")]
+            crate::trace::emit(crate::trace::TraceKind::Tx, &msg);
             let _ = tx.write_all(&msg).await;
         }
     };
@@ -73,10 +118,105 @@ async fn at_bridge<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
     unreachable!()
 }
 
+/// Reader recording when data was last received from the module.
+struct ActivityReader<'c, R> {
+    inner: R,
+    last_rx: &'c Cell<Instant>,
+}
+
+impl<R: ErrorType> ErrorType for ActivityReader<'_, R> {
+    type Error = R::Error;
+}
+
+impl<R: Read> Read for ActivityReader<'_, R> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = self.inner.read(buf).await?;
+        if n > 0 {
+            self.last_rx.set(Instant::now());
+        }
+        Ok(n)
+    }
+}
+
+/// Number of consecutive unanswered keep-alives after which the module is
+/// considered stuck.
+#[cfg(feature = "internal-network-stack")]
+const KEEP_ALIVE_ATTEMPTS: u32 = 2;
+
+/// Supervise the EDM link, returning once the module is considered stuck.
+///
+/// Whenever nothing has been received from the module for `timeout`, an `AT`
+/// keep-alive is sent, and the module is considered stuck once
+/// [`KEEP_ALIVE_ATTEMPTS`] keep-alives in a row go unanswered.
+#[cfg(feature = "internal-network-stack")]
+async fn supervise<const INGRESS_BUF_SIZE: usize, const CMD_BUF_SIZE: usize>(
+    at_client: &ProxyClient<'_, INGRESS_BUF_SIZE, CMD_BUF_SIZE>,
+    last_rx: &Cell<Instant>,
+    timeout: embassy_time::Duration,
+) {
+    let mut failures = 0;
+    loop {
+        Timer::at(last_rx.get() + timeout).await;
+        if last_rx.get().elapsed() < timeout {
+            continue;
+        }
+
+        debug!(
+            "No data from module in {} ms, sending keep-alive",
+            timeout.as_millis()
+        );
+        let mut client = at_client;
+        match client.send(&crate::command::edm::EdmAtCmdWrapper(AT)).await {
+            Ok(_) => failures = 0,
+            Err(e) => {
+                failures += 1;
+                warn!(
+                    "Keep-alive failed ({}/{}): {:?}",
+                    failures, KEEP_ALIVE_ATTEMPTS, e
+                );
+                if failures >= KEEP_ALIVE_ATTEMPTS {
+                    return;
+                }
+                // Do not wait another `timeout` before the next keep-alive
+                last_rx.set(Instant::now() - timeout);
+            }
+        }
+    }
+}
+
+/// The command bringing the RS232 settings of the module in line with
+/// `baud_rate` and `flow_control`, or `None` if they match already.
+async fn rs232_update<A: AtatClient>(
+    mut at_client: A,
+    baud_rate: BaudRate,
+    flow_control: FlowControl,
+) -> Result<Option<SetRS232Settings>, Error> {
+    let settings = at_client.send_retry(&GetRS232Settings).await?;
+    if settings.baud_rate == baud_rate as u32 && settings.flow_control == flow_control {
+        return Ok(None);
+    }
+
+    Ok(Some(SetRS232Settings {
+        baud_rate,
+        flow_control,
+        data_bits: 8,
+        stop_bits: StopBits::One,
+        parity: Parity::None,
+        change_after_confirm: ChangeAfterConfirm::ChangeAfterOK,
+    }))
+}
+
 /// Background runner for the Ublox Module.
 ///
 /// You must call `.run()` in a background task for the Ublox Module to operate.
-pub struct Runner<'a, T: Transport, C, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize> {
+pub struct Runner<
+    'a,
+    T: Transport,
+    C,
+    const INGRESS_BUF_SIZE: usize,
+    const URC_CAPACITY: usize,
+    const CMD_BUF_SIZE: usize = DEFAULT_CMD_BUF_SIZE,
+> {
     transport: T,
 
     ch: state::Runner<'a>,
@@ -84,30 +224,63 @@ pub struct Runner<'a, T: Transport, C, const INGRESS_BUF_SIZE: usize, const URC_
 
     pub urc_channel: &'a UrcChannel<UbloxUrc, URC_CAPACITY, { URC_SUBSCRIBERS }>,
 
-    pub ingress:
-        atat::Ingress<'a, Digester, UbloxUrc, INGRESS_BUF_SIZE, URC_CAPACITY, { URC_SUBSCRIBERS }>,
+    pub ingress: atat::Ingress<
+        'a,
+        IngressDigester<'a>,
+        UbloxUrc,
+        INGRESS_BUF_SIZE,
+        URC_CAPACITY,
+        { URC_SUBSCRIBERS },
+    >,
     pub res_slot: &'a atat::ResponseSlot<INGRESS_BUF_SIZE>,
-    pub req_slot: &'a Channel<NoopRawMutex, heapless::Vec<u8, MAX_CMD_LEN>, 1>,
+    pub req_slot: &'a Channel<NoopRawMutex, heapless::Vec<u8, CMD_BUF_SIZE>, 1>,
+    cmd_lock: &'a CommandLock,
+    fw_rx: &'a Pipe<NoopRawMutex, FW_RX_SIZE>,
+    #[cfg(feature = "internal-network-stack")]
+    direct_rx: &'a super::ublox_stack::DirectRx,
+    /// When data was last received from the module.
+    last_rx: Cell<Instant>,
+    /// Baud rate the module is configured for, [`WifiConfig::BAUD_RATE`]
+    /// until changed at runtime.
+    baud_rate: BaudRate,
 
     #[cfg(feature = "ppp")]
     ppp_runner: Option<embassy_net_ppp::Runner<'a>>,
 }
 
-impl<'a, T, C, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
-    Runner<'a, T, C, INGRESS_BUF_SIZE, URC_CAPACITY>
+impl<
+        'a,
+        T,
+        C,
+        const INGRESS_BUF_SIZE: usize,
+        const URC_CAPACITY: usize,
+        const CMD_BUF_SIZE: usize,
+    > Runner<'a, T, C, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>
 where
     T: Transport + BufRead,
     C: WifiConfig<'a> + 'a,
 {
     pub fn new(
         transport: T,
-        resources: &'a mut Resources<INGRESS_BUF_SIZE, URC_CAPACITY>,
+        resources: &'a mut Resources<INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>,
         config: C,
-    ) -> (Self, Control<'a, INGRESS_BUF_SIZE, URC_CAPACITY>) {
+    ) -> (
+        Self,
+        Control<'a, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>,
+    ) {
         let ch_runner = state::Runner::new(&mut resources.ch);
 
+        #[cfg(feature = "ppp")]
+        let digester = Digester::new();
+        #[cfg(feature = "internal-network-stack")]
+        let digester = Digester::with_capacity(INGRESS_BUF_SIZE);
+        #[cfg(feature = "trace-at")]
+        let digester = crate::trace::TraceDigester::new(digester);
+        #[cfg(feature = "internal-network-stack")]
+        let digester = super::ublox_stack::DirectRxDigester::new(digester, &resources.direct_rx);
+
         let ingress = atat::Ingress::new(
-            Digester::new(),
+            digester,
             &mut resources.ingress_buf,
             &resources.res_slot,
             &resources.urc_channel,
@@ -118,6 +291,8 @@ where
             &resources.urc_channel,
             resources.req_slot.sender(),
             &resources.res_slot,
+            &resources.cmd_lock,
+            &resources.fw_rx,
         );
 
         (
@@ -131,6 +306,12 @@ where
                 ingress,
                 res_slot: &resources.res_slot,
                 req_slot: &resources.req_slot,
+                cmd_lock: &resources.cmd_lock,
+                fw_rx: &resources.fw_rx,
+                #[cfg(feature = "internal-network-stack")]
+                direct_rx: &resources.direct_rx,
+                last_rx: Cell::new(Instant::now()),
+                baud_rate: C::BAUD_RATE,
 
                 #[cfg(feature = "ppp")]
                 ppp_runner: None,
@@ -152,123 +333,154 @@ where
     #[cfg(feature = "internal-network-stack")]
     pub fn internal_stack(
         &mut self,
-    ) -> super::ublox_stack::Device<'a, INGRESS_BUF_SIZE, URC_CAPACITY> {
+    ) -> super::ublox_stack::Device<'a, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE> {
         super::ublox_stack::Device {
             state_ch: self.ch.clone(),
             at_client: core::cell::RefCell::new(ProxyClient::new(
                 self.req_slot.sender(),
-                &self.res_slot,
+                self.res_slot,
+                self.cmd_lock,
             )),
             urc_channel: &self.urc_channel,
+            direct_rx: self.direct_rx,
         }
     }
 
     /// Probe a given baudrate with the goal of establishing initial
-    /// communication with the module, so we can reconfigure it for desired
-    /// baudrate
-    async fn probe_baud(&mut self, baudrate: BaudRate) -> Result<(), Error> {
+    /// communication with the module, hard resetting it first if `reset`.
+    async fn probe_baud(&mut self, baudrate: BaudRate, reset: bool) -> Result<(), Error> {
         info!("Probing wifi module using baud rate: {}", baudrate as u32);
         self.transport.set_baudrate(baudrate as u32);
 
-        let baud_fut = async {
-            let at_client = ProxyClient::new(self.req_slot.sender(), self.res_slot);
+        let probe_fut = async {
+            let at_client = ProxyClient::new(self.req_slot.sender(), self.res_slot, self.cmd_lock);
 
-            // Hard reset module
-            NetDevice::new(&self.ch, &mut self.config, &at_client, self.urc_channel)
-                .reset()
-                .await?;
+            if reset {
+                NetDevice::new(&self.ch, &mut self.config, &at_client, self.urc_channel)
+                    .reset()
+                    .await?;
+            }
 
             (&at_client).send_retry(&AT).await?;
+            Ok::<_, Error>(())
+        };
 
-            // Lets take a shortcut if we are probing for the desired baudrate
-            if baudrate == C::BAUD_RATE {
-                info!("Successfully shortcut the baud probing!");
-                return Ok(None);
-            }
+        match embassy_futures::select::select(
+            probe_fut,
+            at_bridge(
+                &mut self.transport,
+                self.req_slot,
+                &mut self.ingress,
+                &self.last_rx,
+            ),
+        )
+        .await
+        {
+            Either::First(r) => r,
+        }
+    }
+
+    /// Bring the RS232 settings of the module in line with the configured
+    /// baud rate and [`WifiConfig::FLOW_CONTROL`], leaving the module alone
+    /// if they match already.
+    async fn configure_transport(&mut self) -> Result<(), Error> {
+        let update_fut = async {
+            let at_client = ProxyClient::new(self.req_slot.sender(), self.res_slot, self.cmd_lock);
 
             let flow_control = if C::FLOW_CONTROL {
                 FlowControl::On
             } else {
                 FlowControl::Off
             };
-
-            (&at_client)
-                .send_retry(&SetRS232Settings {
-                    baud_rate: C::BAUD_RATE,
-                    flow_control,
-                    data_bits: 8,
-                    stop_bits: StopBits::One,
-                    parity: Parity::None,
-                    change_after_confirm: ChangeAfterConfirm::ChangeAfterOK,
-                })
-                .await?;
-
-            Ok::<_, Error>(Some(C::BAUD_RATE))
+            let Some(cmd) = rs232_update(&at_client, self.baud_rate, flow_control).await? else {
+                return Ok(false);
+            };
+            (&at_client).send_retry(&cmd).await?;
+            Ok::<_, Error>(true)
         };
 
-        match embassy_futures::select::select(
-            baud_fut,
-            at_bridge(&mut self.transport, self.req_slot, &mut self.ingress),
+        let changed = match embassy_futures::select::select(
+            update_fut,
+            at_bridge(
+                &mut self.transport,
+                self.req_slot,
+                &mut self.ingress,
+                &self.last_rx,
+            ),
         )
         .await
         {
-            Either::First(Ok(Some(baud))) => {
-                self.transport.set_baudrate(baud as u32);
-                Timer::after_millis(40).await;
-                Ok(())
-            }
-            Either::First(r) => r.map(drop),
+            Either::First(r) => r?,
+        };
+        if !changed {
+            return Ok(());
+        }
+
+        self.transport.set_baudrate(self.baud_rate as u32);
+        Timer::after_millis(40).await;
+
+        if C::SKIP_NVM_STORE {
+            return Ok(());
         }
+
+        // Attempt to store the settings, so we can shortcut the probing next
+        // time. Ignore any potential failures, as this is purely an
+        // optimization.
+        let _ = embassy_futures::select::select(
+            NetDevice::new(
+                &self.ch,
+                &mut self.config,
+                &ProxyClient::new(self.req_slot.sender(), self.res_slot, self.cmd_lock),
+                self.urc_channel,
+            )
+            .restart(true),
+            at_bridge(
+                &mut self.transport,
+                self.req_slot,
+                &mut self.ingress,
+                &self.last_rx,
+            ),
+        )
+        .await;
+
+        Ok(())
     }
 
     async fn init(&mut self) -> Result<(), Error> {
         // Initialize a new ublox device to a known state
         debug!("Initializing WiFi module");
 
+        let mut found_baudrate =
+            C::ASSUME_CONFIGURED && self.probe_baud(self.baud_rate, false).await.is_ok();
+
         // Probe all possible baudrates with the goal of establishing initial
         // communication with the module, so we can reconfigure it for desired
         // baudrate.
         //
         // Start with the two most likely
-        let mut found_baudrate = false;
-
-        for baudrate in [
-            C::BAUD_RATE,
-            DEFAULT_BAUD_RATE,
-            BaudRate::B9600,
-            BaudRate::B14400,
-            BaudRate::B19200,
-            BaudRate::B28800,
-            BaudRate::B38400,
-            BaudRate::B57600,
-            BaudRate::B76800,
-            BaudRate::B115200,
-            BaudRate::B230400,
-            BaudRate::B250000,
-            BaudRate::B460800,
-            BaudRate::B921600,
-            BaudRate::B3000000,
-            BaudRate::B5250000,
-        ] {
-            if self.probe_baud(baudrate).await.is_ok() {
-                if baudrate != C::BAUD_RATE {
-                    // Attempt to store the desired baudrate, so we can shortcut
-                    // this probing next time. Ignore any potential failures, as
-                    // this is purely an optimization.
-                    let _ = embassy_futures::select::select(
-                        NetDevice::new(
-                            &self.ch,
-                            &mut self.config,
-                            &ProxyClient::new(self.req_slot.sender(), self.res_slot),
-                            self.urc_channel,
-                        )
-                        .restart(true),
-                        at_bridge(&mut self.transport, self.req_slot, &mut self.ingress),
-                    )
-                    .await;
+        if !found_baudrate {
+            for baudrate in [
+                self.baud_rate,
+                DEFAULT_BAUD_RATE,
+                BaudRate::B9600,
+                BaudRate::B14400,
+                BaudRate::B19200,
+                BaudRate::B28800,
+                BaudRate::B38400,
+                BaudRate::B57600,
+                BaudRate::B76800,
+                BaudRate::B115200,
+                BaudRate::B230400,
+                BaudRate::B250000,
+                BaudRate::B460800,
+                BaudRate::B921600,
+                BaudRate::B3000000,
+                BaudRate::B5250000,
+            ] {
+                if self.probe_baud(baudrate, true).await.is_ok() {
+                    found_baudrate = true;
+                    break;
                 }
-                found_baudrate = true;
-                break;
             }
         }
 
@@ -276,10 +488,22 @@ where
             return Err(Error::BaudDetection);
         }
 
-        let at_client = ProxyClient::new(self.req_slot.sender(), self.res_slot);
+        self.configure_transport().await?;
+
+        let at_client = ProxyClient::new(self.req_slot.sender(), self.res_slot, self.cmd_lock);
 
         let setup_fut = async {
-            (&at_client).send_retry(&SoftwareVersion).await?;
+            let SoftwareVersionResponse { version } =
+                (&at_client).send_retry(&SoftwareVersion).await?;
+            let ModelIdentificationResponse { model } =
+                (&at_client).send_retry(&ModelIdentification).await?;
+            let SerialNumberResponse { serial_number } =
+                (&at_client).send_retry(&SerialNumber).await?;
+            let mcu_id = (&at_client)
+                .send_retry(&IdentificationInformationMCUID)
+                .await
+                .ok()
+                .map(|IdentificationInformationMCUIDResponse { mcu_id }| mcu_id);
 
             (&at_client)
                 .send_retry(&SetEcho { on: EchoOn::Off })
@@ -320,48 +544,227 @@ where
                     .await?;
             }
 
-            Ok::<(), Error>(())
+            // Bridges configured to be active on start up are active after a
+            // boot. Modules without bridge support reject the query.
+            let mut bridges_active = [false; 2];
+            for (config_id, active) in [BridgeConfigId::Id1, BridgeConfigId::Id2]
+                .into_iter()
+                .zip(&mut bridges_active)
+            {
+                *active = matches!(
+                    (&at_client)
+                        .send(&GetBridgeConfiguration {
+                            config_id,
+                            parameter: BridgeConfigParameter::ActiveOnStartup,
+                        })
+                        .await,
+                    Ok(BridgeConfigurationResponse {
+                        config: BridgeConfig::ActiveOnStartup(OnOff::On),
+                        ..
+                    })
+                );
+            }
+
+            Ok::<_, Error>((
+                ModuleInfo {
+                    model,
+                    serial_number,
+                    sw_version: version,
+                    mcu_id,
+                },
+                bridges_active,
+            ))
         };
 
-        match embassy_futures::select::select(
+        let (info, bridges_active) = match embassy_futures::select::select(
             setup_fut,
-            at_bridge(&mut self.transport, self.req_slot, &mut self.ingress),
+            at_bridge(
+                &mut self.transport,
+                self.req_slot,
+                &mut self.ingress,
+                &self.last_rx,
+            ),
         )
         .await
         {
             Either::First(r) => r?,
-        }
+        };
 
-        self.ch.mark_initialized();
+        info!(
+            "Initialized {:?} ({:?}) running {:?}",
+            info.model.as_str(),
+            info.serial_number.as_str(),
+            info.sw_version
+        );
+        for (config_id, active) in [BridgeConfigId::Id1, BridgeConfigId::Id2]
+            .into_iter()
+            .zip(bridges_active)
+        {
+            self.ch.set_bridge_active(config_id, active);
+        }
+        self.ch.mark_initialized(info);
 
         Ok(())
     }
 
+    /// Initialize the module, recording the outcome in the shared state.
+    ///
+    /// Once [`WifiConfig::MAX_INIT_ATTEMPTS`] consecutive attempts have
+    /// failed, this waits for a reset to be requested before trying again.
+    async fn try_init(&mut self) -> Result<(), Error> {
+        let Err(e) = self.init().await else {
+            return Ok(());
+        };
+
+        error!("Failed to initialize module: {:?}", e);
+        if self.ch.mark_init_failed(e.clone(), C::MAX_INIT_ATTEMPTS) {
+            error!("Giving up initializing module until reset");
+            self.ch.wait_reset_requested().await;
+        }
+
+        Err(e)
+    }
+
+    /// Follow the module to `baud_rate`, after it has been rebooted with the
+    /// new RS232 settings stored.
+    ///
+    /// The host side of the serial link is switched through
+    /// [`Transport::set_baudrate`], and the module must start up and answer
+    /// an AT command at the new rate. Otherwise the previous rate is kept, so
+    /// the following initialization re-synchronizes at it and restores the
+    /// previous settings on the module.
+    async fn change_baud_rate(&mut self, baud_rate: BaudRate) -> Result<(), Error> {
+        info!("Changing baud rate to: {}", baud_rate as u32);
+        self.transport.set_baudrate(baud_rate as u32);
+
+        let resync_fut = async {
+            let at_client = ProxyClient::new(self.req_slot.sender(), self.res_slot, self.cmd_lock);
+
+            NetDevice::new(&self.ch, &mut self.config, &at_client, self.urc_channel)
+                .wait_rebooted()
+                .await?;
+
+            (&at_client).send_retry(&AT).await?;
+            Ok::<_, Error>(())
+        };
+
+        let res = match embassy_futures::select::select(
+            resync_fut,
+            at_bridge(
+                &mut self.transport,
+                self.req_slot,
+                &mut self.ingress,
+                &self.last_rx,
+            ),
+        )
+        .await
+        {
+            Either::First(r) => r,
+        };
+
+        match res {
+            Ok(()) => self.baud_rate = baud_rate,
+            Err(ref e) => {
+                warn!("Module did not answer at the new baud rate: {:?}", e);
+                self.transport.set_baudrate(self.baud_rate as u32);
+            }
+        }
+
+        res
+    }
+
+    /// Hand the transport over to a firmware update in progress.
+    ///
+    /// Raw requests are forwarded to the boot loader and its replies are
+    /// made available to the `FirmwareUpdater`, until the update is finalized
+    /// or aborted. The module is re-initialized afterwards, booting whichever
+    /// image is valid at that point.
+    async fn firmware_update(&mut self, baud: u32) {
+        info!("Entering firmware update at baud rate: {}", baud);
+        self.ch.mark_uninitialized();
+        self.fw_rx.clear();
+        self.transport.set_baudrate(baud);
+
+        let (mut tx, mut rx) = self.transport.split_ref();
+
+        let tx_fut = async {
+            loop {
+                let msg = self.req_slot.receive().await;
+                let _ = tx.write_all(&msg).await;
+            }
+        };
+
+        let rx_fut = async {
+            let mut buf = [0u8; FW_RX_SIZE];
+            loop {
+                match rx.read(&mut buf).await {
+                    Ok(n) => {
+                        self.fw_rx.write_all(&buf[..n]).await;
+                    }
+                    Err(_) => Timer::after_millis(10).await,
+                }
+            }
+        };
+
+        embassy_futures::select::select3(tx_fut, rx_fut, self.ch.wait_firmware_update_end()).await;
+
+        info!("Firmware update ended, restarting module");
+    }
+
     #[cfg(feature = "internal-network-stack")]
     pub async fn run(&mut self) -> ! {
         loop {
-            if self.init().await.is_err() {
+            if self.try_init().await.is_err() {
                 continue;
             }
 
-            embassy_futures::select::select(
-                NetDevice::new(
-                    &self.ch,
-                    &mut self.config,
-                    &ProxyClient::new(self.req_slot.sender(), &self.res_slot),
-                    self.urc_channel,
-                )
-                .run(),
-                at_bridge(&mut self.transport, &self.req_slot, &mut self.ingress),
+            let at_client = ProxyClient::new(self.req_slot.sender(), self.res_slot, self.cmd_lock);
+            self.last_rx.set(Instant::now());
+
+            // A stuck module is recovered through a regular reset request
+            let supervision = async {
+                if let Some(timeout) = C::SUPERVISION_TIMEOUT {
+                    supervise(&at_client, &self.last_rx, timeout).await;
+                    error!("Module stopped responding, resetting");
+                    self.ch.mark_watchdog_recovery();
+                }
+            };
+
+            let res = embassy_futures::select::select4(
+                NetDevice::new(&self.ch, &mut self.config, &at_client, self.urc_channel).run(),
+                embassy_futures::join::join(
+                    at_bridge(
+                        &mut self.transport,
+                        self.req_slot,
+                        &mut self.ingress,
+                        &self.last_rx,
+                    ),
+                    supervision,
+                ),
+                self.ch.wait_firmware_update_start(),
+                embassy_futures::select::select(
+                    self.ch.wait_reset_requested(),
+                    self.ch.wait_baud_rate_change_requested(),
+                ),
             )
             .await;
+
+            match res {
+                Either4::Third(baud) => self.firmware_update(baud).await,
+                Either4::Fourth(Either::First(())) => info!("Module reset requested"),
+                Either4::Fourth(Either::Second(baud_rate)) => {
+                    let res = self.change_baud_rate(baud_rate).await;
+                    self.ch.set_baud_rate_changed(res);
+                }
+                _ => {}
+            }
         }
     }
 
     #[cfg(feature = "ppp")]
     pub async fn run(&mut self, stack: embassy_net::Stack<'_>) -> ! {
         loop {
-            if self.init().await.is_err() {
+            if self.try_init().await.is_err() {
                 continue;
             }
 
@@ -371,7 +774,12 @@ where
                 // Allow control to send/receive AT commands directly on the
                 // UART, until we are ready to establish connection using PPP
                 let _ = embassy_futures::select::select(
-                    at_bridge(&mut self.transport, self.req_slot, &mut self.ingress),
+                    at_bridge(
+                        &mut self.transport,
+                        self.req_slot,
+                        &mut self.ingress,
+                        &self.last_rx,
+                    ),
                     self.ch.wait_connected(),
                 )
                 .await;
@@ -425,22 +833,9 @@ where
                         .unwrap()
                         .run(&mut self.transport, C::PPP_CONFIG, |ipv4| {
                             debug!("Running on_ipv4_up for wifi!");
-                            let Some(addr) = ipv4.address else {
-                                warn!("PPP did not provide an IP address.");
-                                return;
-                            };
-                            let mut dns_servers = heapless::Vec::new();
-                            for s in ipv4.dns_servers.iter().flatten() {
-                                let _ = dns_servers.push(s.clone());
+                            if let Some(config) = super::ppp::config_v4(ipv4) {
+                                stack.set_config_v4(config);
                             }
-                            let config =
-                                embassy_net::ConfigV4::Static(embassy_net::StaticConfigV4 {
-                                    address: embassy_net::Ipv4Cidr::new(addr, 0),
-                                    gateway: None,
-                                    dns_servers,
-                                });
-
-                            stack.set_config_v4(config);
                         })
                         .await;
                     error!("ppp connection returned");
@@ -451,24 +846,30 @@ where
 
                 let at_fut = async {
                     use crate::asynch::at_udp_socket::AtUdpSocket;
-                    use embassy_net::udp::{PacketMetadata, UdpSocket};
+                    use embassy_net::udp::PacketMetadata;
 
                     let mut rx_meta = [PacketMetadata::EMPTY; 1];
                     let mut tx_meta = [PacketMetadata::EMPTY; 1];
                     let mut socket_rx_buf = [0u8; 64];
                     let mut socket_tx_buf = [0u8; 64];
-                    let mut socket = UdpSocket::new(
+                    let mut at_socket = AtUdpSocket::bind(
                         stack,
+                        self.ch.clone(),
+                        embassy_net::IpEndpoint::new(C::PPP_AT_ADDRESS.into(), C::PPP_AT_PORT),
                         &mut rx_meta,
                         &mut socket_rx_buf,
                         &mut tx_meta,
                         &mut socket_tx_buf,
-                    );
-
-                    socket.bind(AtUdpSocket::PPP_AT_PORT).unwrap();
-                    let mut at_socket = AtUdpSocket(socket);
+                    )
+                    .unwrap();
 
-                    at_bridge(&mut at_socket, self.req_slot, &mut self.ingress).await;
+                    at_bridge(
+                        &mut at_socket,
+                        self.req_slot,
+                        &mut self.ingress,
+                        &self.last_rx,
+                    )
+                    .await;
                 };
 
                 embassy_futures::select::select(ppp_fut, at_fut).await;
@@ -478,7 +879,7 @@ where
                 let _ = NetDevice::new(
                     &self.ch,
                     &mut self.config,
-                    &ProxyClient::new(self.req_slot.sender(), self.res_slot),
+                    &ProxyClient::new(self.req_slot.sender(), self.res_slot, self.cmd_lock),
                     self.urc_channel,
                 )
                 .run()
@@ -487,7 +888,326 @@ where
                 warn!("Breaking to reboot device");
             };
 
-            embassy_futures::select::select(device_fut, network_fut).await;
+            let res = embassy_futures::select::select4(
+                device_fut,
+                network_fut,
+                self.ch.wait_firmware_update_start(),
+                embassy_futures::select::select(
+                    self.ch.wait_reset_requested(),
+                    self.ch.wait_baud_rate_change_requested(),
+                ),
+            )
+            .await;
+            self.ch.set_at_transport(state::AtTransportStatus::Serial);
+
+            match res {
+                Either4::Third(baud) => self.firmware_update(baud).await,
+                Either4::Fourth(Either::First(())) => info!("Module reset requested"),
+                Either4::Fourth(Either::Second(baud_rate)) => {
+                    let res = self.change_baud_rate(baud_rate).await;
+                    self.ch.set_baud_rate_changed(res);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use heapless::Vec;
+
+    /// Serial link to a module that never answers.
+    struct Silent;
+
+    impl ErrorType for Silent {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Read for Silent {
+        async fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Self::Error> {
+            core::future::pending().await
         }
     }
+
+    impl BufRead for Silent {
+        async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+            core::future::pending().await
+        }
+
+        fn consume(&mut self, _amt: usize) {}
+    }
+
+    impl Write for Silent {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            Ok(buf.len())
+        }
+    }
+
+    /// [`Silent`] link recording the baud rates the host switches to.
+    struct SilentTransport {
+        bauds: std::vec::Vec<u32>,
+    }
+
+    impl ErrorType for SilentTransport {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Read for SilentTransport {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            Silent.read(buf).await
+        }
+    }
+
+    impl BufRead for SilentTransport {
+        async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+            core::future::pending().await
+        }
+
+        fn consume(&mut self, _amt: usize) {}
+    }
+
+    impl Write for SilentTransport {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            Ok(buf.len())
+        }
+    }
+
+    impl Transport for SilentTransport {
+        fn set_baudrate(&mut self, baudrate: u32) {
+            self.bauds.push(baudrate);
+        }
+
+        fn split_ref(&mut self) -> (impl Write, impl Read) {
+            (Silent, Silent)
+        }
+    }
+
+    /// Module answering each AT command line written to it with `respond`,
+    /// recording the commands.
+    #[cfg(not(feature = "internal-network-stack"))]
+    #[derive(Clone)]
+    struct ScriptedTransport {
+        script: std::rc::Rc<core::cell::RefCell<Script>>,
+    }
+
+    #[cfg(not(feature = "internal-network-stack"))]
+    struct Script {
+        respond: fn(&str) -> &'static [u8],
+        line: std::vec::Vec<u8>,
+        commands: std::vec::Vec<std::string::String>,
+        pending: std::collections::VecDeque<u8>,
+    }
+
+    #[cfg(not(feature = "internal-network-stack"))]
+    impl ScriptedTransport {
+        fn new(respond: fn(&str) -> &'static [u8]) -> Self {
+            Self {
+                script: std::rc::Rc::new(core::cell::RefCell::new(Script {
+                    respond,
+                    line: std::vec::Vec::new(),
+                    commands: std::vec::Vec::new(),
+                    pending: std::collections::VecDeque::new(),
+                })),
+            }
+        }
+
+        fn commands(&self) -> std::vec::Vec<std::string::String> {
+            self.script.borrow().commands.clone()
+        }
+    }
+
+    #[cfg(not(feature = "internal-network-stack"))]
+    impl ErrorType for ScriptedTransport {
+        type Error = core::convert::Infallible;
+    }
+
+    #[cfg(not(feature = "internal-network-stack"))]
+    impl Read for ScriptedTransport {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            loop {
+                {
+                    let mut script = self.script.borrow_mut();
+                    if !script.pending.is_empty() {
+                        let n = buf.len().min(script.pending.len());
+                        for (b, p) in buf.iter_mut().zip(script.pending.drain(..n)) {
+                            *b = p;
+                        }
+                        return Ok(n);
+                    }
+                }
+                Timer::after_millis(1).await;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "internal-network-stack"))]
+    impl Write for ScriptedTransport {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            let script = &mut *self.script.borrow_mut();
+            for &b in buf {
+                script.line.push(b);
+                if script.line.ends_with(b"\r\n") {
+                    let line =
+                        std::string::String::from_utf8(core::mem::take(&mut script.line)).unwrap();
+                    let command = std::string::String::from(line.trim_end());
+                    script.pending.extend((script.respond)(&command));
+                    script.commands.push(command);
+                }
+            }
+            Ok(buf.len())
+        }
+    }
+
+    #[cfg(not(feature = "internal-network-stack"))]
+    impl Transport for ScriptedTransport {
+        fn set_baudrate(&mut self, _baudrate: u32) {}
+
+        fn split_ref(&mut self) -> (impl Write, impl Read) {
+            (self.clone(), self.clone())
+        }
+    }
+
+    struct NoPin;
+
+    impl embedded_hal::digital::ErrorType for NoPin {
+        type Error = core::convert::Infallible;
+    }
+
+    impl embedded_hal::digital::OutputPin for NoPin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    struct TestConfig;
+
+    impl<'a> WifiConfig<'a> for TestConfig {
+        type ResetPin = NoPin;
+
+        #[cfg(feature = "ppp")]
+        const PPP_CONFIG: embassy_net_ppp::Config<'a> = embassy_net_ppp::Config {
+            username: b"",
+            password: b"",
+        };
+    }
+
+    #[test]
+    fn unanswered_baud_rate_falls_back() {
+        let mut resources = Resources::<256, 2>::new();
+        let (mut runner, _control) = Runner::new(
+            SilentTransport {
+                bauds: std::vec::Vec::new(),
+            },
+            &mut resources,
+            TestConfig,
+        );
+
+        let res = crate::asynch::test_support::block_on(runner.change_baud_rate(BaudRate::B921600));
+        assert!(matches!(res, Err(Error::Timeout)));
+
+        // The host is switched back to the rate the module still uses
+        assert_eq!(runner.transport.bauds, [921600, 115200]);
+        assert_eq!(runner.baud_rate, DEFAULT_BAUD_RATE);
+    }
+
+    /// Run `rs232_update` against a module answering `+UMRS?` with
+    /// `settings`, returning the update and the commands received.
+    fn update_against(
+        settings: &[u8],
+        flow_control: FlowControl,
+    ) -> (Option<SetRS232Settings>, std::vec::Vec<Vec<u8, 64>>) {
+        let req_slot = Channel::<NoopRawMutex, Vec<u8, 64>, 1>::new();
+        let res_slot = atat::ResponseSlot::<256>::new();
+        let cmd_lock = CommandLock::new();
+        let client = ProxyClient::new(req_slot.sender(), &res_slot, &cmd_lock);
+
+        let module = async {
+            let req = req_slot.receive().await;
+            res_slot.signal_response(Ok(settings)).unwrap();
+            std::vec![req]
+        };
+
+        let (update, received) =
+            crate::asynch::test_support::block_on(embassy_futures::join::join(
+                rs232_update(&client, BaudRate::B115200, flow_control),
+                module,
+            ));
+        (update.unwrap(), received)
+    }
+
+    #[test]
+    fn configured_module_left_alone() {
+        let (update, received) = update_against(b"+UMRS:115200,2,8,1,1,1", FlowControl::Off);
+
+        // Neither a reconfiguration, nor a store and reboot
+        assert_eq!(update, None);
+        assert_eq!(received.len(), 1);
+        assert_eq!(&received[0][..], b"AT+UMRS?\r\n");
+    }
+
+    /// Run `configure_transport` against a module answering with `respond`,
+    /// returning the commands it received.
+    #[cfg(not(feature = "internal-network-stack"))]
+    fn configure_against(respond: fn(&str) -> &'static [u8]) -> std::vec::Vec<std::string::String> {
+        let transport = ScriptedTransport::new(respond);
+        let mut resources = Resources::<256, 2>::new();
+        let (mut runner, _control) = Runner::new(transport.clone(), &mut resources, TestConfig);
+
+        crate::asynch::test_support::block_on(runner.configure_transport()).unwrap();
+        transport.commands()
+    }
+
+    #[cfg(not(feature = "internal-network-stack"))]
+    #[test]
+    fn configured_module_not_rebooted() {
+        let commands = configure_against(|cmd| match cmd {
+            "AT+UMRS?" => b"\r\n+UMRS:115200,2,8,1,1,1\r\nOK\r\n",
+            _ => b"\r\nOK\r\n",
+        });
+
+        // Only the settings are read: no &W, and no RebootDCE
+        assert_eq!(commands, ["AT+UMRS?"]);
+        assert!(!commands.iter().any(|c| c == "AT+CPWROFF"));
+    }
+
+    #[cfg(not(feature = "internal-network-stack"))]
+    #[test]
+    fn reconfigured_module_rebooted() {
+        let commands = configure_against(|cmd| match cmd {
+            "AT+UMRS?" => b"\r\n+UMRS:9600,2,8,1,1,1\r\nOK\r\n",
+            _ => b"\r\nOK\r\n",
+        });
+
+        assert_eq!(commands[0], "AT+UMRS?");
+        assert!(commands[1].starts_with("AT+UMRS=115200"));
+        assert_eq!(commands[2..], ["AT&W", "AT+CPWROFF"]);
+    }
+
+    #[test]
+    fn differing_settings_reconfigured() {
+        let (update, _) = update_against(b"+UMRS:115200,2,8,1,1,1", FlowControl::On);
+        assert!(matches!(
+            update,
+            Some(SetRS232Settings {
+                baud_rate: BaudRate::B115200,
+                flow_control: FlowControl::On,
+                ..
+            })
+        ));
+
+        let (update, _) = update_against(b"+UMRS:9600,2,8,1,1,1", FlowControl::Off);
+        assert!(matches!(
+            update,
+            Some(SetRS232Settings {
+                baud_rate: BaudRate::B115200,
+                ..
+            })
+        ));
+    }
 }
@@ -0,0 +1,240 @@
+//! Simple Network Time Protocol (SNTP) client, following RFC 4330.
+//!
+//! The host queries an SNTP server itself, over a UDP socket of its network
+//! stack.
+
+#[cfg(feature = "ppp")]
+use embassy_net::udp::{RecvError, SendError, UdpSocket};
+#[cfg(feature = "ppp")]
+use embassy_net::IpEndpoint;
+#[cfg(feature = "ppp")]
+use embassy_time::{with_timeout, Duration, Instant};
+
+/// UDP port of SNTP servers.
+pub const PORT: u16 = 123;
+
+/// Length of an SNTP message without authenticator.
+pub const MESSAGE_LEN: usize = 48;
+
+/// Seconds from the NTP epoch, 1900-01-01, to the Unix epoch.
+const UNIX_EPOCH_OFFSET: u64 = 2_208_988_800;
+
+/// Error returned when querying the time from an SNTP server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The reply is too short, not sent by a server, or its time is out of
+    /// range.
+    Malformed,
+    /// The reply does not answer the request, e.g. a stale or spoofed reply.
+    Mismatch,
+    /// The server clock is not synchronized.
+    Unsynchronized,
+    /// The server asked not to be queried again, with the given kiss code,
+    /// e.g. `RATE`.
+    KissOfDeath([u8; 4]),
+    #[cfg(feature = "ppp")]
+    Send(SendError),
+    #[cfg(feature = "ppp")]
+    Recv(RecvError),
+    /// No reply was received in time.
+    #[cfg(feature = "ppp")]
+    Timeout,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "malformed SNTP reply"),
+            Self::Mismatch => write!(f, "SNTP reply does not match the request"),
+            Self::Unsynchronized => write!(f, "SNTP server is not synchronized"),
+            Self::KissOfDeath(code) => write!(
+                f,
+                "SNTP server sent kiss code {}",
+                core::str::from_utf8(code).unwrap_or("?")
+            ),
+            #[cfg(feature = "ppp")]
+            Self::Send(e) => write!(f, "failed to send SNTP request: {:?}", e),
+            #[cfg(feature = "ppp")]
+            Self::Recv(e) => write!(f, "failed to receive SNTP reply: {:?}", e),
+            #[cfg(feature = "ppp")]
+            Self::Timeout => write!(f, "no SNTP reply received"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// Build an SNTP client request, with `transmit` as its transmit timestamp.
+///
+/// The server echoes the transmit timestamp as the originate timestamp of its
+/// reply, so it should differ between requests (RFC 4330, section 5).
+pub fn request(transmit: u64) -> [u8; MESSAGE_LEN] {
+    let mut msg = [0; MESSAGE_LEN];
+    // No leap indicator, version 4, client mode
+    msg[0] = (4 << 3) | 3;
+    msg[40..48].copy_from_slice(&transmit.to_be_bytes());
+    msg
+}
+
+/// Parse the SNTP server reply to `request`, returning its transmit timestamp
+/// as seconds since the Unix epoch.
+pub fn parse_reply(msg: &[u8], request: &[u8; MESSAGE_LEN]) -> Result<u64, Error> {
+    if msg.len() < MESSAGE_LEN {
+        return Err(Error::Malformed);
+    }
+
+    let leap_indicator = msg[0] >> 6;
+    let mode = msg[0] & 0x07;
+    let stratum = msg[1];
+
+    // Server or broadcast mode
+    if !matches!(mode, 4 | 5) {
+        return Err(Error::Malformed);
+    }
+    if stratum == 0 {
+        return Err(Error::KissOfDeath([msg[12], msg[13], msg[14], msg[15]]));
+    }
+    if leap_indicator == 3 || stratum > 15 {
+        return Err(Error::Unsynchronized);
+    }
+    if msg[24..32] != request[40..48] {
+        return Err(Error::Mismatch);
+    }
+
+    let seconds = u32::from_be_bytes([msg[40], msg[41], msg[42], msg[43]]);
+    if seconds == 0 {
+        return Err(Error::Unsynchronized);
+    }
+
+    // Timestamps with the most significant bit cleared are counted from
+    // 2036-02-07, when the 32 bit seconds wrap (RFC 4330, section 3).
+    let seconds = if seconds & 0x8000_0000 == 0 {
+        u64::from(seconds) + (1 << 32)
+    } else {
+        u64::from(seconds)
+    };
+    seconds
+        .checked_sub(UNIX_EPOCH_OFFSET)
+        .ok_or(Error::Malformed)
+}
+
+/// Query the time from the SNTP server at `server` over the bound `socket`,
+/// returning seconds since the Unix epoch.
+///
+/// Datagrams from other endpoints are ignored.
+#[cfg(feature = "ppp")]
+pub async fn query(
+    socket: &mut UdpSocket<'_>,
+    server: IpEndpoint,
+    timeout: Duration,
+) -> Result<u64, Error> {
+    let request = request(Instant::now().as_ticks());
+    socket
+        .send_to(&request, server)
+        .await
+        .map_err(Error::Send)?;
+
+    // Room for an authenticator, which is not checked
+    let mut buf = [0; MESSAGE_LEN + 20];
+    with_timeout(timeout, async {
+        loop {
+            let (n, meta) = socket.recv_from(&mut buf).await.map_err(Error::Recv)?;
+            if meta.endpoint == server {
+                return parse_reply(&buf[..n], &request);
+            }
+        }
+    })
+    .await
+    .map_err(|_| Error::Timeout)?
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TRANSMIT: u64 = 0x0123_4567_89ab_cdef;
+
+    fn reply(leap_mode: u8, stratum: u8, seconds: u32) -> [u8; MESSAGE_LEN] {
+        let mut msg = [0; MESSAGE_LEN];
+        msg[0] = leap_mode;
+        msg[1] = stratum;
+        msg[24..32].copy_from_slice(&TRANSMIT.to_be_bytes());
+        msg[40..44].copy_from_slice(&seconds.to_be_bytes());
+        msg
+    }
+
+    #[test]
+    fn client_request() {
+        let msg = request(TRANSMIT);
+        assert_eq!(msg[0], 0x23);
+        assert!(msg[1..40].iter().all(|b| *b == 0));
+        assert_eq!(msg[40..48], TRANSMIT.to_be_bytes());
+    }
+
+    #[test]
+    fn server_reply() {
+        let request = request(TRANSMIT);
+
+        // 2023-11-14T22:13:20Z
+        let seconds = (1_700_000_000 + UNIX_EPOCH_OFFSET) as u32;
+        assert_eq!(
+            parse_reply(&reply(0x24, 2, seconds), &request),
+            Ok(1_700_000_000)
+        );
+
+        // The first second after the NTP era wraps in 2036
+        assert_eq!(
+            parse_reply(&reply(0x24, 2, 0x0000_0001), &request),
+            Ok(2_085_978_497)
+        );
+
+        // Authenticated replies carry a trailing key id and digest
+        let mut long = [0; MESSAGE_LEN + 20];
+        long[..MESSAGE_LEN].copy_from_slice(&reply(0x24, 1, seconds));
+        assert_eq!(parse_reply(&long, &request), Ok(1_700_000_000));
+    }
+
+    #[test]
+    fn invalid_replies() {
+        let request = request(TRANSMIT);
+        let seconds = (1_700_000_000 + UNIX_EPOCH_OFFSET) as u32;
+
+        assert_eq!(parse_reply(&[0x24; 47], &request), Err(Error::Malformed));
+        // Client mode
+        assert_eq!(
+            parse_reply(&reply(0x23, 2, seconds), &request),
+            Err(Error::Malformed)
+        );
+        // Alarm condition
+        assert_eq!(
+            parse_reply(&reply(0xe4, 2, seconds), &request),
+            Err(Error::Unsynchronized)
+        );
+        assert_eq!(
+            parse_reply(&reply(0x24, 2, 0), &request),
+            Err(Error::Unsynchronized)
+        );
+        // Before the Unix epoch
+        assert_eq!(
+            parse_reply(&reply(0x24, 2, 0x8000_0000), &request),
+            Err(Error::Malformed)
+        );
+
+        let mut kod = reply(0x24, 0, 0);
+        kod[12..16].copy_from_slice(b"RATE");
+        assert_eq!(
+            parse_reply(&kod, &request),
+            Err(Error::KissOfDeath(*b"RATE"))
+        );
+    }
+
+    #[test]
+    fn reply_to_other_request() {
+        let seconds = (1_700_000_000 + UNIX_EPOCH_OFFSET) as u32;
+        assert_eq!(
+            parse_reply(&reply(0x24, 2, seconds), &request(TRANSMIT + 1)),
+            Err(Error::Mismatch)
+        );
+    }
+}
@@ -8,7 +8,13 @@ use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::waitqueue::WakerRegistration;
 
-use crate::connection::{WiFiState, WifiConnection};
+use super::control::EthernetLinkStatus;
+use crate::command::general::types::FirmwareVersion;
+use crate::command::network::types::{BridgeConfigId, NetworkErrorReason};
+use crate::command::system::types::{BaudRate, InterfaceID};
+use crate::connection::{EthernetConnection, Uplink, WiFiState, WifiConnection};
+use crate::error::Error;
+use crate::network::WifiMode;
 
 /// The link state of a network device.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -22,6 +28,80 @@ pub enum LinkState {
     Up,
 }
 
+/// The IP address family that must be configured before the link is
+/// considered [`LinkState::Up`].
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum IpFamily {
+    /// An IPv4 address must have been acquired.
+    #[default]
+    V4,
+    /// A global IPv6 address must have been acquired.
+    #[cfg(feature = "ipv6")]
+    V6,
+    /// Either an IPv4 or a global IPv6 address must have been acquired.
+    Any,
+}
+
+/// Outcome of initializing the module.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InitStatus {
+    /// The module has not been initialized yet.
+    Pending,
+    /// The module is initialized and ready for use, with the identification
+    /// read while initializing it.
+    Initialized(ModuleInfo),
+    /// The latest attempt at initializing the module failed, and the runner
+    /// keeps retrying. `attempts` counts the consecutive failures.
+    InitFailed { error: Error, attempts: u32 },
+    /// Initializing the module failed too many times, and the runner has
+    /// given up until a reset is requested.
+    Failed { error: Error, attempts: u32 },
+}
+
+/// Health of the transport carrying AT commands to the module.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AtTransportStatus {
+    /// AT commands are sent on the serial link.
+    #[default]
+    Serial,
+    /// AT commands are tunneled over UDP, while the serial link is in PPP
+    /// mode.
+    #[cfg(feature = "ppp")]
+    Udp,
+    /// The UDP socket tunneling AT commands was closed, and could not be
+    /// reopened yet.
+    #[cfg(feature = "ppp")]
+    Reconnecting,
+}
+
+/// Identification of the module, read once it is initialized.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ModuleInfo {
+    /// Model identification, e.g. `ODIN-W262`.
+    pub model: heapless::String<64>,
+    pub serial_number: heapless::String<64>,
+    pub sw_version: FirmwareVersion,
+    /// MCU ID, `None` if not reported by the firmware.
+    pub mcu_id: Option<heapless::String<64>>,
+}
+
+#[cfg(test)]
+impl ModuleInfo {
+    /// Identification of a module running `sw_version`.
+    pub(crate) fn with_version(sw_version: FirmwareVersion) -> Self {
+        Self {
+            model: heapless::String::new(),
+            serial_number: heapless::String::new(),
+            sw_version,
+            mcu_id: None,
+        }
+    }
+}
+
 pub(crate) struct State {
     shared: Mutex<NoopRawMutex, RefCell<Shared>>,
 }
@@ -33,8 +113,29 @@ impl State {
                 should_connect: false,
                 link_state: LinkState::Uninitialized,
                 wifi_connection: WifiConnection::new(),
+                firmware_update: None,
+                reset_requested: false,
+                baud_rate_change: None,
+                baud_rate_changed: None,
+                module_resets: 0,
+                leaves: 0,
+                open_sockets: 0,
+                bridges_active: [false; 2],
+                ethernet: None,
+                ethernet_connection: EthernetConnection::new(),
+                uplink: Uplink::WiFi,
+                wifi_interface_id: None,
+                network_error: None,
+                network_errors: 0,
+                init_status: InitStatus::Pending,
+                init_failures: 0,
+                watchdog_recoveries: 0,
+                at_transport: AtTransportStatus::Serial,
+                at_reconnects: 0,
+                nvm_stores: 0,
                 state_waker: WakerRegistration::new(),
                 connection_waker: WakerRegistration::new(),
+                stack_waker: WakerRegistration::new(),
             })),
         }
     }
@@ -45,8 +146,85 @@ pub(crate) struct Shared {
     link_state: LinkState,
     should_connect: bool,
     wifi_connection: WifiConnection,
+    /// Boot loader baud rate while a firmware update is in progress.
+    firmware_update: Option<u32>,
+    /// Set when the module should be reset and re-initialized.
+    reset_requested: bool,
+    /// Baud rate the module has been rebooted into, until the runner has
+    /// followed it.
+    baud_rate_change: Option<BaudRate>,
+    /// Outcome of the latest baud rate change, until taken by the control.
+    baud_rate_changed: Option<Result<(), Error>>,
+    /// Number of completed module resets, used to invalidate sockets.
+    module_resets: u32,
+    /// Number of intentional leaves, used to invalidate sockets.
+    leaves: u32,
+    /// Number of sockets holding a peer on the module.
+    open_sockets: usize,
+    /// Whether each network bridge has been activated.
+    bridges_active: [bool; 2],
+    /// Last reported Ethernet link status.
+    ethernet: Option<EthernetLinkStatus>,
+    ethernet_connection: EthernetConnection,
+    /// Interface whose connection determines the link state.
+    uplink: Uplink,
+    /// Network interface id of the WiFi station, once it is known.
+    wifi_interface_id: Option<u8>,
+    /// Last network error reported by the module.
+    network_error: Option<NetworkErrorReason>,
+    /// Number of network errors reported by the module.
+    network_errors: u32,
+    init_status: InitStatus,
+    /// Number of consecutive failed initialization attempts.
+    init_failures: u32,
+    /// Number of resets after the module stopped responding.
+    watchdog_recoveries: u32,
+    at_transport: AtTransportStatus,
+    /// Number of times the UDP socket tunneling AT commands was reopened.
+    at_reconnects: u32,
+    /// Number of times the module configuration was stored to NVM.
+    nvm_stores: u32,
     state_waker: WakerRegistration,
     connection_waker: WakerRegistration,
+    /// Socket stack waiting for a module reset, a leave or new module info.
+    stack_waker: WakerRegistration,
+}
+
+impl Shared {
+    fn is_uplink_connected_for(&self, family: IpFamily) -> bool {
+        match self.uplink {
+            Uplink::WiFi => self.wifi_connection.is_connected_for(family),
+            Uplink::Ethernet => self.ethernet_connection.is_connected_for(family),
+        }
+    }
+
+    fn update_link_state(&mut self) {
+        self.link_state = if self.is_uplink_connected_for(IpFamily::default()) {
+            LinkState::Up
+        } else {
+            LinkState::Down
+        };
+
+        self.state_waker.wake();
+        self.connection_waker.wake();
+    }
+
+    /// Take the link down while the module is reset and re-initialized.
+    fn mark_resetting(&mut self) {
+        if matches!(self.init_status, InitStatus::Failed { .. }) {
+            self.init_failures = 0;
+        }
+        self.init_status = InitStatus::Pending;
+        self.should_connect = false;
+        self.wifi_connection.reset();
+        self.bridges_active = [false; 2];
+        self.ethernet = None;
+        self.ethernet_connection = EthernetConnection::new();
+        self.uplink = Uplink::WiFi;
+        self.link_state = LinkState::Uninitialized;
+        self.state_waker.wake();
+        self.connection_waker.wake();
+    }
 }
 
 #[derive(Clone)]
@@ -61,10 +239,12 @@ impl<'d> Runner<'d> {
         }
     }
 
-    pub(crate) fn mark_initialized(&self) {
+    pub(crate) fn mark_initialized(&self, info: ModuleInfo) {
         self.shared.lock(|s| {
             let s = &mut *s.borrow_mut();
             s.link_state = LinkState::Down;
+            s.init_failures = 0;
+            s.init_status = InitStatus::Initialized(info);
             s.state_waker.wake();
         })
     }
@@ -73,10 +253,59 @@ impl<'d> Runner<'d> {
         self.shared.lock(|s| {
             let s = &mut *s.borrow_mut();
             s.link_state = LinkState::Uninitialized;
+            if matches!(s.init_status, InitStatus::Initialized(_)) {
+                s.init_status = InitStatus::Pending;
+            }
             s.state_waker.wake();
         })
     }
 
+    /// Record a failed initialization attempt.
+    ///
+    /// Returns `true` if `max_attempts` is reached, in which case the runner
+    /// should give up until a reset is requested.
+    pub(crate) fn mark_init_failed(&self, error: Error, max_attempts: Option<u32>) -> bool {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.init_failures = s.init_failures.saturating_add(1);
+            let attempts = s.init_failures;
+            let failed = max_attempts.is_some_and(|max| attempts >= max);
+            s.init_status = if failed {
+                InitStatus::Failed { error, attempts }
+            } else {
+                InitStatus::InitFailed { error, attempts }
+            };
+            s.state_waker.wake();
+            failed
+        })
+    }
+
+    pub(crate) fn init_status(&self, cx: Option<&mut Context>) -> InitStatus {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            if let Some(cx) = cx {
+                s.state_waker.register(cx.waker());
+            }
+            s.init_status.clone()
+        })
+    }
+
+    /// Wait for the initialization status to differ from `old_status`.
+    pub(crate) async fn wait_for_init_status_change(&self) -> InitStatus {
+        let old_status = self.init_status(None);
+
+        poll_fn(|cx| {
+            let new_status = self.init_status(Some(cx));
+            if core::mem::discriminant(&old_status) != core::mem::discriminant(&new_status)
+                || init_attempts(&old_status) != init_attempts(&new_status)
+            {
+                return Poll::Ready(new_status);
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
     pub(crate) fn set_should_connect(&self, should_connect: bool) {
         self.shared.lock(|s| {
             let s = &mut *s.borrow_mut();
@@ -85,20 +314,419 @@ impl<'d> Runner<'d> {
         })
     }
 
-    pub(crate) async fn wait_for_initialized(&self) {
-        if self.link_state(None) != LinkState::Uninitialized {
-            return;
-        }
+    pub(crate) fn set_firmware_update(&self, baud: Option<u32>) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.firmware_update = baud;
+            s.state_waker.wake();
+        })
+    }
 
+    pub(crate) fn firmware_update(&self, cx: Option<&mut Context>) -> Option<u32> {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            if let Some(cx) = cx {
+                s.state_waker.register(cx.waker());
+            }
+            s.firmware_update
+        })
+    }
+
+    /// Wait for a firmware update to be requested, returning the boot loader
+    /// baud rate.
+    pub(crate) async fn wait_firmware_update_start(&self) -> u32 {
+        poll_fn(|cx| match self.firmware_update(Some(cx)) {
+            Some(baud) => Poll::Ready(baud),
+            None => Poll::Pending,
+        })
+        .await
+    }
+
+    pub(crate) async fn wait_firmware_update_end(&self) {
+        poll_fn(|cx| match self.firmware_update(Some(cx)) {
+            Some(_) => Poll::Pending,
+            None => Poll::Ready(()),
+        })
+        .await
+    }
+
+    /// Request the runner to reset and re-initialize the module.
+    ///
+    /// The link goes down immediately, and the module is considered
+    /// uninitialized until the runner has completed the reset.
+    pub(crate) fn request_reset(&self) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.reset_requested = true;
+            s.mark_resetting();
+        })
+    }
+
+    /// Wait for a module reset to be requested, and take the request.
+    pub(crate) async fn wait_reset_requested(&self) {
         poll_fn(|cx| {
-            if self.link_state(Some(cx)) != LinkState::Uninitialized {
-                return Poll::Ready(());
+            self.shared.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                if s.reset_requested {
+                    s.reset_requested = false;
+                    s.module_resets = s.module_resets.wrapping_add(1);
+                    s.stack_waker.wake();
+                    Poll::Ready(())
+                } else {
+                    s.state_waker.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    /// Request the runner to follow the module to `baud_rate`, after it has
+    /// been rebooted with the new RS232 settings stored.
+    ///
+    /// Like a reset, the link goes down immediately, and the module is
+    /// considered uninitialized until the runner has re-initialized it.
+    pub(crate) fn request_baud_rate_change(&self, baud_rate: BaudRate) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.baud_rate_change = Some(baud_rate);
+            s.baud_rate_changed = None;
+            s.mark_resetting();
+        })
+    }
+
+    /// Wait for a baud rate change to be requested, and take the request.
+    pub(crate) async fn wait_baud_rate_change_requested(&self) -> BaudRate {
+        poll_fn(|cx| {
+            self.shared.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                match s.baud_rate_change.take() {
+                    Some(baud_rate) => {
+                        s.module_resets = s.module_resets.wrapping_add(1);
+                        s.stack_waker.wake();
+                        Poll::Ready(baud_rate)
+                    }
+                    None => {
+                        s.state_waker.register(cx.waker());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await
+    }
+
+    /// Record whether the module answered at the new baud rate.
+    pub(crate) fn set_baud_rate_changed(&self, result: Result<(), Error>) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.baud_rate_changed = Some(result);
+            s.state_waker.wake();
+        })
+    }
+
+    /// Wait for the runner to have followed the module to the new baud
+    /// rate, and take the outcome.
+    pub(crate) async fn wait_baud_rate_changed(&self) -> Result<(), Error> {
+        poll_fn(|cx| {
+            self.shared.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                match s.baud_rate_changed.take() {
+                    Some(result) => Poll::Ready(result),
+                    None => {
+                        s.state_waker.register(cx.waker());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await
+    }
+
+    /// Request a reset after the module stopped responding, counting the
+    /// recovery.
+    pub(crate) fn mark_watchdog_recovery(&self) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.watchdog_recoveries = s.watchdog_recoveries.wrapping_add(1);
+        });
+        self.request_reset();
+    }
+
+    pub(crate) fn watchdog_recoveries(&self) -> u32 {
+        self.shared.lock(|s| s.borrow().watchdog_recoveries)
+    }
+
+    pub(crate) fn set_at_transport(&self, status: AtTransportStatus) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            if s.at_transport != status {
+                s.at_transport = status;
+                s.state_waker.wake();
+            }
+        })
+    }
+
+    /// Record that the UDP socket tunneling AT commands was reopened.
+    pub(crate) fn mark_at_reconnect(&self) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.at_reconnects = s.at_reconnects.wrapping_add(1);
+        });
+    }
+
+    pub(crate) fn at_transport(&self, cx: Option<&mut Context>) -> AtTransportStatus {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            if let Some(cx) = cx {
+                s.state_waker.register(cx.waker());
+            }
+            s.at_transport
+        })
+    }
+
+    /// Wait for the AT transport status to differ from the current one.
+    pub(crate) async fn wait_for_at_transport_change(&self) -> AtTransportStatus {
+        let old_status = self.at_transport(None);
+
+        poll_fn(|cx| {
+            let new_status = self.at_transport(Some(cx));
+            if new_status != old_status {
+                return Poll::Ready(new_status);
             }
             Poll::Pending
         })
         .await
     }
 
+    pub(crate) fn at_reconnects(&self) -> u32 {
+        self.shared.lock(|s| s.borrow().at_reconnects)
+    }
+
+    pub(crate) fn record_nvm_store(&self) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.nvm_stores = s.nvm_stores.wrapping_add(1);
+        });
+    }
+
+    pub(crate) fn nvm_stores(&self) -> u32 {
+        self.shared.lock(|s| s.borrow().nvm_stores)
+    }
+
+    pub(crate) fn module_resets(&self) -> u32 {
+        self.shared.lock(|s| s.borrow().module_resets)
+    }
+
+    pub(crate) fn set_network_error(&self, reason: NetworkErrorReason) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.network_error = Some(reason);
+            s.network_errors = s.network_errors.wrapping_add(1);
+            s.state_waker.wake();
+        })
+    }
+
+    pub(crate) fn network_error(&self) -> Option<NetworkErrorReason> {
+        self.shared.lock(|s| s.borrow().network_error)
+    }
+
+    pub(crate) fn network_errors(&self, cx: Option<&mut Context>) -> u32 {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            if let Some(cx) = cx {
+                s.state_waker.register(cx.waker());
+            }
+            s.network_errors
+        })
+    }
+
+    /// Wait for a network error to be reported after `network_errors` were.
+    pub(crate) async fn wait_for_network_error(&self, network_errors: u32) -> NetworkErrorReason {
+        poll_fn(|cx| {
+            if self.network_errors(Some(cx)) == network_errors {
+                return Poll::Pending;
+            }
+            Poll::Ready(self.network_error().unwrap())
+        })
+        .await
+    }
+
+    /// Mark the network as intentionally left.
+    ///
+    /// Disables reconnecting, and invalidates all sockets, as their peers are
+    /// dropped by the module along with the network.
+    pub(crate) fn mark_left(&self) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.should_connect = false;
+            s.leaves = s.leaves.wrapping_add(1);
+            s.state_waker.wake();
+            s.stack_waker.wake();
+        })
+    }
+
+    pub(crate) fn leaves(&self) -> u32 {
+        self.shared.lock(|s| s.borrow().leaves)
+    }
+
+    pub(crate) fn set_open_sockets(&self, open_sockets: usize) {
+        self.shared
+            .lock(|s| s.borrow_mut().open_sockets = open_sockets)
+    }
+
+    pub(crate) fn open_sockets(&self) -> usize {
+        self.shared.lock(|s| s.borrow().open_sockets)
+    }
+
+    pub(crate) fn set_bridge_active(&self, config_id: BridgeConfigId, active: bool) {
+        self.shared
+            .lock(|s| s.borrow_mut().bridges_active[config_id as usize] = active)
+    }
+
+    pub(crate) fn bridge_active(&self, config_id: BridgeConfigId) -> bool {
+        self.shared
+            .lock(|s| s.borrow().bridges_active[config_id as usize])
+    }
+
+    /// Whether the module reported the Ethernet network interface, and the
+    /// PHY reports a link.
+    pub(crate) fn ethernet_active(&self) -> bool {
+        self.shared.lock(|s| {
+            let eth = &s.borrow().ethernet_connection;
+            eth.interface_id.is_some() && eth.link_up
+        })
+    }
+
+    pub(crate) fn set_ethernet_link_status(&self, status: EthernetLinkStatus) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.ethernet_connection.link_up = status.link_up;
+            s.ethernet = Some(status);
+            if s.link_state != LinkState::Uninitialized {
+                s.update_link_state();
+            }
+        })
+    }
+
+    pub(crate) fn set_ethernet_link_down(&self) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            if let Some(status) = s.ethernet.as_mut() {
+                status.link_up = false;
+            }
+            s.ethernet_connection.link_up = false;
+            if s.link_state != LinkState::Uninitialized {
+                s.update_link_state();
+            }
+        })
+    }
+
+    pub(crate) fn update_ethernet_with(&self, f: impl FnOnce(&mut EthernetConnection)) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            f(&mut s.ethernet_connection);
+            if s.link_state != LinkState::Uninitialized {
+                s.update_link_state();
+            }
+        })
+    }
+
+    /// Select the interface whose connection determines the link state.
+    pub(crate) fn set_uplink(&self, uplink: Uplink) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.uplink = uplink;
+            if s.link_state != LinkState::Uninitialized {
+                s.update_link_state();
+            }
+        })
+    }
+
+    pub(crate) fn uplink(&self) -> Uplink {
+        self.shared.lock(|s| s.borrow().uplink)
+    }
+
+    pub(crate) fn set_wifi_interface_id(&self, interface_id: u8) {
+        self.shared
+            .lock(|s| s.borrow_mut().wifi_interface_id = Some(interface_id))
+    }
+
+    /// Network interface id of the Wi-Fi station or Ethernet interface, once
+    /// the module has reported it.
+    pub(crate) fn network_interface_id(&self, interface: &InterfaceID) -> Option<u8> {
+        self.shared.lock(|s| {
+            let s = s.borrow();
+            match interface {
+                InterfaceID::WiFi => s.wifi_interface_id,
+                InterfaceID::Ethernet => s.ethernet_connection.interface_id,
+                _ => None,
+            }
+        })
+    }
+
+    /// Network interface id of the selected uplink, falling back to `0`
+    /// until the module has reported it.
+    pub(crate) fn uplink_interface_id(&self) -> u8 {
+        self.shared.lock(|s| {
+            let s = s.borrow();
+            match s.uplink {
+                Uplink::WiFi => s.wifi_interface_id,
+                Uplink::Ethernet => s.ethernet_connection.interface_id,
+            }
+            .unwrap_or(0)
+        })
+    }
+
+    pub(crate) fn ethernet_link_status(&self) -> Option<EthernetLinkStatus> {
+        self.shared.lock(|s| s.borrow().ethernet.clone())
+    }
+
+    /// Wait for the module to be reset or the network to be left since
+    /// `module_resets` and `leaves` were read.
+    pub(crate) async fn wait_stack_update(&self, module_resets: u32, leaves: u32) {
+        poll_fn(|cx| {
+            self.shared.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                if s.module_resets != module_resets || s.leaves != leaves {
+                    Poll::Ready(())
+                } else {
+                    s.stack_waker.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    pub(crate) fn module_info(&self) -> Option<ModuleInfo> {
+        self.shared.lock(|s| match &s.borrow().init_status {
+            InitStatus::Initialized(info) => Some(info.clone()),
+            _ => None,
+        })
+    }
+
+    /// Wait for the module to be initialized.
+    ///
+    /// Fails with [`Error::Uninitialized`] if the runner has given up
+    /// initializing the module.
+    pub(crate) async fn wait_for_initialized(&self) -> Result<(), Error> {
+        poll_fn(|cx| {
+            self.shared.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                if matches!(s.init_status, InitStatus::Failed { .. }) {
+                    return Poll::Ready(Err(Error::Uninitialized));
+                }
+                if s.link_state != LinkState::Uninitialized {
+                    return Poll::Ready(Ok(()));
+                }
+                s.state_waker.register(cx.waker());
+                Poll::Pending
+            })
+        })
+        .await
+    }
+
     pub(crate) fn link_state(&self, cx: Option<&mut Context>) -> LinkState {
         self.shared.lock(|s| {
             let s = &mut *s.borrow_mut();
@@ -123,6 +751,35 @@ impl<'d> Runner<'d> {
         .await
     }
 
+    /// Link state as seen from the given IP address family.
+    pub(crate) fn link_state_for(&self, family: IpFamily, cx: Option<&mut Context>) -> LinkState {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            if let Some(cx) = cx {
+                s.state_waker.register(cx.waker());
+            }
+            match s.link_state {
+                LinkState::Uninitialized => LinkState::Uninitialized,
+                _ if s.is_uplink_connected_for(family) => LinkState::Up,
+                _ => LinkState::Down,
+            }
+        })
+    }
+
+    pub(crate) async fn wait_for_link_state_for(&self, ls: LinkState, family: IpFamily) {
+        if self.link_state_for(family, None) == ls {
+            return;
+        }
+
+        poll_fn(|cx| {
+            if self.link_state_for(family, Some(cx)) == ls {
+                return Poll::Ready(());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
     pub(crate) fn update_connection_with(&self, f: impl FnOnce(&mut WifiConnection)) {
         self.shared.lock(|s| {
             let s = &mut *s.borrow_mut();
@@ -132,14 +789,22 @@ impl<'d> Runner<'d> {
                 s.wifi_connection.is_connected()
             );
 
-            s.link_state = if s.wifi_connection.is_connected() {
-                LinkState::Up
-            } else {
-                LinkState::Down
-            };
+            s.update_link_state();
+        })
+    }
 
-            s.state_waker.wake();
-            s.connection_waker.wake();
+    pub(crate) fn link_rssi(&self) -> Option<i16> {
+        self.shared.lock(|s| s.borrow().wifi_connection.rssi)
+    }
+
+    pub(crate) fn ap_channel(&self) -> Option<u8> {
+        self.shared.lock(|s| {
+            s.borrow()
+                .wifi_connection
+                .network
+                .as_ref()
+                .filter(|n| n.mode == WifiMode::AccessPoint && n.channel != 0)
+                .map(|n| n.channel)
         })
     }
 
@@ -214,3 +879,218 @@ impl<'d> Runner<'d> {
         .await
     }
 }
+
+fn init_attempts(status: &InitStatus) -> u32 {
+    match status {
+        InitStatus::InitFailed { attempts, .. } | InitStatus::Failed { attempts, .. } => *attempts,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asynch::test_support::block_on;
+
+    #[test]
+    fn reset_request_takes_link_down() {
+        let mut state = State::new();
+        let ch = Runner::new(&mut state);
+        ch.mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(8, 0, 0)));
+        ch.set_should_connect(true);
+        ch.set_open_sockets(2);
+
+        ch.request_reset();
+        assert_eq!(ch.link_state(None), LinkState::Uninitialized);
+        assert_eq!(ch.wifi_state(None), WiFiState::Inactive);
+        assert!(!ch.is_connected(None));
+
+        let resets = ch.module_resets();
+        block_on(ch.wait_reset_requested());
+        assert_eq!(ch.module_resets(), resets.wrapping_add(1));
+    }
+
+    #[test]
+    fn ethernet_link_down_keeps_config() {
+        let mut state = State::new();
+        let ch = Runner::new(&mut state);
+        assert_eq!(ch.ethernet_link_status(), None);
+
+        // Link down before any link up is not reported
+        ch.set_ethernet_link_down();
+        assert_eq!(ch.ethernet_link_status(), None);
+
+        let status = EthernetLinkStatus {
+            link_up: true,
+            speed_mbps: 100,
+            full_duplex: true,
+            mac: [0x00, 0x12, 0xf3, 0x01, 0x02, 0x03],
+        };
+        ch.set_ethernet_link_status(status.clone());
+        ch.set_ethernet_link_down();
+        assert_eq!(
+            ch.ethernet_link_status(),
+            Some(EthernetLinkStatus {
+                link_up: false,
+                ..status
+            })
+        );
+    }
+
+    #[test]
+    fn ethernet_uplink_link_state() {
+        let mut state = State::new();
+        let ch = Runner::new(&mut state);
+        ch.mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(8, 0, 0)));
+        ch.set_wifi_interface_id(1);
+        ch.update_ethernet_with(|eth| {
+            eth.interface_id = Some(3);
+            eth.ipv4_up = true;
+            eth.ipv6_link_local_up = true;
+        });
+
+        // Ethernet is ignored while WiFi is the uplink
+        assert_eq!(ch.link_state(None), LinkState::Down);
+        assert_eq!(ch.uplink_interface_id(), 1);
+
+        ch.set_uplink(Uplink::Ethernet);
+        assert_eq!(ch.uplink_interface_id(), 3);
+        assert_eq!(ch.link_state(None), LinkState::Down);
+
+        ch.set_ethernet_link_status(EthernetLinkStatus {
+            link_up: true,
+            speed_mbps: 100,
+            full_duplex: true,
+            mac: [0x00, 0x12, 0xf3, 0x01, 0x02, 0x03],
+        });
+        assert_eq!(ch.link_state(None), LinkState::Up);
+
+        ch.set_ethernet_link_down();
+        assert_eq!(ch.link_state(None), LinkState::Down);
+
+        ch.set_uplink(Uplink::WiFi);
+        assert_eq!(ch.uplink_interface_id(), 1);
+    }
+
+    #[test]
+    fn reset_clears_ethernet() {
+        let mut state = State::new();
+        let ch = Runner::new(&mut state);
+        ch.mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(8, 0, 0)));
+        ch.set_uplink(Uplink::Ethernet);
+        ch.update_ethernet_with(|eth| {
+            eth.interface_id = Some(3);
+            eth.ipv4_up = true;
+            eth.ipv6_link_local_up = true;
+        });
+        let status = EthernetLinkStatus {
+            link_up: true,
+            speed_mbps: 100,
+            full_duplex: true,
+            mac: [0x00, 0x12, 0xf3, 0x01, 0x02, 0x03],
+        };
+        ch.set_ethernet_link_status(status.clone());
+        assert_eq!(ch.link_state(None), LinkState::Up);
+
+        ch.request_reset();
+        assert_eq!(ch.uplink(), Uplink::WiFi);
+        assert_eq!(ch.ethernet_link_status(), None);
+
+        // Ethernet URCs during initialization keep the link uninitialized
+        ch.set_uplink(Uplink::Ethernet);
+        ch.set_ethernet_link_status(status);
+        assert_eq!(ch.link_state(None), LinkState::Uninitialized);
+    }
+
+    #[test]
+    fn init_gives_up_after_max_attempts() {
+        let mut state = State::new();
+        let ch = Runner::new(&mut state);
+
+        assert!(!ch.mark_init_failed(Error::BaudDetection, Some(2)));
+        assert!(matches!(
+            ch.init_status(None),
+            InitStatus::InitFailed { attempts: 1, .. }
+        ));
+        assert!(ch.mark_init_failed(Error::BaudDetection, Some(2)));
+        assert!(matches!(
+            ch.init_status(None),
+            InitStatus::Failed { attempts: 2, .. }
+        ));
+        assert!(matches!(
+            block_on(ch.wait_for_initialized()),
+            Err(Error::Uninitialized)
+        ));
+
+        // A reset request re-arms initialization
+        ch.request_reset();
+        assert!(matches!(ch.init_status(None), InitStatus::Pending));
+        assert!(!ch.mark_init_failed(Error::BaudDetection, Some(2)));
+
+        let info = ModuleInfo::with_version(FirmwareVersion::new(8, 0, 0));
+        ch.mark_initialized(info.clone());
+        assert!(matches!(ch.init_status(None), InitStatus::Initialized(_)));
+        assert!(block_on(ch.wait_for_initialized()).is_ok());
+        assert_eq!(ch.module_info(), Some(info));
+
+        // The identification goes along with the initialized status
+        ch.mark_uninitialized();
+        assert_eq!(ch.module_info(), None);
+    }
+
+    #[test]
+    fn watchdog_recovery_resets_module() {
+        let mut state = State::new();
+        let ch = Runner::new(&mut state);
+        ch.mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(8, 0, 0)));
+
+        ch.mark_watchdog_recovery();
+
+        assert_eq!(ch.watchdog_recoveries(), 1);
+        assert_eq!(ch.link_state(None), LinkState::Uninitialized);
+        block_on(ch.wait_reset_requested());
+        assert_eq!(ch.module_resets(), 1);
+    }
+
+    #[test]
+    fn baud_rate_change_resets_module() {
+        let mut state = State::new();
+        let ch = Runner::new(&mut state);
+        ch.mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(8, 0, 0)));
+
+        ch.request_baud_rate_change(BaudRate::B921600);
+        assert_eq!(ch.link_state(None), LinkState::Uninitialized);
+        assert!(matches!(ch.init_status(None), InitStatus::Pending));
+
+        assert_eq!(
+            block_on(ch.wait_baud_rate_change_requested()),
+            BaudRate::B921600
+        );
+        assert_eq!(ch.module_resets(), 1);
+
+        ch.set_baud_rate_changed(Err(Error::Timeout));
+        assert!(matches!(
+            block_on(ch.wait_baud_rate_changed()),
+            Err(Error::Timeout)
+        ));
+    }
+
+    #[test]
+    fn leave_disables_reconnect() {
+        let mut state = State::new();
+        let ch = Runner::new(&mut state);
+        ch.mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(8, 0, 0)));
+        ch.set_should_connect(true);
+        ch.update_connection_with(|con| {
+            con.wifi_state = WiFiState::Connected;
+            con.ipv4_up = true;
+            con.ipv6_link_local_up = true;
+        });
+        assert!(ch.is_connected(None));
+
+        let leaves = ch.leaves();
+        ch.mark_left();
+        assert_eq!(ch.leaves(), leaves.wrapping_add(1));
+        assert!(!ch.is_connected(None));
+    }
+}
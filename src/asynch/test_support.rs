@@ -0,0 +1,149 @@
+//! Helpers shared by the host-side tests.
+//!
+//! Tests run on the mock time driver of `embassy-time`, so timeouts and
+//! delays take no wall-clock time and do not depend on the load of the host.
+
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Wake;
+
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel, pipe::Pipe};
+use embassy_time::{Duration, MockDriver};
+
+use super::control::Control;
+use super::runner::{DEFAULT_CMD_BUF_SIZE, FW_RX_SIZE};
+use super::state::{self, ModuleInfo};
+use super::Resources;
+use crate::command::general::types::FirmwareVersion;
+
+/// Step the clock is advanced by while all tasks wait for a timer.
+const TICK: Duration = Duration::from_millis(1);
+/// Longest a test may wait for a timer, before it is considered stuck.
+const MAX_IDLE: Duration = Duration::from_secs(3600);
+
+static CLOCK: Mutex<()> = Mutex::new(());
+
+/// Take exclusive control of the clock, which is shared by all tests.
+///
+/// Held by [`block_on`], so tests advancing the clock do not fire the timers
+/// of another test early.
+pub(crate) fn lock_clock() -> MutexGuard<'static, ()> {
+    CLOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Advance the clock by `duration`, firing the timers due by then.
+pub(crate) fn advance(duration: Duration) {
+    MockDriver::get().advance(duration);
+}
+
+struct WakeFlag(AtomicBool);
+
+impl Wake for WakeFlag {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Run `fut` to completion, like `embassy_futures::block_on`.
+///
+/// Whenever `fut` waits without having been woken, i.e. only a timer can
+/// make progress, the clock is advanced to fire the next timer.
+pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+    let _clock = lock_clock();
+
+    let flag = Arc::new(WakeFlag(AtomicBool::new(true)));
+    let waker = flag.clone().into();
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = pin!(fut);
+
+    let mut idle = Duration::from_ticks(0);
+    loop {
+        if flag.0.swap(false, Ordering::Relaxed) {
+            if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                return output;
+            }
+            idle = Duration::from_ticks(0);
+        } else {
+            assert!(idle < MAX_IDLE, "test stuck waiting for {:?}", idle);
+            advance(TICK);
+            idle += TICK;
+        }
+    }
+}
+
+/// Resources of a test, leaked so that handles on them live as long as
+/// needed.
+pub(crate) fn leak_resources<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>(
+) -> &'static mut Resources<INGRESS_BUF_SIZE, URC_CAPACITY> {
+    Box::leak(Box::new(Resources::new()))
+}
+
+/// [`Control`] handle of a test, without a runner. The test answers the
+/// requests of the handle itself through [`ControlFixture::module`].
+pub(crate) struct ControlFixture {
+    pub ch: state::Runner<'static>,
+    pub control: Control<'static, 256, 2>,
+    pub module: TestModule,
+}
+
+impl ControlFixture {
+    /// Handle on a module that is not initialized yet.
+    pub fn new() -> Self {
+        let resources = leak_resources::<256, 2>();
+        let ch = state::Runner::new(&mut resources.ch);
+        let control = Control::new(
+            ch.clone(),
+            &resources.urc_channel,
+            resources.req_slot.sender(),
+            &resources.res_slot,
+            &resources.cmd_lock,
+            &resources.fw_rx,
+        );
+
+        Self {
+            ch,
+            control,
+            module: TestModule {
+                req_slot: &resources.req_slot,
+                res_slot: &resources.res_slot,
+                fw_rx: &resources.fw_rx,
+            },
+        }
+    }
+
+    /// Handle on a module initialized with firmware 8.0.0.
+    pub fn initialized() -> Self {
+        let fixture = Self::new();
+        fixture
+            .ch
+            .mark_initialized(ModuleInfo::with_version(FirmwareVersion::new(8, 0, 0)));
+        fixture
+    }
+}
+
+/// Module end of a [`ControlFixture`].
+pub(crate) struct TestModule {
+    pub req_slot: &'static Channel<NoopRawMutex, heapless::Vec<u8, DEFAULT_CMD_BUF_SIZE>, 1>,
+    pub res_slot: &'static atat::ResponseSlot<256>,
+    pub fw_rx: &'static Pipe<NoopRawMutex, FW_RX_SIZE>,
+}
+
+impl TestModule {
+    /// Answer every request with the response `respond` returns for it.
+    pub async fn serve(&self, mut respond: impl FnMut(&str) -> &'static [u8]) -> ! {
+        loop {
+            let req = self.req_slot.receive().await;
+            let resp = respond(core::str::from_utf8(&req).unwrap());
+            self.res_slot.signal_response(Ok(resp)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Loopback throughput benchmark against a TCP echo server, used during
+//! hardware bring-up to validate the radio configuration.
+//!
+//! [`Control`](super::control::Control) does not own any sockets, so the
+//! benchmark runs over a TCP socket of the network stack in use, connected
+//! to the echo server by the caller.
+
+use embassy_time::{with_timeout, Duration, Instant};
+use embedded_io_async::{Read, Write};
+
+/// Number of bytes written or read at a time.
+const CHUNK_LEN: usize = 256;
+/// Longest a payload may take to be sent and echoed back.
+const ROUND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Error returned when measuring the throughput.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The payload size is zero, or larger than the socket buffers.
+    BadLength,
+    /// Reading or writing the socket failed.
+    Io(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] embedded_io_async::ErrorKind),
+    /// The connection was closed before the payload was echoed back.
+    Closed,
+    /// The echoed data differs from the payload sent.
+    Mismatch,
+    /// A payload was not echoed back in time.
+    Timeout,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BadLength => write!(f, "payload size must fit the socket buffers"),
+            Self::Io(kind) => write!(f, "socket error: {:?}", kind),
+            Self::Closed => write!(f, "connection closed by echo server"),
+            Self::Mismatch => write!(f, "echoed data differs from payload"),
+            Self::Timeout => write!(f, "payload not echoed back in time"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// Outcome of [`measure_throughput`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ThroughputResult {
+    pub tx_bytes_per_sec: f32,
+    pub rx_bytes_per_sec: f32,
+    /// Average time for a payload to be echoed back, in milliseconds.
+    pub rtt_ms: u32,
+}
+
+/// Byte at `offset` in the payload, a pattern that does not repeat at
+/// power of two boundaries.
+fn pattern(offset: usize) -> u8 {
+    (offset % 251) as u8
+}
+
+fn rate(bytes: u64, elapsed: Duration) -> f32 {
+    match elapsed.as_micros() {
+        0 => 0.0,
+        micros => bytes as f32 * 1_000_000.0 / micros as f32,
+    }
+}
+
+/// Send a payload of `payload_size` bytes to the echo server at the other
+/// end of `socket` repeatedly for `duration`, reading each echo back before
+/// sending the next.
+///
+/// Nothing is read while the payload is being sent, so it must fit in
+/// `buffer_len`, the smaller of the receive and transmit buffers of the
+/// socket. Each round fails with [`Error::Timeout`] if the payload is not
+/// echoed back within 10 seconds.
+pub async fn measure_throughput<S: Read + Write>(
+    socket: &mut S,
+    buffer_len: usize,
+    payload_size: usize,
+    duration: Duration,
+) -> Result<ThroughputResult, Error> {
+    if payload_size == 0 || payload_size > buffer_len {
+        return Err(Error::BadLength);
+    }
+
+    let mut buf = [0; CHUNK_LEN];
+    let mut tx_bytes = 0u64;
+    let mut rx_bytes = 0u64;
+    let mut rounds = 0u32;
+    let mut echo_time = Duration::from_ticks(0);
+
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        let round_start = Instant::now();
+
+        let round = async {
+            let mut offset = 0;
+            while offset < payload_size {
+                let len = CHUNK_LEN.min(payload_size - offset);
+                for (i, b) in buf[..len].iter_mut().enumerate() {
+                    *b = pattern(offset + i);
+                }
+                socket
+                    .write_all(&buf[..len])
+                    .await
+                    .map_err(|e| Error::Io(e.kind()))?;
+                offset += len;
+                tx_bytes += len as u64;
+            }
+            socket.flush().await.map_err(|e| Error::Io(e.kind()))?;
+
+            let mut offset = 0;
+            while offset < payload_size {
+                let len = CHUNK_LEN.min(payload_size - offset);
+                let n = socket
+                    .read(&mut buf[..len])
+                    .await
+                    .map_err(|e| Error::Io(e.kind()))?;
+                if n == 0 {
+                    return Err(Error::Closed);
+                }
+                if buf[..n]
+                    .iter()
+                    .enumerate()
+                    .any(|(i, b)| *b != pattern(offset + i))
+                {
+                    return Err(Error::Mismatch);
+                }
+                offset += n;
+                rx_bytes += n as u64;
+            }
+            Ok::<_, Error>(())
+        };
+        with_timeout(ROUND_TIMEOUT, round)
+            .await
+            .map_err(|_| Error::Timeout)??;
+
+        echo_time += round_start.elapsed();
+        rounds += 1;
+    }
+
+    let elapsed = start.elapsed();
+    Ok(ThroughputResult {
+        tx_bytes_per_sec: rate(tx_bytes, elapsed),
+        rx_bytes_per_sec: rate(rx_bytes, elapsed),
+        rtt_ms: (echo_time.as_millis() / u64::from(rounds.max(1))) as u32,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asynch::test_support::block_on;
+    use core::convert::Infallible;
+    use std::collections::VecDeque;
+
+    /// Echo server at the other end of a socket, flipping the bits of the
+    /// echoed data if `corrupt`, and never answering if `silent`.
+    #[derive(Default)]
+    struct Echo {
+        pending: VecDeque<u8>,
+        corrupt: bool,
+        silent: bool,
+    }
+
+    impl embedded_io_async::ErrorType for Echo {
+        type Error = Infallible;
+    }
+
+    impl Read for Echo {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            if self.silent {
+                core::future::pending::<()>().await;
+            }
+            let n = buf.len().min(self.pending.len());
+            for b in &mut buf[..n] {
+                *b = self.pending.pop_front().unwrap();
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for Echo {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            // Link latency, letting the clock advance
+            embassy_time::Timer::after_millis(1).await;
+            let corrupt = if self.corrupt { 0xff } else { 0 };
+            self.pending.extend(buf.iter().map(|b| b ^ corrupt));
+            Ok(buf.len())
+        }
+    }
+
+    #[test]
+    fn loopback() {
+        let mut echo = Echo::default();
+        let result = block_on(measure_throughput(
+            &mut echo,
+            1024,
+            600,
+            Duration::from_millis(10),
+        ))
+        .unwrap();
+
+        assert!(result.tx_bytes_per_sec > 0.0);
+        assert_eq!(result.tx_bytes_per_sec, result.rx_bytes_per_sec);
+        assert!(echo.pending.is_empty());
+    }
+
+    #[test]
+    fn corrupted_echo() {
+        let mut echo = Echo {
+            corrupt: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            block_on(measure_throughput(
+                &mut echo,
+                1024,
+                600,
+                Duration::from_millis(10)
+            )),
+            Err(Error::Mismatch)
+        );
+    }
+
+    #[test]
+    fn unanswered_echo() {
+        let mut echo = Echo {
+            silent: true,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        assert_eq!(
+            block_on(measure_throughput(
+                &mut echo,
+                1024,
+                600,
+                Duration::from_millis(10)
+            )),
+            Err(Error::Timeout)
+        );
+        assert!(start.elapsed() >= ROUND_TIMEOUT);
+    }
+
+    #[test]
+    fn empty_payload() {
+        let mut echo = Echo::default();
+        assert_eq!(
+            block_on(measure_throughput(
+                &mut echo,
+                1024,
+                0,
+                Duration::from_millis(10)
+            )),
+            Err(Error::BadLength)
+        );
+
+        // Not read back until fully sent, so it would never be echoed
+        assert_eq!(
+            block_on(measure_throughput(
+                &mut echo,
+                1024,
+                1025,
+                Duration::from_millis(10)
+            )),
+            Err(Error::BadLength)
+        );
+        assert!(echo.pending.is_empty());
+    }
+}
@@ -2,10 +2,20 @@ use core::cell::RefCell;
 
 use atat::UrcChannel;
 
-use crate::asynch::{control::ProxyClient, runner::URC_SUBSCRIBERS, state, UbloxUrc};
+use crate::asynch::{
+    control::ProxyClient,
+    runner::{DEFAULT_CMD_BUF_SIZE, URC_SUBSCRIBERS},
+    state, UbloxUrc,
+};
 
-pub struct Device<'a, const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize> {
+pub struct Device<
+    'a,
+    const INGRESS_BUF_SIZE: usize,
+    const URC_CAPACITY: usize,
+    const CMD_BUF_SIZE: usize = DEFAULT_CMD_BUF_SIZE,
+> {
     pub(crate) state_ch: state::Runner<'a>,
-    pub(crate) at_client: RefCell<ProxyClient<'a, INGRESS_BUF_SIZE>>,
+    pub(crate) at_client: RefCell<ProxyClient<'a, INGRESS_BUF_SIZE, CMD_BUF_SIZE>>,
     pub(crate) urc_channel: &'a UrcChannel<UbloxUrc, URC_CAPACITY, URC_SUBSCRIBERS>,
+    pub(crate) direct_rx: &'a super::DirectRx,
 }
@@ -0,0 +1,129 @@
+//! Delivery of received socket data straight from the ingress buffer.
+//!
+//! Data events passed through the URC channel are copied into an
+//! [`EdmEvent`], moved through the channel and copied once more into the
+//! socket buffer. Once a [`UbloxStack`](super::UbloxStack) has been registered
+//! with [`UbloxStack::enable_direct_rx`](super::UbloxStack::enable_direct_rx),
+//! the [`DirectRxDigester`] writes data events into the buffer of the owning
+//! socket as they are digested instead.
+//!
+//! Data keeps taking the URC channel whenever delivering it directly could
+//! reorder it with the events still queued for the stack, or the socket has no
+//! room for it. Connect and disconnect events always take the URC channel, so
+//! other subscribers keep seeing them.
+
+use core::cell::RefCell;
+
+use atat::{DigestResult, Digester};
+use embassy_sync::blocking_mutex::{raw::NoopRawMutex, Mutex};
+use ublox_sockets::ChannelId;
+
+use super::SocketStack;
+use crate::command::edm::urc::{socket_frame, EdmEvent, SocketFrame};
+
+struct Inner {
+    stack: Option<&'static RefCell<SocketStack>>,
+    /// Socket events passed on to the URC channel, and not yet taken by the
+    /// stack.
+    in_flight: usize,
+}
+
+/// Registration of the stack receiving data directly, shared between the
+/// runner's digester and the stack.
+pub(crate) struct DirectRx {
+    inner: Mutex<NoopRawMutex, RefCell<Inner>>,
+}
+
+impl DirectRx {
+    pub(crate) const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(Inner {
+                stack: None,
+                in_flight: 0,
+            })),
+        }
+    }
+
+    pub(crate) fn register(&self, stack: &'static RefCell<SocketStack>) {
+        self.inner
+            .lock(|inner| inner.borrow_mut().stack = Some(stack));
+    }
+
+    /// Write `data` received on `channel_id` into the buffer of its socket.
+    ///
+    /// Returns `false` if the data must take the URC channel.
+    fn deliver(&self, channel_id: ChannelId, data: &[u8]) -> bool {
+        self.inner.lock(|inner| {
+            let inner = inner.borrow();
+            if inner.in_flight > 0 {
+                return false;
+            }
+            inner
+                .stack
+                .and_then(|stack| stack.try_borrow_mut().ok())
+                .is_some_and(|mut s| s.rx_direct(channel_id, data))
+        })
+    }
+
+    fn queued(&self) {
+        self.inner.lock(|inner| inner.borrow_mut().in_flight += 1);
+    }
+
+    /// Account for `event` taken from the URC channel by the stack.
+    pub(crate) fn dequeued(&self, event: &EdmEvent) {
+        if matches!(
+            event,
+            EdmEvent::IPv4ConnectEvent(_)
+                | EdmEvent::IPv6ConnectEvent(_)
+                | EdmEvent::DisconnectEvent(_)
+                | EdmEvent::DataEvent(_)
+        ) {
+            self.lagged(1);
+        }
+    }
+
+    /// Account for `n` events the stack missed on the URC channel.
+    pub(crate) fn lagged(&self, n: usize) {
+        self.inner.lock(|inner| {
+            let mut inner = inner.borrow_mut();
+            inner.in_flight = inner.in_flight.saturating_sub(n);
+        });
+    }
+
+    /// Forget the events published before the stack subscribed to the URC
+    /// channel, as it will never take them.
+    pub(crate) fn reset(&self) {
+        self.inner.lock(|inner| inner.borrow_mut().in_flight = 0);
+    }
+}
+
+/// Digester delivering the data events found by `D` directly to the
+/// registered stack.
+pub struct DirectRxDigester<'a, D> {
+    inner: D,
+    direct_rx: &'a DirectRx,
+}
+
+impl<'a, D: Digester> DirectRxDigester<'a, D> {
+    pub(crate) fn new(inner: D, direct_rx: &'a DirectRx) -> Self {
+        Self { inner, direct_rx }
+    }
+}
+
+impl<D: Digester> Digester for DirectRxDigester<'_, D> {
+    fn digest<'a>(&mut self, buf: &'a [u8]) -> (DigestResult<'a>, usize) {
+        let (result, len) = self.inner.digest(buf);
+        if let DigestResult::Urc(frame) = &result {
+            match socket_frame(frame) {
+                Some(SocketFrame::Data { channel_id, data })
+                    if self.direct_rx.deliver(channel_id, data) =>
+                {
+                    return (DigestResult::None, len);
+                }
+                Some(_) => self.direct_rx.queued(),
+                None => {}
+            }
+        }
+        (result, len)
+    }
+}
@@ -1,6 +1,6 @@
 use core::{cell::RefCell, future::poll_fn, task::Poll};
 
-use core::net::IpAddr;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use embassy_sync::waitqueue::WakerRegistration;
 use embedded_nal_async::AddrType;
 
@@ -20,16 +20,7 @@ pub enum Error {
     Failed,
 }
 
-/// From u-connectXpress AT commands manual:
-/// <domain> depends on the <scheme>. For internet domain names, the maximum
-/// length is 64 characters.
-/// Domain name length is 128 for NINA-W13 and NINA-W15 software version 4.0
-/// .0 or later.
-#[cfg(not(feature = "nina-w1xx"))]
-pub const MAX_DOMAIN_NAME_LENGTH: usize = 64;
-
-#[cfg(feature = "nina-w1xx")]
-pub const MAX_DOMAIN_NAME_LENGTH: usize = 128;
+pub use crate::module_caps::MAX_DOMAIN_NAME_LENGTH;
 
 pub struct DnsTableEntry {
     pub domain_name: heapless::String<MAX_DOMAIN_NAME_LENGTH>,
@@ -103,6 +94,27 @@ impl DnsTable {
     }
 }
 
+/// Resolve `name` without querying the module, if it is a numeric IP address
+/// or `localhost`.
+fn resolve_local(name: &str, addr_type: AddrType) -> Option<IpAddr> {
+    let want_v4 = matches!(addr_type, AddrType::IPv4 | AddrType::Either);
+    let want_v6 = matches!(addr_type, AddrType::IPv6 | AddrType::Either);
+
+    if name.eq_ignore_ascii_case("localhost") {
+        return Some(if want_v4 {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        } else {
+            IpAddr::V6(Ipv6Addr::LOCALHOST)
+        });
+    }
+
+    match name.parse().ok()? {
+        IpAddr::V4(ip) if want_v4 => Some(IpAddr::V4(ip)),
+        IpAddr::V6(ip) if want_v6 => Some(IpAddr::V6(ip)),
+        _ => None,
+    }
+}
+
 /// DNS client compatible with the `embedded-nal-async` traits.
 ///
 /// This exists only for compatibility with crates that use `embedded-nal-async`.
@@ -114,8 +126,13 @@ pub struct DnsSocket<'a> {
 
 impl<'a> DnsSocket<'a> {
     /// Create a new DNS socket using the provided stack.
-    pub fn new<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>(
-        stack: &'a UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+    pub fn new<
+        const INGRESS_BUF_SIZE: usize,
+        const URC_CAPACITY: usize,
+        const EGRESS_BUF_SIZE: usize,
+        const CMD_BUF_SIZE: usize,
+    >(
+        stack: &'a UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
     ) -> Self {
         Self {
             stack: &stack.socket,
@@ -124,18 +141,8 @@ impl<'a> DnsSocket<'a> {
 
     /// Make a query for a given name and return the corresponding IP addresses.
     pub async fn query(&self, name: &str, addr_type: AddrType) -> Result<IpAddr, Error> {
-        match addr_type {
-            AddrType::IPv4 => {
-                if let Ok(ip) = name.parse().map(IpAddr::V4) {
-                    return Ok(ip);
-                }
-            }
-            AddrType::IPv6 => {
-                if let Ok(ip) = name.parse().map(IpAddr::V6) {
-                    return Ok(ip);
-                }
-            }
-            _ => {}
+        if let Some(ip) = resolve_local(name, addr_type) {
+            return Ok(ip);
         }
 
         let name_string = heapless::String::try_from(name).map_err(|_| Error::NameTooLong)?;
@@ -181,3 +188,42 @@ impl<'a> embedded_nal_async::Dns for DnsSocket<'a> {
         unimplemented!()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numeric_addresses_resolve_locally() {
+        assert_eq!(
+            resolve_local("192.168.1.1", AddrType::IPv4),
+            Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)))
+        );
+        assert_eq!(
+            resolve_local("192.168.1.1", AddrType::Either),
+            Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)))
+        );
+        assert_eq!(
+            resolve_local("::1", AddrType::IPv6),
+            Some(IpAddr::V6(Ipv6Addr::LOCALHOST))
+        );
+        assert_eq!(resolve_local("192.168.1.1", AddrType::IPv6), None);
+    }
+
+    #[test]
+    fn localhost_resolves_locally() {
+        assert_eq!(
+            resolve_local("localhost", AddrType::Either),
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
+        assert_eq!(
+            resolve_local("localhost", AddrType::IPv6),
+            Some(IpAddr::V6(Ipv6Addr::LOCALHOST))
+        );
+    }
+
+    #[test]
+    fn hostnames_require_lookup() {
+        assert_eq!(resolve_local("example.com", AddrType::Either), None);
+    }
+}
@@ -0,0 +1,942 @@
+//! Scripted module for host-side tests of the [`UbloxStack`].
+//!
+//! [`MockModule`] stands in for the runner and the serial transport. It
+//! receives the raw commands sent by the stack, and feeds EDM packets back
+//! through the same ingress and digester the runner uses, so a test can
+//! script a sequence like "expect `ConnectPeer`, answer with a peer handle,
+//! then inject a connect event" and assert on what the sockets observe.
+
+use core::cell::RefCell;
+use core::net::SocketAddrV4;
+
+use atat::AtatIngress as _;
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
+use ublox_sockets::ChannelId;
+
+use super::{Device, DirectRxDigester, StackResources, UbloxStack};
+use crate::asynch::control::ProxyClient;
+use crate::asynch::runner::{DEFAULT_CMD_BUF_SIZE, URC_SUBSCRIBERS};
+use crate::asynch::test_support::leak_resources;
+use crate::asynch::{state, UbloxUrc};
+use crate::command::custom_digest::EdmDigester;
+use crate::command::edm::types::{PayloadType, Protocol, AT_COMMAND_POSITION, ENDBYTE, STARTBYTE};
+
+const INGRESS_BUF_SIZE: usize = 1024;
+const URC_CAPACITY: usize = 8;
+const SOCK: usize = 4;
+
+pub(crate) type MockStack = UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>;
+
+/// Create a stack on top of a scripted module.
+///
+/// The stack must be running, i.e. [`UbloxStack::run`] polled alongside the
+/// test, for the module to see any commands.
+pub(crate) fn mock_stack() -> (&'static MockStack, MockModule) {
+    let resources = leak_resources::<INGRESS_BUF_SIZE, URC_CAPACITY>();
+
+    let device = Device {
+        state_ch: state::Runner::new(&mut resources.ch),
+        at_client: RefCell::new(ProxyClient::new(
+            resources.req_slot.sender(),
+            &resources.res_slot,
+            &resources.cmd_lock,
+        )),
+        urc_channel: &resources.urc_channel,
+        direct_rx: &resources.direct_rx,
+    };
+    let stack = Box::leak(Box::new(MockStack::new(
+        device,
+        Box::leak(Box::new(StackResources::<SOCK>::new())),
+    )));
+
+    let module = MockModule {
+        req_slot: &resources.req_slot,
+        ingress: atat::Ingress::new(
+            DirectRxDigester::new(
+                EdmDigester::with_capacity(INGRESS_BUF_SIZE),
+                &resources.direct_rx,
+            ),
+            &mut resources.ingress_buf,
+            &resources.res_slot,
+            &resources.urc_channel,
+        ),
+    };
+
+    (stack, module)
+}
+
+pub(crate) struct MockModule {
+    req_slot: &'static Channel<NoopRawMutex, heapless::Vec<u8, DEFAULT_CMD_BUF_SIZE>, 1>,
+    ingress: atat::Ingress<
+        'static,
+        DirectRxDigester<'static, EdmDigester>,
+        UbloxUrc,
+        INGRESS_BUF_SIZE,
+        URC_CAPACITY,
+        { URC_SUBSCRIBERS },
+    >,
+}
+
+impl MockModule {
+    /// Wait for the next AT request from the stack, asserting that it starts
+    /// with `prefix`, e.g. `AT+UDCP=`, and return the command.
+    pub async fn expect_at(&mut self, prefix: &str) -> String {
+        let req = self.req_slot.receive().await;
+        assert_eq!(
+            req[4],
+            PayloadType::ATRequest as u8,
+            "expected {}, got {:02x?}",
+            prefix,
+            req.as_slice()
+        );
+
+        let at = core::str::from_utf8(&req[AT_COMMAND_POSITION..req.len() - 1]).unwrap();
+        assert!(at.starts_with(prefix), "expected {}, got {:?}", prefix, at);
+        at.to_string()
+    }
+
+    /// Wait for the next data command from the stack, asserting that it is
+    /// sent on `channel`, and return the data.
+    pub async fn expect_data(&mut self, channel: ChannelId) -> Vec<u8> {
+        let req = self.req_slot.receive().await;
+        assert_eq!(
+            req[4],
+            PayloadType::DataCommand as u8,
+            "expected data on {:?}, got {:02x?}",
+            channel,
+            req.as_slice()
+        );
+        assert_eq!(req[5], channel.0, "data sent on wrong channel");
+        req[6..req.len() - 1].to_vec()
+    }
+
+    /// Answer the pending AT request, e.g. with `+UDCP:1\r\nOK\r\n`.
+    pub async fn respond(&mut self, at_resp: &[u8]) {
+        self.packet(PayloadType::ATConfirmation, at_resp).await
+    }
+
+    /// Inject a URC, e.g. `\r\n+UUDPD:1\r\n`.
+    pub async fn urc(&mut self, urc: &[u8]) {
+        self.packet(PayloadType::ATEvent, urc).await
+    }
+
+    /// Inject a TCP connect event for a peer at `remote` on `channel`.
+    pub async fn connect_event(&mut self, channel: ChannelId, remote: SocketAddrV4) {
+        self.ipv4_connect_event(channel, Protocol::TCP, remote)
+            .await
+    }
+
+    /// Inject a UDP connect event for a peer at `remote` on `channel`.
+    pub async fn udp_connect_event(&mut self, channel: ChannelId, remote: SocketAddrV4) {
+        self.ipv4_connect_event(channel, Protocol::UDP, remote)
+            .await
+    }
+
+    async fn ipv4_connect_event(
+        &mut self,
+        channel: ChannelId,
+        protocol: Protocol,
+        remote: SocketAddrV4,
+    ) {
+        let mut payload = vec![channel.0, 0x02, protocol as u8];
+        payload.extend_from_slice(&remote.ip().octets());
+        payload.extend_from_slice(&remote.port().to_be_bytes());
+        payload.extend_from_slice(&[10, 0, 0, 2]);
+        payload.extend_from_slice(&49152u16.to_be_bytes());
+        self.packet(PayloadType::ConnectEvent, &payload).await
+    }
+
+    /// Inject data received from the peer on `channel`.
+    pub async fn data_event(&mut self, channel: ChannelId, data: &[u8]) {
+        let mut payload = vec![channel.0];
+        payload.extend_from_slice(data);
+        self.packet(PayloadType::DataEvent, &payload).await
+    }
+
+    /// Inject the closing of `channel`.
+    pub async fn disconnect_event(&mut self, channel: ChannelId) {
+        self.packet(PayloadType::DisconnectEvent, &[channel.0])
+            .await
+    }
+
+    async fn packet(&mut self, payload_type: PayloadType, payload: &[u8]) {
+        let len = payload.len() + 2;
+        let mut packet = vec![
+            STARTBYTE,
+            (len >> 8) as u8,
+            len as u8,
+            0x00,
+            payload_type as u8,
+        ];
+        packet.extend_from_slice(payload);
+        packet.push(ENDBYTE);
+        self.ingress.write(&packet).await;
+    }
+}
+
+/// Wait for `cond` to hold, letting the stack process pending events.
+///
+/// `cond` is checked every millisecond, so timers keep running meanwhile.
+pub(crate) async fn wait_until(mut cond: impl FnMut() -> bool) {
+    while !cond() {
+        embassy_time::Timer::after_millis(1).await;
+    }
+}
+
+mod test {
+    use super::*;
+    use crate::asynch::test_support::block_on;
+    use crate::asynch::ublox_stack::tcp::TcpSocket;
+    use crate::asynch::ublox_stack::{SocketState, SocketStats};
+    use crate::command::edm::urc::EdmEvent;
+    use core::net::Ipv4Addr;
+    use embassy_futures::{join::join, select::select};
+    use ublox_sockets::{PeerHandle, TcpState};
+
+    const REMOTE: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 1000);
+
+    async fn connect(module: &mut MockModule, channel: ChannelId) {
+        let at = module.expect_at("AT+UDCP=").await;
+        assert!(at.contains("tcp://10.0.0.1:1000/"), "{}", at);
+        module.respond(b"+UDCP:1\r\nOK\r\n").await;
+        module.connect_event(channel, REMOTE).await;
+    }
+
+    #[test]
+    fn connect_read_write_close() {
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+
+        let app = async {
+            socket.connect(REMOTE).await.unwrap();
+
+            assert_eq!(socket.write(b"ping").await, Ok(4));
+            socket.flush().await.unwrap();
+
+            let mut buf = [0u8; 8];
+            let n = socket.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"pong");
+
+            socket.close();
+        };
+
+        let script = async {
+            connect(&mut module, ChannelId(5)).await;
+
+            assert_eq!(module.expect_data(ChannelId(5)).await, b"ping");
+            let ids: Vec<_> = stack.socket_diagnostics().collect();
+            assert!(
+                matches!(ids[..], [(_, Some(ChannelId(5)), Some(PeerHandle(1)))]),
+                "{:?}",
+                ids
+            );
+            module.data_event(ChannelId(5), b"pong").await;
+
+            assert_eq!(module.expect_at("AT+UDCPC=").await, "AT+UDCPC=1\r\n");
+            module.respond(b"\r\nOK\r\n").await;
+            module.urc(b"\r\n+UUDPD:1\r\n").await;
+            module.disconnect_event(ChannelId(5)).await;
+
+            wait_until(|| stack.socket.borrow_mut().peer_count() == 0).await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+
+        assert_eq!(socket.state(), TcpState::TimeWait);
+    }
+
+    #[test]
+    fn connect_churn_leaves_no_stale_settings() {
+        let (stack, mut module) = mock_stack();
+
+        let churn = async {
+            for cycle in 0..100u8 {
+                let (mut rx, mut tx) = ([0u8; 16], [0u8; 16]);
+                let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+                socket.set_priority(200).unwrap();
+                socket.set_nodelay(true).unwrap();
+
+                let channel = ChannelId(1 + cycle % 7);
+                // The module may report the connect event on either side of
+                // the `ConnectPeer` response
+                let event_first = cycle % 2 == 0;
+
+                let app = async {
+                    socket.connect(REMOTE).await.unwrap();
+                    socket.close();
+                };
+
+                let script = async {
+                    module.expect_at("AT+UDCP=").await;
+                    if event_first {
+                        module.connect_event(channel, REMOTE).await;
+                        module.respond(b"+UDCP:1\r\nOK\r\n").await;
+                    } else {
+                        module.respond(b"+UDCP:1\r\nOK\r\n").await;
+                        module.connect_event(channel, REMOTE).await;
+                    }
+
+                    assert_eq!(module.expect_at("AT+UDCPC=").await, "AT+UDCPC=1\r\n");
+                    module.respond(b"\r\nOK\r\n").await;
+                    module.urc(b"\r\n+UUDPD:1\r\n").await;
+                    module.disconnect_event(channel).await;
+
+                    wait_until(|| stack.socket.borrow_mut().peer_count() == 0).await;
+                };
+
+                join(app, script).await;
+                drop(socket);
+
+                let s = stack.socket.borrow();
+                assert_eq!(s.settings_len(), 0, "settings left after cycle {}", cycle);
+            }
+        };
+
+        block_on(select(stack.run(), churn));
+
+        // Nothing was left behind for the purge to find either
+        let s = &mut *stack.socket.borrow_mut();
+        assert_eq!(s.purge_stale(), 0);
+        assert_eq!(s.stats.stale_entries, 0);
+    }
+
+    #[test]
+    fn abort_mid_transfer() {
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+
+        let app = async {
+            socket.connect(REMOTE).await.unwrap();
+
+            let mut buf = [0u8; 2];
+            assert_eq!(socket.read(&mut buf).await, Ok(2));
+            // Pending data is dropped along with the connection
+            assert_eq!(socket.write(b"unsent").await, Ok(6));
+            socket.abort().unwrap();
+
+            assert_eq!(socket.state(), TcpState::Closed);
+            assert!(!socket.may_recv());
+        };
+
+        let script = async {
+            connect(&mut module, ChannelId(3)).await;
+            module.data_event(ChannelId(3), b"pong").await;
+
+            // No data or FIN-WAIT, the peer is closed straight away
+            assert_eq!(module.expect_at("AT+UDCPC=").await, "AT+UDCPC=1\r\n");
+            let ids: Vec<_> = stack.socket_diagnostics().collect();
+            assert!(matches!(ids[..], [(_, None, None)]), "{:?}", ids);
+            assert!(stack
+                .socket_info()
+                .all(|info| info.state == SocketState::Tcp(TcpState::Closed)));
+
+            module.respond(b"\r\nOK\r\n").await;
+            module.urc(b"\r\n+UUDPD:1\r\n").await;
+            module.disconnect_event(ChannelId(3)).await;
+            module.data_event(ChannelId(3), b"late").await;
+
+            wait_until(|| stack.socket.borrow_mut().peer_count() == 0).await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+
+        assert_eq!(socket.state(), TcpState::Closed);
+        assert_eq!(socket.remote_endpoint(), None);
+        assert_eq!(stack.socket_stats().dropped_cleanups, 1);
+    }
+
+    #[test]
+    fn connect_peer_error() {
+        use crate::asynch::ublox_stack::tcp::ConnectError;
+
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+
+        let app = async {
+            assert_eq!(socket.connect(REMOTE).await, Err(ConnectError::NoRoute));
+        };
+
+        let script = async {
+            module.expect_at("AT+UDCP=").await;
+            module.respond(b"\r\nERROR\r\n").await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+
+        assert_eq!(socket.state(), TcpState::Closed);
+        assert_eq!(socket.remote_endpoint(), None);
+        assert_eq!(stack.socket_stats().connect_failures, 1);
+
+        // Nor is the connect retried
+        let retried = block_on(select(
+            stack.run(),
+            embassy_time::with_timeout(
+                embassy_time::Duration::from_millis(50),
+                module.expect_at("AT+UDCP="),
+            ),
+        ));
+        assert!(matches!(
+            retried,
+            embassy_futures::select::Either::Second(Err(_))
+        ));
+    }
+
+    #[cfg(feature = "socket-udp")]
+    #[test]
+    fn udp_datagram_boundaries() {
+        use crate::asynch::ublox_stack::udp::{RecvError, UdpSocket};
+
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = UdpSocket::new(stack, &mut rx, &mut tx);
+
+        let app = async {
+            socket.send_to(b"hello", REMOTE).await.unwrap();
+
+            let mut buf = [0u8; 8];
+            assert_eq!(socket.recv_from(&mut buf).await, Ok((3, REMOTE.into())));
+            assert_eq!(&buf[..3], b"one");
+            assert_eq!(socket.recv_from(&mut buf).await, Ok((5, REMOTE.into())));
+            assert_eq!(&buf[..5], b"three");
+            // Dropped rather than split across reads
+            assert_eq!(socket.recv_from(&mut buf).await, Err(RecvError::Truncated));
+            assert_eq!(socket.recv_from(&mut buf).await, Ok((4, REMOTE.into())));
+            assert_eq!(&buf[..4], b"last");
+        };
+
+        let script = async {
+            let at = module.expect_at("AT+UDCP=").await;
+            assert!(at.contains("udp://10.0.0.1:1000/"), "{}", at);
+            module.respond(b"+UDCP:1\r\nOK\r\n").await;
+            module.udp_connect_event(ChannelId(4), REMOTE).await;
+            assert_eq!(module.expect_data(ChannelId(4)).await, b"hello");
+
+            module.data_event(ChannelId(4), b"one").await;
+            module.data_event(ChannelId(4), b"three").await;
+            module.data_event(ChannelId(4), b"eleven byte").await;
+            module.data_event(ChannelId(4), b"last").await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+
+        assert!(!socket.may_recv());
+        assert_eq!(stack.socket_stats().rx_dropped_datagrams, 0);
+    }
+
+    #[cfg(feature = "socket-udp")]
+    #[test]
+    fn udp_connect_peer_error() {
+        use crate::asynch::ublox_stack::udp::{SendError, UdpSocket};
+        use embassy_time::{with_timeout, Duration};
+
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = UdpSocket::new(stack, &mut rx, &mut tx);
+        let other = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 1000);
+
+        let app = async {
+            socket.send_to(b"hello", REMOTE).await.unwrap();
+            // Waits for the queued datagram, which is dropped
+            assert_eq!(
+                socket.send_to(b"other", other).await,
+                Err(SendError::NoRoute)
+            );
+        };
+
+        let script = async {
+            module.expect_at("AT+UDCP=").await;
+            module.respond(b"\r\nERROR\r\n").await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+        assert_eq!(stack.socket_stats().connect_failures, 1);
+
+        // Nor is the connect retried
+        let retried = block_on(select(
+            stack.run(),
+            with_timeout(Duration::from_millis(500), module.expect_at("AT+UDCP=")),
+        ));
+        assert!(matches!(
+            retried,
+            embassy_futures::select::Either::Second(Err(_))
+        ));
+    }
+
+    #[cfg(feature = "socket-udp")]
+    #[test]
+    fn udp_packet_too_large() {
+        use crate::asynch::ublox_stack::udp::{SendError, UdpSocket};
+
+        let (stack, _module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = UdpSocket::new(stack, &mut rx, &mut tx);
+
+        assert_eq!(
+            block_on(socket.send_to(&[0u8; 65], REMOTE)),
+            Err(SendError::PacketTooLarge)
+        );
+    }
+
+    #[test]
+    fn peer_disconnected_while_reading() {
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+
+        let app = async {
+            socket.connect(REMOTE).await.unwrap();
+
+            let mut buf = [0u8; 8];
+            // EOF once the remote closed the connection
+            assert_eq!(socket.read(&mut buf).await, Ok(0));
+        };
+
+        let script = async {
+            connect(&mut module, ChannelId(2)).await;
+            module.urc(b"\r\n+UUDPD:1\r\n").await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+
+        assert_eq!(socket.state(), TcpState::CloseWait);
+    }
+
+    #[test]
+    fn reconnect_from_same_local_port() {
+        use crate::asynch::ublox_stack::tcp::ConnectError;
+
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let (mut rx2, mut tx2) = ([0u8; 64], [0u8; 64]);
+
+        let app = async {
+            let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+            socket.set_local_port(2001).unwrap();
+            socket.connect(REMOTE).await.unwrap();
+            assert_eq!(socket.set_local_port(2002), Err(ConnectError::InvalidState));
+            socket.close();
+            wait_until(|| socket.state() == TcpState::FinWait2).await;
+            drop(socket);
+
+            // Same 4-tuple, before the module has released the port
+            let mut socket = TcpSocket::new(stack, &mut rx2, &mut tx2);
+            socket.set_local_port(2001).unwrap();
+            socket.connect(REMOTE).await.unwrap();
+        };
+
+        let script = async {
+            let at = module.expect_at("AT+UDCP=").await;
+            assert!(
+                at.contains("tcp://10.0.0.1:1000/?local_port=2001"),
+                "{}",
+                at
+            );
+            module.respond(b"+UDCP:1\r\nOK\r\n").await;
+            module.connect_event(ChannelId(1), REMOTE).await;
+
+            assert_eq!(module.expect_at("AT+UDCPC=").await, "AT+UDCPC=1\r\n");
+            module.respond(b"\r\nOK\r\n").await;
+
+            // The connect is held back until the peer is reported gone
+            wait_until(|| {
+                let s = &mut *stack.socket.borrow_mut();
+                s.sockets.iter_mut().count() == 1 && !s.local_ports.is_empty()
+            })
+            .await;
+            for _ in 0..10 {
+                embassy_futures::yield_now().await;
+            }
+            assert!(module.req_slot.is_empty());
+
+            module.urc(b"\r\n+UUDPD:1\r\n").await;
+            module.disconnect_event(ChannelId(1)).await;
+
+            let at = module.expect_at("AT+UDCP=").await;
+            assert!(
+                at.contains("tcp://10.0.0.1:1000/?local_port=2001"),
+                "{}",
+                at
+            );
+            module.respond(b"+UDCP:2\r\nOK\r\n").await;
+            module.connect_event(ChannelId(2), REMOTE).await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+
+        assert_eq!(stack.socket.borrow().bound_ports.len(), 1);
+    }
+
+    #[test]
+    fn tcp_timeouts() {
+        use crate::asynch::ublox_stack::tcp::{ConnectError, Error};
+        use embassy_time::Duration;
+
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+
+        // Without the stack running, the connect is never requested
+        socket.set_connect_timeout(Duration::from_millis(50));
+        assert_eq!(
+            block_on(socket.connect_with_timeout(REMOTE)),
+            Err(ConnectError::TimedOut)
+        );
+        assert_eq!(socket.state(), TcpState::Closed);
+        assert_eq!(socket.remote_endpoint(), None);
+
+        let app = async {
+            socket.set_connect_timeout(Duration::from_secs(1));
+            socket.connect_with_timeout(REMOTE).await.unwrap();
+
+            let mut buf = [0u8; 8];
+            socket.set_timeout(Some(Duration::from_millis(50)));
+            assert_eq!(socket.read(&mut buf).await, Err(Error::TimedOut));
+        };
+
+        let script = async {
+            connect(&mut module, ChannelId(2)).await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+
+        assert_eq!(socket.state(), TcpState::Established);
+    }
+
+    #[test]
+    fn socket_timeout() {
+        use crate::asynch::ublox_stack::tcp::Error;
+        use embassy_time::{Duration, Timer};
+
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+        socket.set_timeout(Some(Duration::from_millis(100)));
+
+        let app = async {
+            socket.connect(REMOTE).await.unwrap();
+
+            // Empty buffers never wait, so never time out
+            assert_eq!(socket.read(&mut []).await, Ok(0));
+            assert_eq!(socket.write(&[]).await, Ok(0));
+
+            // Each byte arrives within the window, though not both
+            let mut buf = [0u8; 8];
+            assert_eq!(socket.read(&mut buf).await, Ok(1));
+            assert_eq!(socket.read(&mut buf).await, Ok(1));
+
+            let (mut reader, _) = socket.split();
+            assert_eq!(
+                embedded_io_async::Read::read(&mut reader, &mut buf).await,
+                Err(Error::TimedOut)
+            );
+        };
+
+        let script = async {
+            connect(&mut module, ChannelId(2)).await;
+            Timer::after_millis(60).await;
+            module.data_event(ChannelId(2), b"a").await;
+            Timer::after_millis(60).await;
+            module.data_event(ChannelId(2), b"b").await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+        assert_eq!(socket.state(), TcpState::Established);
+
+        // Without the stack running, the transmit buffer is never drained
+        block_on(async {
+            assert_eq!(socket.write(&[0; 64]).await, Ok(64));
+            assert_eq!(socket.write(b"x").await, Err(Error::TimedOut));
+        });
+    }
+
+    #[test]
+    fn sequential_tls_connects() {
+        use crate::asynch::ublox_stack::peer_builder::SecurityCredentials;
+
+        let (stack, mut module) = mock_stack();
+
+        for cycle in 0..3u8 {
+            let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+            let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+            socket
+                .set_tls_credentials(SecurityCredentials {
+                    ca_cert_name: heapless::String::try_from("ca").unwrap(),
+                    c_cert_name: heapless::String::try_from("dev").unwrap(),
+                    c_key_name: heapless::String::try_from("dev").unwrap(),
+                })
+                .unwrap();
+
+            let channel = ChannelId(1 + cycle);
+            let app = async {
+                socket.connect(REMOTE).await.unwrap();
+                socket.close();
+            };
+            let script = async {
+                let at = module.expect_at("AT+UDCP=").await;
+                assert!(at.contains("?ca=ca&cert=dev&privKey=dev"), "{}", at);
+                module.respond(b"+UDCP:1\r\nOK\r\n").await;
+                module.connect_event(channel, REMOTE).await;
+
+                assert_eq!(module.expect_at("AT+UDCPC=").await, "AT+UDCPC=1\r\n");
+                module.respond(b"\r\nOK\r\n").await;
+                module.urc(b"\r\n+UUDPD:1\r\n").await;
+                module.disconnect_event(channel).await;
+
+                wait_until(|| stack.socket.borrow_mut().peer_count() == 0).await;
+            };
+            block_on(select(stack.run(), join(app, script)));
+        }
+
+        assert!(stack.socket.borrow().credential_map.is_empty());
+    }
+
+    #[test]
+    fn flush_dropped_sockets_without_run() {
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+
+        let app = async {
+            socket.connect(REMOTE).await.unwrap();
+        };
+        let script = async {
+            connect(&mut module, ChannelId(4)).await;
+        };
+        block_on(select(stack.run(), join(app, script)));
+
+        // The stack is no longer running, as if starved
+        drop(socket);
+        let script = async {
+            assert_eq!(module.expect_at("AT+UDCPC=").await, "AT+UDCPC=1\r\n");
+            module.respond(b"\r\nOK\r\n").await;
+        };
+        block_on(join(stack.flush_dropped_sockets(), script));
+
+        assert!(stack.socket.borrow().dropped_sockets.is_empty());
+        assert_eq!(stack.socket_stats().dropped_cleanups, 1);
+    }
+
+    fn used_slots(stack: &MockStack) -> usize {
+        stack.socket.borrow_mut().sockets.iter_mut().count()
+    }
+
+    #[test]
+    fn time_wait_recycled_near_capacity() {
+        use embassy_time::{with_timeout, Duration, Timer};
+
+        let (stack, mut module) = mock_stack();
+        stack.set_time_wait_duration(Duration::from_millis(50));
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+        let mut bufs = [[0u8; 16]; 4];
+
+        let app = async {
+            socket.connect(REMOTE).await.unwrap();
+            socket.close();
+            wait_until(|| socket.state() == TcpState::TimeWait).await;
+
+            // Plenty of slots left, so the socket stays in `TimeWait`
+            Timer::after_millis(100).await;
+            assert_eq!(used_slots(stack), 1);
+
+            let [rx1, tx1, rx2, tx2] = &mut bufs;
+            let _a = TcpSocket::new(stack, rx1, tx1);
+            let _b = TcpSocket::new(stack, rx2, tx2);
+
+            // Slots are running out, but the socket is still owned
+            Timer::after_millis(100).await;
+            assert_eq!(used_slots(stack), 3);
+            assert_eq!(socket.state(), TcpState::TimeWait);
+
+            drop(socket);
+            with_timeout(
+                Duration::from_secs(1),
+                wait_until(|| used_slots(stack) == 2),
+            )
+            .await
+            .unwrap();
+        };
+
+        let script = async {
+            connect(&mut module, ChannelId(3)).await;
+            assert_eq!(module.expect_at("AT+UDCPC=").await, "AT+UDCPC=1\r\n");
+            module.respond(b"\r\nOK\r\n").await;
+            module.urc(b"\r\n+UUDPD:1\r\n").await;
+            module.disconnect_event(ChannelId(3)).await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+    }
+
+    #[test]
+    fn time_wait_slot_reclaimed_when_full() {
+        let (stack, mut module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+
+        let app = async {
+            socket.connect(REMOTE).await.unwrap();
+            socket.close();
+            wait_until(|| socket.state() == TcpState::TimeWait).await;
+        };
+
+        let script = async {
+            connect(&mut module, ChannelId(3)).await;
+            assert_eq!(module.expect_at("AT+UDCPC=").await, "AT+UDCPC=1\r\n");
+            module.respond(b"\r\nOK\r\n").await;
+            module.urc(b"\r\n+UUDPD:1\r\n").await;
+            module.disconnect_event(ChannelId(3)).await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+
+        // The slot is kept while the socket is in `TimeWait`
+        drop(socket);
+        assert_eq!(used_slots(stack), 1);
+
+        // Until it is needed for a new socket
+        let mut bufs = [[0u8; 16]; 8];
+        let [rx1, tx1, rx2, tx2, rx3, tx3, rx4, tx4] = &mut bufs;
+        let _sockets = [
+            TcpSocket::new(stack, rx1, tx1),
+            TcpSocket::new(stack, rx2, tx2),
+            TcpSocket::new(stack, rx3, tx3),
+            TcpSocket::new(stack, rx4, tx4),
+        ];
+        assert_eq!(used_slots(stack), SOCK);
+    }
+
+    /// Waker counting its wakeups.
+    #[derive(Default)]
+    struct CountingWaker(std::sync::atomic::AtomicUsize);
+
+    impl std::task::Wake for CountingWaker {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.wake_by_ref()
+        }
+
+        fn wake_by_ref(self: &std::sync::Arc<Self>) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn idle_stack_sleeps() {
+        use crate::asynch::test_support::{advance, lock_clock};
+        use core::future::Future;
+        use core::task::Context;
+        use embassy_time::Duration;
+        use std::sync::atomic::Ordering;
+        use std::sync::Arc;
+
+        let (stack, _module) = mock_stack();
+        let (mut rx, mut tx) = ([0u8; 64], [0u8; 64]);
+        let _socket = TcpSocket::new(stack, &mut rx, &mut tx);
+
+        let _clock = lock_clock();
+        let counter = Arc::new(CountingWaker::default());
+        let waker = counter.clone().into();
+        let mut cx = Context::from_waker(&waker);
+        let mut run = core::pin::pin!(stack.run());
+
+        // Let the stack handle the new socket
+        let mut polls = 0;
+        loop {
+            counter.0.store(0, Ordering::Relaxed);
+            assert!(run.as_mut().poll(&mut cx).is_pending());
+            if counter.0.load(Ordering::Relaxed) == 0 {
+                break;
+            }
+            polls += 1;
+            assert!(polls < 10, "stack keeps waking itself");
+        }
+
+        // Nothing wakes the stack while the clock runs
+        for _ in 0..10_000 {
+            advance(Duration::from_millis(1));
+        }
+        let wakes = counter.0.load(Ordering::Relaxed);
+        assert_eq!(wakes, 0, "idle stack woken {} times", wakes);
+    }
+
+    const PACKETS: usize = 4;
+    const PACKET_LEN: usize = 16;
+
+    /// Receive `PACKETS` packets on a connected socket, waiting for the stack
+    /// to take the connect event first if `settle`.
+    fn receive_packets(direct: bool, settle: bool) -> SocketStats {
+        let (stack, mut module) = mock_stack();
+        if direct {
+            stack.enable_direct_rx();
+        }
+        let mut events = stack.device.urc_channel.subscribe().unwrap();
+        let (mut rx, mut tx) = ([0u8; 128], [0u8; 64]);
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+
+        let app = async {
+            socket.connect(REMOTE).await.unwrap();
+
+            let mut buf = [0u8; PACKETS * PACKET_LEN];
+            let mut n = 0;
+            while n < buf.len() {
+                n += socket.read(&mut buf[n..]).await.unwrap();
+            }
+            for (i, packet) in buf.chunks(PACKET_LEN).enumerate() {
+                assert!(packet.iter().all(|b| *b == i as u8), "reordered data");
+            }
+        };
+
+        let script = async {
+            connect(&mut module, ChannelId(3)).await;
+            if settle {
+                wait_until(|| {
+                    stack
+                        .socket_info()
+                        .any(|info| info.edm_channel == Some(ChannelId(3)))
+                })
+                .await;
+            }
+            for i in 0..PACKETS {
+                module
+                    .data_event(ChannelId(3), &[i as u8; PACKET_LEN])
+                    .await;
+            }
+            module.disconnect_event(ChannelId(3)).await;
+        };
+
+        block_on(select(stack.run(), join(app, script)));
+
+        // Other subscribers keep seeing the connect and disconnect events
+        let (mut connects, mut disconnects) = (0, 0);
+        while let Some(event) = events.try_next_message_pure() {
+            match event {
+                EdmEvent::IPv4ConnectEvent(_) => connects += 1,
+                EdmEvent::DisconnectEvent(ChannelId(3)) => disconnects += 1,
+                _ => {}
+            }
+        }
+        assert_eq!((connects, disconnects), (1, 1));
+
+        stack.socket_stats()
+    }
+
+    #[test]
+    fn direct_rx_copies_once() {
+        let queued = receive_packets(false, true);
+        assert_eq!((queued.rx_queued, queued.rx_direct), (PACKETS as u32, 0));
+
+        let direct = receive_packets(true, true);
+        assert_eq!((direct.rx_queued, direct.rx_direct), (0, PACKETS as u32));
+    }
+
+    #[test]
+    fn direct_rx_keeps_order() {
+        // Data racing the connect event takes the URC channel, and no later
+        // packet may overtake it.
+        let stats = receive_packets(true, false);
+        assert_eq!(stats.rx_queued + stats.rx_direct, PACKETS as u32);
+    }
+}
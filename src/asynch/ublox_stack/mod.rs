@@ -6,38 +6,49 @@ pub mod tls;
 pub mod udp;
 
 mod device;
+mod direct_rx;
 pub mod dns;
+#[cfg(all(test, feature = "socket-tcp"))]
+mod mock;
 mod peer_builder;
+mod settings;
 
 pub use device::Device;
+pub(crate) use direct_rx::DirectRx;
+pub use direct_rx::DirectRxDigester;
 
 use core::cell::RefCell;
 use core::future::poll_fn;
-use core::ops::DerefMut;
 use core::task::Poll;
 
 use crate::command::data_mode::responses::ConnectPeerResponse;
-use crate::command::data_mode::urc::PeerDisconnected;
+use crate::command::data_mode::types::IPProtocol;
+use crate::command::data_mode::urc::{PeerConnected, PeerDisconnected};
 use crate::command::data_mode::{ClosePeerConnection, ConnectPeer};
 use crate::command::edm::types::{DataEvent, Protocol};
 use crate::command::edm::urc::EdmEvent;
-use crate::command::edm::{EdmAtCmdWrapper, EdmDataCommand};
+use crate::command::edm::{EdmAtCmdWrapper, EdmDataCommand, DATA_COMMAND_OVERHEAD};
 use crate::command::ping::types::PingError;
 use crate::command::ping::urc::{PingErrorResponse, PingResponse};
 use crate::command::ping::Ping;
 use crate::command::Urc;
-use peer_builder::{PeerUrlBuilder, SecurityCredentials};
+use peer_builder::PeerUrlBuilder;
+pub use peer_builder::{PeerUrl, SecurityCredentials};
 
 use self::dns::{DnsSocket, DnsState, DnsTable};
+use self::settings::{SettingsStorage, SocketMap};
 
 use super::control::ProxyClient;
+use super::runner::DEFAULT_CMD_BUF_SIZE;
+use super::state::LinkState;
 
 use core::net::IpAddr;
 use core::net::SocketAddr;
 use embassy_futures::select;
+use embassy_sync::pubsub::WaitResult;
 use embassy_sync::waitqueue::WakerRegistration;
-use embassy_time::{Duration, Ticker};
-use portable_atomic::{AtomicBool, AtomicU8, Ordering};
+use embassy_time::{Duration, Instant, Timer};
+use portable_atomic::{AtomicBool, Ordering};
 use ublox_sockets::{
     AnySocket, ChannelId, PeerHandle, Socket, SocketHandle, SocketSet, SocketStorage,
 };
@@ -48,76 +59,1429 @@ use ublox_sockets::TcpState;
 #[cfg(feature = "socket-udp")]
 use ublox_sockets::UdpState;
 
-const MAX_EGRESS_SIZE: usize = 2048;
+/// Default time the slot of a dropped TCP socket is kept in `TimeWait`
+/// before it may be recycled.
+pub const TIME_WAIT_DURATION: Duration = Duration::from_secs(2);
 
-pub struct StackResources<const SOCK: usize> {
+/// Smallest URC channel capacity that does not miss socket events.
+pub const MIN_URC_CAPACITY: usize = 2;
+
+/// Default size of the buffer used to transmit socket data to the module.
+pub const DEFAULT_EGRESS_BUF_SIZE: usize = 2048;
+
+/// Maximum number of sockets reported by [`UbloxStack::socket_info`].
+pub const MAX_SOCKET_INFO: usize = 8;
+
+/// Maximum number of received datagrams queued per UDP socket. Datagrams
+/// arriving while the queue is full are dropped.
+#[cfg(feature = "socket-udp")]
+pub const MAX_RX_DATAGRAMS: usize = 8;
+
+/// Iteration over the sockets of a single type in a [`SocketSet`], sparing
+/// a `match` on [`Socket`] with an arm per socket feature.
+trait SocketSetExt {
+    #[cfg(feature = "socket-tcp")]
+    fn iter_tcp_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (SocketHandle, &mut ublox_sockets::tcp::Socket<'static>)>;
+
+    #[cfg(feature = "socket-udp")]
+    fn iter_udp_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (SocketHandle, &mut ublox_sockets::udp::Socket<'static>)>;
+}
+
+impl SocketSetExt for SocketSet<'static> {
+    #[cfg(feature = "socket-tcp")]
+    fn iter_tcp_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (SocketHandle, &mut ublox_sockets::tcp::Socket<'static>)> {
+        self.iter_mut().filter_map(|(handle, socket)| {
+            ublox_sockets::tcp::Socket::downcast_mut(socket).map(|tcp| (handle, tcp))
+        })
+    }
+
+    #[cfg(feature = "socket-udp")]
+    fn iter_udp_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (SocketHandle, &mut ublox_sockets::udp::Socket<'static>)> {
+        self.iter_mut().filter_map(|(handle, socket)| {
+            ublox_sockets::udp::Socket::downcast_mut(socket).map(|udp| (handle, udp))
+        })
+    }
+}
+
+/// Number of times a socket command is retried while the module is busy.
+const TX_RETRIES: usize = 3;
+const TX_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// State of a socket, as reported by [`UbloxStack::socket_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SocketState {
+    #[cfg(feature = "socket-tcp")]
+    Tcp(TcpState),
+    #[cfg(feature = "socket-udp")]
+    Udp(UdpState),
+}
+
+/// Snapshot of a single socket, for debugging purposes.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SocketInfo {
+    pub handle: SocketHandle,
+    pub state: SocketState,
+    pub edm_channel: Option<ChannelId>,
+    pub peer_handle: Option<PeerHandle>,
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub remote_endpoint: Option<SocketAddr>,
+    /// Bytes queued for transmission. Not tracked for UDP sockets.
+    pub tx_queued: Option<usize>,
+    /// Bytes available to be read. Not tracked for UDP sockets.
+    pub rx_queued: Option<usize>,
+}
+
+/// Counters of socket events since the stack was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SocketStats {
+    /// Number of `ConnectPeer` commands issued.
+    pub connect_attempts: u32,
+    /// Number of `ConnectPeer` commands that failed.
+    pub connect_failures: u32,
+    /// Number of peers closed on behalf of dropped sockets.
+    pub dropped_cleanups: u32,
+    /// Number of dropped sockets whose close did not fit the close queue.
+    pub close_queue_overflows: u32,
+    /// Number of received data packets written directly into socket buffers.
+    pub rx_direct: u32,
+    /// Number of received data packets passed through the URC channel.
+    pub rx_queued: u32,
+    /// Number of per-socket settings found left behind by sockets no longer
+    /// in the stack, and removed.
+    pub stale_entries: u32,
+    /// Number of received UDP datagrams dropped, as they did not fit the
+    /// socket buffer or datagram queue.
+    pub rx_dropped_datagrams: u32,
+}
+
+/// Error returned when a setting of a socket does not fit the stack.
+///
+/// The stack has room for the settings of each of its socket slots, so this
+/// means settings of sockets no longer in the stack were left behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SettingsFull;
+
+impl core::fmt::Display for SettingsFull {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Socket settings full")
+    }
+}
+
+impl core::error::Error for SettingsFull {}
+
+/// Error returned when aborting a TCP socket finds the close queue full.
+///
+/// The socket is closed, but its peer is closed on the module through
+/// `FinWait1`, as after a local close, rather than straight away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CloseQueueFull;
+
+impl core::fmt::Display for CloseQueueFull {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Close queue full")
+    }
+}
+
+impl core::error::Error for CloseQueueFull {}
+
+/// Socket slots of a [`UbloxStack`], along with the per-socket settings of
+/// the stack.
+///
+/// `MAX_HEAP` bounds the memory taken by the slots, checked at compile time.
+/// It defaults to no limit.
+pub struct StackResources<const SOCK: usize, const MAX_HEAP: usize = { usize::MAX }> {
     sockets: [SocketStorage<'static>; SOCK],
+    settings: SettingsStorage<SOCK>,
 }
 
-impl<const SOCK: usize> Default for StackResources<SOCK> {
+impl<const SOCK: usize, const MAX_HEAP: usize> Default for StackResources<SOCK, MAX_HEAP> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<const SOCK: usize> StackResources<SOCK> {
+impl<const SOCK: usize, const MAX_HEAP: usize> StackResources<SOCK, MAX_HEAP> {
     pub fn new() -> Self {
+        const {
+            assert!(
+                SOCK > 0,
+                "StackResources must have at least one socket slot"
+            )
+        };
+        const {
+            assert!(
+                Self::memory_footprint(0, 0) <= MAX_HEAP,
+                "StackResources exceed MAX_HEAP"
+            )
+        };
+
         Self {
             sockets: [SocketStorage::EMPTY; SOCK],
+            settings: SettingsStorage::new(),
         }
     }
+
+    /// Memory taken by `SOCK` sockets with buffers of `tx_buf_size` and
+    /// `rx_buf_size` bytes, including their slots and settings.
+    ///
+    /// The socket buffers are not part of `StackResources`, so this can be
+    /// used to check a complete budget, e.g.
+    /// `const { assert!(StackResources::<4>::memory_footprint(1024, 1024) <= 16 * 1024) }`.
+    pub const fn memory_footprint(tx_buf_size: usize, rx_buf_size: usize) -> usize {
+        SOCK * (tx_buf_size + rx_buf_size + core::mem::size_of::<SocketStorage<'static>>())
+            + core::mem::size_of::<SettingsStorage<SOCK>>()
+    }
+
+    /// Split the socket slots between two independent [`UbloxStack`]s, with
+    /// `A` and `B` slots respectively.
+    ///
+    /// The slots of each stack come with room for the settings of its
+    /// sockets, so the halves are separate resources, e.g. to be placed in
+    /// a `StaticCell` each. Both halves keep the `MAX_HEAP` bound.
+    pub fn split_at<const A: usize, const B: usize>(
+        self,
+    ) -> (StackResources<A, MAX_HEAP>, StackResources<B, MAX_HEAP>) {
+        const { assert!(A > 0 && B > 0, "split_at halves must not be empty") };
+        const { assert!(A + B == SOCK, "split_at must divide all socket slots") };
+
+        (StackResources::new(), StackResources::new())
+    }
 }
 
-pub struct UbloxStack<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize> {
+/// Socket stack running on top of the module's internal network stack.
+///
+/// `EGRESS_BUF_SIZE` is the size of the buffer used to transmit data to the
+/// module, and thereby the maximum payload of a single transmission. It
+/// defaults to [`DEFAULT_EGRESS_BUF_SIZE`] and can be lowered on
+/// memory-constrained targets, e.g. `UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, 512>`.
+///
+/// `CMD_BUF_SIZE` must match the [`Resources`](crate::asynch::Resources) the
+/// device was created from, and fit `EGRESS_BUF_SIZE` bytes of data wrapped in
+/// an EDM data packet. This is checked at compile time.
+pub struct UbloxStack<
+    const INGRESS_BUF_SIZE: usize,
+    const URC_CAPACITY: usize,
+    const EGRESS_BUF_SIZE: usize = DEFAULT_EGRESS_BUF_SIZE,
+    const CMD_BUF_SIZE: usize = DEFAULT_CMD_BUF_SIZE,
+> {
     socket: RefCell<SocketStack>,
-    device: Device<'static, INGRESS_BUF_SIZE, URC_CAPACITY>,
-    last_tx_socket: AtomicU8,
+    device: Device<'static, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>,
     should_tx: AtomicBool,
 }
 
+/// Default transmit priority of sockets. Sockets of higher priority transmit
+/// ahead of all others.
+pub const DEFAULT_TX_PRIORITY: u8 = 128;
+
+/// Pass of `SocketStack::tx_event` over the sockets.
+#[derive(Clone, Copy, PartialEq)]
+enum TxPass {
+    /// Sockets of raised priority.
+    Urgent,
+    /// Other sockets, starting at the cursor.
+    FromCursor,
+    /// Other sockets, wrapping around to the cursor.
+    BeforeCursor,
+}
+
 pub(crate) struct SocketStack {
     sockets: SocketSet<'static>,
     waker: WakerRegistration,
     dns_table: DnsTable,
-    dropped_sockets: heapless::Vec<PeerHandle, 3>,
-    credential_map: heapless::index_map::FnvIndexMap<SocketHandle, SecurityCredentials, 2>,
+    dropped_sockets: heapless::Vec<PeerHandle, { crate::module_caps::MAX_PEERS }>,
+    /// Sockets waiting for room in `dropped_sockets`.
+    close_waker: WakerRegistration,
+    /// Dropped sockets whose close did not fit `dropped_sockets`. Their slots
+    /// are kept, with empty buffers, until the peer has been closed.
+    orphaned: SocketMap<()>,
+    /// TCP socket whose `ConnectPeer` has been sent, until its response has
+    /// been handled.
+    connect_in_flight: Option<SocketHandle>,
+    /// Sockets whose connect was cancelled while their `ConnectPeer` was in
+    /// flight, until its response has been handled.
+    aborted_connects: heapless::Vec<SocketHandle, { crate::module_caps::MAX_PEERS }>,
+    /// TLS credentials of sockets, used whenever they connect.
+    credential_map: SocketMap<SecurityCredentials>,
+    /// Sockets that connect with Nagle's algorithm disabled.
+    nodelay: SocketMap<()>,
+    /// Transmit priority of sockets, [`DEFAULT_TX_PRIORITY`] if not set.
+    tx_priority: SocketMap<u8>,
+    /// Timeout of reads and writes of TCP sockets, waiting as long as it
+    /// takes if not set.
+    #[cfg(feature = "socket-tcp")]
+    io_timeouts: SocketMap<Duration>,
+    /// Local ports sockets connect from, assigned by the module if not set.
+    local_ports: SocketMap<u16>,
+    /// Local ports held by peers on the module. The module only releases a
+    /// port once it reports the peer disconnected, so connects from the port
+    /// are held back until then.
+    bound_ports: heapless::Vec<(u16, PeerHandle), { crate::module_caps::MAX_PEERS }>,
+    /// Why the latest connect of TCP sockets failed, until taken by the
+    /// connect future.
+    #[cfg(feature = "socket-tcp")]
+    connect_errors: SocketMap<tcp::ConnectError>,
+    /// Index of the socket to transmit from next, among those of default or
+    /// lower priority.
+    tx_cursor: usize,
+    /// Remote endpoints UDP sockets send to, set by `UdpSocket::send_to`.
+    #[cfg(feature = "socket-udp")]
+    send_to_endpoints: SocketMap<SocketAddr>,
+    /// Datagrams in the receive buffers of UDP sockets, oldest first.
+    #[cfg(feature = "socket-udp")]
+    rx_datagrams: SocketMap<udp::RxDatagrams>,
+    /// Why the datagrams queued on UDP sockets were dropped, until taken by
+    /// the next `UdpSocket::send_to`.
+    #[cfg(feature = "socket-udp")]
+    send_errors: SocketMap<udp::SendError>,
+    /// Channels on which the module has paused the data flow, as its transmit
+    /// buffer is full.
+    flow_paused: heapless::Vec<ChannelId, { crate::module_caps::MAX_PEERS }>,
+    time_wait: SocketMap<Instant>,
+    /// Sockets in `TimeWait` whose `TcpSocket` has been dropped. Their slots
+    /// are kept, with empty buffers, until recycled.
+    #[cfg(feature = "socket-tcp")]
+    time_wait_dropped: SocketMap<()>,
+    time_wait_duration: Duration,
+    capacity: usize,
+    stats: SocketStats,
 }
 
-impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
-    UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>
-{
-    pub fn new<const SOCK: usize>(
-        device: Device<'static, INGRESS_BUF_SIZE, URC_CAPACITY>,
-        resources: &'static mut StackResources<SOCK>,
+impl SocketStack {
+    fn new<const SOCK: usize>(
+        sockets: SocketSet<'static>,
+        settings: &'static mut SettingsStorage<SOCK>,
     ) -> Self {
-        let sockets = SocketSet::new(&mut resources.sockets[..]);
-
-        let socket = SocketStack {
+        Self {
             sockets,
             dns_table: DnsTable::new(),
             waker: WakerRegistration::new(),
             dropped_sockets: heapless::Vec::new(),
-            credential_map: heapless::IndexMap::new(),
+            close_waker: WakerRegistration::new(),
+            orphaned: SocketMap::new(&mut settings.orphaned),
+            connect_in_flight: None,
+            aborted_connects: heapless::Vec::new(),
+            credential_map: SocketMap::new(&mut settings.credential_map),
+            nodelay: SocketMap::new(&mut settings.nodelay),
+            tx_priority: SocketMap::new(&mut settings.tx_priority),
+            #[cfg(feature = "socket-tcp")]
+            io_timeouts: SocketMap::new(&mut settings.io_timeouts),
+            local_ports: SocketMap::new(&mut settings.local_ports),
+            bound_ports: heapless::Vec::new(),
+            #[cfg(feature = "socket-tcp")]
+            connect_errors: SocketMap::new(&mut settings.connect_errors),
+            tx_cursor: 0,
+            #[cfg(feature = "socket-udp")]
+            send_to_endpoints: SocketMap::new(&mut settings.send_to_endpoints),
+            #[cfg(feature = "socket-udp")]
+            rx_datagrams: SocketMap::new(&mut settings.rx_datagrams),
+            #[cfg(feature = "socket-udp")]
+            send_errors: SocketMap::new(&mut settings.send_errors),
+            flow_paused: heapless::Vec::new(),
+            time_wait: SocketMap::new(&mut settings.time_wait),
+            #[cfg(feature = "socket-tcp")]
+            time_wait_dropped: SocketMap::new(&mut settings.time_wait_dropped),
+            time_wait_duration: TIME_WAIT_DURATION,
+            capacity: SOCK,
+            stats: SocketStats::default(),
+        }
+    }
+
+    /// Remove dropped TCP sockets that have been in `TimeWait` for longer
+    /// than the configured time-wait duration, returning the number of
+    /// reclaimed slots.
+    ///
+    /// Sockets still owned by a `TcpSocket` are left alone, as their owner
+    /// keeps using the handle.
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) fn recycle(&mut self) -> usize {
+        let SocketStack {
+            sockets, time_wait, ..
+        } = self;
+
+        // Forget entries for sockets that have since left `TimeWait`
+        time_wait.retain(|handle, _| {
+            sockets
+                .iter_tcp_mut()
+                .any(|(h, tcp)| h == *handle && tcp.state() == TcpState::TimeWait)
+        });
+
+        let mut recycled = 0;
+        // Dropped sockets that left `TimeWait` have nothing to wait for
+        while let Some(handle) = self
+            .time_wait_dropped
+            .keys()
+            .find(|handle| !self.time_wait.contains_key(handle))
+            .copied()
+        {
+            self.recycle_slot(handle);
+            recycled += 1;
+        }
+
+        while let Some(handle) = self
+            .time_wait
+            .iter()
+            .find(|(handle, entered)| {
+                self.time_wait_dropped.contains_key(handle)
+                    && entered.elapsed() >= self.time_wait_duration
+            })
+            .map(|(handle, _)| *handle)
+        {
+            debug!("Recycling socket {} in TimeWait", handle);
+            self.recycle_slot(handle);
+            recycled += 1;
+        }
+
+        recycled
+    }
+
+    /// Remove the dropped TCP socket `handle`, kept in `TimeWait`.
+    #[cfg(feature = "socket-tcp")]
+    fn recycle_slot(&mut self, handle: SocketHandle) {
+        self.time_wait.remove(&handle);
+        self.time_wait_dropped.remove(&handle);
+        self.sockets.remove(handle);
+    }
+
+    /// Keep the slot of the dropped TCP socket `handle` if it is in
+    /// `TimeWait`, to be recycled once the time-wait duration has passed.
+    ///
+    /// Returns `false` if the socket is not in `TimeWait`, and its slot is to
+    /// be released straight away.
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) fn park_time_wait(&mut self, handle: SocketHandle) -> bool {
+        let tcp = self.sockets.get_mut::<ublox_sockets::tcp::Socket>(handle);
+        if tcp.state() != TcpState::TimeWait
+            || !self.time_wait.contains_key(&handle)
+            || self.time_wait_dropped.insert(handle, ()).is_err()
+        {
+            return false;
+        }
+
+        // The buffers go away with the `TcpSocket`
+        let (rx, tx): (&'static mut [u8], &'static mut [u8]) = (&mut [], &mut []);
+        let mut parked = ublox_sockets::tcp::Socket::new(
+            ublox_sockets::tcp::SocketBuffer::new(rx),
+            ublox_sockets::tcp::SocketBuffer::new(tx),
+        );
+        parked.set_state(TcpState::TimeWait);
+        *tcp = parked;
+        self.waker.wake();
+        true
+    }
+
+    /// Recycle the dropped socket that entered `TimeWait` first, if all slots
+    /// are taken, so that a new socket fits.
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) fn make_room(&mut self) {
+        if self.sockets.iter_mut().count() < self.capacity {
+            return;
+        }
+
+        // Sockets that already left `TimeWait` have no entry, and go first
+        let oldest = self
+            .time_wait_dropped
+            .keys()
+            .min_by_key(|handle| self.time_wait.get(handle).copied())
+            .copied();
+        if let Some(handle) = oldest {
+            debug!("Recycling socket {} in TimeWait to make room", handle);
+            self.recycle_slot(handle);
+        }
+    }
+
+    /// Take the next peer to close on behalf of a dropped socket.
+    fn take_dropped(&mut self) -> Option<PeerHandle> {
+        while let Some(&handle) = self.orphaned.keys().next() {
+            self.orphaned.remove(&handle);
+            let peer_handle = match self.sockets.iter_mut().find(|(h, _)| *h == handle) {
+                #[cfg(feature = "socket-udp")]
+                Some((_, Socket::Udp(udp))) => udp.peer_handle.take(),
+                #[cfg(feature = "socket-tcp")]
+                Some((_, Socket::Tcp(tcp))) => tcp.peer_handle.take(),
+                #[allow(unreachable_patterns)]
+                _ => continue,
+            };
+            self.sockets.remove(handle);
+            if let Some(peer_handle) = peer_handle {
+                warn!("Handling dropped socket {}", peer_handle);
+                self.stats.dropped_cleanups += 1;
+                return Some(peer_handle);
+            }
+        }
+
+        let peer_handle = self.dropped_sockets.pop()?;
+        self.close_waker.wake();
+        warn!("Handling dropped socket {}", peer_handle);
+        self.stats.dropped_cleanups += 1;
+        Some(peer_handle)
+    }
+
+    fn tx_event<'data>(&mut self, buf: &'data mut [u8]) -> Option<TxEvent<'data>> {
+        for query in self.dns_table.table.iter_mut() {
+            if let DnsState::New = query.state {
+                query.state = DnsState::Pending;
+                buf[..query.domain_name.len()].copy_from_slice(query.domain_name.as_bytes());
+                return Some(TxEvent::Dns {
+                    hostname: core::str::from_utf8(&buf[..query.domain_name.len()]).unwrap(),
+                });
+            }
+        }
+
+        // Handle delayed close-by-drop here
+        if let Some(peer_handle) = self.take_dropped() {
+            return Some(TxEvent::Close { peer_handle });
+        }
+
+        let SocketStack {
+            sockets,
+            dns_table,
+            credential_map,
+            nodelay,
+            tx_priority,
+            tx_cursor,
+            flow_paused,
+            time_wait,
+            connect_in_flight,
+            local_ports,
+            bound_ports,
+            #[cfg(feature = "socket-udp")]
+            send_to_endpoints,
+            ..
+        } = self;
+        let port_bound = |port: Option<u16>| {
+            port.is_some_and(|port| bound_ports.iter().any(|(p, _)| *p == port))
+        };
+
+        // Sockets of raised priority go first, and the others take turns
+        let urgent = |handle: &SocketHandle| {
+            tx_priority
+                .get(handle)
+                .is_some_and(|priority| *priority > DEFAULT_TX_PRIORITY)
+        };
+        let cursor = *tx_cursor;
+
+        for pass in [TxPass::Urgent, TxPass::FromCursor, TxPass::BeforeCursor] {
+            for (index, (handle, socket)) in sockets.iter_mut().enumerate() {
+                let selected = match pass {
+                    TxPass::Urgent => urgent(&handle),
+                    TxPass::FromCursor => !urgent(&handle) && index >= cursor,
+                    TxPass::BeforeCursor => !urgent(&handle) && index < cursor,
+                };
+                if !selected {
+                    continue;
+                }
+
+                match socket {
+                    #[cfg(feature = "socket-udp")]
+                    Socket::Udp(udp) => match udp.state() {
+                        // Connect a peer to the endpoint given to `send_to`
+                        UdpState::Closed if udp.peer_handle.is_none() && udp.send_queue() > 0 => {
+                            if let Some(target) = send_to_endpoints.get(&handle) {
+                                // The module still holds the port for the previous peer
+                                let local_port = local_ports.get(&handle).copied();
+                                if port_bound(local_port) {
+                                    continue;
+                                }
+
+                                udp.endpoint = Some(*target);
+                                let url = PeerUrlBuilder::new()
+                                    .address(target)
+                                    .set_local_port(local_port)
+                                    .udp::<128>()
+                                    .unwrap();
+                                buf[..url.len()].copy_from_slice(url.as_bytes());
+
+                                return Some(TxEvent::Connect {
+                                    socket_handle: handle,
+                                    url: core::str::from_utf8(&buf[..url.len()]).unwrap(),
+                                    local_port,
+                                });
+                            }
+                        }
+                        UdpState::Established
+                            if udp.send_queue() > 0
+                                && udp
+                                    .edm_channel
+                                    .is_some_and(|channel| !flow_paused.contains(&channel)) =>
+                        {
+                            let edm_channel = udp.edm_channel.unwrap();
+                            let ev = udp.tx_dequeue(|payload| {
+                                let len = core::cmp::min(payload.len(), buf.len());
+                                buf[..len].copy_from_slice(&payload[..len]);
+                                (
+                                    len,
+                                    Some(TxEvent::Send {
+                                        edm_channel,
+                                        data: &buf[..len],
+                                    }),
+                                )
+                            });
+                            if ev.is_some() {
+                                if pass != TxPass::Urgent {
+                                    *tx_cursor = index + 1;
+                                }
+                                return ev;
+                            }
+                        }
+                        _ => {}
+                    },
+                    #[cfg(feature = "socket-tcp")]
+                    Socket::Tcp(tcp) => {
+                        tcp.poll();
+
+                        match tcp.state() {
+                            TcpState::Closed => {
+                                if let Some(addr) = tcp.remote_endpoint() {
+                                    // The module still holds the port for the previous peer
+                                    let local_port = Some(tcp.local_port).filter(|port| *port != 0);
+                                    if port_bound(local_port) {
+                                        continue;
+                                    }
+
+                                    let mut builder = PeerUrlBuilder::new();
+
+                                    if let Some(hostname) = dns_table.reverse_lookup(addr.ip()) {
+                                        builder.hostname(hostname).port(addr.port())
+                                    } else {
+                                        builder.address(&addr)
+                                    };
+
+                                    if let Some(creds) = credential_map.get(&handle) {
+                                        info!("Found credentials {} for {}", creds, handle);
+                                        builder.creds(creds);
+                                    }
+
+                                    builder.nodelay(nodelay.contains_key(&handle));
+
+                                    let url =
+                                        builder.set_local_port(local_port).tcp::<128>().unwrap();
+
+                                    // FIXME: Write directly into `buf` instead
+                                    buf[..url.len()].copy_from_slice(url.as_bytes());
+
+                                    *connect_in_flight = Some(handle);
+
+                                    return Some(TxEvent::Connect {
+                                        socket_handle: handle,
+                                        url: core::str::from_utf8(&buf[..url.len()]).unwrap(),
+                                        local_port,
+                                    });
+                                }
+                            }
+                            // We transmit data in all states where we may have data in the buffer,
+                            // or the transmit half of the connection is still open.
+                            TcpState::Established
+                            | TcpState::CloseWait
+                            | TcpState::FinWait1
+                            | TcpState::LastAck
+                                if tcp.send_queue() > 0
+                                    && tcp
+                                        .edm_channel
+                                        .is_some_and(|channel| !flow_paused.contains(&channel)) =>
+                            {
+                                let edm_channel = tcp.edm_channel.unwrap();
+                                let ev = tcp.tx_dequeue(|payload| {
+                                    let len = core::cmp::min(payload.len(), buf.len());
+                                    let res = if len != 0 {
+                                        buf[..len].copy_from_slice(&payload[..len]);
+                                        Some(TxEvent::Send {
+                                            edm_channel,
+                                            data: &buf[..len],
+                                        })
+                                    } else {
+                                        None
+                                    };
+
+                                    (len, res)
+                                });
+                                if ev.is_some() {
+                                    if pass != TxPass::Urgent {
+                                        *tx_cursor = index + 1;
+                                    }
+                                    return ev;
+                                }
+                            }
+                            // Locally initiated close, with all pending data flushed. The peer
+                            // handle is released once the module reports `PeerDisconnected`.
+                            TcpState::FinWait1 => match tcp.peer_handle {
+                                Some(peer_handle) => {
+                                    tcp.set_state(TcpState::FinWait2);
+                                    return Some(TxEvent::Close { peer_handle });
+                                }
+                                None => {
+                                    tcp.set_state(TcpState::TimeWait);
+                                    time_wait.insert(handle, Instant::now()).ok();
+                                }
+                            },
+                            // The remote closed first, and the socket has now been closed
+                            // locally as well, so release the peer on the module.
+                            TcpState::LastAck => {
+                                tcp.set_state(TcpState::TimeWait);
+                                time_wait.insert(handle, Instant::now()).ok();
+                                if let Some(peer_handle) = tcp.peer_handle.take() {
+                                    return Some(TxEvent::Close { peer_handle });
+                                }
+                            }
+                            // Waiting for the module to report an inbound peer
+                            TcpState::Listen | TcpState::SynReceived => {}
+                            _ => {}
+                        };
+                    }
+                    _ => {}
+                };
+            }
+        }
+
+        None
+    }
+
+    /// Set the transmit priority of the socket `handle`.
+    pub(crate) fn set_tx_priority(
+        &mut self,
+        handle: SocketHandle,
+        priority: u8,
+    ) -> Result<(), SettingsFull> {
+        if priority == DEFAULT_TX_PRIORITY {
+            self.tx_priority.remove(&handle);
+            return Ok(());
+        }
+        self.insert_setting(|s| &mut s.tx_priority, handle, priority)
+            .map_err(|_| SettingsFull)
+    }
+
+    /// Insert the setting `value` of the socket `handle` into the map selected
+    /// by `map`.
+    ///
+    /// Each map has room for every socket slot of the stack. If the map is
+    /// full, the settings of sockets no longer in the stack are removed
+    /// before trying again. `value` is handed back if it still does not fit.
+    pub(crate) fn insert_setting<V>(
+        &mut self,
+        map: fn(&mut Self) -> &mut SocketMap<V>,
+        handle: SocketHandle,
+        value: V,
+    ) -> Result<(), V> {
+        let Err((_, value)) = map(self).insert(handle, value) else {
+            return Ok(());
+        };
+        self.purge_stale();
+        map(self)
+            .insert(handle, value)
+            .map(|_| ())
+            .map_err(|(_, value)| value)
+    }
+
+    /// Forget all settings of the socket `handle`, e.g. as its slot is taken
+    /// by a new socket.
+    pub(crate) fn forget(&mut self, handle: SocketHandle) {
+        self.time_wait.remove(&handle);
+        #[cfg(feature = "socket-tcp")]
+        self.time_wait_dropped.remove(&handle);
+        self.orphaned.remove(&handle);
+        self.credential_map.remove(&handle);
+        self.nodelay.remove(&handle);
+        self.tx_priority.remove(&handle);
+        self.local_ports.remove(&handle);
+        #[cfg(feature = "socket-tcp")]
+        self.connect_errors.remove(&handle);
+        #[cfg(feature = "socket-tcp")]
+        self.io_timeouts.remove(&handle);
+        #[cfg(feature = "socket-udp")]
+        self.send_to_endpoints.remove(&handle);
+        #[cfg(feature = "socket-udp")]
+        self.rx_datagrams.remove(&handle);
+        #[cfg(feature = "socket-udp")]
+        self.send_errors.remove(&handle);
+    }
+
+    fn settings_len(&self) -> usize {
+        #[cfg(feature = "socket-udp")]
+        let udp = self.send_to_endpoints.len() + self.rx_datagrams.len() + self.send_errors.len();
+        #[cfg(not(feature = "socket-udp"))]
+        let udp = 0;
+        #[cfg(feature = "socket-tcp")]
+        let tcp = self.time_wait_dropped.len() + self.connect_errors.len() + self.io_timeouts.len();
+        #[cfg(not(feature = "socket-tcp"))]
+        let tcp = 0;
+
+        self.time_wait.len()
+            + self.orphaned.len()
+            + self.credential_map.len()
+            + self.nodelay.len()
+            + self.tx_priority.len()
+            + self.local_ports.len()
+            + tcp
+            + udp
+    }
+
+    /// Remove the settings of sockets no longer in the stack, returning how
+    /// many were removed.
+    ///
+    /// Settings are forgotten whenever a socket is dropped or recycled, so
+    /// this only finds entries that bookkeeping missed. They would otherwise
+    /// take up room for good, and be inherited by the next socket reusing the
+    /// handle.
+    pub(crate) fn purge_stale(&mut self) -> usize {
+        let before = self.settings_len();
+
+        let SocketStack {
+            sockets,
+            time_wait,
+            orphaned,
+            credential_map,
+            nodelay,
+            tx_priority,
+            local_ports,
+            #[cfg(feature = "socket-tcp")]
+            time_wait_dropped,
+            #[cfg(feature = "socket-tcp")]
+            connect_errors,
+            #[cfg(feature = "socket-tcp")]
+            io_timeouts,
+            #[cfg(feature = "socket-udp")]
+            send_to_endpoints,
+            #[cfg(feature = "socket-udp")]
+            rx_datagrams,
+            #[cfg(feature = "socket-udp")]
+            send_errors,
+            ..
+        } = self;
+        let mut is_live = |handle: &SocketHandle| sockets.iter_mut().any(|(h, _)| h == *handle);
+
+        time_wait.retain(|handle, _| is_live(handle));
+        orphaned.retain(|handle, _| is_live(handle));
+        credential_map.retain(|handle, _| is_live(handle));
+        nodelay.retain(|handle, _| is_live(handle));
+        tx_priority.retain(|handle, _| is_live(handle));
+        local_ports.retain(|handle, _| is_live(handle));
+        #[cfg(feature = "socket-tcp")]
+        time_wait_dropped.retain(|handle, _| is_live(handle));
+        #[cfg(feature = "socket-tcp")]
+        connect_errors.retain(|handle, _| is_live(handle));
+        #[cfg(feature = "socket-tcp")]
+        io_timeouts.retain(|handle, _| is_live(handle));
+        #[cfg(feature = "socket-udp")]
+        send_to_endpoints.retain(|handle, _| is_live(handle));
+        #[cfg(feature = "socket-udp")]
+        rx_datagrams.retain(|handle, _| is_live(handle));
+        #[cfg(feature = "socket-udp")]
+        send_errors.retain(|handle, _| is_live(handle));
+
+        let stale = before - self.settings_len();
+        if stale > 0 {
+            warn!(
+                "Removed {} settings of sockets no longer in the stack",
+                stale
+            );
+            self.stats.stale_entries += stale as u32;
+        }
+        stale
+    }
+
+    /// Queue closing `peer_handle` on behalf of a dropped socket.
+    ///
+    /// Returns `false` if the queue is full.
+    pub(crate) fn queue_close(&mut self, peer_handle: PeerHandle) -> bool {
+        if self.dropped_sockets.push(peer_handle).is_ok() {
+            return true;
+        }
+
+        self.stats.close_queue_overflows += 1;
+        warn!(
+            "Close queue full, peer {} ({} overflows)",
+            peer_handle, self.stats.close_queue_overflows
+        );
+        false
+    }
+
+    /// Release the slot of the dropped socket `handle`, closing
+    /// `peer_handle` on the module.
+    ///
+    /// If the close does not fit the queue, the slot is kept with empty
+    /// buffers, and the close is emitted from it by the next `tx_event`.
+    pub(crate) fn release(&mut self, handle: SocketHandle, peer_handle: Option<PeerHandle>) {
+        if let Some(peer_handle) = peer_handle {
+            if !self.queue_close(peer_handle) {
+                if self.insert_setting(|s| &mut s.orphaned, handle, ()).is_ok() {
+                    self.orphan(handle, peer_handle);
+                    self.waker.wake();
+                    return;
+                }
+                error!(
+                    "No room to keep {} until peer {} is closed",
+                    handle, peer_handle
+                );
+            }
+        }
+
+        self.sockets.remove(handle);
+        self.waker.wake();
+    }
+
+    /// Replace the socket `handle` by one of the same type without buffers,
+    /// holding `peer_handle` until it is closed.
+    fn orphan(&mut self, handle: SocketHandle, peer_handle: PeerHandle) {
+        let (rx, tx): (&'static mut [u8], &'static mut [u8]) = (&mut [], &mut []);
+        match self.sockets.iter_mut().find(|(h, _)| *h == handle) {
+            #[cfg(feature = "socket-udp")]
+            Some((_, Socket::Udp(udp))) => {
+                *udp = ublox_sockets::udp::Socket::new(
+                    ublox_sockets::udp::SocketBuffer::new(rx),
+                    ublox_sockets::udp::SocketBuffer::new(tx),
+                );
+                udp.peer_handle = Some(peer_handle);
+            }
+            #[cfg(feature = "socket-tcp")]
+            Some((_, Socket::Tcp(tcp))) => {
+                *tcp = ublox_sockets::tcp::Socket::new(
+                    ublox_sockets::tcp::SocketBuffer::new(rx),
+                    ublox_sockets::tcp::SocketBuffer::new(tx),
+                );
+                tcp.peer_handle = Some(peer_handle);
+            }
+            #[allow(unreachable_patterns)]
+            _ => {}
+        }
+    }
+
+    /// Queue closing `peer_handle` of the TCP socket `handle`, which is
+    /// still in use.
+    ///
+    /// If the queue is full, the socket keeps the peer and closes it itself,
+    /// like after a local close. Returns whether the close was queued.
+    #[cfg(feature = "socket-tcp")]
+    fn close_peer(&mut self, handle: SocketHandle, peer_handle: PeerHandle) -> bool {
+        if self.queue_close(peer_handle) {
+            return true;
+        }
+        let tcp = self.sockets.get_mut::<ublox_sockets::tcp::Socket>(handle);
+        tcp.peer_handle = Some(peer_handle);
+        tcp.set_state(TcpState::FinWait1);
+        false
+    }
+
+    /// Cancel a pending connect on `handle`, e.g. because the connect future
+    /// was dropped before the connection was established.
+    ///
+    /// A peer already allocated on the module is closed, and so is one
+    /// allocated by a `ConnectPeer` still in flight.
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) fn abort_connect(&mut self, handle: SocketHandle) {
+        let tcp = self.sockets.get_mut::<ublox_sockets::tcp::Socket>(handle);
+        match tcp.state() {
+            TcpState::Closed if tcp.remote_endpoint.is_some() => {
+                tcp.remote_endpoint = None;
+                // Otherwise `ConnectPeer` has not been sent yet
+                if self.connect_in_flight == Some(handle)
+                    && !self.aborted_connects.contains(&handle)
+                {
+                    self.aborted_connects.push(handle).ok();
+                }
+            }
+            TcpState::SynSent => {
+                tcp.remote_endpoint = None;
+                tcp.set_state(TcpState::Closed);
+                if let Some(peer_handle) = tcp.peer_handle.take() {
+                    // A full queue leaves the peer to the `FinWait1` close
+                    let _ = self.close_peer(handle, peer_handle);
+                }
+            }
+            _ => {}
+        }
+        // Not to fail the next connect
+        self.connect_errors.remove(&handle);
+        self.waker.wake();
+    }
+
+    /// Forcibly close the TCP socket `handle`.
+    ///
+    /// The socket is closed straight away, and its peer is queued for closing
+    /// on the module without going through `FinWait1`. Only if the close
+    /// queue is full, the socket goes through `FinWait1` to close its peer,
+    /// and [`CloseQueueFull`] is returned. A socket no longer in the stack is
+    /// left alone.
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) fn abort(&mut self, handle: SocketHandle) -> Result<(), CloseQueueFull> {
+        if !self.sockets.iter_mut().any(|(h, _)| h == handle) {
+            return Ok(());
+        }
+
+        // An in-flight connect is cancelled like a dropped connect future
+        self.abort_connect(handle);
+
+        let tcp = self.sockets.get_mut::<ublox_sockets::tcp::Socket>(handle);
+        tcp.abort();
+        tcp.set_state(TcpState::Closed);
+        // Keep the closed socket from being connected again
+        tcp.remote_endpoint = None;
+        tcp.edm_channel = None;
+        let peer_handle = tcp.peer_handle.take();
+
+        self.time_wait.remove(&handle);
+        let queued = match peer_handle {
+            Some(peer_handle) => self.close_peer(handle, peer_handle),
+            None => true,
+        };
+        self.waker.wake();
+
+        if queued {
+            Ok(())
+        } else {
+            Err(CloseQueueFull)
+        }
+    }
+
+    /// Handle `ConnectPeer` for `handle` failing with `error`.
+    ///
+    /// A TCP socket stops connecting, and its connect future is woken to
+    /// resolve with the error. A UDP socket drops the datagrams queued for
+    /// the endpoint, instead of connecting again right away, and its next
+    /// `send_to` fails.
+    fn connect_failed(&mut self, handle: SocketHandle, error: atat::Error) {
+        self.connect_in_flight.take_if(|h| *h == handle);
+        if let Some(pos) = self.aborted_connects.iter().position(|h| *h == handle) {
+            self.aborted_connects.swap_remove(pos);
+            return;
+        }
+
+        match self.sockets.iter_mut().find(|(h, _)| *h == handle) {
+            #[cfg(feature = "socket-tcp")]
+            Some((_, Socket::Tcp(tcp))) => {
+                if tcp.state() != TcpState::Closed {
+                    return;
+                }
+                // Keep the socket from connecting again
+                tcp.remote_endpoint = None;
+                // Wakes the connect future
+                tcp.set_state(TcpState::Closed);
+
+                let error = match error {
+                    // No answer, the transport to the module may be stuck
+                    atat::Error::Timeout => tcp::ConnectError::TimedOut,
+                    // The module replied with an error, e.g. as there is no route
+                    // to the host
+                    atat::Error::Error
+                    | atat::Error::CmeError(_)
+                    | atat::Error::InvalidResponse => tcp::ConnectError::NoRoute,
+                    _ => tcp::ConnectError::ConnectionReset,
+                };
+                if self
+                    .insert_setting(|s| &mut s.connect_errors, handle, error)
+                    .is_err()
+                {
+                    warn!("Too many failed connects, dropping error of {}", handle);
+                }
+            }
+            #[cfg(feature = "socket-udp")]
+            Some((_, Socket::Udp(udp))) => {
+                if udp.state() != UdpState::Closed || udp.peer_handle.is_some() {
+                    return;
+                }
+                udp.endpoint = None;
+                // Wakes a `send_to` waiting for room in the queue
+                udp::discard_queued(udp);
+                // Keep the socket from connecting again
+                self.send_to_endpoints.remove(&handle);
+
+                if self
+                    .insert_setting(|s| &mut s.send_errors, handle, udp::SendError::NoRoute)
+                    .is_err()
+                {
+                    warn!("Too many failed connects, dropping error of {}", handle);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Take the error the latest connect of `handle` failed with.
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) fn take_connect_error(&mut self, handle: SocketHandle) -> Option<tcp::ConnectError> {
+        self.connect_errors.remove(&handle)
+    }
+
+    /// Record that `peer_handle` holds `port` on the module.
+    fn bind_port(&mut self, port: u16, peer_handle: PeerHandle) {
+        if self.bound_ports.push((port, peer_handle)).is_err() {
+            warn!("Too many bound ports, not tracking {}", port);
+        }
+    }
+
+    /// Release the port held by `peer_handle`, now that the module reported
+    /// it disconnected, letting connects waiting for the port proceed.
+    fn release_port(&mut self, peer_handle: PeerHandle) {
+        let before = self.bound_ports.len();
+        self.bound_ports.retain(|(_, p)| *p != peer_handle);
+        if self.bound_ports.len() != before {
+            self.waker.wake();
+        }
+    }
+
+    /// Handle the module's response to `ConnectPeer` for `handle`.
+    fn connect_response(&mut self, handle: SocketHandle, peer_handle: PeerHandle) {
+        self.connect_in_flight.take_if(|h| *h == handle);
+        if let Some(pos) = self.aborted_connects.iter().position(|h| *h == handle) {
+            self.aborted_connects.swap_remove(pos);
+            warn!("Closing peer {} of cancelled connect", peer_handle);
+            self.queue_close(peer_handle);
+            return;
+        }
+
+        match self.sockets.iter_mut().find(|(h, _)| *h == handle) {
+            #[cfg(feature = "socket-tcp")]
+            Some((_, Socket::Tcp(tcp))) => match tcp.state() {
+                // Established once the module reports the connect event
+                TcpState::Closed => {
+                    tcp.peer_handle = Some(peer_handle);
+                    tcp.set_state(TcpState::SynSent);
+                }
+                // The module reported the connect event ahead of the response,
+                // and the socket may since have been closed locally
+                TcpState::Established | TcpState::FinWait1 => {
+                    tcp.peer_handle = Some(peer_handle);
+                }
+                _ => {
+                    warn!(
+                        "Closing peer {} of socket no longer connecting",
+                        peer_handle
+                    );
+                    self.queue_close(peer_handle);
+                }
+            },
+            // Established once the module reports the connect event
+            #[cfg(feature = "socket-udp")]
+            Some((_, Socket::Udp(udp))) => udp.peer_handle = Some(peer_handle),
+            _ => {}
+        }
+    }
+
+    /// Invalidate all sockets after the module has been reset or the network
+    /// has been left, as their peers and channels no longer exist on the
+    /// module.
+    fn invalidate(&mut self) {
+        let SocketStack {
+            sockets,
+            dns_table,
+            dropped_sockets,
+            close_waker,
+            aborted_connects,
+            flow_paused,
+            time_wait,
+            bound_ports,
+            ..
+        } = self;
+
+        flow_paused.clear();
+        bound_ports.clear();
+
+        for (handle, socket) in sockets.iter_mut() {
+            match socket {
+                #[cfg(feature = "socket-udp")]
+                Socket::Udp(udp) => {
+                    udp.peer_handle = None;
+                    udp.edm_channel = None;
+                    udp.set_state(UdpState::Closed);
+                }
+                #[cfg(feature = "socket-tcp")]
+                Socket::Tcp(tcp) => {
+                    tcp.peer_handle = None;
+                    tcp.edm_channel = None;
+                    if tcp.state() != TcpState::Closed {
+                        tcp.set_state(TcpState::TimeWait);
+                        time_wait.insert(handle, Instant::now()).ok();
+                    }
+                }
+                #[allow(unreachable_patterns)]
+                _ => {}
+            }
+        }
+
+        for query in dns_table.table.iter_mut() {
+            if matches!(query.state, DnsState::New | DnsState::Pending) {
+                query.state = DnsState::Error(PingError::Other);
+                query.waker.wake();
+            }
+        }
+
+        dropped_sockets.clear();
+        close_waker.wake();
+        aborted_connects.clear();
+    }
+
+    /// Whether at least 75% of the socket slots are in use.
+    #[cfg(feature = "socket-tcp")]
+    fn near_capacity(&mut self) -> bool {
+        let used = self.sockets.iter_mut().count();
+        used * 4 >= self.capacity * 3
+    }
+
+    /// When the next dropped socket in `TimeWait` may be recycled, if slots
+    /// are running out. Nothing else the stack does is driven by time.
+    #[cfg(feature = "socket-tcp")]
+    fn next_deadline(&mut self) -> Option<Instant> {
+        if !self.near_capacity() {
+            return None;
+        }
+        self.time_wait
+            .iter()
+            .filter(|(handle, _)| self.time_wait_dropped.contains_key(handle))
+            .map(|(_, entered)| *entered + self.time_wait_duration)
+            .min()
+    }
+
+    /// Number of sockets currently attached to a peer on the module.
+    pub(crate) fn peer_count(&mut self) -> usize {
+        self.sockets
+            .iter_mut()
+            .filter(|(_, socket)| match socket {
+                #[cfg(feature = "socket-udp")]
+                Socket::Udp(udp) => udp.peer_handle.is_some(),
+                #[cfg(feature = "socket-tcp")]
+                Socket::Tcp(tcp) => tcp.peer_handle.is_some(),
+                #[allow(unreachable_patterns)]
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Write `data` received on `channel_id` into the buffer of its socket.
+    ///
+    /// Returns `false` if no socket is receiving on the channel.
+    ///
+    /// Each packet received for a UDP socket is a datagram, which is either
+    /// queued whole or dropped.
+    fn rx_data(&mut self, channel_id: ChannelId, data: &[u8]) -> bool {
+        let SocketStack {
+            sockets,
+            #[cfg(feature = "socket-udp")]
+            rx_datagrams,
+            #[cfg(feature = "socket-udp")]
+            stats,
+            ..
+        } = self;
+
+        for (_handle, socket) in sockets.iter_mut() {
+            match socket {
+                #[cfg(feature = "socket-udp")]
+                Socket::Udp(udp) if udp.edm_channel == Some(channel_id) => {
+                    if !rx_datagrams.contains_key(&_handle) {
+                        rx_datagrams.insert(_handle, heapless::Deque::new()).ok();
+                    }
+                    let queued = match (rx_datagrams.get_mut(&_handle), udp.endpoint) {
+                        (Some(queue), Some(source))
+                            if !queue.is_full()
+                                && udp.recv_capacity() - udp.recv_queue() >= data.len() =>
+                        {
+                            udp.rx_enqueue_slice(data);
+                            queue
+                                .push_back(udp::RxDatagram {
+                                    len: data.len(),
+                                    source,
+                                })
+                                .ok();
+                            true
+                        }
+                        _ => false,
+                    };
+                    if !queued {
+                        stats.rx_dropped_datagrams += 1;
+                        error!(
+                            "[{}] UDP RX overflow! Dropping datagram of {} bytes",
+                            udp.peer_handle,
+                            data.len()
+                        );
+                    }
+                    return true;
+                }
+                #[cfg(feature = "socket-tcp")]
+                Socket::Tcp(tcp) if tcp.edm_channel == Some(channel_id) && tcp.may_recv() => {
+                    let n = tcp.rx_enqueue_slice(data);
+                    if n < data.len() {
+                        error!(
+                            "[{}] TCP RX data overflow! Discarding {} bytes",
+                            tcp.peer_handle,
+                            data.len() - n
+                        );
+                    }
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Write `data` received on `channel_id` into the buffer of its socket,
+    /// straight from the ingress buffer.
+    ///
+    /// Returns `false`, leaving the data to the URC channel, if no socket is
+    /// receiving on the channel, or a TCP socket has no room for all of it.
+    fn rx_direct(&mut self, channel_id: ChannelId, data: &[u8]) -> bool {
+        #[cfg(feature = "socket-tcp")]
+        let no_room = self.sockets.iter_tcp_mut().any(|(_handle, tcp)| {
+            tcp.edm_channel == Some(channel_id)
+                && tcp.recv_capacity() - tcp.recv_queue() < data.len()
+        });
+        #[cfg(not(feature = "socket-tcp"))]
+        let no_room = false;
+
+        if no_room || !self.rx_data(channel_id, data) {
+            return false;
+        }
+        self.stats.rx_direct += 1;
+        true
+    }
+}
+
+impl<
+        const INGRESS_BUF_SIZE: usize,
+        const URC_CAPACITY: usize,
+        const EGRESS_BUF_SIZE: usize,
+        const CMD_BUF_SIZE: usize,
+    > UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>
+{
+    pub fn new<const SOCK: usize, const MAX_HEAP: usize>(
+        device: Device<'static, INGRESS_BUF_SIZE, URC_CAPACITY, CMD_BUF_SIZE>,
+        resources: &'static mut StackResources<SOCK, MAX_HEAP>,
+    ) -> Self {
+        const {
+            assert!(
+                CMD_BUF_SIZE >= EGRESS_BUF_SIZE + DATA_COMMAND_OVERHEAD,
+                "CMD_BUF_SIZE must fit EGRESS_BUF_SIZE bytes of data in an EDM packet"
+            )
+        };
+        const {
+            assert!(
+                URC_CAPACITY >= MIN_URC_CAPACITY,
+                "URC_CAPACITY must be at least MIN_URC_CAPACITY to not miss events"
+            )
         };
 
+        let StackResources { sockets, settings } = resources;
+        let sockets = SocketSet::new(&mut sockets[..]);
         Self {
-            socket: RefCell::new(socket),
+            socket: RefCell::new(SocketStack::new(sockets, settings)),
             device,
-            last_tx_socket: AtomicU8::new(0),
             should_tx: AtomicBool::new(false),
         }
     }
 
+    /// Set how long the slot of a dropped TCP socket is kept in `TimeWait`
+    /// before it may be recycled. Defaults to [`TIME_WAIT_DURATION`].
+    pub fn set_time_wait_duration(&self, duration: Duration) {
+        let mut s = self.socket.borrow_mut();
+        s.time_wait_duration = duration;
+        s.waker.wake();
+    }
+
+    /// Snapshot of all open sockets.
+    ///
+    /// At most [`MAX_SOCKET_INFO`] sockets are reported.
+    pub fn socket_info(&self) -> impl Iterator<Item = SocketInfo> {
+        let mut infos = heapless::Vec::<SocketInfo, MAX_SOCKET_INFO>::new();
+
+        let mut s = self.socket.borrow_mut();
+        for (handle, socket) in s.sockets.iter_mut() {
+            let info = match socket {
+                #[cfg(feature = "socket-tcp")]
+                Socket::Tcp(tcp) => SocketInfo {
+                    handle,
+                    state: SocketState::Tcp(tcp.state()),
+                    edm_channel: tcp.edm_channel,
+                    peer_handle: tcp.peer_handle,
+                    remote_endpoint: tcp.remote_endpoint(),
+                    tx_queued: Some(tcp.send_queue()),
+                    rx_queued: Some(tcp.recv_queue()),
+                },
+                #[cfg(feature = "socket-udp")]
+                Socket::Udp(udp) => SocketInfo {
+                    handle,
+                    state: SocketState::Udp(udp.state()),
+                    edm_channel: udp.edm_channel,
+                    peer_handle: udp.peer_handle,
+                    remote_endpoint: udp.endpoint(),
+                    tx_queued: None,
+                    rx_queued: None,
+                },
+                #[allow(unreachable_patterns)]
+                _ => continue,
+            };
+
+            if infos.push(info).is_err() {
+                break;
+            }
+        }
+
+        infos.into_iter()
+    }
+
+    /// Handle, EDM channel and peer handle of all open sockets, to correlate
+    /// the IDs reported by the module with the sockets using them.
+    ///
+    /// At most [`MAX_SOCKET_INFO`] sockets are reported.
+    pub fn socket_diagnostics(
+        &self,
+    ) -> impl Iterator<Item = (SocketHandle, Option<ChannelId>, Option<PeerHandle>)> {
+        self.socket_info()
+            .map(|info| (info.handle, info.edm_channel, info.peer_handle))
+    }
+
+    /// Counters of socket events since the stack was created.
+    pub fn socket_stats(&self) -> SocketStats {
+        self.socket.borrow().stats
+    }
+
+    /// Write received socket data into the socket buffers as soon as it is
+    /// digested by the runner, instead of passing it through the URC channel.
+    ///
+    /// This saves copying every received packet twice, which limits the
+    /// throughput at high baud rates.
+    pub fn enable_direct_rx(&'static self) {
+        self.device.direct_rx.register(&self.socket);
+    }
+
+    /// Current link state of the network interface, as tracked by the
+    /// runner.
+    pub fn get_link_state(&self) -> LinkState {
+        self.device.state_ch.link_state(None)
+    }
+
+    /// Wait for the link to be up, i.e. for the module to be connected to
+    /// a network and to have an IP address.
+    pub async fn wait_for_link_up(&self) {
+        self.device
+            .state_ch
+            .wait_for_link_state(LinkState::Up)
+            .await
+    }
+
     pub async fn run(&self) -> ! {
-        let mut tx_buf = [0u8; MAX_EGRESS_SIZE];
+        let mut tx_buf = [0u8; EGRESS_BUF_SIZE];
 
         let Device {
             urc_channel,
             state_ch,
             at_client,
+            direct_rx,
         } = &self.device;
 
         let mut urc_subscription = urc_channel.subscribe().unwrap();
+        direct_rx.reset();
+        let mut module_resets = state_ch.module_resets();
+        let mut leaves = state_ch.leaves();
 
         loop {
             // FIXME: It feels like this can be written smarter/simpler?
@@ -133,25 +1497,85 @@ impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
                 }
             });
 
-            let ticker = Ticker::every(Duration::from_millis(100));
-            futures_util::pin_mut!(ticker);
+            #[cfg(feature = "socket-tcp")]
+            let deadline = self.socket.borrow_mut().next_deadline();
+            #[cfg(not(feature = "socket-tcp"))]
+            let deadline: Option<Instant> = None;
+            let timer = async {
+                match deadline {
+                    Some(deadline) => Timer::at(deadline).await,
+                    None => core::future::pending().await,
+                }
+            };
+
+            let stack_update = state_ch.wait_stack_update(module_resets, leaves);
 
-            match select::select3(
-                urc_subscription.next_message_pure(),
+            match select::select4(
+                urc_subscription.next_message(),
                 should_tx,
-                ticker.next(),
+                timer,
+                stack_update,
             )
             .await
             {
-                select::Either3::First(event) => {
+                select::Either4::First(WaitResult::Message(event)) => {
+                    direct_rx.dequeued(&event);
                     Self::socket_rx(event, &self.socket);
                 }
-                select::Either3::Second(_) | select::Either3::Third(_) => {
+                select::Either4::First(WaitResult::Lagged(n)) => {
+                    warn!("Missed {} socket events", n);
+                    direct_rx.lagged(n as usize);
+                }
+                select::Either4::Second(_) => {
                     if let Some(ev) = self.tx_event(&mut tx_buf) {
                         Self::socket_tx(ev, &self.socket, &at_client).await;
+                        // Other sockets may have events pending as well
+                        self.should_tx.store(true, Ordering::Relaxed);
                     }
                 }
+                select::Either4::Third(_) => {
+                    #[cfg(feature = "socket-tcp")]
+                    {
+                        let n = self.socket.borrow_mut().recycle();
+                        if n > 0 {
+                            info!("Recycled {} sockets in TimeWait", n);
+                        }
+                    }
+                }
+                select::Either4::Fourth(_) => {}
+            }
+
+            let mut s = self.socket.borrow_mut();
+            if state_ch.module_resets() != module_resets {
+                warn!("Module was reset, invalidating sockets");
+                module_resets = state_ch.module_resets();
+                s.invalidate();
+            }
+            if state_ch.leaves() != leaves {
+                info!("Network was left, invalidating sockets");
+                leaves = state_ch.leaves();
+                s.invalidate();
             }
+            state_ch.set_open_sockets(s.peer_count());
+        }
+    }
+
+    /// Close the peers of all dropped sockets on the module now, instead of
+    /// when [`Self::run`] gets to them.
+    ///
+    /// Use this to clean up deterministically while `run` may be starved,
+    /// e.g. before entering low-power mode.
+    pub async fn flush_dropped_sockets(&self) {
+        loop {
+            let Some(peer_handle) = self.socket.borrow_mut().take_dropped() else {
+                break;
+            };
+            Self::socket_tx(
+                TxEvent::Close { peer_handle },
+                &self.socket,
+                &self.device.at_client,
+            )
+            .await;
         }
     }
 
@@ -169,88 +1593,125 @@ impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
         match event {
             EdmEvent::IPv4ConnectEvent(ev) => {
                 let endpoint = SocketAddr::new(ev.remote_ip.into(), ev.remote_port);
+                #[cfg(feature = "socket-tcp")]
+                if ev.protocol == Protocol::TCP {
+                    Self::accept_event(ev.local_port, endpoint, socket);
+                }
                 Self::connect_event(ev.channel_id, ev.protocol, endpoint, socket);
             }
             EdmEvent::IPv6ConnectEvent(ev) => {
                 let endpoint = SocketAddr::new(ev.remote_ip.into(), ev.remote_port);
+                #[cfg(feature = "socket-tcp")]
+                if ev.protocol == Protocol::TCP {
+                    Self::accept_event(ev.local_port, endpoint, socket);
+                }
                 Self::connect_event(ev.channel_id, ev.protocol, endpoint, socket);
             }
             EdmEvent::DisconnectEvent(channel_id) => {
                 let mut s = socket.borrow_mut();
-                for (_handle, socket) in s.sockets.iter_mut() {
-                    match socket {
-                        #[cfg(feature = "socket-udp")]
-                        Socket::Udp(udp) if udp.edm_channel == Some(channel_id) => {
-                            udp.edm_channel = None;
-                            break;
-                        }
-                        #[cfg(feature = "socket-tcp")]
-                        Socket::Tcp(tcp) if tcp.edm_channel == Some(channel_id) => {
-                            tcp.edm_channel = None;
-                            break;
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            EdmEvent::DataEvent(DataEvent { channel_id, data }) => {
-                let mut s = socket.borrow_mut();
-                for (_handle, socket) in s.sockets.iter_mut() {
-                    match socket {
-                        #[cfg(feature = "socket-udp")]
-                        Socket::Udp(udp)
-                            if udp.edm_channel == Some(channel_id) =>
-                            // FIXME:
-                            // if udp.edm_channel == Some(channel_id) && udp.may_recv() =>
-                        {
-                            let n = udp.rx_enqueue_slice(&data);
-                            if n < data.len() {
-                                error!(
-                                    "[{}] UDP RX data overflow! Discarding {} bytes",
-                                    udp.peer_handle,
-                                    data.len() - n
-                                );
-                            }
-                            break;
-                        }
-                        #[cfg(feature = "socket-tcp")]
-                        Socket::Tcp(tcp)
-                            if tcp.edm_channel == Some(channel_id) && tcp.may_recv() =>
-                        {
-                            let n = tcp.rx_enqueue_slice(&data);
-                            if n < data.len() {
-                                error!(
-                                    "[{}] TCP RX data overflow! Discarding {} bytes",
-                                    tcp.peer_handle,
-                                    data.len() - n
-                                );
-                            }
-                            break;
-                        }
-                        _ => {}
+                s.flow_paused.retain(|c| *c != channel_id);
+                #[cfg(feature = "socket-udp")]
+                for (_handle, udp) in s.sockets.iter_udp_mut() {
+                    if udp.edm_channel == Some(channel_id) {
+                        udp.edm_channel = None;
+                    }
+                }
+                #[cfg(feature = "socket-tcp")]
+                for (_handle, tcp) in s.sockets.iter_tcp_mut() {
+                    if tcp.edm_channel == Some(channel_id) {
+                        tcp.edm_channel = None;
                     }
                 }
             }
-            EdmEvent::ATEvent(Urc::PeerDisconnected(PeerDisconnected { handle })) => {
+            EdmEvent::DataEvent(DataEvent { channel_id, data }) => {
+                let mut s = socket.borrow_mut();
+                s.stats.rx_queued += 1;
+                s.rx_data(channel_id, &data);
+            }
+            EdmEvent::FlowControl { channel_id, paused } => {
                 let mut s = socket.borrow_mut();
-                for (_handle, socket) in s.sockets.iter_mut() {
+                if paused {
+                    debug!("Data flow paused on {}", channel_id);
+                    if !s.flow_paused.contains(&channel_id) {
+                        s.flow_paused.push(channel_id).ok();
+                    }
+                } else {
+                    debug!("Data flow resumed on {}", channel_id);
+                    s.flow_paused.retain(|c| *c != channel_id);
+                    // Wake the TX future to send the data held back
+                    s.waker.wake();
+                }
+            }
+            EdmEvent::ATEvent(Urc::PeerConnected(PeerConnected {
+                handle,
+                protocol,
+                local_port,
+                remote_address,
+                remote_port,
+                ..
+            })) => {
+                // Peers connected by the module on its own, i.e. default remote
+                // peers, are only known from this URC.
+                // IPv6 addresses may carry a zone index, e.g. `fe80::1%0`
+                let Some(ip) = core::str::from_utf8(&remote_address)
+                    .ok()
+                    .and_then(|addr| addr.split('%').next())
+                    .and_then(|addr| addr.parse::<IpAddr>().ok())
+                else {
+                    return;
+                };
+                let endpoint = SocketAddr::new(ip, remote_port);
+
+                // Peers connected to a server of the module
+                #[cfg(feature = "socket-tcp")]
+                if protocol == IPProtocol::TCP {
+                    Self::accept_event(local_port, endpoint, socket);
+                }
+
+                #[cfg(feature = "socket-tcp")]
+                if let Some((_handle, tcp)) =
+                    socket.borrow_mut().sockets.iter_tcp_mut().find(|(_, tcp)| {
+                        tcp.peer_handle.is_none()
+                            && tcp.state() != TcpState::Closed
+                            && tcp
+                                .remote_endpoint()
+                                .is_some_and(|ep| same_endpoint(ep, endpoint))
+                    })
+                {
+                    tcp.peer_handle = Some(handle);
+                }
+            }
+            EdmEvent::ATEvent(Urc::PeerDisconnected(PeerDisconnected { handle })) => {
+                let s = &mut *socket.borrow_mut();
+                for (socket_handle, socket) in s.sockets.iter_mut() {
                     match socket {
                         #[cfg(feature = "socket-udp")]
                         Socket::Udp(udp) if udp.peer_handle == Some(handle) => {
+                            // Reconnected once more data is sent
                             udp.peer_handle = None;
-                            // FIXME:
-                            // udp.set_state(UdpState::TimeWait);
+                            udp.set_state(UdpState::Closed);
                             break;
                         }
                         #[cfg(feature = "socket-tcp")]
                         Socket::Tcp(tcp) if tcp.peer_handle == Some(handle) => {
-                            tcp.peer_handle = None;
-                            tcp.set_state(TcpState::TimeWait);
+                            match tcp.state() {
+                                // Remote initiated close. Buffered data can still be read,
+                                // and the peer is released once the socket is closed or
+                                // dropped.
+                                TcpState::Established => tcp.set_state(TcpState::CloseWait),
+                                TcpState::CloseWait => {}
+                                _ => {
+                                    tcp.peer_handle = None;
+                                    tcp.set_state(TcpState::TimeWait);
+                                    s.time_wait.insert(socket_handle, Instant::now()).ok();
+                                }
+                            }
                             break;
                         }
                         _ => {}
                     }
                 }
+                s.release_port(handle);
             }
             EdmEvent::ATEvent(Urc::PingResponse(PingResponse {
                 ip, hostname, rtt, ..
@@ -288,163 +1749,83 @@ impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
     }
 
     fn tx_event<'data>(&self, buf: &'data mut [u8]) -> Option<TxEvent<'data>> {
-        let mut s = self.socket.borrow_mut();
-        for query in s.dns_table.table.iter_mut() {
-            if let DnsState::New = query.state {
-                query.state = DnsState::Pending;
-                buf[..query.domain_name.len()].copy_from_slice(query.domain_name.as_bytes());
-                return Some(TxEvent::Dns {
-                    hostname: core::str::from_utf8(&buf[..query.domain_name.len()]).unwrap(),
-                });
-            }
-        }
-
-        // Handle delayed close-by-drop here
-        if let Some(dropped_peer_handle) = s.dropped_sockets.pop() {
-            warn!("Handling dropped socket {}", dropped_peer_handle);
-            return Some(TxEvent::Close {
-                peer_handle: dropped_peer_handle,
-            });
-        }
-
-        // Make sure to give all sockets an even opportunity to TX
-        // let skip = self
-        //     .last_tx_socket
-        //     .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
-        //         let next = v + 1;
-        //         Some(next.rem(s.sockets.sockets.len() as u8))
-        //     })
-        //     .unwrap();
-        let skip = 0;
-
-        let SocketStack {
-            sockets,
-            dns_table,
-            credential_map,
-            ..
-        } = s.deref_mut();
-
-        for (handle, socket) in sockets.iter_mut().skip(skip as usize) {
-            match socket {
-                #[cfg(feature = "socket-udp")]
-                Socket::Udp(_udp) => todo!(),
-                #[cfg(feature = "socket-tcp")]
-                Socket::Tcp(tcp) => {
-                    tcp.poll();
-
-                    match tcp.state() {
-                        TcpState::Closed => {
-                            if let Some(addr) = tcp.remote_endpoint() {
-                                let mut builder = PeerUrlBuilder::new();
-
-                                if let Some(hostname) = dns_table.reverse_lookup(addr.ip()) {
-                                    builder.hostname(hostname).port(addr.port())
-                                } else {
-                                    builder.address(&addr)
-                                };
-
-                                if let Some(creds) = credential_map.get(&handle) {
-                                    info!("Found credentials {} for {}", creds, handle);
-                                    builder.creds(creds);
-                                }
-
-                                let url =
-                                    builder.set_local_port(tcp.local_port).tcp::<128>().unwrap();
-
-                                // FIXME: Write directly into `buf` instead
-                                buf[..url.len()].copy_from_slice(url.as_bytes());
-
-                                return Some(TxEvent::Connect {
-                                    socket_handle: handle,
-                                    url: core::str::from_utf8(&buf[..url.len()]).unwrap(),
-                                });
-                            }
-                        }
-                        // We transmit data in all states where we may have data in the buffer,
-                        // or the transmit half of the connection is still open.
-                        TcpState::Established | TcpState::CloseWait | TcpState::LastAck => {
-                            if let Some(edm_channel) = tcp.edm_channel {
-                                return tcp.tx_dequeue(|payload| {
-                                    let len = core::cmp::min(payload.len(), MAX_EGRESS_SIZE);
-                                    let res = if len != 0 {
-                                        buf[..len].copy_from_slice(&payload[..len]);
-                                        Some(TxEvent::Send {
-                                            edm_channel,
-                                            data: &buf[..len],
-                                        })
-                                    } else {
-                                        None
-                                    };
-
-                                    (len, res)
-                                });
-                            }
-                        }
-                        TcpState::FinWait1 => {
-                            return Some(TxEvent::Close {
-                                peer_handle: tcp.peer_handle.unwrap(),
-                            });
-                        }
-                        TcpState::Listen => todo!(),
-                        TcpState::SynReceived => todo!(),
-                        _ => {}
-                    };
-                }
-                _ => {}
-            };
-        }
-
-        None
+        self.socket.borrow_mut().tx_event(buf)
     }
 
     async fn socket_tx<'data>(
         ev: TxEvent<'data>,
         socket: &RefCell<SocketStack>,
-        at_client: &RefCell<ProxyClient<'_, INGRESS_BUF_SIZE>>,
+        at_client: &RefCell<ProxyClient<'_, INGRESS_BUF_SIZE, CMD_BUF_SIZE>>,
     ) {
         use atat::asynch::AtatClient;
 
         let at_guard = at_client.borrow();
         let mut at = &*at_guard;
         match ev {
-            TxEvent::Connect { socket_handle, url } => {
+            TxEvent::Connect {
+                socket_handle,
+                url,
+                local_port,
+            } => {
+                socket.borrow_mut().stats.connect_attempts += 1;
                 match at
                     .send_retry(&EdmAtCmdWrapper(ConnectPeer { url: &url }))
                     .await
                 {
                     Ok(ConnectPeerResponse { peer_handle }) => {
                         let mut s = socket.borrow_mut();
-                        let tcp = s
-                            .sockets
-                            .get_mut::<ublox_sockets::tcp::Socket>(socket_handle);
-                        tcp.peer_handle = Some(peer_handle);
-                        tcp.set_state(TcpState::SynSent);
+                        if let Some(port) = local_port {
+                            s.bind_port(port, peer_handle);
+                        }
+                        s.connect_response(socket_handle, peer_handle);
                     }
                     Err(e) => {
-                        error!("Failed to connect?! {}", e)
+                        error!("Failed to connect {}: {}", socket_handle, e);
+                        let mut s = socket.borrow_mut();
+                        s.stats.connect_failures += 1;
+                        s.connect_failed(socket_handle, e);
                     }
                 }
             }
             TxEvent::Send { edm_channel, data } => {
                 warn!("Sending {} bytes on {}", data.len(), edm_channel);
-                at.send_retry(&EdmDataCommand {
-                    channel: edm_channel,
-                    data,
-                })
-                .await
-                .ok();
+                if let Err(e) = at
+                    .send_with_retry(
+                        &EdmDataCommand {
+                            channel: edm_channel,
+                            data,
+                        },
+                        TX_RETRIES,
+                        TX_RETRY_DELAY,
+                    )
+                    .await
+                {
+                    error!("Failed to send data on {}: {}", edm_channel, e);
+                }
             }
             TxEvent::Close { peer_handle } => {
-                at.send_retry(&EdmAtCmdWrapper(ClosePeerConnection { peer_handle }))
+                if let Err(e) = at
+                    .send_with_retry(
+                        &EdmAtCmdWrapper(ClosePeerConnection { peer_handle }),
+                        TX_RETRIES,
+                        TX_RETRY_DELAY,
+                    )
                     .await
-                    .ok();
+                {
+                    error!("Failed to close peer {}: {}", peer_handle, e);
+                }
             }
             TxEvent::Dns { hostname } => {
                 match at
-                    .send_retry(&EdmAtCmdWrapper(Ping {
-                        hostname: &hostname,
-                        retry_num: 1,
-                    }))
+                    .send_with_retry(
+                        &EdmAtCmdWrapper(Ping {
+                            hostname: &hostname,
+                            retry_num: 1,
+                            ..Default::default()
+                        }),
+                        TX_RETRIES,
+                        TX_RETRY_DELAY,
+                    )
                     .await
                 {
                     Ok(_) => {}
@@ -465,6 +1846,32 @@ impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
         }
     }
 
+    /// Bind a peer connected from `endpoint` to a server on `local_port` to a
+    /// socket accepting on the port, unless a socket is connected to it
+    /// already.
+    #[cfg(feature = "socket-tcp")]
+    fn accept_event(local_port: u16, endpoint: SocketAddr, socket: &RefCell<SocketStack>) {
+        let mut s = socket.borrow_mut();
+        let known = s.sockets.iter_tcp_mut().any(|(_handle, tcp)| {
+            tcp.state() != TcpState::Listen
+                && tcp
+                    .remote_endpoint
+                    .is_some_and(|ep| same_endpoint(ep, endpoint))
+        });
+        if known {
+            return;
+        }
+
+        if let Some((_handle, tcp)) = s
+            .sockets
+            .iter_tcp_mut()
+            .find(|(_, tcp)| tcp.state() == TcpState::Listen && tcp.local_port == local_port)
+        {
+            tcp.remote_endpoint = Some(endpoint);
+            tcp.set_state(TcpState::SynReceived);
+        }
+    }
+
     fn connect_event(
         channel_id: ChannelId,
         protocol: Protocol,
@@ -472,38 +1879,49 @@ impl<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>
         socket: &RefCell<SocketStack>,
     ) {
         let mut s = socket.borrow_mut();
-        for (_handle, socket) in s.sockets.iter_mut() {
-            match protocol {
-                #[cfg(feature = "socket-tcp")]
-                Protocol::TCP => match ublox_sockets::tcp::Socket::downcast_mut(socket) {
-                    Some(tcp) if tcp.remote_endpoint == Some(endpoint) => {
-                        tcp.edm_channel = Some(channel_id);
-                        tcp.set_state(TcpState::Established);
-                        break;
-                    }
-                    _ => {}
-                },
-                #[cfg(feature = "socket-udp")]
-                Protocol::UDP => match ublox_sockets::udp::Socket::downcast_mut(socket) {
-                    Some(udp) if udp.endpoint == Some(endpoint) => {
-                        udp.edm_channel = Some(channel_id);
-                        udp.set_state(UdpState::Established);
-                        break;
-                    }
-                    _ => {}
-                },
-                _ => {}
+        match protocol {
+            #[cfg(feature = "socket-tcp")]
+            Protocol::TCP => {
+                if let Some((_handle, tcp)) = s.sockets.iter_tcp_mut().find(|(_, tcp)| {
+                    tcp.remote_endpoint
+                        .is_some_and(|ep| same_endpoint(ep, endpoint))
+                }) {
+                    tcp.edm_channel = Some(channel_id);
+                    tcp.set_state(TcpState::Established);
+                }
+            }
+            #[cfg(feature = "socket-udp")]
+            Protocol::UDP => {
+                if let Some((_handle, udp)) = s
+                    .sockets
+                    .iter_udp_mut()
+                    .find(|(_, udp)| udp.endpoint.is_some_and(|ep| same_endpoint(ep, endpoint)))
+                {
+                    udp.edm_channel = Some(channel_id);
+                    udp.set_state(UdpState::Established);
+                }
             }
+            _ => {}
         }
     }
 }
 
+/// Whether `a` and `b` are the same endpoint, ignoring the IPv6 flow info and
+/// zone index, which the module does not report.
+#[cfg(any(feature = "socket-tcp", feature = "socket-udp"))]
+fn same_endpoint(a: SocketAddr, b: SocketAddr) -> bool {
+    a.ip() == b.ip() && a.port() == b.port()
+}
+
 // TODO: This extra data clone step can probably be avoided by adding a
 // waker/context based API to ATAT.
 enum TxEvent<'data> {
     Connect {
         socket_handle: SocketHandle,
         url: &'data str,
+        /// Local port the peer is connected from, if not assigned by the
+        /// module.
+        local_port: Option<u16>,
     },
     Send {
         edm_channel: ChannelId,
@@ -528,3 +1946,766 @@ impl defmt::Format for TxEvent<'_> {
         }
     }
 }
+
+#[cfg(all(test, feature = "socket-tcp"))]
+mod test {
+    use super::*;
+    use crate::asynch::ublox_stack::tcp::TcpSocket;
+    use core::fmt::Write as _;
+    use core::net::{Ipv4Addr, SocketAddrV4};
+    use ublox_sockets::tcp;
+
+    type Stack = UbloxStack<64, 4>;
+
+    const ROUNDS: usize = 50;
+
+    fn socket_stack() -> RefCell<SocketStack> {
+        socket_stack_with::<2>()
+    }
+
+    fn socket_stack_with<const SOCK: usize>() -> RefCell<SocketStack> {
+        let storage = Box::leak(Box::new([SocketStorage::EMPTY; SOCK]));
+        let settings = Box::leak(Box::new(SettingsStorage::<SOCK>::new()));
+        RefCell::new(SocketStack::new(SocketSet::new(&mut storage[..]), settings))
+    }
+
+    fn add_socket(stack: &RefCell<SocketStack>) -> SocketHandle {
+        let rx: &'static mut [u8] = Box::leak(vec![0u8; 64].into_boxed_slice());
+        let tx: &'static mut [u8] = Box::leak(vec![0u8; 64].into_boxed_slice());
+        stack.borrow_mut().sockets.add(tcp::Socket::new(
+            tcp::SocketBuffer::new(rx),
+            tcp::SocketBuffer::new(tx),
+        ))
+    }
+
+    fn with_tcp<R>(
+        stack: &RefCell<SocketStack>,
+        handle: SocketHandle,
+        f: impl FnOnce(&mut tcp::Socket<'static>) -> R,
+    ) -> R {
+        f(stack.borrow_mut().sockets.get_mut::<tcp::Socket>(handle))
+    }
+
+    fn peer_disconnected(stack: &RefCell<SocketStack>, handle: PeerHandle) {
+        Stack::socket_rx(
+            EdmEvent::ATEvent(Urc::PeerDisconnected(PeerDisconnected { handle })),
+            stack,
+        );
+    }
+
+    #[test]
+    fn sequential_connections_release_peers() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let mut peers = [false; crate::module_caps::MAX_PEERS];
+
+        for round in 0..ROUNDS {
+            let handle = add_socket(&stack);
+            let endpoint = SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::new(10, 0, 0, 1),
+                1000 + round as u16,
+            ));
+            with_tcp(&stack, handle, |tcp| tcp.connect(endpoint, None)).unwrap();
+
+            // Connect, allocating a peer handle the way the module would
+            let ev = stack.borrow_mut().tx_event(&mut buf);
+            assert!(
+                matches!(ev, Some(TxEvent::Connect { socket_handle, .. }) if socket_handle == handle)
+            );
+            let peer = peers
+                .iter()
+                .position(|used| !used)
+                .expect("peer handles leaked");
+            peers[peer] = true;
+            let peer_handle = PeerHandle(peer as u8);
+            with_tcp(&stack, handle, |tcp| {
+                tcp.peer_handle = Some(peer_handle);
+                tcp.set_state(TcpState::SynSent);
+            });
+            Stack::connect_event(ChannelId(round as u8), Protocol::TCP, endpoint, &stack);
+            assert_eq!(
+                with_tcp(&stack, handle, |tcp| tcp.state()),
+                TcpState::Established
+            );
+
+            if round % 2 == 0 {
+                // Local close: the peer is closed and stays allocated until the
+                // module confirms the disconnect.
+                with_tcp(&stack, handle, |tcp| tcp.close());
+                let ev = stack.borrow_mut().tx_event(&mut buf);
+                assert!(matches!(ev, Some(TxEvent::Close { peer_handle: p }) if p == peer_handle));
+                assert_eq!(
+                    with_tcp(&stack, handle, |tcp| tcp.state()),
+                    TcpState::FinWait2
+                );
+
+                peer_disconnected(&stack, peer_handle);
+            } else {
+                // Remote close: the socket stays readable in `CloseWait` until
+                // it is closed locally.
+                peer_disconnected(&stack, peer_handle);
+                assert_eq!(
+                    with_tcp(&stack, handle, |tcp| tcp.state()),
+                    TcpState::CloseWait
+                );
+                assert_eq!(
+                    with_tcp(&stack, handle, |tcp| tcp.peer_handle),
+                    Some(peer_handle)
+                );
+
+                with_tcp(&stack, handle, |tcp| tcp.close());
+                let ev = stack.borrow_mut().tx_event(&mut buf);
+                assert!(matches!(ev, Some(TxEvent::Close { peer_handle: p }) if p == peer_handle));
+            }
+            peers[peer] = false;
+
+            assert_eq!(
+                with_tcp(&stack, handle, |tcp| tcp.state()),
+                TcpState::TimeWait
+            );
+            assert_eq!(with_tcp(&stack, handle, |tcp| tcp.peer_handle), None);
+            assert!(stack.borrow_mut().tx_event(&mut buf).is_none());
+
+            let mut s = stack.borrow_mut();
+            s.time_wait.remove(&handle);
+            s.sockets.remove(handle);
+        }
+
+        assert!(stack.borrow().dropped_sockets.is_empty());
+    }
+
+    #[test]
+    fn flow_control_holds_back_data() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let (handle, endpoint) = start_connect(&stack, &mut buf, 1000);
+        stack.borrow_mut().connect_response(handle, PeerHandle(0));
+        Stack::connect_event(ChannelId(1), Protocol::TCP, endpoint, &stack);
+        with_tcp(&stack, handle, |tcp| tcp.send_slice(b"hello")).unwrap();
+
+        Stack::socket_rx(
+            EdmEvent::FlowControl {
+                channel_id: ChannelId(1),
+                paused: true,
+            },
+            &stack,
+        );
+        assert!(stack.borrow_mut().tx_event(&mut buf).is_none());
+
+        Stack::socket_rx(
+            EdmEvent::FlowControl {
+                channel_id: ChannelId(1),
+                paused: false,
+            },
+            &stack,
+        );
+        let ev = stack.borrow_mut().tx_event(&mut buf);
+        assert!(matches!(
+            ev,
+            Some(TxEvent::Send {
+                edm_channel: ChannelId(1),
+                data: b"hello"
+            })
+        ));
+    }
+
+    #[test]
+    fn ipv6_connect() {
+        use crate::command::edm::types::IPv6ConnectEvent;
+        use core::net::{Ipv6Addr, SocketAddrV6};
+
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let handle = add_socket(&stack);
+        let remote_ip: Ipv6Addr = "fe80::1".parse().unwrap();
+        let endpoint = SocketAddr::V6(SocketAddrV6::new(remote_ip, 8883, 0, 0));
+        with_tcp(&stack, handle, |tcp| tcp.connect(endpoint, None)).unwrap();
+
+        match stack.borrow_mut().tx_event(&mut buf) {
+            Some(TxEvent::Connect { url, .. }) => assert_eq!(url, "tcp://[fe80::1%0]:8883/"),
+            _ => panic!("expected connect"),
+        }
+        stack.borrow_mut().connect_response(handle, PeerHandle(0));
+
+        Stack::socket_rx(
+            EdmEvent::IPv6ConnectEvent(IPv6ConnectEvent {
+                channel_id: ChannelId(1),
+                protocol: Protocol::TCP,
+                remote_ip,
+                remote_port: 8883,
+                local_ip: "fe80::2".parse().unwrap(),
+                local_port: 49152,
+            }),
+            &stack,
+        );
+
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.state()),
+            TcpState::Established
+        );
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.edm_channel),
+            Some(ChannelId(1))
+        );
+    }
+
+    #[test]
+    fn attach_default_peer() {
+        use crate::command::data_mode::types::{ConnectionType, IPProtocol};
+        use atat::heapless_bytes::Bytes;
+
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let handle = add_socket(&stack);
+        let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 1000));
+        with_tcp(&stack, handle, |tcp| {
+            tcp.connect(endpoint, None)
+                .map(|()| tcp.set_state(TcpState::SynSent))
+        })
+        .unwrap();
+
+        // The module connects default remote peers on its own
+        assert!(stack.borrow_mut().tx_event(&mut buf).is_none());
+
+        Stack::socket_rx(
+            EdmEvent::ATEvent(Urc::PeerConnected(PeerConnected {
+                handle: PeerHandle(4),
+                connection_type: ConnectionType::IPv4,
+                protocol: IPProtocol::TCP,
+                local_address: Bytes::from_slice(b"10.0.0.2").unwrap(),
+                local_port: 49152,
+                remote_address: Bytes::from_slice(b"10.0.0.1").unwrap(),
+                remote_port: 1000,
+            })),
+            &stack,
+        );
+        Stack::connect_event(ChannelId(2), Protocol::TCP, endpoint, &stack);
+
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.peer_handle),
+            Some(PeerHandle(4))
+        );
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.state()),
+            TcpState::Established
+        );
+
+        peer_disconnected(&stack, PeerHandle(4));
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.state()),
+            TcpState::CloseWait
+        );
+    }
+
+    #[test]
+    fn accept_inbound_peer() {
+        use crate::command::data_mode::types::ConnectionType;
+        use crate::command::edm::types::IPv4ConnectEvent;
+        use atat::heapless_bytes::Bytes;
+
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let outbound = add_socket(&stack);
+        let listener = add_socket(&stack);
+        let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 40000));
+        with_tcp(&stack, listener, |tcp| {
+            tcp.local_port = 5003;
+            tcp.set_state(TcpState::Listen);
+        });
+        assert!(stack.borrow_mut().tx_event(&mut buf).is_none());
+
+        // The EDM connect event may arrive ahead of `+UUDPC`
+        Stack::socket_rx(
+            EdmEvent::IPv4ConnectEvent(IPv4ConnectEvent {
+                channel_id: ChannelId(3),
+                protocol: Protocol::TCP,
+                remote_ip: Ipv4Addr::new(10, 0, 0, 1),
+                remote_port: 40000,
+                local_ip: Ipv4Addr::new(10, 0, 0, 2),
+                local_port: 5003,
+            }),
+            &stack,
+        );
+        Stack::socket_rx(
+            EdmEvent::ATEvent(Urc::PeerConnected(PeerConnected {
+                handle: PeerHandle(5),
+                connection_type: ConnectionType::IPv4,
+                protocol: IPProtocol::TCP,
+                local_address: Bytes::from_slice(b"10.0.0.2").unwrap(),
+                local_port: 5003,
+                remote_address: Bytes::from_slice(b"10.0.0.1").unwrap(),
+                remote_port: 40000,
+            })),
+            &stack,
+        );
+
+        with_tcp(&stack, listener, |tcp| {
+            assert_eq!(tcp.state(), TcpState::Established);
+            assert_eq!(tcp.remote_endpoint(), Some(endpoint));
+            assert_eq!(tcp.peer_handle, Some(PeerHandle(5)));
+            assert_eq!(tcp.edm_channel, Some(ChannelId(3)));
+        });
+        assert_eq!(
+            with_tcp(&stack, outbound, |tcp| tcp.state()),
+            TcpState::Closed
+        );
+    }
+
+    #[test]
+    fn reset_invalidates_sockets() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let handle = add_socket(&stack);
+        let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 1000));
+        with_tcp(&stack, handle, |tcp| tcp.connect(endpoint, None)).unwrap();
+        stack.borrow_mut().tx_event(&mut buf);
+        with_tcp(&stack, handle, |tcp| {
+            tcp.peer_handle = Some(PeerHandle(0));
+            tcp.set_state(TcpState::SynSent);
+        });
+        Stack::connect_event(ChannelId(0), Protocol::TCP, endpoint, &stack);
+        assert_eq!(stack.borrow_mut().peer_count(), 1);
+
+        stack.borrow_mut().invalidate();
+
+        assert_eq!(stack.borrow_mut().peer_count(), 0);
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.state()),
+            TcpState::TimeWait
+        );
+        assert_eq!(with_tcp(&stack, handle, |tcp| tcp.edm_channel), None);
+        // Nothing is closed on the module, as the peers no longer exist
+        assert!(stack.borrow_mut().tx_event(&mut buf).is_none());
+    }
+
+    fn start_connect(
+        stack: &RefCell<SocketStack>,
+        buf: &mut [u8],
+        port: u16,
+    ) -> (SocketHandle, SocketAddr) {
+        let handle = add_socket(stack);
+        let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), port));
+        with_tcp(stack, handle, |tcp| tcp.connect(endpoint, None)).unwrap();
+        let ev = stack.borrow_mut().tx_event(buf);
+        assert!(matches!(ev, Some(TxEvent::Connect { .. })));
+        (handle, endpoint)
+    }
+
+    #[test]
+    fn clear_tls_credentials() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let handle = add_socket(&stack);
+        let mut socket = TcpSocket::from_handle(&stack, handle);
+
+        socket
+            .set_tls_credentials(SecurityCredentials {
+                ca_cert_name: heapless::String::try_from("ca").unwrap(),
+                c_cert_name: heapless::String::try_from("dev").unwrap(),
+                c_key_name: heapless::String::try_from("dev").unwrap(),
+            })
+            .unwrap();
+        socket.clear_tls_credentials();
+
+        let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 1000));
+        with_tcp(&stack, handle, |tcp| tcp.connect(endpoint, None)).unwrap();
+        match stack.borrow_mut().tx_event(&mut buf) {
+            Some(TxEvent::Connect { url, .. }) => assert_eq!(url, "tcp://10.0.0.1:1000/"),
+            _ => panic!("expected connect"),
+        }
+    }
+
+    #[test]
+    fn concurrent_tls_sockets() {
+        let stack = socket_stack_with::<4>();
+        let mut buf = [0u8; 256];
+
+        for i in 0..4u16 {
+            let handle = add_socket(&stack);
+            let mut name = heapless::String::<16>::new();
+            write!(name, "dev{}", i).unwrap();
+            stack
+                .borrow_mut()
+                .credential_map
+                .insert(
+                    handle,
+                    SecurityCredentials {
+                        ca_cert_name: heapless::String::try_from("ca").unwrap(),
+                        c_cert_name: name.clone(),
+                        c_key_name: name,
+                    },
+                )
+                .unwrap();
+
+            let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8883 + i));
+            with_tcp(&stack, handle, |tcp| tcp.connect(endpoint, None)).unwrap();
+
+            let mut expected = heapless::String::<128>::new();
+            write!(
+                expected,
+                "tcp://10.0.0.1:{}/?ca=ca&cert=dev{}&privKey=dev{}",
+                8883 + i,
+                i,
+                i
+            )
+            .unwrap();
+            match stack.borrow_mut().tx_event(&mut buf) {
+                Some(TxEvent::Connect {
+                    socket_handle, url, ..
+                }) => {
+                    assert_eq!(socket_handle, handle);
+                    assert_eq!(url, expected.as_str());
+                }
+                _ => panic!("expected connect of socket {}", i),
+            }
+            stack
+                .borrow_mut()
+                .connect_response(handle, PeerHandle(i as u8));
+        }
+        assert_eq!(stack.borrow_mut().credential_map.len(), 4);
+    }
+
+    #[test]
+    fn cancelled_connect_closes_peer() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let (handle, endpoint) = start_connect(&stack, &mut buf, 1000);
+        stack.borrow_mut().connect_response(handle, PeerHandle(3));
+
+        // Connect future dropped before the connect event
+        stack.borrow_mut().abort_connect(handle);
+
+        let ev = stack.borrow_mut().tx_event(&mut buf);
+        assert!(matches!(
+            ev,
+            Some(TxEvent::Close {
+                peer_handle: PeerHandle(3)
+            })
+        ));
+
+        // The late connect event does not revive the socket
+        Stack::connect_event(ChannelId(0), Protocol::TCP, endpoint, &stack);
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.state()),
+            TcpState::Closed
+        );
+        assert_eq!(with_tcp(&stack, handle, |tcp| tcp.edm_channel), None);
+    }
+
+    #[test]
+    fn cancelled_connect_in_flight_closes_peer() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let (handle, _) = start_connect(&stack, &mut buf, 1000);
+
+        // Connect future dropped while `ConnectPeer` is awaiting its response
+        stack.borrow_mut().abort_connect(handle);
+        stack.borrow_mut().connect_response(handle, PeerHandle(4));
+
+        assert_eq!(with_tcp(&stack, handle, |tcp| tcp.peer_handle), None);
+        let ev = stack.borrow_mut().tx_event(&mut buf);
+        assert!(matches!(
+            ev,
+            Some(TxEvent::Close {
+                peer_handle: PeerHandle(4)
+            })
+        ));
+        assert!(stack.borrow_mut().tx_event(&mut buf).is_none());
+    }
+
+    #[test]
+    fn reconnect_after_cancelled_connect_in_flight() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let (handle, endpoint) = start_connect(&stack, &mut buf, 1000);
+
+        // Cancelled and connected again while `ConnectPeer` is in flight
+        stack.borrow_mut().abort_connect(handle);
+        with_tcp(&stack, handle, |tcp| tcp.connect(endpoint, None)).unwrap();
+
+        // The peer of the cancelled connect is closed
+        stack.borrow_mut().connect_response(handle, PeerHandle(4));
+        assert_eq!(with_tcp(&stack, handle, |tcp| tcp.peer_handle), None);
+        let ev = stack.borrow_mut().tx_event(&mut buf);
+        assert!(matches!(
+            ev,
+            Some(TxEvent::Close {
+                peer_handle: PeerHandle(4)
+            })
+        ));
+
+        // The new connect gets its own peer
+        let ev = stack.borrow_mut().tx_event(&mut buf);
+        assert!(
+            matches!(ev, Some(TxEvent::Connect { socket_handle, .. }) if socket_handle == handle)
+        );
+        stack.borrow_mut().connect_response(handle, PeerHandle(5));
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.peer_handle),
+            Some(PeerHandle(5))
+        );
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.state()),
+            TcpState::SynSent
+        );
+    }
+
+    #[test]
+    fn cancelled_connect_before_connect_peer() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let handle = add_socket(&stack);
+        let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 1000));
+
+        // Cancelled before `ConnectPeer` was sent, so there is nothing to close
+        with_tcp(&stack, handle, |tcp| tcp.connect(endpoint, None)).unwrap();
+        stack.borrow_mut().abort_connect(handle);
+        assert!(stack.borrow().aborted_connects.is_empty());
+        assert!(stack.borrow_mut().tx_event(&mut buf).is_none());
+
+        with_tcp(&stack, handle, |tcp| tcp.connect(endpoint, None)).unwrap();
+        let ev = stack.borrow_mut().tx_event(&mut buf);
+        assert!(matches!(ev, Some(TxEvent::Connect { .. })));
+        stack.borrow_mut().connect_response(handle, PeerHandle(2));
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.peer_handle),
+            Some(PeerHandle(2))
+        );
+    }
+
+    #[test]
+    fn close_queue_overflow_keeps_closes() {
+        let stack = socket_stack_with::<8>();
+        let mut buf = [0u8; 256];
+
+        let sockets: std::vec::Vec<_> = (0..8u8)
+            .map(|i| {
+                let (handle, _) = start_connect(&stack, &mut buf, 1000 + i as u16);
+                stack.borrow_mut().connect_response(handle, PeerHandle(i));
+                TcpSocket::from_handle(&stack, handle)
+            })
+            .collect();
+
+        // Drop all sockets before the close queue is drained
+        drop(sockets);
+        assert_eq!(
+            stack.borrow().stats.close_queue_overflows as usize,
+            8 - crate::module_caps::MAX_PEERS
+        );
+
+        let mut closed = std::vec::Vec::new();
+        while let Some(ev) = stack.borrow_mut().tx_event(&mut buf) {
+            match ev {
+                TxEvent::Close { peer_handle } => closed.push(peer_handle.0),
+                _ => panic!("expected close"),
+            }
+        }
+        closed.sort();
+        assert_eq!(closed, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(stack.borrow_mut().sockets.iter_mut().count(), 0);
+    }
+
+    #[test]
+    fn cancelled_connect_with_full_close_queue() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+
+        for i in 0..crate::module_caps::MAX_PEERS {
+            assert!(stack.borrow_mut().queue_close(PeerHandle(i as u8)));
+        }
+        let (handle, _) = start_connect(&stack, &mut buf, 1000);
+        stack.borrow_mut().connect_response(handle, PeerHandle(9));
+
+        // The socket keeps the peer, and closes it once the queue is drained
+        stack.borrow_mut().abort_connect(handle);
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.peer_handle),
+            Some(PeerHandle(9))
+        );
+
+        let mut closed = std::vec::Vec::new();
+        while let Some(ev) = stack.borrow_mut().tx_event(&mut buf) {
+            match ev {
+                TxEvent::Close { peer_handle } => closed.push(peer_handle.0),
+                _ => panic!("expected close"),
+            }
+        }
+        assert_eq!(closed.len(), crate::module_caps::MAX_PEERS + 1);
+        assert_eq!(closed.last(), Some(&9));
+    }
+
+    #[test]
+    fn abort_with_full_close_queue() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+
+        for i in 0..crate::module_caps::MAX_PEERS {
+            assert!(stack.borrow_mut().queue_close(PeerHandle(i as u8)));
+        }
+        let (handle, _) = start_connect(&stack, &mut buf, 1000);
+        stack.borrow_mut().connect_response(handle, PeerHandle(9));
+        with_tcp(&stack, handle, |tcp| tcp.set_state(TcpState::Established));
+
+        assert_eq!(stack.borrow_mut().abort(handle), Err(CloseQueueFull));
+        assert_eq!(
+            with_tcp(&stack, handle, |tcp| tcp.state()),
+            TcpState::FinWait1
+        );
+
+        // Aborting a socket no longer in the stack is a no-op
+        stack.borrow_mut().sockets.remove(handle);
+        assert_eq!(stack.borrow_mut().abort(handle), Ok(()));
+    }
+
+    #[test]
+    fn connect_from_local_port() {
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let handle = add_socket(&stack);
+        let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 1000));
+        with_tcp(&stack, handle, |tcp| tcp.connect(endpoint, Some(2001))).unwrap();
+
+        match stack.borrow_mut().tx_event(&mut buf) {
+            Some(TxEvent::Connect { url, .. }) => {
+                assert_eq!(url, "tcp://10.0.0.1:1000/?local_port=2001")
+            }
+            _ => panic!("expected connect"),
+        }
+    }
+
+    #[test]
+    fn split_stack_resources() {
+        let (a, b) = StackResources::<4>::new().split_at::<1, 3>();
+        assert_eq!(a.sockets.len(), 1);
+        assert_eq!(b.sockets.len(), 3);
+
+        const MAX_HEAP: usize = StackResources::<2>::memory_footprint(0, 0);
+        let (a, b): (StackResources<1, MAX_HEAP>, StackResources<1, MAX_HEAP>) =
+            StackResources::<2, MAX_HEAP>::new().split_at::<1, 1>();
+        assert_eq!(a.sockets.len() + b.sockets.len(), 2);
+
+        assert_eq!(
+            StackResources::<2>::memory_footprint(100, 200),
+            2 * (300 + core::mem::size_of::<SocketStorage>())
+                + core::mem::size_of::<SettingsStorage<2>>()
+        );
+    }
+
+    #[cfg(feature = "socket-udp")]
+    #[test]
+    fn udp_send_to_connects_peer() {
+        use ublox_sockets::udp;
+
+        let stack = socket_stack();
+        let mut buf = [0u8; 256];
+        let rx: &'static mut [u8] = Box::leak(vec![0u8; 64].into_boxed_slice());
+        let tx: &'static mut [u8] = Box::leak(vec![0u8; 64].into_boxed_slice());
+        let handle = stack.borrow_mut().sockets.add(udp::Socket::new(
+            udp::SocketBuffer::new(rx),
+            udp::SocketBuffer::new(tx),
+        ));
+        let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 123));
+
+        // What `UdpSocket::send_to` leaves behind
+        {
+            let mut s = stack.borrow_mut();
+            s.send_to_endpoints.insert(handle, endpoint).unwrap();
+            s.sockets
+                .get_mut::<udp::Socket>(handle)
+                .send_slice(b"ntp")
+                .unwrap();
+        }
+
+        match stack.borrow_mut().tx_event(&mut buf) {
+            Some(TxEvent::Connect {
+                socket_handle, url, ..
+            }) => {
+                assert_eq!(socket_handle, handle);
+                assert_eq!(url, "udp://10.0.0.1:123/");
+            }
+            _ => panic!("expected connect"),
+        }
+        stack.borrow_mut().connect_response(handle, PeerHandle(2));
+        assert!(stack.borrow_mut().tx_event(&mut buf).is_none());
+
+        Stack::connect_event(ChannelId(1), Protocol::UDP, endpoint, &stack);
+        match stack.borrow_mut().tx_event(&mut buf) {
+            Some(TxEvent::Send { edm_channel, data }) => {
+                assert_eq!(edm_channel, ChannelId(1));
+                assert_eq!(data, b"ntp");
+            }
+            _ => panic!("expected send"),
+        }
+    }
+
+    #[cfg(feature = "socket-udp")]
+    #[test]
+    fn udp_rx_drops_whole_datagrams() {
+        use ublox_sockets::udp;
+
+        let stack = socket_stack();
+        let rx: &'static mut [u8] = Box::leak(vec![0u8; 16].into_boxed_slice());
+        let tx: &'static mut [u8] = Box::leak(vec![0u8; 16].into_boxed_slice());
+        let handle = stack.borrow_mut().sockets.add(udp::Socket::new(
+            udp::SocketBuffer::new(rx),
+            udp::SocketBuffer::new(tx),
+        ));
+        let endpoint = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 123));
+
+        let s = &mut *stack.borrow_mut();
+        let socket = s.sockets.get_mut::<udp::Socket>(handle);
+        socket.endpoint = Some(endpoint);
+        socket.edm_channel = Some(ChannelId(1));
+        socket.set_state(UdpState::Established);
+
+        // Larger than the receive buffer
+        assert!(s.rx_data(ChannelId(1), &[0xaa; 17]));
+        // One more than the datagram queue holds
+        for i in 0..=MAX_RX_DATAGRAMS as u8 {
+            assert!(s.rx_data(ChannelId(1), &[i]));
+        }
+
+        assert_eq!(s.stats.rx_dropped_datagrams, 2);
+        let queue = s.rx_datagrams.get(&handle).unwrap();
+        assert_eq!(queue.len(), MAX_RX_DATAGRAMS);
+        assert!(queue.iter().all(|d| d.len == 1 && d.source == endpoint));
+        assert_eq!(
+            s.sockets.get_mut::<udp::Socket>(handle).recv_queue(),
+            MAX_RX_DATAGRAMS
+        );
+    }
+
+    #[test]
+    fn tx_priority_and_round_robin() {
+        let stack = socket_stack_with::<3>();
+        let mut buf = [0u8; 256];
+
+        let handles: std::vec::Vec<_> = (0..3u8)
+            .map(|i| {
+                let (handle, endpoint) = start_connect(&stack, &mut buf, 1000 + i as u16);
+                stack.borrow_mut().connect_response(handle, PeerHandle(i));
+                Stack::connect_event(ChannelId(i), Protocol::TCP, endpoint, &stack);
+                handle
+            })
+            .collect();
+        let (bulk, control, other) = (handles[0], handles[1], handles[2]);
+
+        for (handle, data) in [(bulk, b"bulk"), (control, b"ctrl"), (other, b"rest")] {
+            with_tcp(&stack, handle, |tcp| tcp.send_slice(data)).unwrap();
+        }
+        stack.borrow_mut().set_tx_priority(control, 200).unwrap();
+
+        let mut next_send = || match stack.borrow_mut().tx_event(&mut buf) {
+            Some(TxEvent::Send { edm_channel, .. }) => edm_channel,
+            _ => panic!("expected send"),
+        };
+
+        // The prioritized socket goes first, then the others take turns
+        assert_eq!(next_send(), ChannelId(1));
+        assert_eq!(next_send(), ChannelId(0));
+        with_tcp(&stack, bulk, |tcp| tcp.send_slice(b"more")).unwrap();
+        assert_eq!(next_send(), ChannelId(2));
+        assert_eq!(next_send(), ChannelId(0));
+        assert!(stack.borrow_mut().tx_event(&mut buf).is_none());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::Error;
+use crate::module_caps::MAX_DOMAIN_NAME_LENGTH;
 use core::fmt::Write;
 use core::net::{IpAddr, SocketAddr};
 use heapless::String;
@@ -11,13 +12,57 @@ pub struct SecurityCredentials {
     pub c_key_name: heapless::String<16>,
 }
 
+/// URL of a remote peer, e.g. a default remote peer configured with
+/// [`Control::set_default_remote_peer`](crate::asynch::control::Control::set_default_remote_peer).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PeerUrl(String<128>);
+
+impl PeerUrl {
+    /// TCP peer at `addr`, secured with TLS if `creds` are given.
+    pub fn tcp(addr: SocketAddr, creds: Option<&SecurityCredentials>) -> Result<Self, Error> {
+        let mut builder = PeerUrlBuilder::new();
+        builder.address(&addr);
+        if let Some(creds) = creds {
+            builder.creds(creds);
+        }
+        builder.tcp().map(Self)
+    }
+
+    /// TCP peer at `hostname`:`port`, secured with TLS if `creds` are given.
+    pub fn tcp_hostname(
+        hostname: &str,
+        port: u16,
+        creds: Option<&SecurityCredentials>,
+    ) -> Result<Self, Error> {
+        let mut builder = PeerUrlBuilder::new();
+        builder.hostname(hostname).port(port);
+        if let Some(creds) = creds {
+            builder.creds(creds);
+        }
+        builder.tcp().map(Self)
+    }
+
+    /// UDP peer at `addr`.
+    pub fn udp(addr: SocketAddr) -> Result<Self, Error> {
+        PeerUrlBuilder::new().address(&addr).udp().map(Self)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct PeerUrlBuilder<'a> {
     hostname: Option<&'a str>,
     ip_addr: Option<IpAddr>,
+    /// Zone index of an IPv6 link-local address.
+    scope_id: u32,
     port: Option<u16>,
     creds: Option<&'a SecurityCredentials>,
     local_port: Option<u16>,
+    nodelay: bool,
 }
 
 #[allow(dead_code)]
@@ -28,9 +73,19 @@ impl<'a> PeerUrlBuilder<'a> {
 
     fn write_domain<const N: usize>(&self, s: &mut String<N>) -> Result<(), Error> {
         let port = self.port.ok_or(Error::Network)?;
-        let addr = self
-            .ip_addr
-            .and_then(|ip| write!(s, "{}/", SocketAddr::new(ip, port)).ok());
+        let addr = self.ip_addr.and_then(|ip| match ip {
+            // The module needs the zone of link-local addresses, even if 0
+            IpAddr::V6(ip) if ip.is_unicast_link_local() => {
+                write!(s, "[{}%{}]:{}/", ip, self.scope_id, port).ok()
+            }
+            ip => write!(s, "{}/", SocketAddr::new(ip, port)).ok(),
+        });
+        if self
+            .hostname
+            .is_some_and(|host| host.len() > MAX_DOMAIN_NAME_LENGTH)
+        {
+            return Err(Error::DomainNameTooLong);
+        }
         let host = self
             .hostname
             .and_then(|host| write!(s, "{}:{}/", host, port).ok());
@@ -74,6 +129,10 @@ impl<'a> PeerUrlBuilder<'a> {
             write!(&mut s, "privKey={}&", creds.c_key_name).map_err(|_| Error::Overflow)?;
         };
 
+        if self.nodelay {
+            write!(&mut s, "flush_tx=1&").map_err(|_| Error::Overflow)?;
+        }
+
         // Remove trailing '&' or '?' if no query.
         s.pop();
 
@@ -81,6 +140,9 @@ impl<'a> PeerUrlBuilder<'a> {
     }
 
     pub fn address(&mut self, addr: &SocketAddr) -> &mut Self {
+        if let SocketAddr::V6(addr) = addr {
+            self.scope_id = addr.scope_id();
+        }
         self.ip_addr(addr.ip()).port(addr.port())
     }
 
@@ -130,6 +192,12 @@ impl<'a> PeerUrlBuilder<'a> {
         self.local_port = local_port;
         self
     }
+
+    /// Flush every write immediately, i.e. disable Nagle's algorithm (TCP only)
+    pub fn nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.nodelay = nodelay;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -155,7 +223,30 @@ mod test {
             .address(&address)
             .udp::<128>()
             .unwrap();
-        assert_eq!(url, "udp://[fe80::202:b3ff:fe1e:8329]:8080/");
+        assert_eq!(url, "udp://[fe80::202:b3ff:fe1e:8329%0]:8080/");
+    }
+
+    #[test]
+    fn tcp_ipv6_url() {
+        let address = "[2001:db8::1]:8883".parse().unwrap();
+        let url = PeerUrlBuilder::new()
+            .address(&address)
+            .local_port(2001)
+            .tcp::<128>()
+            .unwrap();
+        assert_eq!(url, "tcp://[2001:db8::1]:8883/?local_port=2001");
+
+        let address = SocketAddr::V6(core::net::SocketAddrV6::new(
+            "fe80::1".parse().unwrap(),
+            8883,
+            0,
+            0,
+        ));
+        let url = PeerUrlBuilder::new()
+            .address(&address)
+            .tcp::<128>()
+            .unwrap();
+        assert_eq!(url, "tcp://[fe80::1%0]:8883/");
     }
 
     #[test]
@@ -169,6 +260,30 @@ mod test {
         assert_eq!(url, "udp://example.org:2000/?local_port=2001");
     }
 
+    #[test]
+    fn tls_peer_url() {
+        let creds = SecurityCredentials {
+            ca_cert_name: String::try_from("ca").unwrap(),
+            c_cert_name: String::try_from("cert").unwrap(),
+            c_key_name: String::try_from("key").unwrap(),
+        };
+        let url = PeerUrl::tcp_hostname("example.org", 8883, Some(&creds)).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "tcp://example.org:8883/?ca=ca&cert=cert&privKey=key"
+        );
+    }
+
+    #[test]
+    fn domain_name_too_long() {
+        let hostname = "a".repeat(MAX_DOMAIN_NAME_LENGTH + 1);
+        let res = PeerUrlBuilder::new()
+            .hostname(&hostname)
+            .port(2000)
+            .tcp::<256>();
+        assert!(matches!(res, Err(Error::DomainNameTooLong)));
+    }
+
     #[test]
     fn tcp_certs() {
         let url = PeerUrlBuilder::new()
@@ -187,4 +302,16 @@ mod test {
             "tcp://example.org:2000/?ca=ca.crt&cert=client.crt&privKey=client.key"
         );
     }
+
+    #[test]
+    fn tcp_nodelay() {
+        let address = "192.168.0.1:8080".parse().unwrap();
+        let url = PeerUrlBuilder::new()
+            .address(&address)
+            .local_port(2001)
+            .nodelay(true)
+            .tcp::<128>()
+            .unwrap();
+        assert_eq!(url, "tcp://192.168.0.1:8080/?local_port=2001&flush_tx=1");
+    }
 }
@@ -0,0 +1,147 @@
+//! Per-socket settings of a [`UbloxStack`](super::UbloxStack).
+//!
+//! Settings are kept outside of the sockets of `ublox-sockets`, keyed by
+//! socket handle. Their storage is part of the
+//! [`StackResources`](super::StackResources), with room for one entry per
+//! socket slot, so every socket of the stack fits each map.
+
+use core::net::SocketAddr;
+
+use embassy_time::{Duration, Instant};
+use ublox_sockets::SocketHandle;
+
+use super::SecurityCredentials;
+
+/// Setting of the sockets of a stack, keyed by socket handle.
+pub(crate) struct SocketMap<V: 'static> {
+    entries: &'static mut [Option<(SocketHandle, V)>],
+}
+
+impl<V> SocketMap<V> {
+    pub(crate) fn new(entries: &'static mut [Option<(SocketHandle, V)>]) -> Self {
+        Self { entries }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.is_some()).count()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn get(&self, handle: &SocketHandle) -> Option<&V> {
+        self.iter().find(|(h, _)| *h == handle).map(|(_, v)| v)
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: &SocketHandle) -> Option<&mut V> {
+        self.entries
+            .iter_mut()
+            .flatten()
+            .find(|(h, _)| h == handle)
+            .map(|(_, v)| v)
+    }
+
+    pub(crate) fn contains_key(&self, handle: &SocketHandle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    /// Set `value` for `handle`, returning the value it replaces.
+    ///
+    /// `(handle, value)` is handed back if all entries are taken, which only
+    /// happens if entries of sockets no longer in the stack are left behind.
+    pub(crate) fn insert(
+        &mut self,
+        handle: SocketHandle,
+        value: V,
+    ) -> Result<Option<V>, (SocketHandle, V)> {
+        if let Some(existing) = self.get_mut(&handle) {
+            return Ok(Some(core::mem::replace(existing, value)));
+        }
+        match self.entries.iter_mut().find(|entry| entry.is_none()) {
+            Some(entry) => {
+                *entry = Some((handle, value));
+                Ok(None)
+            }
+            None => Err((handle, value)),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, handle: &SocketHandle) -> Option<V> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.as_ref().is_some_and(|(h, _)| h == handle))?;
+        entry.take().map(|(_, v)| v)
+    }
+
+    pub(crate) fn retain(&mut self, mut f: impl FnMut(&SocketHandle, &mut V) -> bool) {
+        for entry in self.entries.iter_mut() {
+            let keep = match entry {
+                Some((handle, value)) => f(handle, value),
+                None => true,
+            };
+            if !keep {
+                *entry = None;
+            }
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&SocketHandle, &V)> {
+        self.entries.iter().flatten().map(|(h, v)| (h, v))
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &SocketHandle> {
+        self.iter().map(|(h, _)| h)
+    }
+}
+
+/// Entries of a [`SocketMap`], one per socket slot.
+pub(crate) type Entries<V, const SOCK: usize> = [Option<(SocketHandle, V)>; SOCK];
+
+/// Storage of the per-socket settings of a stack with `SOCK` socket slots.
+pub(crate) struct SettingsStorage<const SOCK: usize> {
+    pub(crate) time_wait: Entries<Instant, SOCK>,
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) time_wait_dropped: Entries<(), SOCK>,
+    pub(crate) orphaned: Entries<(), SOCK>,
+    pub(crate) credential_map: Entries<SecurityCredentials, SOCK>,
+    pub(crate) nodelay: Entries<(), SOCK>,
+    pub(crate) tx_priority: Entries<u8, SOCK>,
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) io_timeouts: Entries<Duration, SOCK>,
+    pub(crate) local_ports: Entries<u16, SOCK>,
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) connect_errors: Entries<super::tcp::ConnectError, SOCK>,
+    #[cfg(feature = "socket-udp")]
+    pub(crate) send_to_endpoints: Entries<SocketAddr, SOCK>,
+    #[cfg(feature = "socket-udp")]
+    pub(crate) rx_datagrams: Entries<super::udp::RxDatagrams, SOCK>,
+    #[cfg(feature = "socket-udp")]
+    pub(crate) send_errors: Entries<super::udp::SendError, SOCK>,
+}
+
+impl<const SOCK: usize> SettingsStorage<SOCK> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            time_wait: [const { None }; SOCK],
+            #[cfg(feature = "socket-tcp")]
+            time_wait_dropped: [const { None }; SOCK],
+            orphaned: [const { None }; SOCK],
+            credential_map: [const { None }; SOCK],
+            nodelay: [const { None }; SOCK],
+            tx_priority: [const { None }; SOCK],
+            #[cfg(feature = "socket-tcp")]
+            io_timeouts: [const { None }; SOCK],
+            local_ports: [const { None }; SOCK],
+            #[cfg(feature = "socket-tcp")]
+            connect_errors: [const { None }; SOCK],
+            #[cfg(feature = "socket-udp")]
+            send_to_endpoints: [const { None }; SOCK],
+            #[cfg(feature = "socket-udp")]
+            rx_datagrams: [const { None }; SOCK],
+            #[cfg(feature = "socket-udp")]
+            send_errors: [const { None }; SOCK],
+        }
+    }
+}
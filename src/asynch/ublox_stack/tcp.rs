@@ -7,7 +7,8 @@ use core::net::SocketAddr;
 use embassy_time::Duration;
 use ublox_sockets::{tcp, SocketHandle, TcpState};
 
-use super::{SocketStack, UbloxStack};
+use super::{CloseQueueFull, SecurityCredentials, SettingsFull, SocketStack, UbloxStack};
+use crate::asynch::OnDrop;
 
 /// Error returned by TcpSocket read/write functions.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -17,12 +18,15 @@ pub enum Error {
     ///
     /// This can happen on receiving a RST packet, or on timeout.
     ConnectionReset,
+    /// The operation did not complete within its timeout.
+    TimedOut,
 }
 
 impl core::fmt::Display for Error {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Error::ConnectionReset => write!(f, "Connection reset"),
+            Error::TimedOut => write!(f, "Timed out"),
         }
     }
 }
@@ -41,6 +45,8 @@ pub enum ConnectError {
     TimedOut,
     /// No route to host.
     NoRoute,
+    /// The module has no room for another peer connection.
+    TooManyPeers,
 }
 
 impl core::fmt::Display for ConnectError {
@@ -50,6 +56,7 @@ impl core::fmt::Display for ConnectError {
             ConnectError::ConnectionReset => write!(f, "Connection reset"),
             ConnectError::TimedOut => write!(f, "Timed out"),
             ConnectError::NoRoute => write!(f, "No route"),
+            ConnectError::TooManyPeers => write!(f, "Too many peers"),
         }
     }
 }
@@ -68,9 +75,39 @@ pub enum AcceptError {
     ConnectionReset,
 }
 
+/// Default timeout of [`TcpSocket::connect_with_timeout`], covering both the
+/// `ConnectPeer` command and the module reporting the connection.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run `fut` to completion, or fail with [`Error::TimedOut`] once `timeout`
+/// has passed.
+///
+/// `fut` is dropped on timeout, cancelling the operation.
+pub async fn with_tcp_timeout<T, Fut: Future<Output = T>>(
+    fut: Fut,
+    timeout: Duration,
+) -> Result<T, Error> {
+    embassy_time::with_timeout(timeout, fut)
+        .await
+        .map_err(|_| Error::TimedOut)
+}
+
+/// Run `fut` like [`with_tcp_timeout`] if `timeout` is set, or else to
+/// completion.
+async fn with_optional_timeout<T>(
+    fut: impl Future<Output = Result<T, Error>>,
+    timeout: Option<Duration>,
+) -> Result<T, Error> {
+    match timeout {
+        Some(timeout) => with_tcp_timeout(fut, timeout).await?,
+        None => fut.await,
+    }
+}
+
 /// A TCP socket.
 pub struct TcpSocket<'a> {
     pub(crate) io: TcpIo<'a>,
+    connect_timeout: Duration,
 }
 
 /// The reader half of a TCP socket.
@@ -157,24 +194,34 @@ impl<'a> TcpWriter<'a> {
 
 impl<'a> TcpSocket<'a> {
     /// Create a new TCP socket on the given stack, with the given buffers.
-    pub fn new<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>(
-        stack: &'a UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+    pub fn new<
+        const INGRESS_BUF_SIZE: usize,
+        const URC_CAPACITY: usize,
+        const EGRESS_BUF_SIZE: usize,
+        const CMD_BUF_SIZE: usize,
+    >(
+        stack: &'a UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
         rx_buffer: &'a mut [u8],
         tx_buffer: &'a mut [u8],
     ) -> Self {
         let s = &mut *stack.socket.borrow_mut();
         let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
         let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
+        s.make_room();
         let handle = s.sockets.add(tcp::Socket::new(
             tcp::SocketBuffer::new(rx_buffer),
             tcp::SocketBuffer::new(tx_buffer),
         ));
+        s.forget(handle);
+        // The stack may have to start recycling sockets in `TimeWait`
+        s.waker.wake();
 
         Self {
             io: TcpIo {
                 stack: &stack.socket,
                 handle,
             },
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
         }
     }
 
@@ -221,22 +268,104 @@ impl<'a> TcpSocket<'a> {
         (TcpReader { io: self.io }, TcpWriter { io: self.io })
     }
 
-    /// Connect to a remote host.
+    /// Connect to a remote host, from the local port set with
+    /// [`set_local_port()`](TcpSocket::set_local_port), or else one assigned
+    /// by the module.
     pub async fn connect<T>(&mut self, remote_endpoint: T) -> Result<(), ConnectError>
     where
         T: Into<SocketAddr>,
     {
-        match { self.io.with_mut(|s| s.connect(remote_endpoint, None)) } {
+        let local_port = self
+            .io
+            .stack
+            .borrow()
+            .local_ports
+            .get(&self.io.handle)
+            .copied();
+        self.connect_inner(remote_endpoint.into(), local_port).await
+    }
+
+    /// Connect to a remote host from `local_port`.
+    ///
+    /// This is useful where firewalls only let traffic from known source ports
+    /// through.
+    pub async fn connect_from<T>(
+        &mut self,
+        remote_endpoint: T,
+        local_port: u16,
+    ) -> Result<(), ConnectError>
+    where
+        T: Into<SocketAddr>,
+    {
+        self.connect_inner(remote_endpoint.into(), Some(local_port))
+            .await
+    }
+
+    async fn connect_inner(
+        &mut self,
+        remote_endpoint: SocketAddr,
+        local_port: Option<u16>,
+    ) -> Result<(), ConnectError> {
+        if self.io.stack.borrow_mut().peer_count() >= crate::module_caps::MAX_PEERS {
+            return Err(ConnectError::TooManyPeers);
+        }
+
+        match { self.io.with_mut(|s| s.connect(remote_endpoint, local_port)) } {
             Ok(()) => {}
             Err(_) => return Err(ConnectError::InvalidState),
             // Err(tcp::ConnectError::Unaddressable) => return Err(ConnectError::NoRoute),
         }
 
+        // If this future is dropped before the connection is established, the
+        // stack must not leave a peer behind on the module.
+        let io = self.io;
+        let on_cancel = OnDrop::new(move || io.stack.borrow_mut().abort_connect(io.handle));
+
+        let res = self.wait_established().await;
+
+        on_cancel.defuse();
+        res
+    }
+
+    /// Attach to a default remote peer at `remote_endpoint`, configured with
+    /// [`Control::set_default_remote_peer`](crate::asynch::control::Control::set_default_remote_peer),
+    /// waiting for the module to connect it.
+    ///
+    /// No connection is requested, as the module connects default remote
+    /// peers on its own. `remote_endpoint` must be the address the module
+    /// reports for the peer, i.e. the resolved address for a hostname URL.
+    pub async fn attach_default_peer<T>(&mut self, remote_endpoint: T) -> Result<(), ConnectError>
+    where
+        T: Into<SocketAddr>,
+    {
+        match self.io.with_mut(|s| {
+            s.connect(remote_endpoint, None)
+                .map(|()| s.set_state(tcp::State::SynSent))
+        }) {
+            Ok(()) => {}
+            Err(_) => return Err(ConnectError::InvalidState),
+        }
+
+        self.wait_established().await
+    }
+
+    async fn wait_established(&mut self) -> Result<(), ConnectError> {
         poll_fn(|cx| {
+            if let Some(e) = self
+                .io
+                .stack
+                .borrow_mut()
+                .take_connect_error(self.io.handle)
+            {
+                return Poll::Ready(Err(e));
+            }
+
             self.io.with_mut(|s| match s.state() {
                 tcp::State::TimeWait => Poll::Ready(Err(ConnectError::ConnectionReset)),
-                tcp::State::Listen => unreachable!(),
-                tcp::State::Closed | tcp::State::SynSent | tcp::State::SynReceived => {
+                tcp::State::Closed
+                | tcp::State::Listen
+                | tcp::State::SynSent
+                | tcp::State::SynReceived => {
                     s.register_send_waker(cx.waker());
                     Poll::Pending
                 }
@@ -246,31 +375,30 @@ impl<'a> TcpSocket<'a> {
         .await
     }
 
-    // /// Accept a connection from a remote host.
-    // ///
-    // /// This function puts the socket in listening mode, and waits until a connection is received.
-    // pub async fn accept<T>(&mut self, local_endpoint: T) -> Result<(), AcceptError>
-    // where
-    //     T: Into<IpListenEndpoint>,
-    // {
-    //     todo!()
-    //     // match self.io.with_mut(|s, _| s.listen(local_endpoint)) {
-    //     //     Ok(()) => {}
-    //     //     Err(tcp::ListenError::InvalidState) => return Err(AcceptError::InvalidState),
-    //     //     Err(tcp::ListenError::Unaddressable) => return Err(AcceptError::InvalidPort),
-    //     // }
-
-    //     // poll_fn(|cx| {
-    //     //     self.io.with_mut(|s, _| match s.state() {
-    //     //         tcp::State::Listen | tcp::State::SynSent | tcp::State::SynReceived => {
-    //     //             s.register_send_waker(cx.waker());
-    //     //             Poll::Pending
-    //     //         }
-    //     //         _ => Poll::Ready(Ok(())),
-    //     //     })
-    //     // })
-    //     // .await
-    // }
+    /// Accept a connection from a remote host.
+    ///
+    /// This function puts the socket in listening mode on `local_port`, and
+    /// waits until a peer connects to it. The TCP server listening on the port
+    /// is started with
+    /// [`Control::open_server`](crate::asynch::control::Control::open_server).
+    pub async fn accept(&mut self, local_port: u16) -> Result<(), AcceptError> {
+        if local_port == 0 {
+            return Err(AcceptError::InvalidPort);
+        }
+
+        self.io.with_mut(|s| {
+            if s.state() != tcp::State::Closed {
+                return Err(AcceptError::InvalidState);
+            }
+            s.local_port = local_port;
+            s.set_state(tcp::State::Listen);
+            Ok(())
+        })?;
+
+        self.wait_established()
+            .await
+            .map_err(|_| AcceptError::ConnectionReset)
+    }
 
     /// Read data from the socket.
     ///
@@ -302,13 +430,50 @@ impl<'a> TcpSocket<'a> {
         self.io.flush()
     }
 
-    /// Set the timeout for the socket.
+    /// Connect to a remote host like [`connect()`](TcpSocket::connect), giving
+    /// up after the connect timeout.
     ///
-    /// If the timeout is set, the socket will be closed if no data is received for the
-    /// specified duration.
-    pub fn set_timeout(&mut self, _duration: Option<Duration>) {
-        todo!()
-        // self.io.with_mut(|s| s.set_timeout(duration))
+    /// A connect that times out is cancelled, and the socket can be connected
+    /// again.
+    pub async fn connect_with_timeout<T>(&mut self, remote_endpoint: T) -> Result<(), ConnectError>
+    where
+        T: Into<SocketAddr>,
+    {
+        let timeout = self.connect_timeout;
+        with_tcp_timeout(self.connect(remote_endpoint), timeout)
+            .await
+            .map_err(|_| ConnectError::TimedOut)?
+    }
+
+    /// Set the timeout of [`connect_with_timeout()`](TcpSocket::connect_with_timeout),
+    /// defaulting to [`DEFAULT_CONNECT_TIMEOUT`].
+    pub fn set_connect_timeout(&mut self, timeout: Duration) {
+        self.connect_timeout = timeout;
+    }
+
+    /// Set the timeout of reads, writes and flushes of the socket, including
+    /// those of its split halves and through the `embedded-io-async` traits.
+    ///
+    /// A read fails with [`Error::TimedOut`] if no data arrives within
+    /// `duration`, and a write if the transmit buffer has no room for that
+    /// long. The window starts over with each call, i.e. on any progress.
+    /// The socket stays open, so the operation can be retried. Without a
+    /// timeout, the default, they wait as long as it takes.
+    pub fn set_timeout(&mut self, duration: Option<Duration>) {
+        let mut stack = self.io.stack.borrow_mut();
+        match duration {
+            None => {
+                stack.io_timeouts.remove(&self.io.handle);
+            }
+            Some(duration) => {
+                if stack
+                    .insert_setting(|s| &mut s.io_timeouts, self.io.handle, duration)
+                    .is_err()
+                {
+                    warn!("Too many sockets with a timeout set, ignoring");
+                }
+            }
+        }
     }
 
     /// Set the keep-alive interval for the socket.
@@ -323,6 +488,92 @@ impl<'a> TcpSocket<'a> {
         //     .with_mut(|s| s.set_keep_alive(interval.map(duration_to_smoltcp)))
     }
 
+    /// Enable or disable Nagle's algorithm for the socket.
+    ///
+    /// With `nodelay` set, every write is sent immediately instead of being
+    /// batched, which favors latency over throughput. The setting applies
+    /// from the next [`connect()`](TcpSocket::connect), and is kept for any
+    /// later reconnects of the socket.
+    pub fn set_nodelay(&mut self, nodelay: bool) -> Result<(), SettingsFull> {
+        let mut stack = self.io.stack.borrow_mut();
+        if !nodelay {
+            stack.nodelay.remove(&self.io.handle);
+            return Ok(());
+        }
+        stack
+            .insert_setting(|s| &mut s.nodelay, self.io.handle, ())
+            .map_err(|_| SettingsFull)
+    }
+
+    /// Connect from `local_port` from the next [`connect()`](TcpSocket::connect)
+    /// on, and for any later reconnects of the socket. A port of 0 lets the
+    /// module assign one again.
+    ///
+    /// Reconnecting from the same port waits for the module to release it
+    /// from the previous connection. Fails with [`ConnectError::InvalidState`]
+    /// if the socket is connected or listening.
+    pub fn set_local_port(&mut self, local_port: u16) -> Result<(), ConnectError> {
+        if !matches!(self.state(), TcpState::Closed | TcpState::TimeWait) {
+            return Err(ConnectError::InvalidState);
+        }
+
+        let mut stack = self.io.stack.borrow_mut();
+        if local_port == 0 {
+            stack.local_ports.remove(&self.io.handle);
+        } else if stack
+            .insert_setting(|s| &mut s.local_ports, self.io.handle, local_port)
+            .is_err()
+        {
+            warn!("Too many sockets with a local port set, ignoring");
+        }
+        Ok(())
+    }
+
+    /// Set the transmit priority of the socket, defaulting to
+    /// [`DEFAULT_TX_PRIORITY`](super::DEFAULT_TX_PRIORITY).
+    ///
+    /// Pending data of sockets with a higher priority is sent ahead of that
+    /// of all other sockets, which take turns.
+    pub fn set_priority(&mut self, priority: u8) -> Result<(), SettingsFull> {
+        self.io
+            .stack
+            .borrow_mut()
+            .set_tx_priority(self.io.handle, priority)
+    }
+
+    /// Secure connections of the socket with TLS, using `creds`.
+    ///
+    /// The credentials apply from the next [`connect()`](TcpSocket::connect),
+    /// and are kept for any later reconnects of the socket, until it is
+    /// dropped or they are cleared with
+    /// [`clear_tls_credentials()`](TcpSocket::clear_tls_credentials).
+    ///
+    /// `creds` is handed back if they do not fit the stack, see
+    /// [`SettingsFull`].
+    pub fn set_tls_credentials(
+        &mut self,
+        creds: SecurityCredentials,
+    ) -> Result<(), SecurityCredentials> {
+        let mut stack = self.io.stack.borrow_mut();
+        info!("Associating credentials {} with {}", creds, self.io.handle);
+        stack
+            .insert_setting(|s| &mut s.credential_map, self.io.handle, creds)
+            .inspect_err(|_| warn!("Too many sockets with TLS credentials"))
+    }
+
+    /// Forget the TLS credentials of the socket, so that it connects without
+    /// TLS from its next [`connect()`](TcpSocket::connect).
+    ///
+    /// Credentials are forgotten when the socket is dropped, so this is only
+    /// needed to reuse a socket for plain connections.
+    pub fn clear_tls_credentials(&mut self) {
+        self.io
+            .stack
+            .borrow_mut()
+            .credential_map
+            .remove(&self.io.handle);
+    }
+
     // /// Set the hop limit field in the IP header of sent packets.
     // pub fn set_hop_limit(&mut self, hop_limit: Option<u8>) {
     //     self.io.with_mut(|s| s.set_hop_limit(hop_limit))
@@ -354,7 +605,11 @@ impl<'a> TcpSocket<'a> {
     /// socket can still receive data.
     ///
     /// Data that has been written to the socket and not yet sent (or not yet ACKed) will still
-    /// still sent. The last segment of the pending to send data is sent with the FIN flag set.
+    /// still sent. Once flushed, the peer is closed on the module, and released when the module
+    /// reports the peer as disconnected.
+    ///
+    /// If the remote closed the connection first, the socket is in `CloseWait` and any buffered
+    /// data remains readable until the socket is closed or dropped.
     pub fn close(&mut self) {
         self.io.with_mut(|s| s.close())
     }
@@ -364,12 +619,15 @@ impl<'a> TcpSocket<'a> {
     /// This instantly closes both the read and write halves of the socket. Any pending data
     /// that has not been sent will be lost.
     ///
-    /// Note that the TCP RST packet is not sent immediately - if the `TcpSocket` is dropped too soon
-    /// the remote host may not know the connection has been closed.
-    /// `abort()` callers should wait for a [`flush()`](TcpSocket::flush) call to complete before
-    /// dropping or reusing the socket.
-    pub fn abort(&mut self) {
-        self.io.with_mut(|s| s.abort())
+    /// The socket goes straight to `Closed`, skipping the `FinWait1` handshake of
+    /// [`close()`](TcpSocket::close), and the peer is closed on the module once the stack
+    /// gets to send the request. Dropping the socket afterwards is safe, as the close stays
+    /// queued in the stack.
+    ///
+    /// If the close queue of the stack is full, the peer is closed through `FinWait1` like
+    /// after [`close()`](TcpSocket::close) instead, and [`CloseQueueFull`] is returned.
+    pub fn abort(&mut self) -> Result<(), CloseQueueFull> {
+        self.io.stack.borrow_mut().abort(self.io.handle)
     }
 
     /// Get whether the socket is ready to send data, i.e. whether there is space in the send buffer.
@@ -391,24 +649,44 @@ impl<'a> TcpSocket<'a> {
     }
 }
 
+#[cfg(test)]
+impl<'a> TcpSocket<'a> {
+    /// Wrap the socket `handle` already added to `stack`.
+    pub(crate) fn from_handle(stack: &'a RefCell<SocketStack>, handle: SocketHandle) -> Self {
+        Self {
+            io: TcpIo { stack, handle },
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        }
+    }
+}
+
 impl<'a> Drop for TcpSocket<'a> {
     fn drop(&mut self) {
-        if matches!(
-            self.state(),
-            TcpState::Listen | TcpState::Established | TcpState::FinWait1
-        ) {
-            if let Some(peer_handle) = self.io.with(|s| s.peer_handle) {
-                self.io
-                    .stack
-                    .borrow_mut()
-                    .dropped_sockets
-                    .push(peer_handle)
-                    .ok();
+        {
+            let mut stack = self.io.stack.borrow_mut();
+            stack.credential_map.remove(&self.io.handle);
+            stack.nodelay.remove(&self.io.handle);
+            stack.tx_priority.remove(&self.io.handle);
+            stack.local_ports.remove(&self.io.handle);
+            stack.io_timeouts.remove(&self.io.handle);
+            // The slot is recycled once the socket has been in `TimeWait`
+            // for long enough
+            if stack.park_time_wait(self.io.handle) {
+                return;
             }
+            stack.time_wait.remove(&self.io.handle);
         }
-        let mut stack = self.io.stack.borrow_mut();
-        stack.sockets.remove(self.io.handle);
-        stack.waker.wake();
+
+        // Peers that already sent their close (`FinWait2`) or are gone
+        // (`TimeWait`) are released by the module itself.
+        let peer_handle = match self.state() {
+            TcpState::FinWait2 | TcpState::TimeWait => None,
+            _ => self.io.with(|s| s.peer_handle),
+        };
+        self.io
+            .stack
+            .borrow_mut()
+            .release(self.io.handle, peer_handle);
     }
 }
 
@@ -435,11 +713,17 @@ impl<'d> TcpIo<'d> {
         res
     }
 
+    /// Timeout set with [`TcpSocket::set_timeout`].
+    fn timeout(&self) -> Option<Duration> {
+        self.stack.borrow().io_timeouts.get(&self.handle).copied()
+    }
+
     fn read<'a>(
         &'a mut self,
         buf: &'a mut [u8],
     ) -> impl Future<Output = Result<usize, Error>> + use<'a, 'd> {
-        poll_fn(move |cx| {
+        let timeout = self.timeout();
+        let read = poll_fn(move |cx| {
             // CAUTION: smoltcp semantics around EOF are different to what you'd expect
             // from posix-like IO, so we have to tweak things here.
             self.with_mut(|s| match s.recv_slice(buf) {
@@ -464,15 +748,19 @@ impl<'d> TcpIo<'d> {
                 // Connection reset. TODO: this can also be timeouts etc, investigate.
                 // Err(tcp::RecvError::InvalidState) => Poll::Ready(Err(Error::ConnectionReset)),
             })
-        })
+        });
+        with_optional_timeout(read, timeout)
     }
 
     fn write<'a>(
         &'a mut self,
         buf: &'a [u8],
     ) -> impl Future<Output = Result<usize, Error>> + use<'a, 'd> {
-        poll_fn(move |cx| {
+        let timeout = self.timeout();
+        let write = poll_fn(move |cx| {
             self.with_mut(|s| match s.send_slice(buf) {
+                // Nothing to send, which must not block either
+                Ok(0) if buf.is_empty() => Poll::Ready(Ok(0)),
                 // Not ready to send (no space in the tx buffer)
                 Ok(0) => {
                     s.register_send_waker(cx.waker());
@@ -485,7 +773,8 @@ impl<'d> TcpIo<'d> {
                 // FIXME:
                 // Err(tcp::SendError::InvalidState) => Poll::Ready(Err(Error::ConnectionReset)),
             })
-        })
+        });
+        with_optional_timeout(write, timeout)
     }
 
     fn write_with<'a, F, R>(
@@ -554,7 +843,8 @@ impl<'d> TcpIo<'d> {
     }
 
     fn flush(&mut self) -> impl Future<Output = Result<(), Error>> + use<'_, 'd> {
-        poll_fn(move |cx| {
+        let timeout = self.timeout();
+        let flush = poll_fn(move |cx| {
             self.with_mut(|s| {
                 // If there are outstanding send operations, register for wake up and wait
                 // smoltcp issues wake-ups when octets are dequeued from the send buffer
@@ -566,7 +856,8 @@ impl<'d> TcpIo<'d> {
                     Poll::Ready(Ok(()))
                 }
             })
-        })
+        });
+        with_optional_timeout(flush, timeout)
     }
 
     fn recv_capacity(&self) -> usize {
@@ -588,6 +879,7 @@ mod embedded_io_impls {
                 ConnectError::TimedOut => embedded_io_async::ErrorKind::TimedOut,
                 ConnectError::NoRoute => embedded_io_async::ErrorKind::NotConnected,
                 ConnectError::InvalidState => embedded_io_async::ErrorKind::Other,
+                ConnectError::TooManyPeers => embedded_io_async::ErrorKind::OutOfMemory,
             }
         }
     }
@@ -596,6 +888,7 @@ mod embedded_io_impls {
         fn kind(&self) -> embedded_io_async::ErrorKind {
             match self {
                 Error::ConnectionReset => embedded_io_async::ErrorKind::ConnectionReset,
+                Error::TimedOut => embedded_io_async::ErrorKind::TimedOut,
             }
         }
     }
@@ -675,7 +968,8 @@ pub mod client {
     use core::mem::MaybeUninit;
     use core::ptr::NonNull;
 
-    use crate::asynch::ublox_stack::dns::DnsSocket;
+    use crate::asynch::runner::DEFAULT_CMD_BUF_SIZE;
+    use crate::asynch::ublox_stack::{dns::DnsSocket, DEFAULT_EGRESS_BUF_SIZE};
 
     use super::*;
 
@@ -689,8 +983,11 @@ pub mod client {
         const N: usize,
         const TX_SZ: usize = 1024,
         const RX_SZ: usize = 1024,
+        const EGRESS_BUF_SIZE: usize = DEFAULT_EGRESS_BUF_SIZE,
+        const CMD_BUF_SIZE: usize = DEFAULT_CMD_BUF_SIZE,
     > {
-        pub(crate) stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+        pub(crate) stack:
+            &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
         pub(crate) state: &'d TcpClientState<N, TX_SZ, RX_SZ>,
     }
 
@@ -701,8 +998,19 @@ pub mod client {
             const N: usize,
             const TX_SZ: usize,
             const RX_SZ: usize,
+            const EGRESS_BUF_SIZE: usize,
+            const CMD_BUF_SIZE: usize,
         > embedded_nal_async::Dns
-        for TcpClient<'d, INGRESS_BUF_SIZE, URC_CAPACITY, N, TX_SZ, RX_SZ>
+        for TcpClient<
+            'd,
+            INGRESS_BUF_SIZE,
+            URC_CAPACITY,
+            N,
+            TX_SZ,
+            RX_SZ,
+            EGRESS_BUF_SIZE,
+            CMD_BUF_SIZE,
+        >
     {
         type Error = crate::asynch::ublox_stack::dns::Error;
 
@@ -730,11 +1038,23 @@ pub mod client {
             const N: usize,
             const TX_SZ: usize,
             const RX_SZ: usize,
-        > TcpClient<'d, INGRESS_BUF_SIZE, URC_CAPACITY, N, TX_SZ, RX_SZ>
+            const EGRESS_BUF_SIZE: usize,
+            const CMD_BUF_SIZE: usize,
+        >
+        TcpClient<
+            'd,
+            INGRESS_BUF_SIZE,
+            URC_CAPACITY,
+            N,
+            TX_SZ,
+            RX_SZ,
+            EGRESS_BUF_SIZE,
+            CMD_BUF_SIZE,
+        >
     {
         /// Create a new `TcpClient`.
         pub fn new(
-            stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+            stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
             state: &'d TcpClientState<N, TX_SZ, RX_SZ>,
         ) -> Self {
             Self { stack, state }
@@ -748,8 +1068,19 @@ pub mod client {
             const N: usize,
             const TX_SZ: usize,
             const RX_SZ: usize,
+            const EGRESS_BUF_SIZE: usize,
+            const CMD_BUF_SIZE: usize,
         > embedded_nal_async::TcpConnect
-        for TcpClient<'d, INGRESS_BUF_SIZE, URC_CAPACITY, N, TX_SZ, RX_SZ>
+        for TcpClient<
+            'd,
+            INGRESS_BUF_SIZE,
+            URC_CAPACITY,
+            N,
+            TX_SZ,
+            RX_SZ,
+            EGRESS_BUF_SIZE,
+            CMD_BUF_SIZE,
+        >
     {
         type Error = Error;
         type Connection<'m>
@@ -782,8 +1113,13 @@ pub mod client {
     impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize>
         TcpConnection<'d, N, TX_SZ, RX_SZ>
     {
-        fn new<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>(
-            stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+        fn new<
+            const INGRESS_BUF_SIZE: usize,
+            const URC_CAPACITY: usize,
+            const EGRESS_BUF_SIZE: usize,
+            const CMD_BUF_SIZE: usize,
+        >(
+            stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
             state: &'d TcpClientState<N, TX_SZ, RX_SZ>,
         ) -> Result<Self, Error> {
             let mut bufs = state.pool.alloc().ok_or(Error::ConnectionReset)?;
@@ -6,8 +6,8 @@ use ublox_sockets::TcpState as State;
 use super::peer_builder::SecurityCredentials;
 
 use super::{
-    tcp::{ConnectError, Error, TcpIo, TcpReader, TcpSocket, TcpWriter},
-    UbloxStack,
+    tcp::{ConnectError, Error, TcpReader, TcpSocket, TcpWriter},
+    CloseQueueFull, SettingsFull, UbloxStack,
 };
 
 pub struct TlsSocket<'a> {
@@ -16,21 +16,26 @@ pub struct TlsSocket<'a> {
 
 impl<'a> TlsSocket<'a> {
     /// Create a new TCP socket on the given stack, with the given buffers.
-    pub fn new<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>(
-        stack: &'a UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+    ///
+    /// Fails with [`SettingsFull`] if the credentials do not fit the stack,
+    /// see [`TcpSocket::set_tls_credentials`].
+    pub fn new<
+        const INGRESS_BUF_SIZE: usize,
+        const URC_CAPACITY: usize,
+        const EGRESS_BUF_SIZE: usize,
+        const CMD_BUF_SIZE: usize,
+    >(
+        stack: &'a UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
         rx_buffer: &'a mut [u8],
         tx_buffer: &'a mut [u8],
         credentials: SecurityCredentials,
-    ) -> Self {
-        let tcp_socket = TcpSocket::new(stack, rx_buffer, tx_buffer);
-
-        let TcpIo { stack, handle } = tcp_socket.io;
+    ) -> Result<Self, SettingsFull> {
+        let mut tcp_socket = TcpSocket::new(stack, rx_buffer, tx_buffer);
+        tcp_socket
+            .set_tls_credentials(credentials)
+            .map_err(|_| SettingsFull)?;
 
-        let s = &mut *stack.borrow_mut();
-        info!("Associating credentials {} with {}", credentials, handle);
-        s.credential_map.insert(handle, credentials).unwrap();
-
-        Self { inner: tcp_socket }
+        Ok(Self { inner: tcp_socket })
     }
 
     /// Return the maximum number of bytes inside the recv buffer.
@@ -90,6 +95,18 @@ impl<'a> TlsSocket<'a> {
         self.inner.connect(remote_endpoint)
     }
 
+    /// Connect to a remote host from `local_port`.
+    pub fn connect_from<T>(
+        &mut self,
+        remote_endpoint: T,
+        local_port: u16,
+    ) -> impl Future<Output = Result<(), ConnectError>> + use<'_, 'a, T>
+    where
+        T: Into<SocketAddr>,
+    {
+        self.inner.connect_from(remote_endpoint, local_port)
+    }
+
     // /// Accept a connection from a remote host.
     // ///
     // /// This function puts the socket in listening mode, and waits until a connection is received.
@@ -146,13 +163,10 @@ impl<'a> TlsSocket<'a> {
         self.inner.flush()
     }
 
-    /// Set the timeout for the socket.
-    ///
-    /// If the timeout is set, the socket will be closed if no data is received for the
-    /// specified duration.
-    pub fn set_timeout(&mut self, _duration: Option<Duration>) {
-        todo!()
-        // self.inner.set_timeout(duration)
+    /// Set the timeout of reads, writes and flushes of the socket, see
+    /// [`TcpSocket::set_timeout`].
+    pub fn set_timeout(&mut self, duration: Option<Duration>) {
+        self.inner.set_timeout(duration)
     }
 
     /// Set the keep-alive interval for the socket.
@@ -165,6 +179,12 @@ impl<'a> TlsSocket<'a> {
         self.inner.set_keep_alive(interval)
     }
 
+    /// Forget the TLS credentials of the socket, see
+    /// [`TcpSocket::clear_tls_credentials`].
+    pub fn clear_tls_credentials(&mut self) {
+        self.inner.clear_tls_credentials()
+    }
+
     // /// Set the hop limit field in the IP header of sent packets.
     // pub fn set_hop_limit(&mut self, hop_limit: Option<u8>) {
     //     self.inner.set_hop_limit()
@@ -210,7 +230,9 @@ impl<'a> TlsSocket<'a> {
     /// the remote host may not know the connection has been closed.
     /// `abort()` callers should wait for a [`flush()`](TlsSocket::flush) call to complete before
     /// dropping or reusing the socket.
-    pub fn abort(&mut self) {
+    ///
+    /// Returns [`CloseQueueFull`] as [`TcpSocket::abort`] does.
+    pub fn abort(&mut self) -> Result<(), CloseQueueFull> {
         self.inner.abort()
     }
 
@@ -233,13 +255,6 @@ impl<'a> TlsSocket<'a> {
     }
 }
 
-impl<'a> Drop for TlsSocket<'a> {
-    fn drop(&mut self) {
-        let mut stack = self.inner.io.stack.borrow_mut();
-        stack.credential_map.remove(&self.inner.io.handle);
-    }
-}
-
 mod embedded_io_impls {
     use super::*;
 
@@ -280,8 +295,9 @@ mod embedded_io_impls {
 pub mod client {
     use core::ptr::NonNull;
 
-    use crate::asynch::ublox_stack::dns::DnsSocket;
+    use crate::asynch::runner::DEFAULT_CMD_BUF_SIZE;
     use crate::asynch::ublox_stack::tcp::client::TcpClientState;
+    use crate::asynch::ublox_stack::{dns::DnsSocket, DEFAULT_EGRESS_BUF_SIZE};
 
     use super::*;
 
@@ -295,8 +311,11 @@ pub mod client {
         const N: usize,
         const TX_SZ: usize = 1024,
         const RX_SZ: usize = 1024,
+        const EGRESS_BUF_SIZE: usize = DEFAULT_EGRESS_BUF_SIZE,
+        const CMD_BUF_SIZE: usize = DEFAULT_CMD_BUF_SIZE,
     > {
-        pub(crate) stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+        pub(crate) stack:
+            &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
         pub(crate) state: &'d TcpClientState<N, TX_SZ, RX_SZ>,
         pub(crate) credentials: SecurityCredentials,
     }
@@ -308,8 +327,19 @@ pub mod client {
             const N: usize,
             const TX_SZ: usize,
             const RX_SZ: usize,
+            const EGRESS_BUF_SIZE: usize,
+            const CMD_BUF_SIZE: usize,
         > embedded_nal_async::Dns
-        for TlsClient<'d, INGRESS_BUF_SIZE, URC_CAPACITY, N, TX_SZ, RX_SZ>
+        for TlsClient<
+            'd,
+            INGRESS_BUF_SIZE,
+            URC_CAPACITY,
+            N,
+            TX_SZ,
+            RX_SZ,
+            EGRESS_BUF_SIZE,
+            CMD_BUF_SIZE,
+        >
     {
         type Error = crate::asynch::ublox_stack::dns::Error;
 
@@ -337,11 +367,23 @@ pub mod client {
             const N: usize,
             const TX_SZ: usize,
             const RX_SZ: usize,
-        > TlsClient<'d, INGRESS_BUF_SIZE, URC_CAPACITY, N, TX_SZ, RX_SZ>
+            const EGRESS_BUF_SIZE: usize,
+            const CMD_BUF_SIZE: usize,
+        >
+        TlsClient<
+            'd,
+            INGRESS_BUF_SIZE,
+            URC_CAPACITY,
+            N,
+            TX_SZ,
+            RX_SZ,
+            EGRESS_BUF_SIZE,
+            CMD_BUF_SIZE,
+        >
     {
         /// Create a new `TlsClient`.
         pub fn new(
-            stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+            stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
             state: &'d TcpClientState<N, TX_SZ, RX_SZ>,
             credentials: SecurityCredentials,
         ) -> Self {
@@ -360,8 +402,19 @@ pub mod client {
             const N: usize,
             const TX_SZ: usize,
             const RX_SZ: usize,
+            const EGRESS_BUF_SIZE: usize,
+            const CMD_BUF_SIZE: usize,
         > embedded_nal_async::TcpConnect
-        for TlsClient<'d, INGRESS_BUF_SIZE, URC_CAPACITY, N, TX_SZ, RX_SZ>
+        for TlsClient<
+            'd,
+            INGRESS_BUF_SIZE,
+            URC_CAPACITY,
+            N,
+            TX_SZ,
+            RX_SZ,
+            EGRESS_BUF_SIZE,
+            CMD_BUF_SIZE,
+        >
     {
         type Error = Error;
         type Connection<'m>
@@ -394,24 +447,36 @@ pub mod client {
     impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize>
         TlsConnection<'d, N, TX_SZ, RX_SZ>
     {
-        fn new<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>(
-            stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+        fn new<
+            const INGRESS_BUF_SIZE: usize,
+            const URC_CAPACITY: usize,
+            const EGRESS_BUF_SIZE: usize,
+            const CMD_BUF_SIZE: usize,
+        >(
+            stack: &'d UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
             state: &'d TcpClientState<N, TX_SZ, RX_SZ>,
             credentials: SecurityCredentials,
         ) -> Result<Self, Error> {
             let mut bufs = state.pool.alloc().ok_or(Error::ConnectionReset)?;
-            Ok(Self {
-                socket: unsafe {
-                    TlsSocket::new(
-                        stack,
-                        &mut bufs.as_mut().1,
-                        &mut bufs.as_mut().0,
-                        credentials,
-                    )
-                },
-                state,
-                bufs,
-            })
+            let socket = unsafe {
+                TlsSocket::new(
+                    stack,
+                    &mut bufs.as_mut().1,
+                    &mut bufs.as_mut().0,
+                    credentials,
+                )
+            };
+            match socket {
+                Ok(socket) => Ok(Self {
+                    socket,
+                    state,
+                    bufs,
+                }),
+                Err(SettingsFull) => {
+                    unsafe { state.pool.free(bufs) };
+                    Err(Error::ConnectionReset)
+                }
+            }
         }
     }
 
@@ -1,12 +1,13 @@
 //! UDP sockets.
 use core::cell::RefCell;
-
+use core::future::poll_fn;
 use core::mem;
+use core::task::{Context, Poll};
 
 use core::net::SocketAddr;
 use ublox_sockets::{udp, SocketHandle, UdpState};
 
-use super::{SocketStack, UbloxStack};
+use super::{SettingsFull, SocketStack, UbloxStack};
 
 /// Error returned by [`UdpSocket::bind`].
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -26,16 +27,30 @@ pub enum SendError {
     NoRoute,
     /// Socket not bound to an outgoing port.
     SocketNotBound,
+    /// The datagram is larger than the transmit buffer of the socket.
+    PacketTooLarge,
 }
 
-/// Error returned by [`UdpSocket::recv_from`] and [`UdpSocket::send_to`].
+/// Error returned by [`UdpSocket::recv_from`].
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum RecvError {
-    /// Provided buffer was smaller than the received packet.
+    /// Provided buffer was smaller than the received packet. The packet is
+    /// dropped.
     Truncated,
 }
 
+/// Datagram in the receive buffer of a UDP socket.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RxDatagram {
+    pub len: usize,
+    /// Remote endpoint of the peer the datagram was received from.
+    pub source: SocketAddr,
+}
+
+/// Datagrams in the receive buffer of a UDP socket, oldest first.
+pub(crate) type RxDatagrams = heapless::Deque<RxDatagram, { super::MAX_RX_DATAGRAMS }>;
+
 /// An UDP socket.
 pub struct UdpSocket<'a> {
     stack: &'a RefCell<SocketStack>,
@@ -44,18 +59,27 @@ pub struct UdpSocket<'a> {
 
 impl<'a> UdpSocket<'a> {
     /// Create a new UDP socket using the provided stack and buffers.
-    pub fn new<const INGRESS_BUF_SIZE: usize, const URC_CAPACITY: usize>(
-        stack: &'a UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY>,
+    pub fn new<
+        const INGRESS_BUF_SIZE: usize,
+        const URC_CAPACITY: usize,
+        const EGRESS_BUF_SIZE: usize,
+        const CMD_BUF_SIZE: usize,
+    >(
+        stack: &'a UbloxStack<INGRESS_BUF_SIZE, URC_CAPACITY, EGRESS_BUF_SIZE, CMD_BUF_SIZE>,
         rx_buffer: &'a mut [u8],
         tx_buffer: &'a mut [u8],
     ) -> Self {
         let s = &mut *stack.socket.borrow_mut();
         let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
         let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
+        #[cfg(feature = "socket-tcp")]
+        s.make_room();
         let handle = s.sockets.add(udp::Socket::new(
             udp::SocketBuffer::new(rx_buffer),
             udp::SocketBuffer::new(tx_buffer),
         ));
+        s.forget(handle);
+        s.waker.wake();
 
         Self {
             stack: &stack.socket,
@@ -97,86 +121,126 @@ impl<'a> UdpSocket<'a> {
         res
     }
 
-    // /// Receive a datagram.
-    // ///
-    // /// This method will wait until a datagram is received.
-    // ///
-    // /// Returns the number of bytes received and the remote endpoint.
-    // pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, IpEndpoint), RecvError> {
-    //     poll_fn(move |cx| self.poll_recv_from(buf, cx)).await
-    // }
+    /// Receive a datagram.
+    ///
+    /// This method will wait until a datagram is received.
+    ///
+    /// Returns the number of bytes received and the remote endpoint.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), RecvError> {
+        poll_fn(move |cx| self.poll_recv_from(buf, cx)).await
+    }
 
-    // /// Receive a datagram.
-    // ///
-    // /// When no datagram is available, this method will return `Poll::Pending` and
-    // /// register the current task to be notified when a datagram is received.
-    // ///
-    // /// When a datagram is received, this method will return `Poll::Ready` with the
-    // /// number of bytes received and the remote endpoint.
-    // pub fn poll_recv_from(
-    //     &self,
-    //     buf: &mut [u8],
-    //     cx: &mut Context<'_>,
-    // ) -> Poll<Result<(usize, IpEndpoint), RecvError>> {
-    //     self.with_mut(|s| match s.recv_slice(buf) {
-    //         Ok((n, meta)) => Poll::Ready(Ok((n, meta.endpoint))),
-    //         // No data ready
-    //         // Err(udp::RecvError::Truncated) => Poll::Ready(Err(RecvError::Truncated)),
-    //         // Err(udp::RecvError::Exhausted) => {
-    //         Err(_) => {
-    //             s.register_recv_waker(cx.waker());
-    //             Poll::Pending
-    //         }
-    //     })
-    // }
+    /// Receive a datagram.
+    ///
+    /// When no datagram is available, this method will return `Poll::Pending` and
+    /// register the current task to be notified when a datagram is received.
+    ///
+    /// When a datagram is received, this method will return `Poll::Ready` with the
+    /// number of bytes received and the remote endpoint. A datagram larger than
+    /// `buf` is dropped with [`RecvError::Truncated`].
+    pub fn poll_recv_from(
+        &self,
+        buf: &mut [u8],
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(usize, SocketAddr), RecvError>> {
+        let s = &mut *self.stack.borrow_mut();
+        let udp = s.sockets.get_mut::<udp::Socket>(self.handle);
 
-    // /// Send a datagram to the specified remote endpoint.
-    // ///
-    // /// This method will wait until the datagram has been sent.
-    // ///
-    // /// When the remote endpoint is not reachable, this method will return `Err(SendError::NoRoute)`
-    // pub async fn send_to<T>(&self, buf: &[u8], remote_endpoint: T) -> Result<(), SendError>
-    // where
-    //     T: Into<IpEndpoint>,
-    // {
-    //     let remote_endpoint: IpEndpoint = remote_endpoint.into();
-    //     poll_fn(move |cx| self.poll_send_to(buf, remote_endpoint, cx)).await
-    // }
+        let Some(RxDatagram { len, source }) = s
+            .rx_datagrams
+            .get_mut(&self.handle)
+            .and_then(|queue| queue.pop_front())
+        else {
+            udp.register_recv_waker(cx.waker());
+            return Poll::Pending;
+        };
 
-    // /// Send a datagram to the specified remote endpoint.
-    // ///
-    // /// When the datagram has been sent, this method will return `Poll::Ready(Ok())`.
-    // ///
-    // /// When the socket's send buffer is full, this method will return `Poll::Pending`
-    // /// and register the current task to be notified when the buffer has space available.
-    // ///
-    // /// When the remote endpoint is not reachable, this method will return `Poll::Ready(Err(Error::NoRoute))`.
-    // pub fn poll_send_to<T>(
-    //     &self,
-    //     buf: &[u8],
-    //     remote_endpoint: T,
-    //     cx: &mut Context<'_>,
-    // ) -> Poll<Result<(), SendError>>
-    // where
-    //     T: Into<IpEndpoint>,
-    // {
-    //     self.with_mut(|s| match s.send_slice(buf, remote_endpoint) {
-    //         // Entire datagram has been sent
-    //         Ok(()) => Poll::Ready(Ok(())),
-    //         Err(udp::SendError::BufferFull) => {
-    //             s.register_send_waker(cx.waker());
-    //             Poll::Pending
-    //         }
-    //         Err(udp::SendError::Unaddressable) => {
-    //             // If no sender/outgoing port is specified, there is not really "no route"
-    //             if s.endpoint().port == 0 {
-    //                 Poll::Ready(Err(SendError::SocketNotBound))
-    //             } else {
-    //                 Poll::Ready(Err(SendError::NoRoute))
-    //             }
-    //         }
-    //     })
-    // }
+        let n = len.min(buf.len());
+        udp.recv_slice(&mut buf[..n]).ok();
+        if n < len {
+            discard(udp, len - n);
+            return Poll::Ready(Err(RecvError::Truncated));
+        }
+        Poll::Ready(Ok((n, source)))
+    }
+
+    /// Send a datagram to `remote_endpoint`.
+    ///
+    /// The module sends UDP data through a peer connected to a single remote
+    /// endpoint. Sending to another endpoint than the previous datagram waits
+    /// for the queued datagrams to be sent, then closes the peer and connects
+    /// a new one.
+    ///
+    /// If the module fails to connect the peer, the queued datagrams are
+    /// dropped, and the next call fails with [`SendError::NoRoute`].
+    ///
+    /// Fails with [`SendError::PacketTooLarge`] if `buf` does not fit the
+    /// transmit buffer of the socket.
+    pub async fn send_to<T>(&mut self, buf: &[u8], remote_endpoint: T) -> Result<(), SendError>
+    where
+        T: Into<SocketAddr>,
+    {
+        let remote_endpoint = remote_endpoint.into();
+        poll_fn(|cx| self.poll_send_to(buf, remote_endpoint, cx)).await
+    }
+
+    fn poll_send_to(
+        &self,
+        buf: &[u8],
+        remote_endpoint: SocketAddr,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), SendError>> {
+        let s = &mut *self.stack.borrow_mut();
+
+        if let Some(error) = s.send_errors.remove(&self.handle) {
+            return Poll::Ready(Err(error));
+        }
+
+        if buf.len() > s.sockets.get::<udp::Socket>(self.handle).send_capacity() {
+            return Poll::Ready(Err(SendError::PacketTooLarge));
+        }
+
+        if s.send_to_endpoints.get(&self.handle) != Some(&remote_endpoint) {
+            let udp = s.sockets.get_mut::<udp::Socket>(self.handle);
+            if udp.send_queue() > 0 {
+                udp.register_send_waker(cx.waker());
+                return Poll::Pending;
+            }
+
+            if let Some(peer_handle) = udp.peer_handle {
+                // Keep the peer until its close can be queued, rather than
+                // leaking it on the module
+                if !s.queue_close(peer_handle) {
+                    s.close_waker.register(cx.waker());
+                    return Poll::Pending;
+                }
+            }
+
+            let udp = s.sockets.get_mut::<udp::Socket>(self.handle);
+            udp.peer_handle = None;
+            udp.edm_channel = None;
+            udp.endpoint = None;
+            udp.set_state(UdpState::Closed);
+
+            if s.insert_setting(|s| &mut s.send_to_endpoints, self.handle, remote_endpoint)
+                .is_err()
+            {
+                return Poll::Ready(Err(SendError::NoRoute));
+            }
+        }
+
+        let udp = s.sockets.get_mut::<udp::Socket>(self.handle);
+        if udp.send_capacity() - udp.send_queue() < buf.len() {
+            udp.register_send_waker(cx.waker());
+            return Poll::Pending;
+        }
+        let res = match udp.send_slice(buf) {
+            Ok(_) => Poll::Ready(Ok(())),
+            Err(_) => Poll::Ready(Err(SendError::NoRoute)),
+        };
+        s.waker.wake();
+        res
+    }
 
     /// Returns the local endpoint of the socket.
     pub fn endpoint(&self) -> Option<SocketAddr> {
@@ -188,9 +252,49 @@ impl<'a> UdpSocket<'a> {
         self.with(|s| s.is_open())
     }
 
-    /// Close the socket.
+    /// Close the socket, dropping any datagrams not yet received.
     pub fn close(&mut self) {
-        self.with_mut(|s| s.close())
+        self.with_mut(|s| {
+            let queued = s.recv_queue();
+            discard(s, queued);
+            s.close()
+        });
+        self.stack.borrow_mut().rx_datagrams.remove(&self.handle);
+    }
+
+    /// Send from `local_port`, for peers connected by later
+    /// [`send_to()`](UdpSocket::send_to) calls. A port of 0 lets the module
+    /// assign one again.
+    ///
+    /// Reconnecting from the same port, e.g. to send to another endpoint,
+    /// waits for the module to release it from the previous peer. Fails with
+    /// [`BindError::InvalidState`] if the socket is open.
+    pub fn set_local_port(&mut self, local_port: u16) -> Result<(), BindError> {
+        if self.is_open() {
+            return Err(BindError::InvalidState);
+        }
+
+        let mut stack = self.stack.borrow_mut();
+        if local_port == 0 {
+            stack.local_ports.remove(&self.handle);
+        } else if stack
+            .insert_setting(|s| &mut s.local_ports, self.handle, local_port)
+            .is_err()
+        {
+            warn!("Too many sockets with a local port set, ignoring");
+        }
+        Ok(())
+    }
+
+    /// Set the transmit priority of the socket, defaulting to
+    /// [`DEFAULT_TX_PRIORITY`](super::DEFAULT_TX_PRIORITY).
+    ///
+    /// Pending data of sockets with a higher priority is sent ahead of that
+    /// of all other sockets, which take turns.
+    pub fn set_priority(&mut self, priority: u8) -> Result<(), SettingsFull> {
+        self.stack
+            .borrow_mut()
+            .set_tx_priority(self.handle, priority)
     }
 
     // /// Returns whether the socket is ready to send data, i.e. it has enough buffer space to hold a packet.
@@ -231,17 +335,38 @@ impl<'a> UdpSocket<'a> {
 
 impl<'a> Drop for UdpSocket<'a> {
     fn drop(&mut self) {
-        if matches!(self.with(|s| s.state()), UdpState::Established) {
-            if let Some(peer_handle) = self.with(|s| s.peer_handle) {
-                self.stack
-                    .borrow_mut()
-                    .dropped_sockets
-                    .push(peer_handle)
-                    .ok();
-            }
-        }
+        let peer_handle = match self.with(|s| s.state()) {
+            UdpState::Established => self.with(|s| s.peer_handle),
+            _ => None,
+        };
         let mut stack = self.stack.borrow_mut();
-        stack.sockets.remove(self.handle);
-        stack.waker.wake();
+        stack.send_to_endpoints.remove(&self.handle);
+        stack.rx_datagrams.remove(&self.handle);
+        stack.send_errors.remove(&self.handle);
+        stack.tx_priority.remove(&self.handle);
+        stack.local_ports.remove(&self.handle);
+        stack.release(self.handle, peer_handle);
+    }
+}
+
+/// Drop the datagrams queued for sending on `udp`.
+pub(super) fn discard_queued(udp: &mut udp::Socket) {
+    while udp.send_queue() > 0 {
+        let len = udp.tx_dequeue(|payload| (payload.len(), payload.len()));
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+/// Drop the next `len` bytes of the receive buffer of `udp`.
+fn discard(udp: &mut udp::Socket, mut len: usize) {
+    let mut scratch = [0u8; 32];
+    while len > 0 {
+        let chunk = len.min(scratch.len());
+        match udp.recv_slice(&mut scratch[..chunk]) {
+            Ok(n) if n > 0 => len -= n,
+            _ => break,
+        }
     }
 }
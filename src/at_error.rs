@@ -0,0 +1,119 @@
+//! Typed errors reported by the module, in place of a bare `ERROR` response.
+
+use core::fmt::Write as _;
+
+/// Error reported by, or derived from the behavior of, the module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ModuleError {
+    /// The SSID is empty or longer than 32 bytes.
+    InvalidSsid,
+    /// The network rejected the credentials.
+    SecurityFailure,
+    /// The Wi-Fi link came up, but no IP address was acquired in time.
+    DhcpTimeout,
+    /// Plain `ERROR` response without further details.
+    Unspecified,
+    /// Extended numeric error code, e.g. `ERROR:7` or `+CME ERROR: 7`.
+    Code(u16),
+    /// Unrecognized error text, truncated to fit.
+    Other(heapless::String<32>),
+}
+
+impl ModuleError {
+    /// Code of `+CME ERROR: 10`, reported while the module is busy, e.g.
+    /// associating with a network.
+    pub const BUSY_CODE: u16 = 10;
+
+    /// Parse the error reported in a raw AT or EDM response, if any.
+    pub fn from_response(resp: &[u8]) -> Option<Self> {
+        let pos = resp.windows(b"ERROR".len()).position(|w| w == b"ERROR")?;
+
+        let detail = &resp[pos + b"ERROR".len()..];
+        let end = detail
+            .iter()
+            .position(|b| matches!(b, b'\r' | b'\n'))
+            .unwrap_or(detail.len());
+        let detail = core::str::from_utf8(&detail[..end])
+            .ok()?
+            .trim_start_matches(':')
+            .trim();
+
+        if detail.is_empty() {
+            return Some(Self::Unspecified);
+        }
+
+        if let Ok(code) = detail.parse() {
+            return Some(Self::Code(code));
+        }
+
+        let mut text = heapless::String::new();
+        for c in detail.chars() {
+            if text.write_char(c).is_err() {
+                break;
+            }
+        }
+        Some(Self::Other(text))
+    }
+
+    /// Whether the operation may succeed if retried as is.
+    ///
+    /// Errors caused by invalid input or configuration are fatal, and so are
+    /// codes the crate does not know the meaning of.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::DhcpTimeout | Self::Unspecified)
+    }
+}
+
+impl core::fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ModuleError::InvalidSsid => write!(f, "Invalid SSID"),
+            ModuleError::SecurityFailure => write!(f, "Security failure"),
+            ModuleError::DhcpTimeout => write!(f, "DHCP timeout"),
+            ModuleError::Unspecified => write!(f, "Unspecified error"),
+            ModuleError::Code(code) => write!(f, "Error code {}", code),
+            ModuleError::Other(text) => write!(f, "Error: {}", text),
+        }
+    }
+}
+
+impl core::error::Error for ModuleError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plain_error() {
+        assert_eq!(
+            ModuleError::from_response(b"\r\nERROR\r\n"),
+            Some(ModuleError::Unspecified)
+        );
+        assert_eq!(ModuleError::from_response(b"\r\nOK\r\n"), None);
+    }
+
+    #[test]
+    fn extended_error() {
+        assert_eq!(
+            ModuleError::from_response(b"ERROR:7\r\n"),
+            Some(ModuleError::Code(7))
+        );
+        assert_eq!(
+            ModuleError::from_response(b"\r\n+CME ERROR: 23\r\n"),
+            Some(ModuleError::Code(23))
+        );
+
+        // EDM framed response
+        let resp = [
+            0xAA, 0x00, 0x0F, 0x00, 0x45, b'E', b'R', b'R', b'O', b'R', b':', b' ', b'n', b'o',
+            b' ', b'r', b'o', b'u', b't', b'e', b'\r', b'\n', 0x55,
+        ];
+        assert_eq!(
+            ModuleError::from_response(&resp),
+            Some(ModuleError::Other(
+                heapless::String::try_from("no route").unwrap()
+            ))
+        );
+    }
+}
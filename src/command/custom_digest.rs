@@ -1,27 +1,100 @@
 use crate::command::edm::{
-    calc_payload_len,
-    types::{PayloadType, AT_COMMAND_POSITION, EDM_OVERHEAD, ENDBYTE, STARTBYTE},
+    calc_payload_len, is_final_confirmation,
+    types::{PayloadType, EDM_OVERHEAD, ENDBYTE, STARTBYTE},
 };
 use atat::{helpers::LossyStr, DigestResult, Digester, InternalError};
 
 use super::edm::types::{AUTOCONNECTMESSAGE, STARTUPMESSAGE};
 
 /// Digester for EDM context
+///
+/// Long AT responses may be split across several ATConfirmation packets.
+/// These are passed on together, once the packet holding the final result
+/// code has been received.
 #[derive(Debug, Default)]
-pub struct EdmDigester;
+pub struct EdmDigester {
+    /// Size of the ingress buffer, if known.
+    capacity: Option<usize>,
+}
 
 impl EdmDigester {
     pub fn new() -> Self {
-        Self
+        Self::default()
     }
-}
 
-impl Digester for EdmDigester {
-    fn digest<'a>(&mut self, buf: &'a [u8]) -> (DigestResult<'a>, usize) {
+    /// Digester for an ingress buffer of `capacity` bytes.
+    ///
+    /// A split response that does not fit the buffer is reported as an
+    /// `InvalidResponse` error, instead of stalling the ingress.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: Some(capacity),
+        }
+    }
+
+    /// Find the end of the response starting with the ATConfirmation packet
+    /// `buf[..edm_len]`, including any continuation packets.
+    ///
+    /// Returns `None` if more data is needed.
+    fn confirmation_end(&self, buf: &[u8], edm_len: usize) -> Option<usize> {
+        let mut end = edm_len;
+        let mut last = 0;
+
+        loop {
+            if is_final_confirmation(&buf[last..end]) {
+                return Some(end);
+            }
+
+            let next = &buf[end..];
+            if next.len() < EDM_OVERHEAD {
+                return None;
+            }
+            // Anything but a continuation packet ends the response
+            if next[0] != STARTBYTE || PayloadType::from(next[4]) != PayloadType::ATConfirmation {
+                return Some(end);
+            }
+            let next_len = calc_payload_len(next) + EDM_OVERHEAD;
+            if next.len() < next_len {
+                return None;
+            }
+            if next[next_len - 1] != ENDBYTE {
+                return Some(end);
+            }
+
+            last = end;
+            end += next_len;
+        }
+    }
+
+    /// Whether the packet header at the start of `buf` can be valid, i.e. the
+    /// reserved bits are cleared and the packet fits the ingress buffer.
+    fn is_valid_header(&self, buf: &[u8]) -> bool {
+        let edm_len = calc_payload_len(buf) + EDM_OVERHEAD;
+        buf[1] & 0xF0 == 0
+            && buf[3] == 0x00
+            && edm_len >= EDM_OVERHEAD + 2
+            && self.capacity.is_none_or(|capacity| edm_len <= capacity)
+    }
+
+    /// Skip the invalid packet at the start of `buf`, up to the next
+    /// `STARTBYTE`.
+    fn skip_packet<'a>(&self, buf: &'a [u8]) -> Step<'a> {
+        match buf[1..].iter().position(|&b| b == STARTBYTE) {
+            Some(pos) => Step::Skip(pos + 1),
+            None => {
+                warn!("Discarding {} bytes of invalid EDM data", buf.len());
+                Step::Done(DigestResult::None, buf.len())
+            }
+        }
+    }
+
+    /// Digest the data at the start of `buf`, or find the invalid data to
+    /// discard before trying again.
+    fn step<'a>(&self, buf: &'a [u8]) -> Step<'a> {
         // TODO: Handle module restart, tests and set default startupmessage in client, and optimize this!
 
         if buf.is_empty() {
-            return (DigestResult::None, 0);
+            return Step::Done(DigestResult::None, 0);
         }
 
         trace!("Digest {:?}", LossyStr(buf));
@@ -31,20 +104,22 @@ impl Digester for EdmDigester {
                 let len = i + 4;
                 trace!("Digest common at {:?}; i: {:?}", LossyStr(&buf[..len]), i);
                 if buf[..len] == *STARTUPMESSAGE {
-                    return (
+                    return Step::Done(
                         DigestResult::Urc(&buf[..STARTUPMESSAGE.len()]),
                         STARTUPMESSAGE.len(),
                     );
                 } else if len == AUTOCONNECTMESSAGE.len() || len == AUTOCONNECTMESSAGE.len() + 1 {
-                    return (DigestResult::Urc(&buf[..len]), len);
+                    return Step::Done(DigestResult::Urc(&buf[..len]), len);
                 } else {
-                    return (DigestResult::None, len);
+                    return Step::Done(DigestResult::None, len);
                 }
             }
         } else if buf.len() > STARTUPMESSAGE.len()
             && buf[buf.len() - STARTUPMESSAGE.len()..] == *STARTUPMESSAGE
+            // Leading EDM packets are digested first
+            && !buf[..buf.len() - STARTUPMESSAGE.len()].contains(&STARTBYTE)
         {
-            return (
+            return Step::Done(
                 DigestResult::Urc(&buf[buf.len() - STARTUPMESSAGE.len()..]),
                 buf.len(),
             );
@@ -52,23 +127,29 @@ impl Digester for EdmDigester {
 
         let start_pos = match buf.windows(1).position(|byte| byte[0] == STARTBYTE) {
             Some(pos) => pos,
-            None => return (DigestResult::None, 0), // handle leading error data. // TODO: handle error input without message start.
+            None => return Step::Done(DigestResult::None, 0), // handle leading error data. // TODO: handle error input without message start.
         };
 
         // Trim leading invalid data.
         if start_pos != 0 {
-            return (DigestResult::None, start_pos);
+            return Step::Skip(start_pos);
         }
 
         // Verify payload length and end byte position
         if buf.len() < EDM_OVERHEAD {
-            return (DigestResult::None, 0);
+            return Step::Done(DigestResult::None, 0);
+        }
+        if !self.is_valid_header(buf) {
+            return self.skip_packet(buf);
         }
         let payload_len = calc_payload_len(buf);
 
         let edm_len = payload_len + EDM_OVERHEAD;
-        if buf.len() < edm_len || buf[edm_len - 1] != ENDBYTE {
-            return (DigestResult::None, 0);
+        if buf.len() < edm_len {
+            return Step::Done(DigestResult::None, 0);
+        }
+        if buf[edm_len - 1] != ENDBYTE {
+            return self.skip_packet(buf);
         }
 
         // Debug statement for trace properly
@@ -77,21 +158,22 @@ impl Digester for EdmDigester {
         }
 
         // Filter message by payload
-        match PayloadType::from(buf[4]) {
-            PayloadType::ATConfirmation => {
-                let resp = &buf[..edm_len];
-                let return_val = if resp.windows(b"ERROR".len()).nth(AT_COMMAND_POSITION)
-                    == Some(b"ERROR")
-                    || resp.windows(b"ERROR".len()).nth(AT_COMMAND_POSITION + 2) == Some(b"ERROR")
-                {
-                    DigestResult::Response(Err(InternalError::InvalidResponse))
-                } else {
-                    DigestResult::Response(Ok(resp))
-                };
-                (return_val, edm_len)
-            }
-            PayloadType::StartEvent => (DigestResult::Response(Ok(&buf[..edm_len])), edm_len),
-            PayloadType::ATEvent
+        let (result, len) = match PayloadType::from(buf[4]) {
+            // Error confirmations are passed on as is, so the error text can
+            // be recovered by `ModuleError::from_response`.
+            PayloadType::ATConfirmation => match self.confirmation_end(buf, edm_len) {
+                Some(end) => (DigestResult::Response(Ok(&buf[..end])), end),
+                None if self.capacity.is_some_and(|capacity| buf.len() >= capacity) => {
+                    warn!("AT response does not fit the ingress buffer");
+                    (
+                        DigestResult::Response(Err(InternalError::InvalidResponse)),
+                        buf.len(),
+                    )
+                }
+                None => (DigestResult::None, 0),
+            },
+            PayloadType::StartEvent
+            | PayloadType::ATEvent
             | PayloadType::ConnectEvent
             | PayloadType::DataEvent
             | PayloadType::DisconnectEvent => {
@@ -102,10 +184,189 @@ impl Digester for EdmDigester {
                 // Wrong/Unsupported packet, thrown away.
                 (DigestResult::None, edm_len)
             }
+        };
+        Step::Done(result, len)
+    }
+}
+
+/// Outcome of [`EdmDigester::step`].
+enum Step<'a> {
+    /// Digested the given number of bytes.
+    Done(DigestResult<'a>, usize),
+    /// Discard the given number of bytes of invalid data, and digest the
+    /// remainder.
+    Skip(usize),
+}
+
+impl Digester for EdmDigester {
+    fn digest<'a>(&mut self, buf: &'a [u8]) -> (DigestResult<'a>, usize) {
+        // Resynchronize after a framing error or leading invalid data
+        let mut skipped = 0;
+        loop {
+            match self.step(&buf[skipped..]) {
+                Step::Done(result, len) => return (result, skipped + len),
+                Step::Skip(len) => {
+                    warn!("Discarding {} bytes of invalid EDM data", len);
+                    skipped += len;
+                }
+            }
         }
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+    use heapless::Vec;
+
+    fn packet(payload_type: PayloadType, data: &[u8]) -> Vec<u8, 128> {
+        let payload_len = data.len() + 2;
+        let mut packet = Vec::from_slice(&[
+            STARTBYTE,
+            (payload_len >> 8) as u8,
+            payload_len as u8,
+            0x00,
+            payload_type as u8,
+        ])
+        .unwrap();
+        packet.extend_from_slice(data).unwrap();
+        packet.push(ENDBYTE).unwrap();
+        packet
+    }
+
+    #[test]
+    fn split_confirmation() {
+        let mut digester = EdmDigester::new();
+        let mut buf = packet(
+            PayloadType::ATConfirmation,
+            b"\r\n+UWSCAN:7C8BCA0F12A0,1,\"ap\"\r\n",
+        );
+
+        // Wait for the final result code
+        assert_eq!(digester.digest(&buf), (DigestResult::None, 0));
+
+        buf.extend_from_slice(&packet(PayloadType::ATConfirmation, b"\r\nOK\r\n"))
+            .unwrap();
+        assert_eq!(
+            digester.digest(&buf),
+            (DigestResult::Response(Ok(&buf[..])), buf.len())
+        );
+    }
+
+    #[test]
+    fn confirmation_followed_by_event() {
+        let mut digester = EdmDigester::new();
+        let confirmation = packet(PayloadType::ATConfirmation, b"+UMSTAT:1,100\r\n");
+        let mut buf = confirmation.clone();
+        buf.extend_from_slice(&packet(PayloadType::ATEvent, b"\r\n+UUNU:0\r\n"))
+            .unwrap();
+
+        assert_eq!(
+            digester.digest(&buf),
+            (
+                DigestResult::Response(Ok(&confirmation[..])),
+                confirmation.len()
+            )
+        );
+        assert_eq!(
+            digester.digest(&buf[confirmation.len()..]).0,
+            DigestResult::Urc(&buf[confirmation.len()..])
+        );
+    }
+
+    #[test]
+    fn startup_event_around_startup_urc() {
+        let mut digester = EdmDigester::new();
+        let start_event = packet(PayloadType::StartEvent, &[]);
+
+        // `+STARTUP` after a reboot, then the EDM startup event
+        let mut buf = Vec::<u8, 128>::from_slice(STARTUPMESSAGE).unwrap();
+        buf.extend_from_slice(&start_event).unwrap();
+        assert_eq!(
+            digester.digest(&buf),
+            (DigestResult::Urc(STARTUPMESSAGE), STARTUPMESSAGE.len())
+        );
+        assert_eq!(
+            digester.digest(&buf[STARTUPMESSAGE.len()..]),
+            (DigestResult::Urc(&start_event[..]), start_event.len())
+        );
+
+        // A `+STARTUP` behind the startup event does not swallow it
+        let mut buf = start_event.clone();
+        buf.extend_from_slice(STARTUPMESSAGE).unwrap();
+        assert_eq!(
+            digester.digest(&buf),
+            (DigestResult::Urc(&start_event[..]), start_event.len())
+        );
+        assert_eq!(
+            digester.digest(&buf[start_event.len()..]),
+            (DigestResult::Urc(STARTUPMESSAGE), STARTUPMESSAGE.len())
+        );
+    }
+
+    #[test]
+    fn resync_after_garbage() {
+        let mut digester = EdmDigester::new();
+        let frame = packet(PayloadType::ATEvent, b"\r\n+UUNU:0\r\n");
+
+        // Garbage ending in a stray start byte
+        let mut buf = Vec::<u8, 128>::from_slice(&[0x01, 0x02, STARTBYTE]).unwrap();
+        buf.extend_from_slice(&frame).unwrap();
+        assert_eq!(
+            digester.digest(&buf),
+            (DigestResult::Urc(&frame[..]), buf.len())
+        );
+
+        // Truncated packet, whose end byte is missing
+        let mut buf = Vec::<u8, 128>::from_slice(&frame[..frame.len() - 3]).unwrap();
+        buf.extend_from_slice(&frame).unwrap();
+        assert_eq!(
+            digester.digest(&buf),
+            (DigestResult::Urc(&frame[..]), buf.len())
+        );
+
+        // Packet length exceeding the ingress buffer
+        let mut digester = EdmDigester::with_capacity(64);
+        let mut buf = Vec::<u8, 128>::from_slice(&[STARTBYTE, 0x0F, 0xFF, 0x00]).unwrap();
+        buf.extend_from_slice(&frame).unwrap();
+        assert_eq!(
+            digester.digest(&buf),
+            (DigestResult::Urc(&frame[..]), buf.len())
+        );
+    }
+
+    #[test]
+    fn resync_after_many_invalid_packets() {
+        let mut digester = EdmDigester::new();
+        let frame = packet(PayloadType::ATEvent, b"\r\n+UUNU:0\r\n");
+
+        // Each start byte opens a packet with reserved bits set
+        let mut buf = std::vec::Vec::new();
+        for _ in 0..100_000 {
+            buf.extend_from_slice(&[STARTBYTE, 0xF0]);
+        }
+        buf.extend_from_slice(&frame);
+        assert_eq!(
+            digester.digest(&buf),
+            (DigestResult::Urc(&frame[..]), buf.len())
+        );
+    }
+
+    #[test]
+    fn split_confirmation_overflow() {
+        let buf = packet(PayloadType::ATConfirmation, b"\r\n+UWSCAN:7C8BCA0F12A0\r\n");
+
+        let mut digester = EdmDigester::with_capacity(buf.len());
+        assert_eq!(
+            digester.digest(&buf),
+            (
+                DigestResult::Response(Err(InternalError::InvalidResponse)),
+                buf.len()
+            )
+        );
+    }
+}
+
 // #[cfg(test)]
 // mod test {
 //     use super::*;
@@ -3,6 +3,8 @@ pub mod responses;
 pub mod types;
 pub mod urc;
 
+use core::fmt::Write as _;
+
 use atat::atat_derive::AtatCmd;
 use heapless::String;
 use responses::*;
@@ -97,6 +99,341 @@ pub struct ServerConfigurationUrl {
     pub server_config: String<128>,
 }
 
+/// 5.6 Server configuration +UDSC
+///
+/// Writes a server configuration built with [`ServerConfigBuilder`]. The
+/// meaning of the options differs per server type, so the builder only offers
+/// those of the type being configured. The options follow the +UDSC
+/// description of the u-connectXpress AT commands manual, in the same order
+/// as [`ServerType`].
+#[derive(Clone)]
+pub struct ConfigureServer {
+    id: u8,
+    server: Server,
+}
+
+#[derive(Clone)]
+enum Server {
+    Disabled,
+    Tcp(TcpServer),
+    Udp(UdpServer),
+    Spp(SppServer),
+    Atp(AtpServer),
+}
+
+impl ConfigureServer {
+    /// The server ID to configure.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Whether the command disables the server.
+    pub fn is_disabled(&self) -> bool {
+        matches!(self.server, Server::Disabled)
+    }
+}
+
+impl atat::AtatCmd for ConfigureServer {
+    type Response = NoResponse;
+
+    const MAX_LEN: usize = 96;
+    const MAX_TIMEOUT_MS: u32 = 1000;
+
+    fn write(&self, buf: &mut [u8]) -> usize {
+        let mut cmd = String::<96>::new();
+        // Every configuration fits in MAX_LEN
+        let _ = match &self.server {
+            Server::Disabled => write!(cmd, "AT+UDSC={},0", self.id),
+            Server::Tcp(tcp) => tcp.write(self.id, &mut cmd),
+            Server::Udp(udp) => udp.write(self.id, &mut cmd),
+            Server::Spp(spp) => spp.write(self.id, &mut cmd),
+            Server::Atp(atp) => atp.write(self.id, &mut cmd),
+        };
+        let _ = cmd.push_str("\r\n");
+
+        buf[..cmd.len()].copy_from_slice(cmd.as_bytes());
+        cmd.len()
+    }
+
+    fn parse(
+        &self,
+        res: Result<&[u8], atat::InternalError>,
+    ) -> core::result::Result<Self::Response, atat::Error> {
+        match res {
+            Ok(resp) => {
+                atat::serde_at::from_slice::<NoResponse>(resp).map_err(|_e| atat::Error::Parse)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Builder of a [`ConfigureServer`] command for server `id` (0-6).
+///
+/// ```ignore
+/// let cmd = ServerConfigBuilder::new(0).tcp(5003).immediate_flush(true).build();
+/// ```
+pub struct ServerConfigBuilder {
+    id: u8,
+}
+
+impl ServerConfigBuilder {
+    pub fn new(id: u8) -> Self {
+        Self { id }
+    }
+
+    /// Disable the server.
+    pub fn disabled(self) -> ConfigureServer {
+        ConfigureServer {
+            id: self.id,
+            server: Server::Disabled,
+        }
+    }
+
+    /// TCP server listening on `port`.
+    pub fn tcp(self, port: u16) -> TcpServerBuilder {
+        TcpServerBuilder {
+            id: self.id,
+            server: TcpServer {
+                port,
+                immediate_flush: false,
+                tls: None,
+            },
+        }
+    }
+
+    /// UDP server listening on `port`.
+    pub fn udp(self, port: u16) -> UdpServerBuilder {
+        UdpServerBuilder {
+            id: self.id,
+            server: UdpServer {
+                port,
+                behaviour: UDPBehaviour::NoConnect,
+                ip_version: IPVersion::IPv4,
+            },
+        }
+    }
+
+    /// Bluetooth SPP server.
+    pub fn spp(self) -> SppServerBuilder {
+        SppServerBuilder {
+            id: self.id,
+            server: SppServer {
+                service_name: String::new(),
+                uuid: None,
+            },
+        }
+    }
+
+    /// Server accepting AT commands from clients connecting over `interface`.
+    pub fn atp(self, interface: Interface) -> AtpServerBuilder {
+        AtpServerBuilder {
+            id: self.id,
+            server: AtpServer {
+                interface,
+                port: None,
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TcpServer {
+    port: u16,
+    immediate_flush: bool,
+    tls: Option<ServerCredentials>,
+}
+
+impl TcpServer {
+    /// `<port>[,<immediate_flush>[,<tls>,<ca>,<cert>,<key>]]`, where `<tls>`
+    /// is 1 for a TLS server using the named certificates and private key.
+    fn write<const N: usize>(&self, id: u8, cmd: &mut String<N>) -> core::fmt::Result {
+        write!(cmd, "AT+UDSC={},1,{}", id, self.port)?;
+        if self.immediate_flush || self.tls.is_some() {
+            write!(cmd, ",{}", u8::from(self.immediate_flush))?;
+        }
+        if let Some(tls) = &self.tls {
+            write!(
+                cmd,
+                ",1,\"{}\",\"{}\",\"{}\"",
+                tls.ca_cert_name, tls.cert_name, tls.key_name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Options of a TCP server, see [`ServerConfigBuilder::tcp`].
+pub struct TcpServerBuilder {
+    id: u8,
+    server: TcpServer,
+}
+
+impl TcpServerBuilder {
+    /// Send data to the peer as soon as it is received from the host, instead
+    /// of gathering it into larger packets. Disabled by default.
+    pub fn immediate_flush(mut self, enable: bool) -> Self {
+        self.server.immediate_flush = enable;
+        self
+    }
+
+    /// Secure the connections of peers with TLS.
+    pub fn tls(mut self, creds: ServerCredentials) -> Self {
+        self.server.tls = Some(creds);
+        self
+    }
+
+    pub fn build(self) -> ConfigureServer {
+        ConfigureServer {
+            id: self.id,
+            server: Server::Tcp(self.server),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct UdpServer {
+    port: u16,
+    behaviour: UDPBehaviour,
+    ip_version: IPVersion,
+}
+
+impl UdpServer {
+    fn write<const N: usize>(&self, id: u8, cmd: &mut String<N>) -> core::fmt::Result {
+        write!(cmd, "AT+UDSC={},2,{}", id, self.port)?;
+        let ipv6 = self.ip_version == IPVersion::IPv6;
+        if self.behaviour != UDPBehaviour::NoConnect || ipv6 {
+            write!(cmd, ",{}", self.behaviour.clone() as u8)?;
+        }
+        if ipv6 {
+            write!(cmd, ",{}", IPVersion::IPv6 as u8)?;
+        }
+        Ok(())
+    }
+}
+
+/// Options of a UDP server, see [`ServerConfigBuilder::udp`].
+pub struct UdpServerBuilder {
+    id: u8,
+    server: UdpServer,
+}
+
+impl UdpServerBuilder {
+    /// How datagrams from new sources are delivered. Defaults to
+    /// [`UDPBehaviour::NoConnect`].
+    pub fn behaviour(mut self, behaviour: UDPBehaviour) -> Self {
+        self.server.behaviour = behaviour;
+        self
+    }
+
+    /// IP version of the server. Defaults to [`IPVersion::IPv4`].
+    pub fn ip_version(mut self, ip_version: IPVersion) -> Self {
+        self.server.ip_version = ip_version;
+        self
+    }
+
+    pub fn build(self) -> ConfigureServer {
+        ConfigureServer {
+            id: self.id,
+            server: Server::Udp(self.server),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SppServer {
+    service_name: String<15>,
+    uuid: Option<String<37>>,
+}
+
+impl SppServer {
+    /// `3[,<service_name>]` for an SPP server, and
+    /// `5,<service_name>,<uuid>` for a custom service.
+    fn write<const N: usize>(&self, id: u8, cmd: &mut String<N>) -> core::fmt::Result {
+        match &self.uuid {
+            None => {
+                write!(cmd, "AT+UDSC={},3", id)?;
+                if !self.service_name.is_empty() {
+                    write!(cmd, ",\"{}\"", self.service_name)?;
+                }
+                Ok(())
+            }
+            Some(uuid) => write!(
+                cmd,
+                "AT+UDSC={},5,\"{}\",\"{}\"",
+                id, self.service_name, uuid
+            ),
+        }
+    }
+}
+
+/// Options of an SPP server, see [`ServerConfigBuilder::spp`].
+pub struct SppServerBuilder {
+    id: u8,
+    server: SppServer,
+}
+
+impl SppServerBuilder {
+    /// Name the service is announced under. The module default is used when
+    /// empty.
+    pub fn service_name(mut self, name: String<15>) -> Self {
+        self.server.service_name = name;
+        self
+    }
+
+    /// Announce the server under a custom service `uuid` instead of the SPP
+    /// one.
+    pub fn uuid(mut self, uuid: String<37>) -> Self {
+        self.server.uuid = Some(uuid);
+        self
+    }
+
+    pub fn build(self) -> ConfigureServer {
+        ConfigureServer {
+            id: self.id,
+            server: Server::Spp(self.server),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AtpServer {
+    interface: Interface,
+    port: Option<u16>,
+}
+
+impl AtpServer {
+    /// `8,<interface>[,<port>]`
+    fn write<const N: usize>(&self, id: u8, cmd: &mut String<N>) -> core::fmt::Result {
+        write!(cmd, "AT+UDSC={},8,{}", id, self.interface.clone() as u8)?;
+        if let Some(port) = self.port {
+            write!(cmd, ",{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+/// Options of an AT server, see [`ServerConfigBuilder::atp`].
+pub struct AtpServerBuilder {
+    id: u8,
+    server: AtpServer,
+}
+
+impl AtpServerBuilder {
+    /// Port to listen on, for the IP interfaces.
+    pub fn port(mut self, port: u16) -> Self {
+        self.server.port = Some(port);
+        self
+    }
+
+    pub fn build(self) -> ConfigureServer {
+        ConfigureServer {
+            id: self.id,
+            server: Server::Atp(self.server),
+        }
+    }
+}
+
 /// 5.7 Server flags +UDSF
 ///
 /// Bit 0, remote configuration: When the remote configuration bit is set, the module will look for the escape
@@ -162,9 +499,142 @@ pub struct SetBind {
 /// of a successful bind command.
 #[derive(Clone, AtatCmd)]
 #[at_cmd("+UDBINDC", NoResponse, timeout_ms = 1000)]
-pub struct SoftwareUpdate {
+pub struct BindToChannel {
     #[at_arg(position = 0)]
     pub stream_id: u8,
     #[at_arg(position = 1)]
     pub channel_id: u8,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atat::AtatCmd;
+
+    fn written(cmd: ConfigureServer) -> std::string::String {
+        let mut buf = [0u8; ConfigureServer::MAX_LEN];
+        let len = cmd.write(&mut buf);
+        std::string::String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn server_configuration() {
+        assert_eq!(
+            written(ServerConfigBuilder::new(0).disabled()),
+            "AT+UDSC=0,0\r\n"
+        );
+
+        // TCP server on port 5003, options left at their defaults
+        assert_eq!(
+            written(ServerConfigBuilder::new(1).tcp(5003).build()),
+            "AT+UDSC=1,1,5003\r\n"
+        );
+        assert_eq!(
+            written(
+                ServerConfigBuilder::new(1)
+                    .tcp(5003)
+                    .immediate_flush(true)
+                    .build()
+            ),
+            "AT+UDSC=1,1,5003,1\r\n"
+        );
+        let creds = ServerCredentials {
+            ca_cert_name: String::try_from("ca").unwrap(),
+            cert_name: String::try_from("cert").unwrap(),
+            key_name: String::try_from("key").unwrap(),
+        };
+        assert_eq!(
+            written(ServerConfigBuilder::new(1).tcp(443).tls(creds).build()),
+            "AT+UDSC=1,1,443,0,1,\"ca\",\"cert\",\"key\"\r\n"
+        );
+
+        // UDP server on port 5003
+        assert_eq!(
+            written(ServerConfigBuilder::new(2).udp(5003).build()),
+            "AT+UDSC=2,2,5003\r\n"
+        );
+        assert_eq!(
+            written(
+                ServerConfigBuilder::new(2)
+                    .udp(5003)
+                    .behaviour(UDPBehaviour::AutoConnect)
+                    .build()
+            ),
+            "AT+UDSC=2,2,5003,1\r\n"
+        );
+        assert_eq!(
+            written(
+                ServerConfigBuilder::new(2)
+                    .udp(5003)
+                    .ip_version(IPVersion::IPv6)
+                    .build()
+            ),
+            "AT+UDSC=2,2,5003,0,1\r\n"
+        );
+
+        assert_eq!(
+            written(ServerConfigBuilder::new(3).spp().build()),
+            "AT+UDSC=3,3\r\n"
+        );
+        assert_eq!(
+            written(
+                ServerConfigBuilder::new(3)
+                    .spp()
+                    .service_name(String::try_from("Serial").unwrap())
+                    .build()
+            ),
+            "AT+UDSC=3,3,\"Serial\"\r\n"
+        );
+        let uuid = String::try_from("00001101-0000-1000-8000-00805f9b34fb").unwrap();
+        assert_eq!(
+            written(
+                ServerConfigBuilder::new(3)
+                    .spp()
+                    .service_name(String::try_from("Custom").unwrap())
+                    .uuid(uuid)
+                    .build()
+            ),
+            "AT+UDSC=3,5,\"Custom\",\"00001101-0000-1000-8000-00805f9b34fb\"\r\n"
+        );
+
+        assert_eq!(
+            written(
+                ServerConfigBuilder::new(4)
+                    .atp(Interface::TCP)
+                    .port(5001)
+                    .build()
+            ),
+            "AT+UDSC=4,8,1,5001\r\n"
+        );
+        assert_eq!(
+            written(ServerConfigBuilder::new(4).atp(Interface::SPS).build()),
+            "AT+UDSC=4,8,6\r\n"
+        );
+    }
+
+    /// Examples of the +UDSC description in the u-connectXpress AT commands
+    /// manual.
+    #[test]
+    fn manual_examples() {
+        // Start a TCP server on port 5003
+        assert_eq!(
+            written(ServerConfigBuilder::new(1).tcp(5003).build()),
+            "AT+UDSC=1,1,5003\r\n"
+        );
+        // Start a UDP server on port 5003
+        assert_eq!(
+            written(ServerConfigBuilder::new(2).udp(5003).build()),
+            "AT+UDSC=2,2,5003\r\n"
+        );
+        // Start an SPP server
+        assert_eq!(
+            written(ServerConfigBuilder::new(0).spp().build()),
+            "AT+UDSC=0,3\r\n"
+        );
+        // Disable server 0
+        assert_eq!(
+            written(ServerConfigBuilder::new(0).disabled()),
+            "AT+UDSC=0,0\r\n"
+        );
+    }
+}
@@ -66,6 +66,17 @@ pub enum ServerType {
     ATP(Interface, Option<u16>),
 }
 
+/// Names of the certificates and key securing a TCP server with TLS, as
+/// imported with [`PrepareSecurityDataImport`](crate::command::security::PrepareSecurityDataImport).
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServerCredentials {
+    /// CA certificate verifying the clients, empty to not verify them.
+    pub ca_cert_name: String<16>,
+    pub cert_name: String<16>,
+    pub key_name: String<16>,
+}
+
 #[derive(Clone, PartialEq, AtatEnum)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
@@ -36,3 +36,22 @@ pub struct PeerDisconnected {
     #[at_arg(position = 0)]
     pub handle: ublox_sockets::PeerHandle,
 }
+
+/// Data flow stopped on an EDM channel +UUFLOWS
+///
+/// The transmit buffer of the module is full, and no more data must be sent on
+/// the channel until +UUFLOWR is received.
+#[cfg(feature = "internal-network-stack")]
+#[derive(Debug, PartialEq, Clone, atat::atat_derive::AtatResp)]
+pub struct DataFlowStopped {
+    #[at_arg(position = 0)]
+    pub channel_id: u8,
+}
+
+/// Data flow resumed on an EDM channel +UUFLOWR
+#[cfg(feature = "internal-network-stack")]
+#[derive(Debug, PartialEq, Clone, atat::atat_derive::AtatResp)]
+pub struct DataFlowResumed {
+    #[at_arg(position = 0)]
+    pub channel_id: u8,
+}
@@ -16,6 +16,78 @@ use ublox_sockets::ChannelId;
 pub(crate) fn calc_payload_len(resp: &[u8]) -> usize {
     (u16::from_be_bytes(resp[1..3].try_into().unwrap()) & EDM_FULL_SIZE_FILTER) as usize
 }
+
+/// Whether an ATConfirmation packet holds a final result code, i.e. ends the
+/// response to the pending command.
+///
+/// Result codes are only matched as whole lines, so information text such as
+/// an SSID containing `ERROR` does not end the response. Empty confirmations
+/// carry no response text, and are final as well.
+pub(crate) fn is_final_confirmation(packet: &[u8]) -> bool {
+    let at_resp = &packet[AT_COMMAND_POSITION..packet.len() - 1];
+    at_resp.is_empty()
+        || at_resp
+            .split(|&b| b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .any(|line| line == b"OK" || line == b"ERROR" || line.starts_with(b"+CME ERROR:"))
+}
+
+/// Parse the numeric code of a `+CME ERROR:` response, given the text
+/// following the colon.
+fn parse_error_code(detail: &[u8]) -> Option<u16> {
+    let end = detail
+        .iter()
+        .position(|b| matches!(b, b'\r' | b'\n' | ENDBYTE))
+        .unwrap_or(detail.len());
+    core::str::from_utf8(&detail[..end])
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Join the AT responses of consecutive ATConfirmation packets, as passed on
+/// by the [`EdmDigester`](super::custom_digest::EdmDigester), into a single
+/// packet.
+///
+/// Returns `None` if `resp` holds a single packet, or the packets are
+/// malformed, in which case `resp` should be parsed as is.
+pub(crate) fn join_confirmations<const N: usize>(resp: &[u8]) -> Option<heapless::Vec<u8, N>> {
+    if resp.len() < PAYLOAD_OVERHEAD || resp.len() == calc_payload_len(resp) + EDM_OVERHEAD {
+        return None;
+    }
+
+    let mut joined = heapless::Vec::<u8, N>::new();
+    joined
+        .extend_from_slice(&resp[..AT_COMMAND_POSITION])
+        .ok()?;
+
+    let mut rest = resp;
+    while !rest.is_empty() {
+        if rest.len() < PAYLOAD_OVERHEAD {
+            return None;
+        }
+        let len = calc_payload_len(rest) + EDM_OVERHEAD;
+        if rest.len() < len || rest[4] != PayloadType::ATConfirmation as u8 {
+            return None;
+        }
+        joined
+            .extend_from_slice(&rest[AT_COMMAND_POSITION..len - 1])
+            .ok()?;
+        rest = &rest[len..];
+    }
+    joined.push(ENDBYTE).ok()?;
+
+    let payload_len = joined.len() - EDM_OVERHEAD;
+    if payload_len > EDM_FULL_SIZE_FILTER as usize {
+        return None;
+    }
+    joined[1] = (payload_len >> 8) as u8 & EDM_SIZE_FILTER;
+    joined[2] = (payload_len & 0xff) as u8;
+
+    Some(joined)
+}
+
 /// EDM wrapper for AT-Commands
 // Note:
 // The AT+UMRS command to change serial settings does not work exactly the same as in command
@@ -69,6 +141,19 @@ impl<T: AtatCmd> atat::AtatCmd for EdmAtCmdWrapper<T> {
                 return Err(atat::InternalError::InvalidResponse);
             }
 
+            // Error confirmation, see `ModuleError::from_response` for details
+            let at_resp = &resp[AT_COMMAND_POSITION..];
+            let at_resp = at_resp.strip_prefix(b"\r\n").unwrap_or(at_resp);
+            if at_resp.starts_with(b"ERROR") {
+                return Err(atat::InternalError::InvalidResponse);
+            }
+            if let Some(detail) = at_resp.strip_prefix(b"+CME ERROR:") {
+                return Err(match parse_error_code(detail) {
+                    Some(code) => atat::InternalError::CmeError(atat::CmeError::from(code)),
+                    None => atat::InternalError::InvalidResponse,
+                });
+            }
+
             // Received OK response code in EDM response?
             match resp
                 .windows(b"\r\nOK".len())
@@ -86,6 +171,10 @@ impl<T: AtatCmd> atat::AtatCmd for EdmAtCmdWrapper<T> {
     }
 }
 
+/// Bytes added around the data of an [`EdmDataCommand`]: the packet header,
+/// payload type, channel and end byte.
+pub(crate) const DATA_COMMAND_OVERHEAD: usize = 7;
+
 #[derive(Debug, Clone)]
 pub struct EdmDataCommand<'a> {
     pub channel: ChannelId,
@@ -95,7 +184,7 @@ pub struct EdmDataCommand<'a> {
 impl<'a> atat::AtatCmd for EdmDataCommand<'a> {
     type Response = NoResponse;
 
-    const MAX_LEN: usize = DATA_PACKAGE_SIZE + 7;
+    const MAX_LEN: usize = DATA_PACKAGE_SIZE + DATA_COMMAND_OVERHEAD;
 
     const EXPECTS_RESPONSE_CODE: bool = false;
 
@@ -153,6 +242,10 @@ impl atat::AtatCmd for EdmResendConnectEventsCommand {
     }
 }
 
+/// Switch to extended data mode.
+///
+/// The module confirms the switch with an EDM startup event, received as
+/// [`EdmEvent::StartUp`](urc::EdmEvent::StartUp), rather than a response.
 #[derive(Debug, Clone)]
 pub struct SwitchToEdmCommand;
 
@@ -163,6 +256,8 @@ impl atat::AtatCmd for SwitchToEdmCommand {
 
     const MAX_TIMEOUT_MS: u32 = 2000;
 
+    const EXPECTS_RESPONSE_CODE: bool = false;
+
     fn write(&self, buf: &mut [u8]) -> usize {
         ChangeMode {
             mode: data_mode::types::Mode::ExtendedDataMode,
@@ -263,6 +358,115 @@ mod test {
         assert_eq!(parse.parse(Ok(response)), Ok(correct_response));
     }
 
+    #[test]
+    fn parse_error_confirmation() {
+        let parse = EdmAtCmdWrapper(AT);
+        // AT-response: "ERROR\r\n"
+        let response = &[
+            0xAA, 0x00, 0x09, 0x00, 0x45, 0x45, 0x52, 0x52, 0x4f, 0x52, 0x0D, 0x0a, 0x55,
+        ];
+        assert_eq!(parse.parse(Ok(response)), Err(Error::InvalidResponse));
+        assert_eq!(
+            crate::at_error::ModuleError::from_response(response),
+            Some(crate::at_error::ModuleError::Unspecified)
+        );
+    }
+
+    #[test]
+    fn parse_cme_error_confirmation() {
+        let parse = EdmAtCmdWrapper(AT);
+
+        assert_eq!(
+            parse.parse(Ok(&confirmation(b"\r\n+CME ERROR: 3\r\n"))),
+            Err(Error::CmeError(atat::CmeError::from(3)))
+        );
+        assert_eq!(
+            parse.parse(Ok(&confirmation(b"+CME ERROR:10\r\n"))),
+            Err(Error::CmeError(atat::CmeError::from(10)))
+        );
+        assert_eq!(
+            parse.parse(Ok(&confirmation(b"\r\nERROR\r\n"))),
+            Err(Error::InvalidResponse)
+        );
+        // The module error is still recovered from the raw response
+        assert_eq!(
+            crate::at_error::ModuleError::from_response(&confirmation(b"\r\n+CME ERROR: 3\r\n")),
+            Some(crate::at_error::ModuleError::Code(3))
+        );
+    }
+
+    fn confirmation(at_resp: &[u8]) -> Vec<u8, 256> {
+        let payload_len = at_resp.len() + 2;
+        let mut packet = Vec::from_slice(&[
+            STARTBYTE,
+            (payload_len >> 8) as u8,
+            payload_len as u8,
+            0x00,
+            PayloadType::ATConfirmation as u8,
+        ])
+        .unwrap();
+        packet.extend_from_slice(at_resp).unwrap();
+        packet.push(ENDBYTE).unwrap();
+        packet
+    }
+
+    #[test]
+    fn join_split_scan_response() {
+        let first = b"\r\n+UWSCAN:7C8BCA0F12A0,1,\"ap-one\",6,-48,18,8,8\r\n";
+        let second = b"+UWSCAN:7C8BCA0F12A1,1,\"ap-two\",11,-71,18,8,8\r\nOK\r\n";
+
+        let mut resp = confirmation(first);
+        assert!(!is_final_confirmation(&resp));
+        resp.extend_from_slice(&confirmation(second)).unwrap();
+
+        let mut at_resp = Vec::<u8, 128>::from_slice(first).unwrap();
+        at_resp.extend_from_slice(second).unwrap();
+        assert_eq!(
+            join_confirmations::<256>(&resp).unwrap(),
+            confirmation(&at_resp)
+        );
+    }
+
+    #[test]
+    fn result_code_in_information_text() {
+        let scan = b"\r\n+UWSCAN:7C8BCA0F12A0,1,\"ERROR-net\",6,-48,18,8,8\r\n";
+        assert!(!is_final_confirmation(&confirmation(scan)));
+        let scan = b"+UWSCAN:7C8BCA0F12A0,1,\"OK\",6,-48,18,8,8\r\n";
+        assert!(!is_final_confirmation(&confirmation(scan)));
+
+        assert!(is_final_confirmation(&confirmation(b"OK\r\n")));
+        assert!(is_final_confirmation(&confirmation(b"\r\nERROR\r\n")));
+        assert!(is_final_confirmation(&confirmation(
+            b"\r\n+CME ERROR: 3\r\n"
+        )));
+        assert!(is_final_confirmation(&confirmation(b"")));
+    }
+
+    #[test]
+    fn parse_ok_in_second_confirmation() {
+        let parse = EdmAtCmdWrapper(SystemStatus {
+            status_id: StatusID::SavedStatus,
+        });
+
+        let mut resp = confirmation(b"+UMSTAT:1,100\r\n");
+        assert!(!is_final_confirmation(&resp));
+        let last = confirmation(b"\r\nOK\r\n");
+        assert!(is_final_confirmation(&last));
+        resp.extend_from_slice(&last).unwrap();
+
+        let joined = join_confirmations::<256>(&resp).unwrap();
+        assert_eq!(
+            parse.parse(Ok(&joined)),
+            Ok(SystemStatusResponse {
+                status_id: StatusID::SavedStatus,
+                status_val: 100,
+            })
+        );
+
+        // A single packet is parsed as is
+        assert_eq!(join_confirmations::<256>(&last), None);
+    }
+
     #[test]
     fn parse_wrong_at_responses() {
         let parse = EdmAtCmdWrapper(AT);
@@ -1,6 +1,7 @@
 use super::calc_payload_len;
 use super::types::*;
 use super::Urc;
+use crate::command::data_mode::urc::{DataFlowResumed, DataFlowStopped};
 use atat::helpers::LossyStr;
 use atat::AtatUrc;
 use core::net::{Ipv4Addr, Ipv6Addr};
@@ -16,8 +17,13 @@ pub enum EdmEvent {
     /// Disconnect wrapping Channel Id
     DisconnectEvent(ChannelId),
     DataEvent(DataEvent),
+    /// Data flow on a channel was paused or resumed by the module.
+    FlowControl {
+        channel_id: ChannelId,
+        paused: bool,
+    },
     ATEvent(Urc),
-    // TODO: Handle module restart. Especially to Digest
+    /// The module entered extended data mode.
     StartUp,
 }
 
@@ -62,7 +68,7 @@ impl AtatUrc for EdmEvent {
             .position(|window| window == STARTUPMESSAGE)
             == Some(0)
         {
-            return EdmEvent::StartUp.into();
+            return EdmEvent::ATEvent(Urc::StartUp).into();
         }
 
         if resp.len() < PAYLOAD_OVERHEAD
@@ -84,8 +90,18 @@ impl AtatUrc for EdmEvent {
                 if let Some(i) = urc.iter().position(|x| !x.is_ascii_whitespace()) {
                     urc = &urc[i..];
                 };
-                let cmd = Urc::parse(urc)?;
-                EdmEvent::ATEvent(cmd).into()
+                match Urc::parse(urc)? {
+                    Urc::DataFlowStopped(DataFlowStopped { channel_id }) => EdmEvent::FlowControl {
+                        channel_id: ChannelId(channel_id),
+                        paused: true,
+                    },
+                    Urc::DataFlowResumed(DataFlowResumed { channel_id }) => EdmEvent::FlowControl {
+                        channel_id: ChannelId(channel_id),
+                        paused: false,
+                    },
+                    cmd => EdmEvent::ATEvent(cmd),
+                }
+                .into()
             }
 
             PayloadType::ConnectEvent => {
@@ -163,7 +179,12 @@ impl AtatUrc for EdmEvent {
                     .map(EdmEvent::DataEvent)
             }
 
-            PayloadType::StartEvent => EdmEvent::StartUp.into(),
+            PayloadType::StartEvent => {
+                if payload_len != 2 {
+                    return None;
+                }
+                EdmEvent::StartUp.into()
+            }
 
             _ => {
                 error!("[Parse URC Error] {:?}", LossyStr(resp));
@@ -173,6 +194,55 @@ impl AtatUrc for EdmEvent {
     }
 }
 
+/// Socket event carried by an EDM packet, as found by [`socket_frame`].
+#[derive(Debug, PartialEq)]
+pub(crate) enum SocketFrame<'a> {
+    /// Connect or disconnect event.
+    Event,
+    /// Data received on `channel_id`.
+    Data {
+        channel_id: ChannelId,
+        data: &'a [u8],
+    },
+}
+
+/// Find the socket event in the EDM packet `resp`, without copying its data.
+///
+/// Accepts exactly the packets [`EdmEvent::parse`] turns into connect,
+/// disconnect and data events.
+pub(crate) fn socket_frame(resp: &[u8]) -> Option<SocketFrame<'_>> {
+    if resp.len() < PAYLOAD_OVERHEAD
+        || !resp.starts_with(&[STARTBYTE])
+        || !resp.ends_with(&[ENDBYTE])
+    {
+        return None;
+    }
+    let payload_len = calc_payload_len(resp);
+    if resp.len() != payload_len + EDM_OVERHEAD {
+        return None;
+    }
+
+    match resp[4].into() {
+        PayloadType::ConnectEvent if payload_len >= 4 => {
+            let valid_len = match resp[6].into() {
+                ConnectType::IPv4 => payload_len == 17,
+                ConnectType::IPv6 => payload_len == 41,
+                _ => false,
+            };
+            (valid_len && Protocol::from(resp[7]) != Protocol::Unknown)
+                .then_some(SocketFrame::Event)
+        }
+        PayloadType::DisconnectEvent if payload_len == 3 => Some(SocketFrame::Event),
+        PayloadType::DataEvent if payload_len >= 4 && payload_len - 3 <= DATA_PACKAGE_SIZE => {
+            Some(SocketFrame::Data {
+                channel_id: ChannelId(resp[5]),
+                data: &resp[6..payload_len + 3],
+            })
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -202,6 +272,27 @@ mod test {
         assert_eq!(parsed_urc, Some(urc), "Parsing URC failed");
     }
 
+    #[test]
+    fn parse_flow_control() {
+        let resp = b"\xAA\x00\x10\x00\x41\r\n+UUFLOWS:3\r\n\x55";
+        assert_eq!(
+            EdmEvent::parse(resp),
+            Some(EdmEvent::FlowControl {
+                channel_id: ChannelId(3),
+                paused: true
+            })
+        );
+
+        let resp = b"\xAA\x00\x10\x00\x41\r\n+UUFLOWR:3\r\n\x55";
+        assert_eq!(
+            EdmEvent::parse(resp),
+            Some(EdmEvent::FlowControl {
+                channel_id: ChannelId(3),
+                paused: false
+            })
+        );
+    }
+
     #[test]
     fn parse_ipv4_connect_event() {
         // AT-urc: +UUDPD:3
@@ -259,6 +350,22 @@ mod test {
         assert_eq!(parsed_event, Some(event), "Parsing Disconnect Event failed");
     }
 
+    #[test]
+    fn parse_startup_event() {
+        let resp = &[0xAA, 0x00, 0x02, 0x00, 0x71, 0x55];
+        assert_eq!(EdmEvent::parse(resp), Some(EdmEvent::StartUp));
+
+        // Only the EDM startup event signals extended data mode
+        assert_eq!(
+            EdmEvent::parse(b"\r\n+STARTUP\r\n"),
+            Some(EdmEvent::ATEvent(Urc::StartUp))
+        );
+        assert_eq!(
+            EdmEvent::parse(b"\r\n+STARTUP\r\nATO2"),
+            Some(EdmEvent::ATEvent(Urc::StartUp))
+        );
+    }
+
     #[test]
     fn parse_data_event() {
         // AT-event: +UUDPD:3
@@ -270,4 +377,31 @@ mod test {
         let parsed_event = EdmEvent::parse(resp);
         assert_eq!(parsed_event, Some(event), "Parsing Data Event failed");
     }
+
+    #[test]
+    fn socket_frames() {
+        let data = &[0xAA, 0x00, 0x05, 0x00, 0x31, 0x03, 0x12, 0x34, 0x55];
+        assert_eq!(
+            socket_frame(data),
+            Some(SocketFrame::Data {
+                channel_id: ChannelId(3),
+                data: &[0x12, 0x34]
+            })
+        );
+
+        let connect = &[
+            0xAA, 0x00, 0x11, 0x00, 0x11, 0x05, 0x02, 0x00, 0xC0, 0xA8, 0x00, 0x02, 0x13, 0x88,
+            0xC0, 0xA8, 0x00, 0x01, 0x0F, 0xA0, 0x55,
+        ];
+        assert_eq!(socket_frame(connect), Some(SocketFrame::Event));
+        let disconnect = &[0xAA, 0x00, 0x03, 0x00, 0x21, 0x03, 0x55];
+        assert_eq!(socket_frame(disconnect), Some(SocketFrame::Event));
+
+        // Not turned into socket events by `parse` either
+        let bluetooth = &[0xAA, 0x00, 0x05, 0x00, 0x11, 0x01, 0x01, 0x00, 0x55];
+        assert_eq!(socket_frame(bluetooth), None);
+        assert_eq!(EdmEvent::parse(bluetooth), None);
+        let urc = b"\xAA\x00\x10\x00\x41\r\n+UUFLOWS:3\r\n\x55";
+        assert_eq!(socket_frame(urc), None);
+    }
 }
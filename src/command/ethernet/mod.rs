@@ -39,7 +39,7 @@ pub struct GetEthernetConfiguration {
 ///
 /// Sets network type.
 #[derive(Clone, AtatCmd)]
-#[at_cmd("+UETHC", NoResponse, timeout_ms = 1000)]
+#[at_cmd("+UETHCA", NoResponse, timeout_ms = 1000)]
 pub struct EthernetConfigurationAction {
     #[at_arg(position = 0)]
     pub action: EthernetConfigAction,
@@ -5,6 +5,6 @@ use atat::atat_derive::AtatResp;
 #[derive(Debug, PartialEq, Clone, AtatResp)]
 pub struct EthernetLinkUp;
 
-/// 8.4 Ethernet link down +UUETHLU
+/// 8.4 Ethernet link down +UUETHLD
 #[derive(Debug, PartialEq, Clone, AtatResp)]
 pub struct EthernetLinkDown;
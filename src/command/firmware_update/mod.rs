@@ -0,0 +1,26 @@
+//! ### 4.11 - Firmware Update
+//!
+//! Serial update of the u-connect software. After `+UFWUPD` the module
+//! restarts into its boot loader at the requested baud rate, and the new
+//! image is transferred using XMODEM-CRC (see [`xmodem`]).
+pub mod responses;
+pub mod types;
+pub mod xmodem;
+
+use atat::atat_derive::AtatCmd;
+use responses::*;
+use types::*;
+
+/// 4.11 Software update +UFWUPD
+///
+/// Force start of the boot loader. The boot loader will start at the defined baud rate.
+/// To update any binary image other than the u-connect software, enter the bootloader
+/// mode and follow the boot menu commands.
+#[derive(Debug, PartialEq, Clone, AtatCmd)]
+#[at_cmd("+UFWUPD", SoftwareUpdateResponse, timeout_ms = 1000)]
+pub struct SoftwareUpdate {
+    #[at_arg(position = 0)]
+    pub mode: SoftwareUpdateMode,
+    #[at_arg(position = 1)]
+    pub baud: SoftwareUpdateBaudRate,
+}
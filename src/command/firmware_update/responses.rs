@@ -0,0 +1,11 @@
+//! Responses for Firmware Update Commands
+use atat::atat_derive::AtatResp;
+use heapless::String;
+
+/// 4.11 Software update +UFWUPD
+#[derive(Debug, PartialEq, Clone, AtatResp)]
+pub struct SoftwareUpdateResponse {
+    /// Should contain CCC and then the software updater boots up
+    #[at_arg(position = 0)]
+    pub serial_number: String<64>,
+}
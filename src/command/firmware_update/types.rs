@@ -0,0 +1,20 @@
+//! Argument and parameter types used by Firmware Update Commands and Responses
+
+use atat::atat_derive::AtatEnum;
+
+#[derive(Debug, Clone, PartialEq, AtatEnum)]
+#[repr(u8)]
+pub enum SoftwareUpdateMode {
+    ///  u-connect software update using serial port
+    SoftwareUpdate = 0,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, AtatEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[at_enum(u32)]
+pub enum SoftwareUpdateBaudRate {
+    /// Default
+    B115200 = 115200,
+    B230400 = 230400,
+    B460800 = 460800,
+}
@@ -0,0 +1,74 @@
+//! XMODEM-CRC framing used by the boot loader to receive a new image.
+
+/// Start of a 128 byte block.
+pub const SOH: u8 = 0x01;
+/// End of transmission.
+pub const EOT: u8 = 0x04;
+/// Block received successfully.
+pub const ACK: u8 = 0x06;
+/// Block rejected, retransmit.
+pub const NAK: u8 = 0x15;
+/// Transfer cancelled.
+pub const CAN: u8 = 0x18;
+/// Sent repeatedly by the receiver to request a CRC transfer.
+pub const CRC_REQUEST: u8 = b'C';
+/// Padding used to fill the last block.
+pub const PADDING: u8 = 0x1A;
+
+/// Payload size of a single block.
+pub const BLOCK_SIZE: usize = 128;
+/// Size of a complete block including header and CRC.
+pub const PACKET_SIZE: usize = BLOCK_SIZE + 5;
+
+/// Encode `data` as block number `block`.
+///
+/// `data` shorter than [`BLOCK_SIZE`] is padded with [`PADDING`].
+pub fn packet(block: u8, data: &[u8]) -> [u8; PACKET_SIZE] {
+    let mut packet = [PADDING; PACKET_SIZE];
+    packet[0] = SOH;
+    packet[1] = block;
+    packet[2] = !block;
+
+    let len = data.len().min(BLOCK_SIZE);
+    packet[3..3 + len].copy_from_slice(&data[..len]);
+
+    let crc = crc16(&packet[3..3 + BLOCK_SIZE]);
+    packet[PACKET_SIZE - 2..].copy_from_slice(&crc.to_be_bytes());
+    packet
+}
+
+/// CRC-16/XMODEM (polynomial 0x1021, initial value 0).
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, b| {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn padded_packet() {
+        let p = packet(1, b"abc");
+        assert_eq!(&p[..6], &[SOH, 0x01, 0xFE, b'a', b'b', b'c']);
+        assert!(p[6..3 + BLOCK_SIZE].iter().all(|b| *b == PADDING));
+        assert_eq!(
+            u16::from_be_bytes([p[PACKET_SIZE - 2], p[PACKET_SIZE - 1]]),
+            crc16(&p[3..3 + BLOCK_SIZE])
+        );
+    }
+}
@@ -54,7 +54,7 @@ pub struct IdentificationInformationSoftwareVersionResponse {
 /// 3.10 Identification information I10
 #[derive(Clone, AtatResp)]
 pub struct IdentificationInformationMCUIDResponse {
-    /// Text string that identifies the serial number.
+    /// Text string that identifies the MCU.
     #[at_arg(position = 0)]
-    pub serial_number: String<64>,
+    pub mcu_id: String<64>,
 }
@@ -27,6 +27,47 @@ pub enum IdentificationInfoEnum {
     MCUID = 10,
 }
 
+/// Module family, as reported by Model identification +CGMM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ModuleFamily {
+    /// ODIN-W2 series
+    OdinW2,
+    /// NINA-W1 series
+    NinaW1,
+    /// Any module not recognized by this driver
+    Unknown,
+}
+
+impl ModuleFamily {
+    /// Determine the module family from the model identification string,
+    /// e.g. `ODIN-W262`.
+    pub fn from_model(model: &str) -> Self {
+        if model.starts_with("ODIN-W2") {
+            Self::OdinW2
+        } else if model.starts_with("NINA-W1") {
+            Self::NinaW1
+        } else {
+            Self::Unknown
+        }
+    }
+
+    /// Maximum Wi-Fi transmit power level in dBm supported by the module
+    /// family, excluding the adaptive setting (255).
+    ///
+    /// Provisional: the AT commands manual only documents 0-20 for the
+    /// +UWCFG power level. The per-family maximums are not confirmed by the
+    /// module data sheets yet, and unknown modules get the documented 20.
+    #[cfg(feature = "provisional-at")]
+    pub const fn max_power_level(&self) -> u8 {
+        match self {
+            Self::OdinW2 => 18,
+            Self::NinaW1 => 16,
+            Self::Unknown => 20,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FirmwareVersion {
     major: u8,
@@ -48,3 +48,32 @@ pub struct WriteGPIO {
     #[at_arg(position = 1)]
     pub value: GPIOValue,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atat::AtatCmd;
+
+    #[test]
+    fn read_with_pin_id() {
+        let resp = ReadGPIO { id: GPIOId::A12 }
+            .parse(Ok(b"+UGPIOR:28,1\r\n"))
+            .unwrap();
+        assert_eq!(resp.id(), Some(GPIOId::A12 as u8));
+        assert!(resp.value() == Some(GPIOValue::High));
+    }
+
+    #[test]
+    fn read_without_pin_id() {
+        let resp = ReadGPIO { id: GPIOId::A12 }
+            .parse(Ok(b"+UGPIOR:0\r\n"))
+            .unwrap();
+        assert_eq!(resp.id(), None);
+        assert!(resp.value() == Some(GPIOValue::Low));
+
+        let resp = ReadGPIO { id: GPIOId::A12 }
+            .parse(Ok(b"+UGPIOR:7\r\n"))
+            .unwrap();
+        assert!(resp.value().is_none());
+    }
+}
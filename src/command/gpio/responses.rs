@@ -3,10 +3,30 @@ use super::types::*;
 use atat::atat_derive::AtatResp;
 
 /// 14.2 GPIO Read +UGPIOR
+///
+/// Depending on the software version, the module responds with either the
+/// pin id and its value, or the value only.
 #[derive(Clone, PartialEq, AtatResp)]
 pub struct ReadGPIOResponse {
     #[at_arg(position = 0)]
-    pub id: GPIOId,
+    first: u8,
     #[at_arg(position = 1)]
-    pub value: GPIOValue,
+    second: Option<GPIOValue>,
+}
+
+impl ReadGPIOResponse {
+    /// Id of the pin, if echoed by the module.
+    pub fn id(&self) -> Option<u8> {
+        self.second.as_ref().map(|_| self.first)
+    }
+
+    /// Value of the pin, `None` if the response is malformed.
+    pub fn value(&self) -> Option<GPIOValue> {
+        match (&self.second, self.first) {
+            (Some(value), _) => Some(value.clone()),
+            (None, 0) => Some(GPIOValue::Low),
+            (None, 1) => Some(GPIOValue::High),
+            (None, _) => None,
+        }
+    }
 }
@@ -7,6 +7,7 @@ pub mod data_mode;
 #[cfg(feature = "internal-network-stack")]
 pub mod edm;
 pub mod ethernet;
+pub mod firmware_update;
 pub mod general;
 pub mod gpio;
 pub mod network;
@@ -37,6 +38,14 @@ pub enum Urc {
     #[cfg(feature = "internal-network-stack")]
     #[at_urc("+UUDPD")]
     PeerDisconnected(data_mode::urc::PeerDisconnected),
+    /// Data flow stopped +UUFLOWS
+    #[cfg(feature = "internal-network-stack")]
+    #[at_urc("+UUFLOWS")]
+    DataFlowStopped(data_mode::urc::DataFlowStopped),
+    /// Data flow resumed +UUFLOWR
+    #[cfg(feature = "internal-network-stack")]
+    #[at_urc("+UUFLOWR")]
+    DataFlowResumed(data_mode::urc::DataFlowResumed),
     /// 7.15 Wi-Fi Link connected +UUWLE
     #[at_urc("+UUWLE")]
     WifiLinkConnected(wifi::urc::WifiLinkConnected),
@@ -7,7 +7,7 @@ use atat::atat_derive::AtatCmd;
 use responses::*;
 use types::*;
 
-use super::NoResponse;
+use super::{NoResponse, OnOff};
 
 /// 7.10 Wi-Fi Acess point status +UWAPSTAT
 ///
@@ -50,7 +50,7 @@ pub struct Layer2Routing {
     #[at_arg(position = 0)]
     pub routing_tag: RoutingTag,
     #[at_arg(position = 1)]
-    pub routing_value: bool,
+    pub routing_value: OnOff,
 }
 
 /// 10.4 Bridge configuration +UBRGC
@@ -71,6 +71,18 @@ pub struct SetBridgeConfiguration {
     pub config_tag: BridgeConfig,
 }
 
+/// 10.4 Bridge configuration +UBRGC
+///
+/// Reads a parameter of a network bridge configuration.
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+UBRGC", BridgeConfigurationResponse, timeout_ms = 1000)]
+pub struct GetBridgeConfiguration {
+    #[at_arg(position = 0)]
+    pub config_id: BridgeConfigId,
+    #[at_arg(position = 1)]
+    pub parameter: BridgeConfigParameter,
+}
+
 /// 10.5 Bridge configuration action +UBRGCA
 ///
 /// Executes an action for the network bridge configuration.
@@ -92,3 +104,87 @@ pub struct AddressConflictDetectionTiming {
     #[at_arg(position = 0)]
     pub parameter: Timing,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atat::AtatCmd;
+
+    #[test]
+    fn bridge_configuration_sequence() {
+        let mut buf = [0u8; 64];
+
+        let configure = SetBridgeConfiguration {
+            config_id: BridgeConfigId::Id1,
+            config_tag: BridgeConfig::ActiveOnStartup(OnOff::Off),
+        };
+        let len = configure.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UBRGC=0,0,0\r\n");
+
+        let activate = BridgeConfigurationAction {
+            config_id: BridgeConfigId::Id1,
+            action: BridgeAction::Activate,
+        };
+        let len = activate.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UBRGCA=0,3\r\n");
+
+        let deactivate = BridgeConfigurationAction {
+            config_id: BridgeConfigId::Id2,
+            action: BridgeAction::Deactivate,
+        };
+        let len = deactivate.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UBRGCA=1,4\r\n");
+
+        let read = GetBridgeConfiguration {
+            config_id: BridgeConfigId::Id2,
+            parameter: BridgeConfigParameter::ActiveOnStartup,
+        };
+        let len = read.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UBRGC=1,0\r\n");
+        let resp = read.parse(Ok(b"+UBRGC:1,0,1")).unwrap();
+        assert!(resp.config_id == BridgeConfigId::Id2);
+        assert!(matches!(
+            resp.config,
+            BridgeConfig::ActiveOnStartup(OnOff::On)
+        ));
+    }
+
+    #[test]
+    fn bridge_ip_configuration() {
+        let mut buf = [0u8; 64];
+
+        let address = SetBridgeConfiguration {
+            config_id: BridgeConfigId::Id1,
+            config_tag: BridgeConfig::IPv4Address(core::net::Ipv4Addr::new(192, 168, 2, 1)),
+        };
+        let len = address.write(&mut buf);
+        let at = core::str::from_utf8(&buf[..len]).unwrap();
+        assert!(at.starts_with("AT+UBRGC=0,101,"), "{}", at);
+        assert!(at.contains("192.168.2.1"), "{}", at);
+
+        let routing = Layer2Routing {
+            routing_tag: RoutingTag::Enabled,
+            routing_value: OnOff::On,
+        };
+        let len = routing.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UNL2RCFG=0,1\r\n");
+    }
+
+    #[test]
+    fn network_error_reason() {
+        use atat::AtatUrc as _;
+
+        let Some(crate::command::Urc::NetworkError(err)) =
+            crate::command::Urc::parse(b"+UUNERR:0,128")
+        else {
+            panic!("expected +UUNERR");
+        };
+        assert_eq!(err.interface_id, 0);
+        assert_eq!(err.reason(), NetworkErrorReason::AddressConflict);
+
+        assert_eq!(NetworkErrorReason::from(7), NetworkErrorReason::Unknown(7));
+        #[allow(deprecated)]
+        let reason = NetworkErrorReason::from(ErrorType::IpAddressConflict);
+        assert_eq!(reason, NetworkErrorReason::AddressConflict);
+    }
+}
@@ -10,6 +10,15 @@ pub struct APStatusResponse {
     pub status_val: AccessPointStatus,
 }
 
+/// 10.4 Bridge configuration +UBRGC
+#[derive(Clone, AtatResp)]
+pub struct BridgeConfigurationResponse {
+    #[at_arg(position = 0)]
+    pub config_id: BridgeConfigId,
+    #[at_arg(position = 1)]
+    pub config: BridgeConfig,
+}
+
 /// 10.2 Network status +UNSTAT
 #[derive(Clone, AtatResp)]
 pub struct NetworkStatusResponse {
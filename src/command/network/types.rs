@@ -2,6 +2,7 @@
 
 use atat::atat_derive::AtatEnum;
 use atat::heapless_bytes::Bytes;
+use core::net::Ipv4Addr;
 
 use heapless::String;
 
@@ -216,21 +217,21 @@ pub enum BridgeConfig {
     IPv4Mode(IPv4Mode),
     /// <param_val> is the IPv4 address. The factory default value is 0.0.0.0
     #[at_arg(value = 101)]
-    IPv4Address(#[at_arg(len = 16)] Bytes<16>),
+    IPv4Address(#[at_arg(len = 16)] Ipv4Addr),
     /// <param_val> is the subnet mask. The factory default value is 0.0.0.0
     #[at_arg(value = 102)]
-    SubnetMask(#[at_arg(len = 16)] Bytes<16>),
+    SubnetMask(#[at_arg(len = 16)] Ipv4Addr),
     /// <param_val> is the default gateway. The factory default value is 0.0.0.0
     #[at_arg(value = 103)]
-    DefaultGateway(#[at_arg(len = 16)] Bytes<16>),
+    DefaultGateway(#[at_arg(len = 16)] Ipv4Addr),
     /// <param_val> is the primary DNS server IP address. The factory default value is 0
     /// .0.0.0
     #[at_arg(value = 104)]
-    PrimaryDNS(#[at_arg(len = 16)] Bytes<16>),
+    PrimaryDNS(#[at_arg(len = 16)] Ipv4Addr),
     /// <param_val> is the secondary DNS server IP address. The factory default value is
     /// 0.0.0.0
     #[at_arg(value = 105)]
-    SecondaryDNS(#[at_arg(len = 16)] Bytes<16>),
+    SecondaryDNS(#[at_arg(len = 16)] Ipv4Addr),
     /// <param_val> is the DHCP server configuration.
     /// - 0 (default): Disable DHCP server
     /// - 1: Enable DHCP server. The DHCP Server will provide addresses according to the
@@ -246,6 +247,23 @@ pub enum BridgeConfig {
     AddressConflictDetection(OnOff),
 }
 
+/// Parameter tags of [`BridgeConfig`], to read a single parameter.
+#[derive(Clone, PartialEq, AtatEnum)]
+#[repr(u8)]
+pub enum BridgeConfigParameter {
+    ActiveOnStartup = 0,
+    LinkLayerList = 1,
+    IPInterfaceList = 2,
+    IPv4Mode = 100,
+    IPv4Address = 101,
+    SubnetMask = 102,
+    DefaultGateway = 103,
+    PrimaryDNS = 104,
+    SecondaryDNS = 105,
+    DHCPServer = 106,
+    AddressConflictDetection = 107,
+}
+
 #[derive(Clone, PartialEq, AtatEnum)]
 #[repr(u8)]
 pub enum IPv4Mode {
@@ -300,8 +318,38 @@ pub enum Timing {
     AnnounceInterval(u16),
 }
 
-#[derive(Debug, Clone, PartialEq, AtatEnum)]
+/// Error code of a +UUNERR network error.
+#[deprecated(note = "use `NetworkErrorReason`, which also covers undocumented codes")]
+#[derive(Debug, Clone, PartialEq)]
 #[repr(u8)]
 pub enum ErrorType {
     IpAddressConflict = 128,
 }
+
+#[allow(deprecated)]
+impl From<ErrorType> for NetworkErrorReason {
+    fn from(error: ErrorType) -> Self {
+        match error {
+            ErrorType::IpAddressConflict => NetworkErrorReason::AddressConflict,
+        }
+    }
+}
+
+/// Reason of a +UUNERR network error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NetworkErrorReason {
+    /// Another host on the network uses the IP address of the module.
+    AddressConflict,
+    /// Error code not documented by the AT manual.
+    Unknown(u8),
+}
+
+impl From<u8> for NetworkErrorReason {
+    fn from(code: u8) -> Self {
+        match code {
+            128 => NetworkErrorReason::AddressConflict,
+            code => NetworkErrorReason::Unknown(code),
+        }
+    }
+}
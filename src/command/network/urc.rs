@@ -21,6 +21,13 @@ pub struct NetworkDown {
 pub struct NetworkError {
     #[at_arg(position = 0)]
     pub interface_id: u8,
+    /// Error code, see [`NetworkError::reason`].
     #[at_arg(position = 1)]
-    pub error: ErrorType,
+    pub error: u8,
+}
+
+impl NetworkError {
+    pub fn reason(&self) -> NetworkErrorReason {
+        self.error.into()
+    }
 }
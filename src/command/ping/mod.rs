@@ -36,4 +36,67 @@ pub struct Ping<'a> {
     /// - Default value: 4
     #[at_arg(position = 1)]
     pub retry_num: i32,
+    /// Size in bytes of the echo packet payload.
+    /// - Range: 4-1472
+    /// - Default value: 32
+    #[at_arg(position = 2)]
+    pub data_size: u16,
+    /// The maximum time in milliseconds to wait for an echo reply response.
+    /// - Range: 10-60000
+    /// - Default value: 5000
+    #[at_arg(position = 3)]
+    pub timeout: u16,
+}
+
+impl<'a> Ping<'a> {
+    /// Ping `hostname` `retry_num` times, with `data_size` bytes of payload
+    /// and waiting up to `timeout` milliseconds for each echo reply before
+    /// moving on to the next request.
+    pub fn with_size_and_interval(
+        hostname: &'a str,
+        retry_num: i32,
+        data_size: u16,
+        timeout: u16,
+    ) -> Self {
+        Self {
+            hostname,
+            retry_num,
+            data_size,
+            timeout,
+        }
+    }
+}
+
+impl Default for Ping<'_> {
+    fn default() -> Self {
+        Self {
+            hostname: "",
+            retry_num: 4,
+            data_size: 32,
+            timeout: 5000,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atat::AtatCmd;
+
+    #[test]
+    fn ping_defaults() {
+        let mut buf = [0u8; 64];
+
+        let ping = Ping {
+            hostname: "example.com",
+            retry_num: 1,
+            ..Default::default()
+        };
+        let len = ping.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UPING=\"example.com\",1,32,5000\r\n");
+
+        let ping = Ping::with_size_and_interval("10.0.0.1", 3, 64, 1000);
+        let len = ping.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UPING=\"10.0.0.1\",3,64,1000\r\n");
+    }
 }
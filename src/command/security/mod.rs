@@ -50,6 +50,36 @@ pub struct SendSecurityDataImport<'a> {
     pub data: &'a atat::serde_bytes::Bytes,
 }
 
+/// Leading part of the data of a certificate or private key being imported.
+///
+/// The module only responds once all announced data is received, so data too
+/// large for a single command is written as a number of chunks followed by a
+/// final [`SendSecurityDataImport`].
+#[derive(Clone)]
+pub struct SecurityDataChunk<'a> {
+    pub data: &'a [u8],
+}
+
+impl<'a> atat::AtatCmd for SecurityDataChunk<'a> {
+    type Response = NoResponse;
+
+    const MAX_LEN: usize = 8192;
+
+    const EXPECTS_RESPONSE_CODE: bool = false;
+
+    fn parse(
+        &self,
+        _resp: Result<&[u8], atat::InternalError>,
+    ) -> core::result::Result<Self::Response, atat::Error> {
+        Ok(NoResponse)
+    }
+
+    fn write(&self, buf: &mut [u8]) -> usize {
+        buf[..self.data.len()].copy_from_slice(self.data);
+        self.data.len()
+    }
+}
+
 /// 11.1 SSL/TLS certificates and private keys manager +USECMNG
 ///
 /// Manages the X.509 certificates and private keys with the following functionalities:
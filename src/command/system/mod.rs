@@ -8,6 +8,8 @@ use types::*;
 
 use super::NoResponse;
 
+pub use super::firmware_update::SoftwareUpdate;
+
 /// 4.1 Store current configuration &W
 ///
 /// Commits all the settings to be stored in start up database. The parameters are
@@ -135,20 +137,6 @@ pub struct SetBackspaceCharacter {
     pub backspace: u8,
 }
 
-/// 4.11 Software update +UFWUPD
-///
-/// Force start of the boot loader. The boot loader will start at the defined baud rate.
-/// To update any binary image other than the u-connect software, enter the bootloader
-/// mode and follow the boot menu commands.
-#[derive(Debug, PartialEq, Clone, AtatCmd)]
-#[at_cmd("+UFWUPD", SoftwareUpdateResponse, timeout_ms = 1000)]
-pub struct SoftwareUpdate {
-    #[at_arg(position = 0)]
-    pub mode: SoftwareUpdateMode,
-    #[at_arg(position = 1)]
-    pub baud: SoftwareUpdateBaudRate,
-}
-
 /// 4.12 Module switch off +CPWROFF
 ///
 /// Reboot the DCE. During shutdown, the settings marked for storing to start up the
@@ -227,6 +215,13 @@ pub struct SetRS232Settings {
     pub change_after_confirm: ChangeAfterConfirm,
 }
 
+/// 4.16 RS232 Settings +UMRS
+///
+/// Reads the current RS232 settings.
+#[derive(Debug, PartialEq, Clone, AtatCmd)]
+#[at_cmd("+UMRS?", RS232SettingsResponse, timeout_ms = 1000)]
+pub struct GetRS232Settings;
+
 /// 4.17 Route radio signals to GPIOs +UMRSIG
 /// Enable routing of radio signals to EXT_TX_EN and EXT_RX_EN pins.
 /// When routing is enabled on both the pins, it is recommended not to use other
@@ -1,15 +1,7 @@
 //! Responses for System Commands
 use super::types::*;
+pub use crate::command::firmware_update::responses::SoftwareUpdateResponse;
 use atat::{atat_derive::AtatResp, serde_at::HexStr};
-use heapless::String;
-
-/// 4.11 Software update +UFWUPD
-#[derive(Debug, PartialEq, Clone, AtatResp)]
-pub struct SoftwareUpdateResponse {
-    /// Should contain CCC and then the software updater boots up
-    #[at_arg(position = 0)]
-    pub serial_number: String<64>,
-}
 
 /// 4.14 Read Local address +UMLA
 #[derive(Debug, PartialEq, Clone, AtatResp)]
@@ -28,6 +20,25 @@ pub struct SystemStatusResponse {
     #[at_arg(position = 1)]
     pub status_val: u32,
 }
+/// 4.16 RS232 Settings +UMRS
+#[derive(Debug, PartialEq, Clone, AtatResp)]
+pub struct RS232SettingsResponse {
+    /// The baud rate in use, which may differ from the requested
+    /// [`BaudRate`].
+    #[at_arg(position = 0)]
+    pub baud_rate: u32,
+    #[at_arg(position = 1)]
+    pub flow_control: FlowControl,
+    #[at_arg(position = 2)]
+    pub data_bits: u8,
+    #[at_arg(position = 3)]
+    pub stop_bits: StopBits,
+    #[at_arg(position = 4)]
+    pub parity: Parity,
+    #[at_arg(position = 5)]
+    pub change_after_confirm: Option<ChangeAfterConfirm>,
+}
+
 /// 4.19 LPO detection +UMLPO
 #[derive(Debug, PartialEq, Clone, AtatResp)]
 pub struct LPODetectionResponse {
@@ -2,6 +2,8 @@
 
 use atat::atat_derive::AtatEnum;
 
+pub use crate::command::firmware_update::types::{SoftwareUpdateBaudRate, SoftwareUpdateMode};
+
 #[derive(Debug, Clone, PartialEq, AtatEnum)]
 #[repr(u8)]
 pub enum Mode {
@@ -53,22 +55,6 @@ pub enum EchoOn {
     On = 1,
 }
 
-#[derive(Debug, Clone, PartialEq, AtatEnum)]
-#[repr(u8)]
-pub enum SoftwareUpdateMode {
-    ///  u-connect software update using serial port
-    SoftwareUpdate = 0,
-}
-
-#[derive(Debug, Clone, PartialEq, AtatEnum)]
-#[at_enum(u32)]
-pub enum SoftwareUpdateBaudRate {
-    /// Default
-    B115200 = 115200,
-    B230400 = 230400,
-    B460800 = 460800,
-}
-
 #[derive(Debug, Clone, PartialEq, AtatEnum)]
 #[repr(u8)]
 pub enum ModuleStartMode {
@@ -4,7 +4,11 @@ pub mod types;
 pub mod urc;
 
 use atat::atat_derive::AtatCmd;
-use heapless::Vec;
+use atat::heapless_bytes::Bytes;
+use core::cell::RefCell;
+use core::net::Ipv4Addr;
+use core::ops::ControlFlow;
+use heapless::{String, Vec};
 use responses::*;
 use types::*;
 
@@ -131,13 +135,188 @@ pub struct ExecWifiStationAction {
 /// in the immediate surroundings, then return OK or ERROR if unable to start scan.
 /// Channels scanned is given by the channel list. See +UWCL for more information. If
 /// the SSID is defined, a directed scan will be performed.
-#[derive(Clone, AtatCmd)]
-#[at_cmd("+UWSCAN", WifiScanResponse, timeout_ms = 1000)]
+///
+/// The module responds with one `+UWSCAN:` line per network, which
+/// `atat_derive` is not able to represent. The digesters pass on all lines up
+/// to the final result code, which are split by `parse`.
+///
+/// Malformed lines are skipped, and networks beyond the capacity of
+/// [`WifiScanResponse`] are dropped.
+#[derive(Debug, Clone)]
 pub struct WifiScan<'a> {
-    #[at_arg(position = 0, len = 64)]
     pub ssid: Option<&'a str>,
 }
 
+impl atat::AtatCmd for WifiScan<'_> {
+    type Response = WifiScanResponse;
+
+    const MAX_LEN: usize = 78;
+    /// The module only responds once it has gone through all channels of the
+    /// channel list, which takes several seconds for both bands.
+    const MAX_TIMEOUT_MS: u32 = 10000;
+
+    fn write(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        let mut put = |bytes: &[u8]| {
+            buf[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+
+        put(b"AT+UWSCAN");
+        if let Some(ssid) = self.ssid {
+            put(b"=\"");
+            put(&ssid.as_bytes()[..ssid.len().min(64)]);
+            put(b"\"");
+        }
+        put(b"\r\n");
+        len
+    }
+
+    fn parse(
+        &self,
+        res: Result<&[u8], atat::InternalError>,
+    ) -> core::result::Result<Self::Response, atat::Error> {
+        let mut network_list = Vec::new();
+        for network in scanned_networks(res?)?.flatten() {
+            if network_list.push(network).is_err() {
+                warn!(
+                    "Scan found more than {} networks, dropping the rest",
+                    network_list.capacity()
+                );
+                break;
+            }
+        }
+        Ok(WifiScanResponse { network_list })
+    }
+}
+
+/// 7.3 Scan +UWSCAN
+///
+/// Like [`WifiScan`], but hands each network to `visit` as its line is parsed
+/// rather than collecting them, so no more than one [`ScannedWifiNetwork`] is
+/// held at a time. Returning [`ControlFlow::Break`] from `visit` skips the
+/// remaining lines. Malformed lines are skipped.
+///
+/// The raw response still has to fit the ingress buffer of the AT client.
+pub struct WifiScanWith<'a, F> {
+    pub ssid: Option<&'a str>,
+    visit: RefCell<F>,
+}
+
+impl<'a, B, F: FnMut(ScannedWifiNetwork) -> ControlFlow<B>> WifiScanWith<'a, F> {
+    pub fn new(ssid: Option<&'a str>, visit: F) -> Self {
+        Self {
+            ssid,
+            visit: RefCell::new(visit),
+        }
+    }
+}
+
+impl<B, F: FnMut(ScannedWifiNetwork) -> ControlFlow<B>> atat::AtatCmd for WifiScanWith<'_, F> {
+    type Response = WifiScanWithResponse<B>;
+
+    const MAX_LEN: usize = <WifiScan as atat::AtatCmd>::MAX_LEN;
+    const MAX_TIMEOUT_MS: u32 = <WifiScan as atat::AtatCmd>::MAX_TIMEOUT_MS;
+
+    fn write(&self, buf: &mut [u8]) -> usize {
+        atat::AtatCmd::write(&WifiScan { ssid: self.ssid }, buf)
+    }
+
+    fn parse(
+        &self,
+        res: Result<&[u8], atat::InternalError>,
+    ) -> core::result::Result<Self::Response, atat::Error> {
+        let visit = &mut *self.visit.borrow_mut();
+
+        let mut visited = 0;
+        for network in scanned_networks(res?)?.flatten() {
+            visited += 1;
+            if let ControlFlow::Break(b) = visit(network) {
+                return Ok(WifiScanWithResponse {
+                    visited,
+                    stopped: Some(b),
+                });
+            }
+        }
+        Ok(WifiScanWithResponse {
+            visited,
+            stopped: None,
+        })
+    }
+}
+
+/// Split a +UWSCAN response into its lines, parsing each one only once it
+/// is reached. Malformed lines are yielded as `None`.
+fn scanned_networks(
+    res: &[u8],
+) -> Result<impl Iterator<Item = Option<ScannedWifiNetwork>> + '_, atat::Error> {
+    let resp = core::str::from_utf8(res).map_err(|_| atat::Error::Parse)?;
+    Ok(resp
+        .split("\r\n")
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|line| {
+            let network = parse_scanned_network(line);
+            if network.is_none() {
+                warn!("Skipping malformed scan result");
+            }
+            network
+        }))
+}
+
+/// Remove the backslashes escaping `"` and `\` in a quoted string.
+fn unescape<const N: usize>(s: &str) -> Option<String<N>> {
+    let mut unescaped = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => chars.next()?,
+            c => c,
+        };
+        unescaped.push(c).ok()?;
+    }
+    Some(unescaped)
+}
+
+/// Parse a `+UWSCAN:<bssid>,<op_mode>,<ssid>,<channel>,<rssi>,
+/// <authentication_suites>,<unicast_ciphers>,<group_ciphers>` line. The SSID
+/// may hold commas, so it is taken as whatever is left between the leading and
+/// trailing fields.
+fn parse_scanned_network(line: &str) -> Option<ScannedWifiNetwork> {
+    let fields = line.strip_prefix("+UWSCAN:").unwrap_or(line);
+
+    let (bssid, fields) = fields.split_once(',')?;
+    let (op_mode, fields) = fields.split_once(',')?;
+    let mut tail = fields.rsplitn(6, ',');
+    let group_ciphers = tail.next()?;
+    let unicast_ciphers = tail.next()?;
+    let authentication_suites = tail.next()?;
+    let rssi = tail.next()?;
+    let channel = tail.next()?;
+    let ssid = tail.next()?.trim();
+    let ssid = ssid
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(ssid);
+
+    let hex = |field: &str| u8::from_str_radix(field.trim(), 16).ok();
+
+    Some(ScannedWifiNetwork {
+        bssid: Bytes::from_slice(bssid.trim().trim_matches('"').as_bytes()).ok()?,
+        op_mode: match op_mode.trim() {
+            "1" => OperationMode::Infrastructure,
+            "2" => OperationMode::AdHoc,
+            _ => return None,
+        },
+        ssid: unescape(ssid)?,
+        channel: channel.trim().parse().ok()?,
+        rssi: rssi.trim().parse().ok()?,
+        authentication_suites: hex(authentication_suites)?,
+        unicast_ciphers: hex(unicast_ciphers)?,
+        group_ciphers: hex(group_ciphers)?,
+    })
+}
+
 /// 7.4 Channel list +UWCL
 ///
 /// Writes the required channel list for station mode.
@@ -158,12 +337,72 @@ pub struct WifiScan<'a> {
 /// - Any DFS channel will be disabled for active use until an appropriate authoritative source has been found
 ///   for clearing each specific channel.
 #[derive(Clone, AtatCmd)]
-#[at_cmd("+UWCL", WifiScanResponse, timeout_ms = 1000)]
+#[at_cmd("+UWCL", NoResponse, timeout_ms = 1000)]
 pub struct SetChannelList {
     #[at_arg(position = 0)]
     pub channels: Vec<u8, 10>,
 }
 
+impl SetChannelList {
+    /// Create a channel list, validating each channel against the channels
+    /// supported by the module.
+    pub fn new(channels: &[u8]) -> Result<Self, crate::error::Error> {
+        if let Some(&channel) = channels
+            .iter()
+            .find(|&&c| !crate::module_caps::is_supported_channel(c))
+        {
+            return Err(crate::error::Error::UnsupportedChannel(channel));
+        }
+
+        Ok(Self {
+            channels: Vec::from_slice(channels).map_err(|_| crate::error::Error::Overflow)?,
+        })
+    }
+}
+
+/// 7.4 Channel list +UWCL
+///
+/// Restores the channel list for station mode to the factory default.
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+UWCL", NoResponse, timeout_ms = 1000)]
+pub struct ResetChannelList;
+
+/// 7.4 Channel list +UWCL
+///
+/// Reads the channel list for station mode. The module responds with a
+/// variable number of channels, which `atat_derive` is not able to represent.
+#[derive(Debug, Clone)]
+pub struct GetChannelList;
+
+impl atat::AtatCmd for GetChannelList {
+    type Response = ChannelListResponse;
+
+    const MAX_LEN: usize = 10;
+    const MAX_TIMEOUT_MS: u32 = 1000;
+
+    fn write(&self, buf: &mut [u8]) -> usize {
+        let cmd = b"AT+UWCL?\r\n";
+        buf[..cmd.len()].copy_from_slice(cmd);
+        cmd.len()
+    }
+
+    fn parse(
+        &self,
+        res: Result<&[u8], atat::InternalError>,
+    ) -> core::result::Result<Self::Response, atat::Error> {
+        let resp = core::str::from_utf8(res?).map_err(|_| atat::Error::Parse)?;
+        let resp = resp.trim();
+        let list = resp.strip_prefix("+UWCL:").unwrap_or(resp).trim();
+
+        let mut channels = Vec::new();
+        for channel in list.split(',').filter(|c| !c.trim().is_empty()) {
+            let channel = channel.trim().parse().map_err(|_| atat::Error::Parse)?;
+            channels.push(channel).map_err(|_| atat::Error::Parse)?;
+        }
+        Ok(ChannelListResponse { channels })
+    }
+}
+
 /// 7.5 Wi-Fi station status +UWSSTAT
 ///
 /// Writes the required channel list for station mode.
@@ -335,14 +574,526 @@ pub struct WifiAPStatus {
 
 /// 7.11 Wi-Fi Access point station list +UWAPSTALIST
 ///
-/// Lists all the stations connected to the Wireless access point.
-#[derive(Clone, AtatCmd)]
-#[at_cmd("+UWAPSTALIST?", WiFiAPStationListResponse, timeout_ms = 1000)]
+/// Lists all the stations connected to the Wireless access point. The module
+/// responds with one line per station, which `atat_derive` is not able to
+/// represent.
+#[derive(Debug, Clone)]
 pub struct WiFiAPStationList;
 
+impl atat::AtatCmd for WiFiAPStationList {
+    type Response = WiFiAPStationListResponse;
+
+    const MAX_LEN: usize = 17;
+    const MAX_TIMEOUT_MS: u32 = 1000;
+
+    fn write(&self, buf: &mut [u8]) -> usize {
+        let cmd = b"AT+UWAPSTALIST?\r\n";
+        buf[..cmd.len()].copy_from_slice(cmd);
+        cmd.len()
+    }
+
+    fn parse(
+        &self,
+        res: Result<&[u8], atat::InternalError>,
+    ) -> core::result::Result<Self::Response, atat::Error> {
+        let resp = core::str::from_utf8(res?).map_err(|_| atat::Error::Parse)?;
+
+        let mut stations = Vec::new();
+        for line in resp.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let station = parse_ap_station(line).ok_or(atat::Error::Parse)?;
+            stations.push(station).map_err(|_| atat::Error::Parse)?;
+        }
+        Ok(WiFiAPStationListResponse { stations })
+    }
+}
+
+/// Parse a `+UWAPSTALIST:<id>,<mac_addr>,<rssi>` line. Firmware listing the
+/// address of the station reports it ahead of the RSSI.
+fn parse_ap_station(line: &str) -> Option<ApStation> {
+    let fields = line.strip_prefix("+UWAPSTALIST:").unwrap_or(line);
+    let (fields, rssi) = fields.rsplit_once(',')?;
+
+    let mut mac = None;
+    let mut ip = None;
+    for field in fields.split(',').map(|f| f.trim().trim_matches('"')) {
+        if let Ok(addr) = field.parse::<Ipv4Addr>() {
+            ip = Some(addr);
+        } else if mac.is_none() {
+            mac = parse_mac(field);
+        }
+    }
+
+    Some(ApStation {
+        mac: mac?,
+        ip,
+        rssi: rssi.trim().parse().ok()?,
+    })
+}
+
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let mut hex: [u8; 12] = mac.as_bytes().try_into().ok()?;
+    crate::hex::from_hex(&mut hex).ok()?.try_into().ok()
+}
+
+/// 7.11 Wi-Fi Access point station list +UWAPSTALIST
+///
+/// Counts the stations connected to the Wireless access point, without
+/// parsing the list.
+#[derive(Debug, Clone)]
+pub struct WiFiAPStationCount;
+
+impl atat::AtatCmd for WiFiAPStationCount {
+    type Response = WiFiAPStationCountResponse;
+
+    const MAX_LEN: usize = 17;
+    const MAX_TIMEOUT_MS: u32 = 1000;
+
+    fn write(&self, buf: &mut [u8]) -> usize {
+        let cmd = b"AT+UWAPSTALIST?\r\n";
+        buf[..cmd.len()].copy_from_slice(cmd);
+        cmd.len()
+    }
+
+    fn parse(
+        &self,
+        res: Result<&[u8], atat::InternalError>,
+    ) -> core::result::Result<Self::Response, atat::Error> {
+        let resp = res?;
+        let count = resp
+            .split(|b| *b == b'\n')
+            .filter(|line| line.iter().any(|b| !b.is_ascii_whitespace()))
+            .count();
+        Ok(WiFiAPStationCountResponse { count })
+    }
+}
+
 /// 7.12 Wi-Fi MAC address +UWAPMACADDR
 ///
 /// Lists the currently used MAC address.
 #[derive(Clone, AtatCmd)]
 #[at_cmd("+UWAPMACADDR", WifiMacResponse, timeout_ms = 1000)]
 pub struct GetWifiMac;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atat::AtatCmd;
+    use core::net::{Ipv4Addr, Ipv6Addr};
+    use heapless::String;
+
+    #[test]
+    fn ap_mac_lists() {
+        let mut buf = [0u8; 256];
+
+        let whitelist = SetWifiAPConfig {
+            ap_config_id: AccessPointId::Id0,
+            ap_config_param: AccessPointConfig::WhiteList(
+                "AABBCCDDEEFF",
+                Some("001122334455"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            ),
+        };
+        let len = whitelist.write(&mut buf);
+        assert_eq!(
+            &buf[..len],
+            b"AT+UWAPC=0,19,\"AABBCCDDEEFF\",\"001122334455\"\r\n"
+        );
+
+        let clear = SetWifiAPConfig {
+            ap_config_id: AccessPointId::Id0,
+            ap_config_param: AccessPointConfig::BlackList(
+                "0", None, None, None, None, None, None, None, None, None,
+            ),
+        };
+        let len = clear.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UWAPC=0,20,\"0\"\r\n");
+    }
+
+    #[test]
+    fn dtim_interval() {
+        assert!(matches!(
+            DtimInterval::try_from(0),
+            Err(crate::error::Error::OutOfRange)
+        ));
+        assert!(matches!(
+            DtimInterval::try_from(101),
+            Err(crate::error::Error::OutOfRange)
+        ));
+        assert_eq!(DtimInterval::try_from(100).unwrap().get(), 100);
+        assert_eq!(DtimInterval::default(), DtimInterval::try_from(1).unwrap());
+
+        let mut buf = [0u8; 32];
+        let set = SetWifiAPConfig {
+            ap_config_id: AccessPointId::Id0,
+            ap_config_param: AccessPointConfig::DTIM(DtimInterval::try_from(3).unwrap()),
+        };
+        let len = set.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UWAPC=0,300,3\r\n");
+    }
+
+    fn station_config(resp: &[u8]) -> WifiStationConfigR {
+        let cmd = GetWifiStationConfig {
+            config_id: 0,
+            parameter: None,
+        };
+        let resp = cmd.parse(Ok(resp)).unwrap();
+        assert_eq!(resp.config_id, 0);
+        resp.parameter
+    }
+
+    #[test]
+    fn ap_station_list() {
+        let resp = WiFiAPStationList
+            .parse(Ok(b"+UWAPSTALIST:0,D4CA6E123456,-45\r\n\
+                        +UWAPSTALIST:1,0012F3ABCDEF,-71\r\n\
+                        +UWAPSTALIST:2,A0B1C2D3E4F5,192.168.2.3,-60"))
+            .unwrap();
+        assert_eq!(
+            &resp.stations[..],
+            &[
+                ApStation {
+                    mac: [0xD4, 0xCA, 0x6E, 0x12, 0x34, 0x56],
+                    ip: None,
+                    rssi: -45,
+                },
+                ApStation {
+                    mac: [0x00, 0x12, 0xF3, 0xAB, 0xCD, 0xEF],
+                    ip: None,
+                    rssi: -71,
+                },
+                ApStation {
+                    mac: [0xA0, 0xB1, 0xC2, 0xD3, 0xE4, 0xF5],
+                    ip: Some(Ipv4Addr::new(192, 168, 2, 3)),
+                    rssi: -60,
+                },
+            ]
+        );
+
+        let resp = WiFiAPStationList.parse(Ok(b"")).unwrap();
+        assert!(resp.stations.is_empty());
+
+        assert!(WiFiAPStationList
+            .parse(Ok(b"+UWAPSTALIST:0,D4CA6E12,-45"))
+            .is_err());
+    }
+
+    #[test]
+    fn wifi_scan() {
+        let cmd = WifiScan { ssid: None };
+        let mut buf = [0; WifiScan::MAX_LEN];
+        let len = cmd.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UWSCAN\r\n");
+
+        let cmd = WifiScan {
+            ssid: Some("Home, sweet home"),
+        };
+        let len = cmd.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UWSCAN=\"Home, sweet home\"\r\n");
+
+        let resp = cmd
+            .parse(Ok(
+                b"+UWSCAN:D4CA6E123456,1,\"Home, sweet home\",6,-52,18,8,8\r\n\
+                        +UWSCAN:0012F3ABCDEF,1,\"\",11,-81,0,0,0\r\n\
+                        +UWSCAN:A0B1C2D3E4F5,2,\"adhoc\",1,-70,1,3,3",
+            ))
+            .unwrap();
+        assert_eq!(resp.network_list.len(), 3);
+
+        let home = &resp.network_list[0];
+        assert_eq!(&home.bssid[..], b"D4CA6E123456");
+        assert_eq!(home.op_mode, OperationMode::Infrastructure);
+        assert_eq!(home.ssid.as_str(), "Home, sweet home");
+        assert_eq!(home.channel, 6);
+        assert_eq!(home.rssi, -52);
+        assert_eq!(home.authentication_suites, 0x18);
+        assert_eq!(home.unicast_ciphers, 0x08);
+        assert_eq!(home.group_ciphers, 0x08);
+
+        assert_eq!(resp.network_list[1].ssid.as_str(), "");
+        assert_eq!(resp.network_list[2].op_mode, OperationMode::AdHoc);
+
+        let resp = cmd.parse(Ok(b"")).unwrap();
+        assert!(resp.network_list.is_empty());
+
+        // Malformed lines are skipped
+        let resp = cmd
+            .parse(Ok(b"+UWSCAN:D4CA6E123456,1,\"x\",6\r\n\
+                        +UWSCAN:A0B1C2D3E4F5,2,\"adhoc\",1,-70,1,3,3"))
+            .unwrap();
+        assert_eq!(resp.network_list.len(), 1);
+        assert_eq!(resp.network_list[0].ssid.as_str(), "adhoc");
+
+        // Escaped quotes and backslashes are unescaped
+        let resp = cmd
+            .parse(Ok(
+                br#"+UWSCAN:D4CA6E123456,1,"\"quoted\", a\\b",6,-52,18,8,8"#,
+            ))
+            .unwrap();
+        assert_eq!(resp.network_list[0].ssid.as_str(), r#""quoted", a\b"#);
+
+        // Networks beyond the capacity are dropped
+        let line = b"+UWSCAN:D4CA6E123456,1,\"x\",6,-52,18,8,8\r\n";
+        let resp = cmd.parse(Ok(&line.repeat(40))).unwrap();
+        assert_eq!(resp.network_list.len(), 32);
+    }
+
+    #[cfg(feature = "provisional-at")]
+    #[test]
+    fn minimum_pmf() {
+        let mut buf = [0u8; 32];
+
+        let set = SetWifiConfig {
+            config_param: WifiConfig::PMFMode(PMF::Required),
+        };
+        let len = set.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UWCFG=26,2\r\n");
+
+        let get = GetWifiConfig {
+            config_param: WifiConfigParameter::PMFMode,
+        };
+        let len = get.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UWCFG=26\r\n");
+
+        let resp = get.parse(Ok(b"+UWCFG:26,1\r\n")).unwrap();
+        assert!(matches!(
+            resp.config_param,
+            WifiConfig::PMFMode(PMF::Optional)
+        ));
+    }
+
+    #[test]
+    fn wifi_scan_with() {
+        let resp: &[u8] = b"+UWSCAN:D4CA6E123456,1,\"Home, sweet home\",6,-52,18,8,8\r\n\
+                            +UWSCAN:0012F3ABCDEF,1,\"\",11,-81,0,0,0\r\n\
+                            +UWSCAN:A0B1C2D3E4F5,2,\"adhoc\",1,-70,1,3,3";
+
+        let mut buf = [0; WifiScan::MAX_LEN];
+        let cmd = WifiScanWith::new(Some("adhoc"), |_| ControlFlow::<()>::Continue(()));
+        let len = cmd.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UWSCAN=\"adhoc\"\r\n");
+
+        let mut ssids = std::vec::Vec::new();
+        let cmd = WifiScanWith::new(None, |n: ScannedWifiNetwork| {
+            ssids.push(n.ssid);
+            ControlFlow::<()>::Continue(())
+        });
+        let resp_all = cmd.parse(Ok(resp)).unwrap();
+        assert_eq!(resp_all.visited, 3);
+        assert_eq!(resp_all.stopped, None);
+        drop(cmd);
+        assert_eq!(ssids, ["Home, sweet home", "", "adhoc"]);
+
+        // Stops at the first match, without parsing the lines after it
+        let cmd = WifiScanWith::new(None, |n: ScannedWifiNetwork| {
+            if n.ssid.is_empty() {
+                ControlFlow::Break(n.channel)
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        let mut truncated = resp.to_vec();
+        truncated.extend_from_slice(b"\r\n+UWSCAN:garbage");
+        let found = cmd.parse(Ok(&truncated)).unwrap();
+        assert_eq!(found.visited, 2);
+        assert_eq!(found.stopped, Some(11));
+
+        // Malformed lines are skipped
+        let found = cmd.parse(Ok(b"+UWSCAN:D4CA6E123456,1,\"x\",6")).unwrap();
+        assert_eq!(found.visited, 0);
+        assert_eq!(found.stopped, None);
+    }
+
+    #[test]
+    fn ap_status() {
+        let status = |id: AccessPointStatusId, resp: &[u8]| {
+            WifiAPStatus { ap_status_id: id }
+                .parse(Ok(resp))
+                .unwrap()
+                .ap_status_id
+        };
+
+        assert_eq!(
+            status(AccessPointStatusId::Status, b"+UWAPSTAT:3,0").into_enabled(),
+            Some(false)
+        );
+        assert_eq!(
+            status(AccessPointStatusId::SSID, b"+UWAPSTAT:0,\"UBXWifi\"")
+                .into_ssid()
+                .as_deref(),
+            Some("UBXWifi")
+        );
+        let channel = status(AccessPointStatusId::Channel, b"+UWAPSTAT:2,11");
+        assert_eq!(channel.clone().into_channel(), Some(11));
+        assert_eq!(channel.into_enabled(), None);
+    }
+
+    #[test]
+    fn wifi_status_rssi() {
+        let rssi = |resp: &[u8]| {
+            GetWifiStatus {
+                status_id: StatusId::Rssi,
+            }
+            .parse(Ok(resp))
+            .unwrap()
+            .status_id
+            .into_rssi()
+        };
+
+        assert_eq!(rssi(b"+UWSSTAT:6,-61\r\n"), Ok(Some(-61)));
+        assert_eq!(rssi(b"+UWSSTAT:6,-32768\r\n"), Ok(None));
+        // Reported as a u32 by some firmware
+        assert_eq!(rssi(b"+UWSSTAT:6,4294967235\r\n"), Ok(Some(-61)));
+        assert_eq!(rssi(b"+UWSSTAT:6,4294934528\r\n"), Ok(None));
+
+        assert_eq!(i16::try_from(WifiStatus::Rssi(4294967235)), Ok(-61));
+        assert_eq!(i16::try_from(WifiStatus::Rssi(-32768)), Ok(-32768));
+        assert!(i16::try_from(WifiStatus::Channel(6)).is_err());
+    }
+
+    #[test]
+    fn station_config_strings() {
+        assert!(
+            station_config(b"+UWSC:0,2,\"UBXWifi\"\r\n")
+                == WifiStationConfigR::SSID(String::try_from("UBXWifi").unwrap())
+        );
+        assert!(
+            station_config(b"+UWSC:0,8,\"passphrase\"\r\n")
+                == WifiStationConfigR::WpaPskOrPassphrase(String::try_from("passphrase").unwrap())
+        );
+        assert!(
+            station_config(b"+UWSC:0,9,\"secret\"\r\n")
+                == WifiStationConfigR::EAPPassword(String::try_from("secret").unwrap())
+        );
+        assert!(
+            station_config(b"+UWSC:0,10,\"user\"\r\n")
+                == WifiStationConfigR::UserName(String::try_from("user").unwrap())
+        );
+        assert!(
+            station_config(b"+UWSC:0,11,\"example.com\"\r\n")
+                == WifiStationConfigR::DomainName(String::try_from("example.com").unwrap())
+        );
+        assert!(
+            station_config(b"+UWSC:0,12,\"client.crt\"\r\n")
+                == WifiStationConfigR::ClientCertificateName(
+                    String::try_from("client.crt").unwrap()
+                )
+        );
+        assert!(
+            station_config(b"+UWSC:0,13,\"client.key\"\r\n")
+                == WifiStationConfigR::ClientPrivateKey(String::try_from("client.key").unwrap())
+        );
+        assert!(
+            station_config(b"+UWSC:0,14,\"ca.crt\"\r\n")
+                == WifiStationConfigR::CACertificateName(String::try_from("ca.crt").unwrap())
+        );
+    }
+
+    #[test]
+    fn station_config_wep_keys() {
+        assert!(
+            station_config(b"+UWSC:0,6,\"0102030405\"\r\n")
+                == WifiStationConfigR::WEPKeys(
+                    String::try_from("0102030405").unwrap(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+        );
+        assert!(
+            station_config(
+                b"+UWSC:0,6,\"0102030405060708090A0B0C0D\",\"0102030405\",\"\",\"\",\"\"\r\n"
+            ) == WifiStationConfigR::WEPKeys(
+                String::try_from("0102030405060708090A0B0C0D").unwrap(),
+                Some(String::try_from("0102030405").unwrap()),
+                Some(String::new()),
+                Some(String::new()),
+                Some(String::new()),
+            )
+        );
+        assert!(station_config(b"+UWSC:0,7,1\r\n") == WifiStationConfigR::ActiveKey(1));
+    }
+
+    #[test]
+    fn station_config_enums() {
+        assert!(
+            station_config(b"+UWSC:0,0,1\r\n") == WifiStationConfigR::ActiveOnStartup(OnOff::On)
+        );
+        assert!(
+            station_config(b"+UWSC:0,5,2\r\n")
+                == WifiStationConfigR::Authentication(Authentication::WpaWpa2Psk)
+        );
+        assert!(
+            station_config(b"+UWSC:0,15,0\r\n")
+                == WifiStationConfigR::ValidateCACertificate(OnOff::Off)
+        );
+        assert!(
+            station_config(b"+UWSC:0,100,2\r\n") == WifiStationConfigR::IPv4Mode(IPv4Mode::DHCP)
+        );
+        assert!(
+            station_config(b"+UWSC:0,106,0\r\n")
+                == WifiStationConfigR::AddressConflictDetection(OnOff::Off)
+        );
+        assert!(
+            station_config(b"+UWSC:0,200,1\r\n")
+                == WifiStationConfigR::IPv6Mode(IPv6Mode::LinkLocalIPAddress)
+        );
+        assert!(
+            station_config(b"+UWSC:0,300,16\r\n")
+                == WifiStationConfigR::WiFiBeaconListenInterval(16)
+        );
+        assert!(
+            station_config(b"+UWSC:0,301,1\r\n") == WifiStationConfigR::DTIMInPowerSave(OnOff::On)
+        );
+    }
+
+    #[test]
+    fn station_config_addresses() {
+        assert!(
+            station_config(b"+UWSC:0,101,192.168.0.10\r\n")
+                == WifiStationConfigR::IPv4Address(Ipv4Addr::new(192, 168, 0, 10))
+        );
+        assert!(
+            station_config(b"+UWSC:0,102,255.255.255.0\r\n")
+                == WifiStationConfigR::SubnetMask(Ipv4Addr::new(255, 255, 255, 0))
+        );
+        assert!(
+            station_config(b"+UWSC:0,103,192.168.0.1\r\n")
+                == WifiStationConfigR::DefaultGateway(Ipv4Addr::new(192, 168, 0, 1))
+        );
+        assert!(
+            station_config(b"+UWSC:0,104,8.8.8.8\r\n")
+                == WifiStationConfigR::DNSServer1(Ipv4Addr::new(8, 8, 8, 8))
+        );
+        assert!(
+            station_config(b"+UWSC:0,105,0.0.0.0\r\n")
+                == WifiStationConfigR::DNSServer2(Ipv4Addr::UNSPECIFIED)
+        );
+        assert!(
+            station_config(b"+UWSC:0,201,fe80::1\r\n")
+                == WifiStationConfigR::IPv6LinkLocalAddress(Ipv6Addr::new(
+                    0xfe80, 0, 0, 0, 0, 0, 0, 1
+                ))
+        );
+    }
+
+    #[test]
+    fn channel_list() {
+        let mut buf = [0u8; 16];
+        let len = ResetChannelList.write(&mut buf);
+        assert_eq!(&buf[..len], b"AT+UWCL\r\n");
+
+        let resp = GetChannelList.parse(Ok(b"+UWCL:1,6,11,36\r\n")).unwrap();
+        assert_eq!(resp.channels, [1, 6, 11, 36]);
+        let resp = GetChannelList.parse(Ok(b"+UWCL:")).unwrap();
+        assert!(resp.channels.is_empty());
+        assert!(GetChannelList.parse(Ok(b"+UWCL:1,x")).is_err());
+    }
+}
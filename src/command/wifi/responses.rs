@@ -20,6 +20,25 @@ pub struct WifiScanResponse {
     pub network_list: Vec<ScannedWifiNetwork, 32>,
 }
 
+/// 7.3 Scan +UWSCAN, as visited by [`WifiScanWith`](super::WifiScanWith)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiScanWithResponse<B> {
+    /// Number of networks handed to the visitor.
+    pub visited: usize,
+    /// Value the visitor stopped the scan with, if it did.
+    pub stopped: Option<B>,
+}
+
+impl<B> atat::AtatResp for WifiScanWithResponse<B> {}
+
+/// 7.4 Channel list +UWCL
+#[derive(Debug, Clone)]
+pub struct ChannelListResponse {
+    pub channels: Vec<u8, 64>,
+}
+
+impl atat::AtatResp for ChannelListResponse {}
+
 /// 7.5 Wi-Fi station status +UWSSTAT
 #[derive(Clone, AtatResp)]
 pub struct WifiStatusResponse {
@@ -51,16 +70,22 @@ pub struct WifiAPStatusResponse {
 }
 
 /// 7.11 Wi-Fi Access point station list +UWAPSTALIST
-#[derive(Clone, AtatResp)]
+#[derive(Debug, Clone)]
 pub struct WiFiAPStationListResponse {
-    #[at_arg(position = 0)]
-    pub id: u32,
-    #[at_arg(position = 1)]
-    pub mac_addr: Bytes<12>,
-    #[at_arg(position = 2)]
-    pub rssi: i32,
+    pub stations: Vec<ApStation, 10>,
 }
 
+impl atat::AtatResp for WiFiAPStationListResponse {}
+
+/// 7.11 Wi-Fi Access point station list +UWAPSTALIST
+#[derive(Debug, Clone)]
+pub struct WiFiAPStationCountResponse {
+    /// Number of stations connected to the access point.
+    pub count: usize,
+}
+
+impl atat::AtatResp for WiFiAPStationCountResponse {}
+
 /// 7.11 Wi-Fi Access point station list +UWAPSTALIST
 #[derive(Clone, AtatResp)]
 pub struct WifiMacResponse {
@@ -5,7 +5,7 @@ use atat::atat_derive::AtatEnum;
 use atat::heapless_bytes::Bytes;
 use core::net::{Ipv4Addr, Ipv6Addr};
 use heapless::{String, Vec};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, PartialEq, AtatEnum)]
 #[repr(u16)]
@@ -23,6 +23,10 @@ pub enum WifiStationConfigParameter {
     /// - 3: LEAP
     /// - 4: PEAP
     /// - 5: EAP-TLS
+    /// - 6: WPA2/WPA3 PSK transition mode, software versions 8.0.0 onwards
+    ///   (provisional, `provisional-at` feature)
+    /// - 7: WPA3 SAE, software versions 8.0.0 onwards (provisional,
+    ///   `provisional-at` feature)
     Authentication = 5,
     /// WEP Keys - <param_val1>...<param_val4> are the WEP encryption keys. A
     /// WEP key is either 5 bytes (while using WEP 64), or 13 bytes (while using
@@ -134,6 +138,10 @@ pub enum WifiStationConfig<'a> {
     /// - 3: LEAP
     /// - 4: PEAP
     /// - 5: EAP-TLS
+    /// - 6: WPA2/WPA3 PSK transition mode, software versions 8.0.0 onwards
+    ///   (provisional, `provisional-at` feature)
+    /// - 7: WPA3 SAE, software versions 8.0.0 onwards (provisional,
+    ///   `provisional-at` feature)
     #[at_arg(value = 5)]
     Authentication(Authentication),
     /// WEP Keys - <param_val1>...<param_val4> are the WEP encryption keys. A
@@ -256,8 +264,12 @@ pub enum WifiStationConfig<'a> {
     DTIMInPowerSave(OnOff),
 }
 
+/// Parameter read back with [`GetWifiStationConfig`]. The param_tags for
+/// the Wi-Fi power save settings exceed `u8`.
+///
+/// [`GetWifiStationConfig`]: super::GetWifiStationConfig
 #[derive(Clone, PartialEq, AtatEnum)]
-#[repr(u8)]
+#[repr(u16)]
 pub enum WifiStationConfigR {
     /// <param_val1> decides if the station is active on start up.
     /// - Off (default): Inactive
@@ -274,6 +286,10 @@ pub enum WifiStationConfigR {
     /// - 3: LEAP
     /// - 4: PEAP
     /// - 5: EAP-TLS
+    /// - 6: WPA2/WPA3 PSK transition mode, software versions 8.0.0 onwards
+    ///   (provisional, `provisional-at` feature)
+    /// - 7: WPA3 SAE, software versions 8.0.0 onwards (provisional,
+    ///   `provisional-at` feature)
     #[at_arg(value = 5)]
     Authentication(Authentication),
     /// WEP Keys - <param_val1>...<param_val4> are the WEP encryption keys. A
@@ -404,6 +420,17 @@ pub enum Authentication {
     LEAP = 3,
     PEAP = 4,
     EAPTLS = 5,
+    /// WPA2/WPA3 transition mode, joining with WPA3 SAE or WPA2 PSK, whichever
+    /// the access point offers.
+    ///
+    /// Provisional: the value is not confirmed by the AT commands manual yet.
+    #[cfg(feature = "provisional-at")]
+    Wpa2Wpa3Psk = 6,
+    /// WPA3 Simultaneous Authentication of Equals.
+    ///
+    /// Provisional: the value is not confirmed by the AT commands manual yet.
+    #[cfg(feature = "provisional-at")]
+    Wpa3Psk = 7,
 }
 
 #[derive(Clone, PartialEq, AtatEnum)]
@@ -424,6 +451,17 @@ pub enum WifiStationAction {
     /// It deactivates the specified profile. Disconnects the profile if
     /// connected, and may reconnect to other active network.
     Deactivate = 4,
+    /// Starts WPS push-button configuration on the specified profile. On
+    /// success the negotiated SSID and passphrase are stored in the profile.
+    ///
+    /// Provisional: the value is not confirmed by the AT commands manual yet.
+    #[cfg(feature = "provisional-at")]
+    ActivateWPS = 5,
+    /// Aborts an ongoing WPS push-button configuration.
+    ///
+    /// Provisional: the value is not confirmed by the AT commands manual yet.
+    #[cfg(feature = "provisional-at")]
+    AbortWPS = 6,
 }
 
 #[derive(Debug, Clone, PartialEq, AtatEnum)]
@@ -481,6 +519,17 @@ pub struct ScannedWifiNetwork {
     pub group_ciphers: u8,
 }
 
+/// Station connected to the access point, as listed by +UWAPSTALIST.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ApStation {
+    pub mac: [u8; 6],
+    /// Address of the station, if reported by the firmware.
+    pub ip: Option<Ipv4Addr>,
+    /// RSSI of the station in dBm.
+    pub rssi: i8,
+}
+
 #[derive(Clone, PartialEq, AtatEnum)]
 pub enum WifiStatus {
     #[at_arg(value = 0)]
@@ -498,8 +547,12 @@ pub enum WifiStatus {
     Status(WifiStatusVal),
     /// The <status_val> is the RSSI value of the current connection; will
     /// return-32768, if not connected.
+    ///
+    /// Some firmware reports the value as a u32, see [`WifiStatus::into_rssi`].
+    /// Code matching on an `i16` here can convert with `i16::try_from`
+    /// instead.
     #[at_arg(value = 6)]
-    Rssi(i16),
+    Rssi(i64),
     /// The <status_val> is the mobility domain of the last or current
     /// connection This tag is supported by ODIN-W2 from software version 6.0.0
     /// onwards only.
@@ -512,7 +565,98 @@ pub enum WifiStatus {
     Region(WifiRegion),
 }
 
-#[derive(Clone, PartialEq, AtatEnum)]
+impl WifiStatus {
+    /// The SSID, if this is a [`WifiStatus::SSID`]
+    pub fn into_ssid(self) -> Option<String<64>> {
+        match self {
+            WifiStatus::SSID(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The BSSID, if this is a [`WifiStatus::BSSID`]
+    pub fn into_bssid(self) -> Option<Bytes<20>> {
+        match self {
+            WifiStatus::BSSID(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The RSSI in dBm, if this is a [`WifiStatus::Rssi`], or `None` if not
+    /// connected.
+    ///
+    /// Negative values reported as a u32 are converted back to dBm.
+    pub fn into_rssi(self) -> Result<Option<i16>, atat::Error> {
+        let WifiStatus::Rssi(raw) = self else {
+            return Err(atat::Error::InvalidResponse);
+        };
+        let raw = match u32::try_from(raw) {
+            Ok(raw) if raw > i32::MAX as u32 => raw as i32 as i64,
+            _ => raw,
+        };
+        match i16::try_from(raw) {
+            Ok(i16::MIN) => Ok(None),
+            Ok(rssi) => Ok(Some(rssi)),
+            Err(_) => Err(atat::Error::InvalidResponse),
+        }
+    }
+
+    /// The mobility domain, if this is a [`WifiStatus::MobilityDomain`]
+    pub fn into_mobility_domain(self) -> Option<String<64>> {
+        match self {
+            WifiStatus::MobilityDomain(m) => Some(m),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<WifiStatus> for WifiStatusVal {
+    type Error = atat::Error;
+
+    fn try_from(status: WifiStatus) -> Result<Self, Self::Error> {
+        match status {
+            WifiStatus::Status(s) => Ok(s),
+            _ => Err(atat::Error::InvalidResponse),
+        }
+    }
+}
+
+impl TryFrom<WifiStatus> for WifiRegion {
+    type Error = atat::Error;
+
+    fn try_from(status: WifiStatus) -> Result<Self, Self::Error> {
+        match status {
+            WifiStatus::Region(r) => Ok(r),
+            _ => Err(atat::Error::InvalidResponse),
+        }
+    }
+}
+
+/// The RSSI in dBm as an `i16`, the type [`WifiStatus::Rssi`] held before
+/// it was widened, with `-32768` if not connected.
+///
+/// Prefer [`WifiStatus::into_rssi`], which maps `-32768` to `None`.
+impl TryFrom<WifiStatus> for i16 {
+    type Error = atat::Error;
+
+    fn try_from(status: WifiStatus) -> Result<Self, Self::Error> {
+        Ok(status.into_rssi()?.unwrap_or(i16::MIN))
+    }
+}
+
+impl TryFrom<WifiStatus> for u8 {
+    type Error = atat::Error;
+
+    fn try_from(status: WifiStatus) -> Result<Self, Self::Error> {
+        match status {
+            WifiStatus::Channel(c) => Ok(c),
+            _ => Err(atat::Error::InvalidResponse),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, AtatEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum WifiStatusVal {
     Disabled = 0,
@@ -520,7 +664,8 @@ pub enum WifiStatusVal {
     Connected = 2,
 }
 
-#[derive(Clone, PartialEq, AtatEnum)]
+#[derive(Debug, Clone, PartialEq, AtatEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum WifiRegion {
     World = 0,
@@ -531,6 +676,7 @@ pub enum WifiRegion {
 }
 
 #[derive(Clone, PartialEq, AtatEnum)]
+#[repr(u8)]
 pub enum WifiConfigParameter {
     /// Wi-Fi enabled
     WifiEnabled = 0,
@@ -676,6 +822,12 @@ pub enum WifiConfigParameter {
     /// Maximum TLS version. Default: TLS v1.2 Supported software versions 7.0.2
     /// onwards
     MaximumTlsVersion = 25,
+    /// Minimum Protected Management Frames (PMF) requirement of all station
+    /// connections
+    ///
+    /// Provisional: the tag is not confirmed by the AT commands manual yet.
+    #[cfg(feature = "provisional-at")]
+    PMFMode = 26,
 }
 
 #[derive(Clone, PartialEq, AtatEnum)]
@@ -829,9 +981,20 @@ pub enum WifiConfig {
     /// onwards
     #[at_arg(value = 25)]
     MaximumTlsVersion(TLSVersion),
+    /// Minimum Protected Management Frames (PMF) requirement of all station
+    /// connections, on top of the PMF setting of each network.
+    /// - Disable: PMF is not used
+    /// - Optional (default): PMF is used if the access point supports it
+    /// - Required: Only connect to access points advertising PMF capability
+    ///
+    /// Provisional: the tag is not confirmed by the AT commands manual yet.
+    #[cfg(feature = "provisional-at")]
+    #[at_arg(value = 26)]
+    PMFMode(PMF),
 }
 
-#[derive(Clone, PartialEq, AtatEnum)]
+#[derive(Debug, Clone, PartialEq, AtatEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum WifiMode {
     Disable = 0,
@@ -839,7 +1002,8 @@ pub enum WifiMode {
     Auto = 2,
 }
 
-#[derive(Clone, PartialEq, AtatEnum)]
+#[derive(Debug, Clone, PartialEq, AtatEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum PowerSaveMode {
     ActiveMode = 0,
@@ -868,7 +1032,8 @@ pub enum ScanType {
     PassiveScan = 0,
 }
 
-#[derive(Clone, PartialEq, AtatEnum)]
+#[derive(Debug, Clone, PartialEq, AtatEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum TLSVersion {
     TLSv1_0 = 1,
@@ -907,6 +1072,10 @@ pub enum AccessPointConfig<'a> {
     /// - 2 (default): WPA2 (AES-CCMP)
     /// - 3: WPA/WPA2 Mixed mode (RC4-TKIP + AES-CCMP)
     /// - 4: WPA (RC4-TKIP)
+    /// - 5: WPA2/WPA3 transition mode (AES-CCMP), software versions 8.0.0
+    ///   onwards (provisional, `provisional-at` feature)
+    /// - 6: WPA3 SAE (AES-CCMP), software versions 8.0.0 onwards
+    ///   (provisional, `provisional-at` feature)
     ///
     /// <param_val2>:
     /// - 1: Open
@@ -984,8 +1153,15 @@ pub enum AccessPointConfig<'a> {
     #[at_arg(value = 19)]
     WhiteList(
         #[at_arg(len = 20)] &'a str,
-        #[at_arg(len = 20)] &'a str,
-        #[at_arg(len = 20)] &'a str,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
     ),
     /// Black List - <param_val1>...<param_val10> List of MAC addresses of
     /// stations that will be rejected or 0 to not reject any. The factory
@@ -993,9 +1169,21 @@ pub enum AccessPointConfig<'a> {
     #[at_arg(value = 20)]
     BlackList(
         #[at_arg(len = 20)] &'a str,
-        #[at_arg(len = 20)] &'a str,
-        #[at_arg(len = 20)] &'a str,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
+        #[at_arg(len = 20)] Option<&'a str>,
     ),
+    /// Max number of stations - <param_val1> is the maximum number of
+    /// stations allowed to connect to the access point. Valid values are 1 to
+    /// 10. Supported software versions 6.0.0 onwards
+    #[at_arg(value = 21)]
+    MaxStations(u8),
     /// IPv4 Mode - <param_val1> to set the way to retrieve an IP address
     /// - 1:(default) Static
     #[at_arg(value = 100)]
@@ -1040,10 +1228,58 @@ pub enum AccessPointConfig<'a> {
     /// bit MAC identifier. The factory default value is:
     #[at_arg(value = 201)]
     IPv6LinkLocalAddress(#[at_arg(len = 40)] Ipv6Addr),
+    /// <param_val> is the IPv6 global address of the access point.
+    #[at_arg(value = 204)]
+    IPv6GlobalAddress(#[at_arg(len = 40)] Ipv6Addr),
+    /// Derive the global address prefix from router advertisements (SLAAC).
+    /// The factory default value is 0 (disabled).
+    /// - 0: Disabled
+    /// - 1: Enabled
+    #[at_arg(value = 205)]
+    IPv6PrefixFromRA(OnOff),
     /// <param_val> is the DTIM interval. The factory default value is 1. Valid
     /// values are 1 to 100.
     #[at_arg(value = 300)]
-    DTIM(u8),
+    DTIM(DtimInterval),
+}
+
+/// DTIM interval of an access point, in beacon intervals, 1 to 100.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DtimInterval(u8);
+
+impl DtimInterval {
+    /// Factory default interval, every beacon is a DTIM.
+    pub const DEFAULT: Self = Self(1);
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl Default for DtimInterval {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl TryFrom<u8> for DtimInterval {
+    type Error = crate::error::Error;
+
+    /// Fails with [`Error::OutOfRange`](crate::error::Error::OutOfRange)
+    /// unless `interval` is 1 to 100.
+    fn try_from(interval: u8) -> Result<Self, Self::Error> {
+        if (1..=100).contains(&interval) {
+            Ok(Self(interval))
+        } else {
+            Err(crate::error::Error::OutOfRange)
+        }
+    }
+}
+
+impl atat::AtatLen for DtimInterval {
+    const LEN: usize = <u8 as atat::AtatLen>::LEN;
+    const ESCAPED_LEN: usize = <u8 as atat::AtatLen>::ESCAPED_LEN;
 }
 
 #[derive(Clone, PartialEq, AtatEnum)]
@@ -1062,7 +1298,13 @@ pub enum AccessPointConfigParameter {
     /// - 1: Open
     /// - 2 (default): WPA2 (AES-CCMP)
     /// - 3: WPA/WPA2 Mixed mode (RC4-TKIP + AES-CCMP)
-    /// - 4: WPA (RC4-TKIP) <param_val2>:
+    /// - 4: WPA (RC4-TKIP)
+    /// - 5: WPA2/WPA3 transition mode (AES-CCMP), software versions 8.0.0
+    ///   onwards (provisional, `provisional-at` feature)
+    /// - 6: WPA3 SAE (AES-CCMP), software versions 8.0.0 onwards
+    ///   (provisional, `provisional-at` feature)
+    ///
+    /// <param_val2>:
     /// - 1: Open
     /// - 2 (default): Pre shared key PSK
     SecurityMode = 5,
@@ -1133,6 +1375,10 @@ pub enum AccessPointConfigParameter {
     /// stations that will be rejected or 0 to not reject any. The factory
     /// default is 0.
     BlackList = 20,
+    /// Max number of stations - <param_val1> is the maximum number of
+    /// stations allowed to connect to the access point. Valid values are 1 to
+    /// 10. Supported software versions 6.0.0 onwards
+    MaxStations = 21,
     /// IPv4 Mode - <param_val1> to set the way to retrieve an IP address
     /// - 1:(default) Static
     IPv4Mode = 100,
@@ -1167,6 +1413,10 @@ pub enum AccessPointConfigParameter {
     /// link local address is automatically generated from the interface IEEE 48
     /// bit MAC identifier. The factory default value is:
     IPv6LinkLocalAddress = 201,
+    /// <param_val> is the IPv6 global address of the access point.
+    IPv6GlobalAddress = 204,
+    /// Derive the global address prefix from router advertisements (SLAAC).
+    IPv6PrefixFromRA = 205,
     /// <param_val> is the DTIM interval. The factory default value is 1. Valid
     /// values are 1 to 100.
     DTIM = 301,
@@ -1191,7 +1441,13 @@ pub enum AccessPointConfigResponse {
     /// - 1: Open
     /// - 2 (default): WPA2 (AES-CCMP)
     /// - 3: WPA/WPA2 Mixed mode (RC4-TKIP + AES-CCMP)
-    /// - 4: WPA (RC4-TKIP) <param_val2>:
+    /// - 4: WPA (RC4-TKIP)
+    /// - 5: WPA2/WPA3 transition mode (AES-CCMP), software versions 8.0.0
+    ///   onwards (provisional, `provisional-at` feature)
+    /// - 6: WPA3 SAE (AES-CCMP), software versions 8.0.0 onwards
+    ///   (provisional, `provisional-at` feature)
+    ///
+    /// <param_val2>:
     /// - 1: Open
     /// - 2 (default): Pre shared key PSK
     #[at_arg(value = 5)]
@@ -1271,6 +1527,11 @@ pub enum AccessPointConfigResponse {
     /// default is 0.
     #[at_arg(value = 20)]
     BlackList(String<64>, String<64>, String<64>),
+    /// Max number of stations - <param_val1> is the maximum number of
+    /// stations allowed to connect to the access point. Valid values are 1 to
+    /// 10. Supported software versions 6.0.0 onwards
+    #[at_arg(value = 21)]
+    MaxStations(u8),
     /// IPv4 Mode - <param_val1> to set the way to retrieve an IP address
     /// - 1:(default) Static
     #[at_arg(value = 100)]
@@ -1315,6 +1576,15 @@ pub enum AccessPointConfigResponse {
     /// bit MAC identifier. The factory default value is:
     #[at_arg(value = 201)]
     IPv6LinkLocalAddress(#[at_arg(len = 40)] Ipv6Addr),
+    /// <param_val> is the IPv6 global address of the access point.
+    #[at_arg(value = 204)]
+    IPv6GlobalAddress(#[at_arg(len = 40)] Ipv6Addr),
+    /// Derive the global address prefix from router advertisements (SLAAC).
+    /// The factory default value is 0 (disabled).
+    /// - 0: Disabled
+    /// - 1: Enabled
+    #[at_arg(value = 205)]
+    IPv6PrefixFromRA(OnOff),
     /// <param_val> is the DTIM interval. The factory default value is 1. Valid
     /// values are 1 to 100.
     #[at_arg(value = 301)]
@@ -1328,6 +1598,16 @@ pub enum SecurityMode {
     Wpa2AesCcmp = 2,
     WpaWpa2Mixed = 3,
     WpaRC4Tkip = 4,
+    /// WPA2/WPA3 transition mode, requires PMF to be at least optional.
+    ///
+    /// Provisional: the value is not confirmed by the AT commands manual yet.
+    #[cfg(feature = "provisional-at")]
+    Wpa2Wpa3Mixed = 5,
+    /// WPA3 SAE, requires PMF.
+    ///
+    /// Provisional: the value is not confirmed by the AT commands manual yet.
+    #[cfg(feature = "provisional-at")]
+    Wpa3Sae = 6,
 }
 
 #[derive(Clone, PartialEq, AtatEnum)]
@@ -1428,6 +1708,41 @@ pub enum AccessPointStatus {
     Status(OnOff),
 }
 
+impl AccessPointStatus {
+    /// The SSID, if this is a [`AccessPointStatus::SSID`]
+    pub fn into_ssid(self) -> Option<String<64>> {
+        match self {
+            AccessPointStatus::SSID(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The BSSID, if this is a [`AccessPointStatus::BSSID`]
+    pub fn into_bssid(self) -> Option<Bytes<20>> {
+        match self {
+            AccessPointStatus::BSSID(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The channel, if this is a [`AccessPointStatus::Channel`]
+    pub fn into_channel(self) -> Option<u8> {
+        match self {
+            AccessPointStatus::Channel(c) => c.try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// Whether the access point is enabled, if this is a
+    /// [`AccessPointStatus::Status`]
+    pub fn into_enabled(self) -> Option<bool> {
+        match self {
+            AccessPointStatus::Status(s) => Some(s.into()),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, AtatEnum)]
 pub enum AccessPointStatusValue {
     Unsigned(u8),
@@ -12,6 +12,26 @@ pub trait WifiConfig<'a> {
     const FLOW_CONTROL: bool = false;
     const BAUD_RATE: BaudRate = DEFAULT_BAUD_RATE;
 
+    /// Probe the module at [`Self::BAUD_RATE`] without resetting it first,
+    /// assuming it was configured on an earlier boot. Falls back to resetting
+    /// the module and detecting its baud rate if it does not answer.
+    const ASSUME_CONFIGURED: bool = false;
+
+    /// Do not store changed RS232 settings to NVM, which also avoids the
+    /// reboot needed to commit them. The settings are then reapplied on every
+    /// boot.
+    const SKIP_NVM_STORE: bool = false;
+
+    /// Number of consecutive failed initialization attempts after which the
+    /// runner gives up until a reset is requested. Retries forever if `None`.
+    const MAX_INIT_ATTEMPTS: Option<u32> = None;
+
+    /// Period without any data from the module after which the runner probes
+    /// it with a keep-alive, resetting it if two keep-alives in a row go
+    /// unanswered. The link is not supervised if `None`.
+    #[cfg(feature = "internal-network-stack")]
+    const SUPERVISION_TIMEOUT: Option<embassy_time::Duration> = None;
+
     #[cfg(feature = "internal-network-stack")]
     const TLS_IN_BUFFER_SIZE: Option<u16> = None;
     #[cfg(feature = "internal-network-stack")]
@@ -20,6 +40,15 @@ pub trait WifiConfig<'a> {
     #[cfg(feature = "ppp")]
     const PPP_CONFIG: embassy_net_ppp::Config<'a>;
 
+    /// Address of the module on the PPP link, to which AT commands are sent
+    /// over UDP while the serial link is in PPP mode.
+    #[cfg(feature = "ppp")]
+    const PPP_AT_ADDRESS: embassy_net::Ipv4Address = embassy_net::Ipv4Address::new(172, 30, 0, 251);
+    /// UDP port on which the module accepts AT commands on the PPP link. The
+    /// local end of the side channel is bound to the same port.
+    #[cfg(feature = "ppp")]
+    const PPP_AT_PORT: u16 = 23;
+
     fn reset_pin(&mut self) -> Option<&mut Self::ResetPin> {
         None
     }
@@ -1,5 +1,6 @@
-use core::net::Ipv4Addr;
+use core::net::{Ipv4Addr, Ipv6Addr};
 
+use crate::asynch::IpFamily;
 use crate::network::{WifiMode, WifiNetwork};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -12,6 +13,17 @@ pub enum WiFiState {
     Connected,
 }
 
+/// Network interface carrying the traffic of the network stack.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Uplink {
+    /// The WiFi station interface.
+    #[default]
+    WiFi,
+    /// The Ethernet interface.
+    Ethernet,
+}
+
 /// Static IP address configuration.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StaticConfigV4 {
@@ -25,6 +37,13 @@ pub struct StaticConfigV4 {
     pub dns_servers: DnsServers,
 }
 
+/// IPv6 address configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticConfigV6 {
+    /// Link-local address.
+    pub link_local: Ipv6Addr,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DnsServers {
     pub primary: Option<Ipv4Addr>,
@@ -38,6 +57,8 @@ pub struct WifiConnection {
     #[cfg(feature = "ipv6")]
     pub ipv6_up: bool,
     pub network: Option<WifiNetwork>,
+    /// RSSI in dBm measured when the station link came up.
+    pub rssi: Option<i16>,
 }
 
 impl WifiConnection {
@@ -46,6 +67,7 @@ impl WifiConnection {
             wifi_state: WiFiState::Inactive,
             ipv6_link_local_up: false,
             network: None,
+            rssi: None,
             ipv4_up: false,
             #[cfg(feature = "ipv6")]
             ipv6_up: false,
@@ -68,28 +90,35 @@ impl WifiConnection {
     /// Get whether the network stack has a valid IP configuration.
     /// This is true if the network stack has a static IP configuration or if DHCP has completed
     pub fn is_config_up(&self) -> bool {
-        let v6_up;
-        let v4_up = self.ipv4_up;
+        self.is_config_up_for(IpFamily::default())
+    }
 
+    /// Get whether the network stack has a valid IP configuration for the
+    /// given address family.
+    ///
+    /// The IPv6 link-local address is typically attached before DHCPv4 has
+    /// completed, so it is never sufficient on its own.
+    pub fn is_config_up_for(&self, family: IpFamily) -> bool {
         #[cfg(feature = "ipv6")]
-        {
-            v6_up = self.ipv6_up;
-        }
+        let v6_up = self.ipv6_up;
         #[cfg(not(feature = "ipv6"))]
-        {
-            v6_up = false;
-        }
+        let v6_up = false;
 
-        (v4_up || v6_up) && self.ipv6_link_local_up
+        ip_config_up(family, self.ipv4_up, v6_up, self.ipv6_link_local_up)
     }
 
     pub fn is_connected(&self) -> bool {
         self.is_config_up() && self.wifi_state == WiFiState::Connected
     }
+
+    pub fn is_connected_for(&self, family: IpFamily) -> bool {
+        self.is_config_up_for(family) && self.wifi_state == WiFiState::Connected
+    }
     pub fn reset(&mut self) {
         self.wifi_state = WiFiState::Inactive;
         self.ipv6_link_local_up = false;
         self.network = None;
+        self.rssi = None;
         self.ipv4_up = false;
         #[cfg(feature = "ipv6")]
         {
@@ -97,3 +126,67 @@ impl WifiConnection {
         }
     }
 }
+
+/// State of the Ethernet interface, tracked separately from the WiFi
+/// connection.
+pub struct EthernetConnection {
+    /// Whether the Ethernet PHY reports a link.
+    pub link_up: bool,
+    pub ipv6_link_local_up: bool,
+    pub ipv4_up: bool,
+    #[cfg(feature = "ipv6")]
+    pub ipv6_up: bool,
+    /// Network interface id reported by the module, once it is known.
+    pub interface_id: Option<u8>,
+}
+
+impl EthernetConnection {
+    pub(crate) const fn new() -> Self {
+        EthernetConnection {
+            link_up: false,
+            ipv6_link_local_up: false,
+            ipv4_up: false,
+            #[cfg(feature = "ipv6")]
+            ipv6_up: false,
+            interface_id: None,
+        }
+    }
+
+    /// Get whether the Ethernet interface has a valid IP configuration for
+    /// the given address family.
+    pub fn is_config_up_for(&self, family: IpFamily) -> bool {
+        #[cfg(feature = "ipv6")]
+        let v6_up = self.ipv6_up;
+        #[cfg(not(feature = "ipv6"))]
+        let v6_up = false;
+
+        ip_config_up(family, self.ipv4_up, v6_up, self.ipv6_link_local_up)
+    }
+
+    pub fn is_connected_for(&self, family: IpFamily) -> bool {
+        self.link_up && self.is_config_up_for(family)
+    }
+
+    /// Forget the IP configuration, keeping the link and interface id.
+    pub fn reset_config(&mut self) {
+        self.ipv6_link_local_up = false;
+        self.ipv4_up = false;
+        #[cfg(feature = "ipv6")]
+        {
+            self.ipv6_up = false;
+        }
+    }
+}
+
+/// Whether the address for `family` is up, in addition to the IPv6 link-local
+/// address.
+fn ip_config_up(family: IpFamily, v4_up: bool, v6_up: bool, link_local_up: bool) -> bool {
+    let up = match family {
+        IpFamily::V4 => v4_up,
+        #[cfg(feature = "ipv6")]
+        IpFamily::V6 => v6_up,
+        IpFamily::Any => v4_up || v6_up,
+    };
+
+    up && link_local_up
+}
@@ -1,7 +1,8 @@
+pub use crate::at_error::ModuleError;
 #[cfg(feature = "internal-network-stack")]
 pub use ublox_sockets::Error as SocketError;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     Overflow,
@@ -35,12 +36,97 @@ pub enum Error {
     ShadowStoreBug,
     AlreadyConnected,
     NotConnected,
+    PowerLevel(PowerLevelError),
+    /// The Wi-Fi channel is not supported by the module.
+    UnsupportedChannel(u8),
+    /// The channel list holds channels the module is not able to use.
+    ChannelList(ChannelListError),
+    /// The domain name exceeds the maximum length supported by the module.
+    DomainNameTooLong,
+    /// A parameter is outside the range supported by the module.
+    OutOfRange,
+    FirmwareUpdate(FirmwareUpdateError),
+    /// Sockets are still open on the module. Close them first, or force the
+    /// operation.
+    SocketsOpen(usize),
+    /// A list holds more entries than supported by the module.
+    TooManyEntries,
+    /// Error reported by the module.
+    Module(ModuleError),
+    /// The module rejected the command with `+CME ERROR: 10` because it is
+    /// busy, e.g. associating with a network. Retrying may succeed.
+    ModuleBusy,
+    /// The module did not confirm switching to extended data mode.
+    EdmSwitchFailed,
+    /// The feature is not supported by the firmware of the module.
+    UnsupportedFirmware,
+    /// The module reported a network error, e.g. an IP address conflict.
+    NetworkError(crate::command::network::types::NetworkErrorReason),
+    Bridge(BridgeError),
     _Unknown,
 }
 
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Overflow => write!(f, "Overflow"),
+            Error::SetState => write!(f, "Failed to set state"),
+            Error::BadLength => write!(f, "Bad length"),
+            Error::SecurityProblems => write!(f, "Security problems"),
+            Error::Network => write!(f, "Network error"),
+            Error::Pin => write!(f, "Pin error"),
+            Error::BaudDetection => write!(f, "Baud rate detection failed"),
+            Error::SocketClosed => write!(f, "Socket closed"),
+            Error::WrongSocketType => write!(f, "Wrong socket type"),
+            Error::SocketNotFound => write!(f, "Socket not found"),
+            Error::SocketNotConnected => write!(f, "Socket not connected"),
+            Error::MissingSocketSet => write!(f, "Missing socket set"),
+            Error::NoWifiSetup => write!(f, "No Wi-Fi setup"),
+            #[cfg(feature = "internal-network-stack")]
+            Error::Socket(e) => write!(f, "Socket error: {:?}", e),
+            Error::AT(e) => write!(f, "AT error: {:?}", e),
+            Error::Busy => write!(f, "Busy"),
+            Error::InvalidHex => write!(f, "Invalid hex"),
+            Error::Dns(e) => write!(f, "DNS error: {:?}", e),
+            Error::DuplicateCredentials => write!(f, "Duplicate credentials"),
+            Error::Uninitialized => write!(f, "Uninitialized"),
+            Error::Unimplemented => write!(f, "Unimplemented"),
+            Error::SocketMemory => write!(f, "Out of socket memory"),
+            Error::SocketMapMemory => write!(f, "Out of socket map memory"),
+            Error::Supplicant => write!(f, "Supplicant error"),
+            Error::Timeout => write!(f, "Timeout"),
+            Error::ShadowStoreBug => write!(f, "Shadow store bug"),
+            Error::AlreadyConnected => write!(f, "Already connected"),
+            Error::NotConnected => write!(f, "Not connected"),
+            Error::PowerLevel(e) => write!(f, "Power level: {}", e),
+            Error::UnsupportedChannel(channel) => write!(f, "Unsupported channel {}", channel),
+            Error::ChannelList(e) => write!(f, "Channel list: {}", e),
+            Error::DomainNameTooLong => write!(f, "Domain name too long"),
+            Error::OutOfRange => write!(f, "Out of range"),
+            Error::FirmwareUpdate(e) => write!(f, "Firmware update: {}", e),
+            Error::SocketsOpen(n) => write!(f, "{} sockets open", n),
+            Error::TooManyEntries => write!(f, "Too many entries"),
+            Error::Module(e) => write!(f, "Module error: {}", e),
+            Error::ModuleBusy => write!(f, "Module busy"),
+            Error::EdmSwitchFailed => write!(f, "Failed to switch to EDM"),
+            Error::UnsupportedFirmware => write!(f, "Unsupported firmware"),
+            Error::NetworkError(reason) => write!(f, "Network error: {:?}", reason),
+            Error::Bridge(e) => write!(f, "Bridge: {}", e),
+            Error::_Unknown => write!(f, "Unknown error"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
 impl From<atat::Error> for Error {
     fn from(e: atat::Error) -> Self {
-        Error::AT(e)
+        match e {
+            atat::Error::CmeError(code) if code as u16 == ModuleError::BUSY_CODE => {
+                Error::ModuleBusy
+            }
+            e => Error::AT(e),
+        }
     }
 }
 
@@ -50,6 +136,33 @@ impl From<embassy_time::TimeoutError> for Error {
     }
 }
 
+impl From<PowerLevelError> for Error {
+    fn from(e: PowerLevelError) -> Self {
+        Error::PowerLevel(e)
+    }
+}
+
+impl From<ModuleError> for Error {
+    fn from(e: ModuleError) -> Self {
+        match e {
+            ModuleError::Code(ModuleError::BUSY_CODE) => Error::ModuleBusy,
+            e => Error::Module(e),
+        }
+    }
+}
+
+impl From<FirmwareUpdateError> for Error {
+    fn from(e: FirmwareUpdateError) -> Self {
+        Error::FirmwareUpdate(e)
+    }
+}
+
+impl From<BridgeError> for Error {
+    fn from(e: BridgeError) -> Self {
+        Error::Bridge(e)
+    }
+}
+
 #[cfg(feature = "internal-network-stack")]
 impl From<ublox_sockets::Error> for Error {
     fn from(e: ublox_sockets::Error) -> Self {
@@ -57,6 +170,104 @@ impl From<ublox_sockets::Error> for Error {
     }
 }
 
+/// Error that occurs when attempting to set the Wi-Fi transmit power level.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerLevelError {
+    /// Valid values are 0-20 dBm and 255 (adaptive).
+    OutOfRange,
+    /// The detected module family supports at most `max` dBm.
+    ///
+    /// Provisional, see the `provisional-at` feature.
+    #[cfg(feature = "provisional-at")]
+    ExceedsMaximum { max: u8 },
+}
+
+impl core::fmt::Display for PowerLevelError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PowerLevelError::OutOfRange => write!(f, "Out of range"),
+            #[cfg(feature = "provisional-at")]
+            PowerLevelError::ExceedsMaximum { max } => write!(f, "Exceeds maximum of {} dBm", max),
+        }
+    }
+}
+
+impl core::error::Error for PowerLevelError {}
+
+/// Error that occurs when attempting to set the Wi-Fi channel list.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelListError {
+    /// Channels not supported by the module.
+    pub unsupported: heapless::Vec<u8, 10>,
+    /// Channels the module disables in the current region.
+    pub restricted: heapless::Vec<u8, 10>,
+}
+
+impl core::fmt::Display for ChannelListError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Unsupported channels {:?}, restricted channels {:?}",
+            self.unsupported, self.restricted
+        )
+    }
+}
+
+impl core::error::Error for ChannelListError {}
+
+/// Error that occurs when configuring a network bridge.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BridgeError {
+    /// A bridge needs at least two distinct interfaces.
+    TooFewInterfaces,
+    /// The DHCP server requires a static IPv4 configuration of the bridge.
+    DhcpWithoutAddress,
+    /// The interface must be configured and active before it is bridged.
+    InterfaceInactive(crate::options::BridgeInterface),
+    /// The bridge must be deactivated before it is reconfigured.
+    Active,
+}
+
+impl core::fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BridgeError::TooFewInterfaces => write!(f, "Too few interfaces"),
+            BridgeError::DhcpWithoutAddress => write!(f, "DHCP server without static address"),
+            BridgeError::InterfaceInactive(iface) => write!(f, "{:?} interface inactive", iface),
+            BridgeError::Active => write!(f, "Bridge active"),
+        }
+    }
+}
+
+impl core::error::Error for BridgeError {}
+
+/// Error that occurs during a serial firmware update.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FirmwareUpdateError {
+    /// The boot loader cancelled the transfer.
+    Cancelled,
+    /// A block was not acknowledged after the maximum number of retries.
+    TooManyRetries,
+    /// The module did not report the expected version after the update.
+    VersionMismatch,
+}
+
+impl core::fmt::Display for FirmwareUpdateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FirmwareUpdateError::Cancelled => write!(f, "Cancelled"),
+            FirmwareUpdateError::TooManyRetries => write!(f, "Too many retries"),
+            FirmwareUpdateError::VersionMismatch => write!(f, "Version mismatch"),
+        }
+    }
+}
+
+impl core::error::Error for FirmwareUpdateError {}
+
 /// Error that occurs when attempting to connect to a wireless network.
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -79,6 +290,25 @@ pub enum WifiConnectionError {
     Internal(Error),
 }
 
+impl core::fmt::Display for WifiConnectionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WifiConnectionError::FailedToConnect => write!(f, "Failed to connect"),
+            WifiConnectionError::FailedToDisconnect => write!(f, "Failed to disconnect"),
+            WifiConnectionError::Other { kind } => write!(f, "{}", kind),
+            WifiConnectionError::WaitingForWifiDeactivation => {
+                write!(f, "Waiting for Wi-Fi deactivation")
+            }
+            WifiConnectionError::BufferOverflow => write!(f, "Buffer overflow"),
+            WifiConnectionError::IncompatibleConfigId => write!(f, "Incompatible config id"),
+            WifiConnectionError::Illegal => write!(f, "Illegal state"),
+            WifiConnectionError::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for WifiConnectionError {}
+
 impl From<Error> for WifiConnectionError {
     fn from(e: Error) -> Self {
         WifiConnectionError::Internal(e)
@@ -102,6 +332,20 @@ pub enum WifiError {
     // Other,
 }
 
+impl core::fmt::Display for WifiError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WifiError::WifiDisabled => write!(f, "Wi-Fi disabled"),
+            WifiError::UnexpectedResponse => write!(f, "Unexpected response"),
+            WifiError::InterfaceFailedToOn => write!(f, "Interface failed to switch on"),
+            WifiError::ATError(e) => write!(f, "AT error: {:?}", e),
+            WifiError::HexError => write!(f, "Hex error"),
+        }
+    }
+}
+
+impl core::error::Error for WifiError {}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum WifiHotspotError {
@@ -117,6 +361,19 @@ pub enum WifiHotspotError {
     Internal(Error),
 }
 
+impl core::fmt::Display for WifiHotspotError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WifiHotspotError::CreationFailed => write!(f, "Creation failed"),
+            WifiHotspotError::FailedToStop => write!(f, "Failed to stop"),
+            WifiHotspotError::Other { kind } => write!(f, "{}", kind),
+            WifiHotspotError::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for WifiHotspotError {}
+
 impl From<Error> for WifiHotspotError {
     fn from(e: Error) -> Self {
         WifiHotspotError::Internal(e)
@@ -156,3 +413,135 @@ impl From<atat::Error> for WifiError {
         WifiError::ATError(error)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::ping::types::PingError;
+
+    /// One error of each variant.
+    fn errors() -> std::vec::Vec<Error> {
+        std::vec![
+            Error::Overflow,
+            Error::SetState,
+            Error::BadLength,
+            Error::SecurityProblems,
+            Error::Network,
+            Error::Pin,
+            Error::BaudDetection,
+            Error::SocketClosed,
+            Error::WrongSocketType,
+            Error::SocketNotFound,
+            Error::SocketNotConnected,
+            Error::MissingSocketSet,
+            Error::NoWifiSetup,
+            Error::AT(atat::Error::Timeout),
+            Error::Busy,
+            Error::InvalidHex,
+            Error::Dns(PingError::Timeout),
+            Error::DuplicateCredentials,
+            Error::Uninitialized,
+            Error::Unimplemented,
+            Error::SocketMemory,
+            Error::SocketMapMemory,
+            Error::Supplicant,
+            Error::Timeout,
+            Error::ShadowStoreBug,
+            Error::AlreadyConnected,
+            Error::NotConnected,
+            Error::PowerLevel(PowerLevelError::OutOfRange),
+            Error::UnsupportedChannel(14),
+            Error::ChannelList(ChannelListError {
+                unsupported: heapless::Vec::from_slice(&[14]).unwrap(),
+                restricted: heapless::Vec::new(),
+            }),
+            Error::DomainNameTooLong,
+            Error::OutOfRange,
+            Error::FirmwareUpdate(FirmwareUpdateError::Cancelled),
+            Error::SocketsOpen(1),
+            Error::TooManyEntries,
+            Error::Module(ModuleError::Code(7)),
+            Error::ModuleBusy,
+            Error::EdmSwitchFailed,
+            Error::UnsupportedFirmware,
+            Error::NetworkError(
+                crate::command::network::types::NetworkErrorReason::AddressConflict,
+            ),
+            Error::_Unknown,
+        ]
+    }
+
+    #[cfg(feature = "defmt")]
+    fn assert_format<T: defmt::Format>(_: &T) {}
+
+    #[cfg(feature = "defmt")]
+    #[test]
+    fn errors_implement_format() {
+        errors().iter().for_each(assert_format);
+
+        #[cfg(feature = "internal-network-stack")]
+        assert_format::<Option<SocketError>>(&None);
+
+        assert_format(&WifiConnectionError::Other {
+            kind: WifiError::ATError(atat::Error::Timeout),
+        });
+        assert_format(&WifiConnectionError::Internal(Error::Timeout));
+        assert_format(&WifiHotspotError::Other {
+            kind: WifiError::HexError,
+        });
+        assert_format(&WifiHotspotError::Internal(Error::Timeout));
+    }
+
+    #[test]
+    fn errors_implement_display() {
+        for error in errors() {
+            assert!(!std::format!("{}", error).is_empty(), "{:?}", error);
+        }
+
+        for error in [
+            WifiConnectionError::FailedToConnect,
+            WifiConnectionError::FailedToDisconnect,
+            WifiConnectionError::Other {
+                kind: WifiError::ATError(atat::Error::Timeout),
+            },
+            WifiConnectionError::WaitingForWifiDeactivation,
+            WifiConnectionError::BufferOverflow,
+            WifiConnectionError::IncompatibleConfigId,
+            WifiConnectionError::Illegal,
+            WifiConnectionError::Internal(Error::Timeout),
+        ] {
+            assert!(!std::format!("{}", error).is_empty(), "{:?}", error);
+        }
+
+        for error in [
+            WifiHotspotError::CreationFailed,
+            WifiHotspotError::FailedToStop,
+            WifiHotspotError::Other {
+                kind: WifiError::WifiDisabled,
+            },
+            WifiHotspotError::Other {
+                kind: WifiError::UnexpectedResponse,
+            },
+            WifiHotspotError::Other {
+                kind: WifiError::InterfaceFailedToOn,
+            },
+            WifiHotspotError::Other {
+                kind: WifiError::HexError,
+            },
+            WifiHotspotError::Internal(Error::Timeout),
+        ] {
+            assert!(!std::format!("{}", error).is_empty(), "{:?}", error);
+        }
+
+        for error in [
+            ModuleError::InvalidSsid,
+            ModuleError::SecurityFailure,
+            ModuleError::DhcpTimeout,
+            ModuleError::Unspecified,
+            ModuleError::Code(7),
+            ModuleError::Other(heapless::String::try_from("FAIL").unwrap()),
+        ] {
+            assert!(!std::format!("{}", error).is_empty(), "{:?}", error);
+        }
+    }
+}
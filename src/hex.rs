@@ -29,3 +29,24 @@ pub fn from_hex(hex: &mut [u8]) -> Result<&[u8], FromHexError> {
     }
     Ok(&hex[..len])
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_in_place() {
+        let mut hex = *b"00a1B2fF";
+        assert_eq!(from_hex(&mut hex), Ok(&[0x00, 0xa1, 0xb2, 0xff][..]));
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(from_hex(&mut *b"abc"), Err(FromHexError::OddLength));
+        assert_eq!(
+            from_hex(&mut *b"0g"),
+            Err(FromHexError::InvalidHexCharacter)
+        );
+        assert_eq!(from_hex(&mut []), Ok(&[][..]));
+    }
+}
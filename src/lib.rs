@@ -7,6 +7,11 @@ compile_error!("You may not enable both `ppp` and `internal-network-stack` featu
 #[cfg(not(any(feature = "ppp", feature = "internal-network-stack")))]
 compile_error!("You must enable either `ppp` or `internal-network-stack`.");
 
+// `socket-tcp` and `socket-udp` need no guard of their own, as they enable the
+// sockets of the stack selected above: those of `embassy-net` for `ppp`, and
+// those of `ublox-sockets`, including its `edm` feature, for
+// `internal-network-stack`.
+
 #[cfg(not(any(
     feature = "odin-w2xx",
     feature = "nina-w1xx",
@@ -20,6 +25,8 @@ compile_error!("No module feature activated. You must activate exactly one of th
 mod fmt;
 
 pub mod asynch;
+pub mod at_error;
+pub mod module_caps;
 pub mod options;
 
 mod config;
@@ -32,7 +39,10 @@ pub use atat;
 
 pub mod command;
 pub mod error;
+#[cfg(feature = "trace-at")]
+pub mod trace;
 pub use config::{Transport, WifiConfig};
+pub use connection::{DnsServers, StaticConfigV4};
 
 use command::system::types::BaudRate;
 pub const DEFAULT_BAUD_RATE: BaudRate = BaudRate::B115200;
@@ -0,0 +1,139 @@
+//! Capabilities of the u-blox module selected through the module cargo
+//! feature (`odin-w2xx`, `nina-w1xx`, ...).
+//!
+//! If more than one module feature is enabled, the most capable module takes
+//! precedence.
+
+use crate::command::wifi::types::WifiRegion;
+
+/// Maximum length of an internet domain name in a peer URL.
+///
+/// From u-connectXpress AT commands manual:
+/// <domain> depends on the <scheme>. For internet domain names, the maximum
+/// length is 64 characters.
+/// Domain name length is 128 for NINA-W13 and NINA-W15 software version 4.0
+/// .0 or later.
+#[cfg(not(feature = "nina-w1xx"))]
+pub const MAX_DOMAIN_NAME_LENGTH: usize = 64;
+#[cfg(feature = "nina-w1xx")]
+pub const MAX_DOMAIN_NAME_LENGTH: usize = 128;
+
+/// Maximum number of concurrently connected peers.
+#[cfg(any(feature = "odin-w2xx", feature = "nina-w1xx"))]
+pub const MAX_PEERS: usize = 7;
+#[cfg(not(any(feature = "odin-w2xx", feature = "nina-w1xx")))]
+pub const MAX_PEERS: usize = 3;
+
+/// Maximum payload size of a single EDM data packet.
+pub const EDM_MAX_PAYLOAD: usize = crate::command::edm::types::DATA_PACKAGE_SIZE;
+
+const CHANNELS_2_4_GHZ: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13];
+
+/// Wi-Fi channels the module is able to operate on. Regulatory restrictions
+/// may further limit the channels in use.
+#[cfg(feature = "odin-w2xx")]
+pub const WIFI_CHANNELS: &[u8] = &[
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 36, 40, 44, 48, 52, 56, 60, 64, 100, 104, 108, 112,
+    116, 120, 124, 128, 132, 136, 140, 149, 153, 157, 161, 165,
+];
+#[cfg(all(feature = "nina-w1xx", not(feature = "odin-w2xx")))]
+pub const WIFI_CHANNELS: &[u8] = CHANNELS_2_4_GHZ;
+#[cfg(not(any(feature = "odin-w2xx", feature = "nina-w1xx")))]
+pub const WIFI_CHANNELS: &[u8] = &[];
+
+/// Whether the module supports the 5 GHz band.
+pub const SUPPORTS_5GHZ: bool = WIFI_CHANNELS.len() > CHANNELS_2_4_GHZ.len();
+
+/// Whether `channel` is a Wi-Fi channel supported by the module.
+pub fn is_supported_channel(channel: u8) -> bool {
+    WIFI_CHANNELS.contains(&channel)
+}
+
+/// Channels disabled by the module until it has determined that it operates
+/// outside the FCC region.
+const FCC_RESTRICTED_CHANNELS: &[u8] = &[12, 13, 120, 124, 128];
+
+/// Channels disabled by the module until they are allowed for the current
+/// region, which is never the case in the ETSI region.
+const ETSI_RESTRICTED_CHANNELS: &[u8] = &[149, 153, 157, 161, 165];
+
+/// Whether the module enables `channel` in `region`.
+///
+/// In world mode the module lifts the restrictions at runtime, once it has
+/// determined the region it operates in, so none apply here. The same goes
+/// for DFS channels, which the module clears at runtime in every region.
+pub fn is_channel_allowed(channel: u8, region: &WifiRegion) -> bool {
+    match region {
+        WifiRegion::FCC => !FCC_RESTRICTED_CHANNELS.contains(&channel),
+        WifiRegion::ETSI => !ETSI_RESTRICTED_CHANNELS.contains(&channel),
+        WifiRegion::World | WifiRegion::ALL => true,
+    }
+}
+
+/// Whether the GPIO commands are available for the pins of
+/// [`GPIOId`](crate::command::gpio::types::GPIOId).
+///
+/// The pin ids follow the ODIN-W2 pinout, which other modules do not share.
+pub const SUPPORTS_GPIO: bool = cfg!(feature = "odin-w2xx")
+    || !cfg!(any(
+        feature = "nina-w1xx",
+        feature = "nina-b1xx",
+        feature = "anna-b1xx",
+        feature = "nina-b2xx",
+        feature = "nina-b3xx"
+    ));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn channels_sorted_and_unique() {
+        assert!(WIFI_CHANNELS.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn channels_match_band_support() {
+        assert_eq!(
+            SUPPORTS_5GHZ,
+            WIFI_CHANNELS.iter().any(|&c| c > 14),
+            "5 GHz support must match the channel list"
+        );
+        #[cfg(any(feature = "odin-w2xx", feature = "nina-w1xx"))]
+        assert!(CHANNELS_2_4_GHZ.iter().all(|&c| is_supported_channel(c)));
+        #[cfg(all(feature = "nina-w1xx", not(feature = "odin-w2xx")))]
+        assert!(!SUPPORTS_5GHZ);
+    }
+
+    #[test]
+    fn regulatory_restrictions() {
+        assert!(is_channel_allowed(1, &WifiRegion::World));
+        assert!(is_channel_allowed(13, &WifiRegion::World));
+        assert!(is_channel_allowed(149, &WifiRegion::World));
+
+        assert!(!is_channel_allowed(12, &WifiRegion::FCC));
+        assert!(!is_channel_allowed(124, &WifiRegion::FCC));
+        assert!(is_channel_allowed(149, &WifiRegion::FCC));
+
+        assert!(is_channel_allowed(12, &WifiRegion::ETSI));
+        assert!(is_channel_allowed(13, &WifiRegion::ETSI));
+        assert!(is_channel_allowed(124, &WifiRegion::ETSI));
+        assert!(!is_channel_allowed(149, &WifiRegion::ETSI));
+        assert!(!is_channel_allowed(165, &WifiRegion::ETSI));
+
+        assert!(is_channel_allowed(165, &WifiRegion::ALL));
+    }
+
+    #[test]
+    fn domain_name_length() {
+        assert!(MAX_DOMAIN_NAME_LENGTH >= 64);
+        #[cfg(feature = "nina-w1xx")]
+        assert_eq!(MAX_DOMAIN_NAME_LENGTH, 128);
+    }
+
+    #[test]
+    fn peers_and_payload() {
+        assert!(MAX_PEERS > 0);
+        assert!(EDM_MAX_PAYLOAD <= 4096);
+    }
+}
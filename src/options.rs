@@ -1,6 +1,9 @@
 use core::net::Ipv4Addr;
 use heapless::Vec;
 
+use crate::command::wifi::types::DtimInterval;
+use crate::connection::StaticConfigV4;
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 /// Channel to broadcast wireless hotspot on.
@@ -34,6 +37,7 @@ pub struct HotspotOptions {
     pub(crate) channel: Option<Channel>,
     pub(crate) band: Option<Band>,
     pub(crate) dhcp_server: bool,
+    pub(crate) dtim: Option<DtimInterval>,
 }
 
 impl HotspotOptions {
@@ -42,6 +46,7 @@ impl HotspotOptions {
             channel: Some(Channel::One),
             band: Some(Band::Bg),
             dhcp_server: true,
+            dtim: None,
         }
     }
 
@@ -59,6 +64,13 @@ impl HotspotOptions {
         self.dhcp_server = dhcp_server;
         self
     }
+
+    /// Interval of beacons carrying a DTIM, left at the module's setting if
+    /// not set.
+    pub fn dtim(mut self, dtim: DtimInterval) -> Self {
+        self.dtim = Some(dtim);
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -67,9 +79,90 @@ pub enum WifiAuthentication<'a> {
     #[default]
     None,
     WpaPsk(&'a str),
+    /// WPA2/WPA3 transition mode with a passphrase, using WPA3 SAE where the
+    /// access point supports it. Requires firmware 8.0.0 or newer.
+    ///
+    /// Provisional: the AT values and the minimum firmware are not confirmed
+    /// by the AT commands manual yet.
+    #[cfg(feature = "provisional-at")]
+    Wpa2Wpa3Personal(&'a str),
+    /// WPA3 SAE with a passphrase. Requires firmware 8.0.0 or newer.
+    ///
+    /// Provisional, see [`Self::Wpa2Wpa3Personal`].
+    #[cfg(feature = "provisional-at")]
+    Wpa3Personal(&'a str),
     // WpaEap(todo!()),
 }
 
+/// A certificate or private key used for enterprise authentication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EnterpriseCertificate<'a> {
+    /// Name of the certificate on the module.
+    pub name: &'a str,
+    /// PEM or DER data to import under `name` before joining. `None` if the
+    /// certificate is already stored on the module.
+    pub data: Option<&'a [u8]>,
+}
+
+impl<'a> EnterpriseCertificate<'a> {
+    /// A certificate already stored on the module under `name`.
+    pub fn stored(name: &'a str) -> Self {
+        Self { name, data: None }
+    }
+
+    /// A certificate imported under `name` before joining.
+    pub fn import(name: &'a str, data: &'a [u8]) -> Self {
+        Self {
+            name,
+            data: Some(data),
+        }
+    }
+}
+
+/// Validation of the certificate of the authentication server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CaValidation<'a> {
+    /// Validate the server certificate against this CA certificate.
+    Certificate(EnterpriseCertificate<'a>),
+    /// Accept any server certificate.
+    ///
+    /// The network can then be impersonated, and the credentials captured.
+    /// Only use this for testing.
+    InsecureSkipCaValidation,
+}
+
+impl<'a> CaValidation<'a> {
+    /// The CA certificate, unless validation is skipped.
+    pub fn certificate(&self) -> Option<EnterpriseCertificate<'a>> {
+        match self {
+            Self::Certificate(cert) => Some(*cert),
+            Self::InsecureSkipCaValidation => None,
+        }
+    }
+}
+
+/// WPA-Enterprise authentication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EnterpriseAuth<'a> {
+    /// Protected EAP with a user name and password.
+    PEAP {
+        username: &'a str,
+        password: &'a str,
+        domain: Option<&'a str>,
+        ca_cert: CaValidation<'a>,
+    },
+    /// EAP-TLS with a client certificate.
+    EAPTLS {
+        username: &'a str,
+        ca_cert: CaValidation<'a>,
+        client_cert: EnterpriseCertificate<'a>,
+        client_key: EnterpriseCertificate<'a>,
+    },
+}
+
 impl<'a> From<&'a str> for WifiAuthentication<'a> {
     fn from(s: &'a str) -> Self {
         Self::WpaPsk(s)
@@ -111,6 +204,20 @@ impl<'a> ConnectionOptions<'a> {
         self
     }
 
+    /// Provisional, see the `provisional-at` feature.
+    #[cfg(feature = "provisional-at")]
+    pub fn wpa2_wpa3_personal(mut self, passphrase: &'a str) -> Self {
+        self.auth = WifiAuthentication::Wpa2Wpa3Personal(passphrase);
+        self
+    }
+
+    /// Provisional, see the `provisional-at` feature.
+    #[cfg(feature = "provisional-at")]
+    pub fn wpa3_personal(mut self, passphrase: &'a str) -> Self {
+        self.auth = WifiAuthentication::Wpa3Personal(passphrase);
+        self
+    }
+
     pub fn ip_address(mut self, ip_addr: Ipv4Addr) -> Self {
         self.ip = Some(ip_addr);
         self
@@ -133,3 +240,64 @@ impl<'a> ConnectionOptions<'a> {
         self
     }
 }
+
+/// Credentials of a network, e.g. as negotiated through WPS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WifiCredentials {
+    pub ssid: heapless::String<64>,
+    pub passphrase: Option<heapless::String<64>>,
+}
+
+impl WifiCredentials {
+    /// Connection options for reconnecting to the network using these
+    /// credentials.
+    pub fn connection_options(&self) -> ConnectionOptions<'_> {
+        let options = ConnectionOptions::new(&self.ssid);
+        match &self.passphrase {
+            Some(passphrase) => options.wpa_psk(passphrase),
+            None => options.no_auth(),
+        }
+    }
+}
+
+/// Interface that can be attached to a network bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BridgeInterface {
+    WifiAccessPoint = 2,
+    Ethernet = 3,
+}
+
+/// Layer-2 bridge between network interfaces, e.g. to turn the module into
+/// a Wi-Fi to Ethernet adapter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BridgeProfile {
+    /// Interfaces bridged together, at least two.
+    pub interfaces: Vec<BridgeInterface, 2>,
+    /// Run a DHCP server on the bridge. Requires `static_v4`.
+    pub dhcp: bool,
+    /// IPv4 configuration of the bridge, which then accepts IP traffic on
+    /// all bridged interfaces. Without it, the bridge only forwards frames
+    /// between the interfaces.
+    pub static_v4: Option<StaticConfigV4>,
+}
+
+impl BridgeProfile {
+    pub fn new(interfaces: Vec<BridgeInterface, 2>) -> Self {
+        Self {
+            interfaces,
+            ..Default::default()
+        }
+    }
+
+    pub fn static_v4(mut self, config: StaticConfigV4) -> Self {
+        self.static_v4 = Some(config);
+        self
+    }
+
+    pub fn dhcp(mut self, dhcp: bool) -> Self {
+        self.dhcp = dhcp;
+        self
+    }
+}
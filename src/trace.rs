@@ -0,0 +1,201 @@
+//! Transcript of the traffic with the module, for debugging.
+//!
+//! Register a hook with [`set_hook`] to receive every AT command issued,
+//! every buffer written to the module (including EDM frames), and every
+//! response and URC received. The hook is called from the runner and from
+//! the tasks issuing commands, so it should only copy the data, e.g. into a
+//! pipe drained to RTT or a log file.
+//!
+//! Secret arguments, like the WPA passphrase of `+UWSC`, are cut from the
+//! transcript.
+
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use atat::{DigestResult, Digester};
+
+#[cfg(feature = "internal-network-stack")]
+use crate::command::edm::types::{PayloadType, STARTBYTE};
+
+/// Kind of traffic reported to the trace hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TraceKind {
+    /// AT command issued by the driver, without EDM framing.
+    Command,
+    /// Buffer written to the module.
+    Tx,
+    /// Response to a command.
+    Response,
+    /// Unsolicited result code or EDM event.
+    Urc,
+}
+
+/// Traffic reported to the trace hook.
+#[derive(Debug)]
+pub struct TraceEvent<'a> {
+    pub kind: TraceKind,
+    /// The traffic, truncated to the limit given to [`set_hook`] and cut in
+    /// front of a redacted argument.
+    pub data: &'a [u8],
+    /// Length of the traffic before truncation.
+    pub len: usize,
+    /// Whether a secret argument was cut from `data`.
+    pub redacted: bool,
+}
+
+/// Hook receiving the traffic with the module.
+pub type TraceHook = fn(&TraceEvent<'_>);
+
+static HOOK: AtomicPtr<()> = AtomicPtr::new(null_mut());
+static LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Commands with secret arguments, and the parameter tags of those.
+const SECRET_ARGS: &[(&[u8], &[&[u8]])] = &[
+    // WEP keys, WPA passphrase and EAP password
+    (b"+UWSC=", &[b"6", b"8", b"9"]),
+    // WPA passphrase of the access point
+    (b"+UWAPC=", &[b"8"]),
+];
+
+/// Register `hook` to receive the traffic with the module, truncated to
+/// `limit` bytes, or unregister the hook with `None`.
+pub fn set_hook(hook: Option<TraceHook>, limit: usize) {
+    LIMIT.store(limit, Ordering::Relaxed);
+    HOOK.store(
+        hook.map_or(null_mut(), |hook| hook as *mut ()),
+        Ordering::Release,
+    );
+}
+
+pub(crate) fn emit(kind: TraceKind, data: &[u8]) {
+    let hook = HOOK.load(Ordering::Acquire);
+    if hook.is_null() {
+        return;
+    }
+    // SAFETY: `HOOK` only ever holds null or a `TraceHook`.
+    let hook = unsafe { core::mem::transmute::<*mut (), TraceHook>(hook) };
+
+    let data = match kind {
+        TraceKind::Command => at_payload(data),
+        _ => data,
+    };
+    let secret = secret_start(data);
+    let end = secret
+        .unwrap_or(data.len())
+        .min(LIMIT.load(Ordering::Relaxed));
+
+    hook(&TraceEvent {
+        kind,
+        data: &data[..end],
+        len: data.len(),
+        redacted: secret.is_some(),
+    });
+}
+
+/// The AT command wrapped in the EDM request `data`, or `data` as is.
+#[cfg(feature = "internal-network-stack")]
+fn at_payload(data: &[u8]) -> &[u8] {
+    match data {
+        [STARTBYTE, _, _, _, kind, at @ .., _] if *kind == PayloadType::ATRequest as u8 => at,
+        _ => data,
+    }
+}
+
+/// Commands are only wrapped in EDM with the internal network stack.
+#[cfg(not(feature = "internal-network-stack"))]
+fn at_payload(data: &[u8]) -> &[u8] {
+    data
+}
+
+/// Offset of a secret argument in `data`, if any.
+fn secret_start(data: &[u8]) -> Option<usize> {
+    SECRET_ARGS.iter().find_map(|(cmd, tags)| {
+        let start = data.windows(cmd.len()).position(|w| w == *cmd)? + cmd.len();
+        let args = &data[start..];
+        let tag_start = args.iter().position(|&b| b == b',')? + 1;
+        let tag_len = args[tag_start..].iter().position(|&b| b == b',')?;
+        let tag = &args[tag_start..tag_start + tag_len];
+
+        tags.contains(&tag)
+            .then_some(start + tag_start + tag_len + 1)
+    })
+}
+
+/// Digester reporting the responses and URCs found by `D` to the trace hook.
+pub(crate) struct TraceDigester<D> {
+    inner: D,
+}
+
+impl<D: Digester> TraceDigester<D> {
+    pub(crate) fn new(inner: D) -> Self {
+        Self { inner }
+    }
+}
+
+impl<D: Digester> Digester for TraceDigester<D> {
+    fn digest<'a>(&mut self, buf: &'a [u8]) -> (DigestResult<'a>, usize) {
+        let (result, len) = self.inner.digest(buf);
+        match &result {
+            DigestResult::Response(Ok(resp)) => emit(TraceKind::Response, resp),
+            DigestResult::Response(Err(_)) => emit(TraceKind::Response, &buf[..len]),
+            DigestResult::Urc(urc) => emit(TraceKind::Urc, urc),
+            _ => {}
+        }
+        (result, len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    static EVENTS: Mutex<Vec<(TraceKind, Vec<u8>, usize, bool)>> = Mutex::new(Vec::new());
+
+    fn record(event: &TraceEvent<'_>) {
+        EVENTS
+            .lock()
+            .unwrap()
+            .push((event.kind, event.data.to_vec(), event.len, event.redacted));
+    }
+
+    #[test]
+    fn redact_secrets() {
+        assert_eq!(secret_start(b"AT+UWSC=0,2,\"ssid\"\r\n"), None);
+        assert_eq!(secret_start(b"AT+UWSC=0,8,\"secret\"\r\n"), Some(12));
+        assert_eq!(secret_start(b"AT+UWAPC=0,8,\"secret\"\r\n"), Some(13));
+        assert_eq!(secret_start(b"AT+UWAPC=0,6,1\r\n"), None);
+    }
+
+    #[cfg(feature = "internal-network-stack")]
+    #[test]
+    fn unwrap_edm_request() {
+        let mut frame = std::vec![STARTBYTE, 0x00, 0x19, 0x00, PayloadType::ATRequest as u8];
+        frame.extend_from_slice(b"AT+UWSC=0,8,\"secret\"\r\n");
+        frame.push(crate::command::edm::types::ENDBYTE);
+        assert_eq!(at_payload(&frame), b"AT+UWSC=0,8,\"secret\"\r\n");
+
+        assert_eq!(at_payload(b"AT\r\n"), b"AT\r\n");
+    }
+
+    #[test]
+    fn hook_truncates_and_redacts() {
+        set_hook(Some(record), 8);
+
+        emit(TraceKind::Command, b"AT+UWSC=0,8,\"secret\"\r\n");
+        emit(TraceKind::Urc, b"+UUNU:0\r\n");
+
+        set_hook(None, usize::MAX);
+        emit(TraceKind::Urc, b"+UUND:0\r\n");
+
+        // Commands issued by other tests may be recorded as well
+        let events = EVENTS.lock().unwrap();
+        assert!(events.contains(&(TraceKind::Command, b"AT+UWSC=".to_vec(), 22, true)));
+        assert!(events.contains(&(TraceKind::Urc, b"+UUNU:0\r".to_vec(), 9, false)));
+        assert!(!events
+            .iter()
+            .any(|(_, data, _, _)| data.starts_with(b"+UUND")));
+    }
+}